@@ -0,0 +1,81 @@
+//! Random programs through the evaluator. The input bytes pick forms from a
+//! small grammar of pure builtins and special forms, so most programs get past
+//! the reader and exercise the builtins. Errors are fine; panics aren't.
+//!
+//! Programs run in a sandboxed interpreter, so they can't touch files, and
+//! with a fuel limit, so loops end.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate telescope;
+
+use telescope::Interpreter;
+
+const FUEL: usize = 10_000;
+const DEPTH: usize = 4;
+
+const CALLS: &[&str] = &[
+    "+", "-", "*", "/", "<", "=", "not", "list", "values", "first", "rest", "cons", "get",
+    "map", "filter", "some", "every?", "group-by", "frequencies", "partition", "interleave",
+    "zip", "zipmap", "distinct", "flatten", "range", "sum", "median", "take", "str", "join",
+    "str-len", "substr", "format", "parse-int", "type", "atom", "swap!", "reset!", "deref",
+    "freeze", "partial", "call/ec", "read-string", "diff", "matrix", "transpose",
+    "if", "let", "fn", "do", "def", "quote", "and", "or", "when", "case", "->", "match", "for",
+    "with-handler", "block", "return-from",
+];
+const ATOMS: &[&str] = &["x", "y", "f", "nil", "#t", "#f", ":a", "[]", "{}", "()", "-1", "0"];
+
+// The input, a byte at a time, then zeroes
+struct Choices<'a>(&'a [u8]);
+
+impl<'a> Choices<'a> {
+    fn pick(&mut self, count: usize) -> usize {
+        match self.0.split_first() {
+            Some((&byte, rest)) => {
+                self.0 = rest;
+                byte as usize % count
+            }
+            None => 0,
+        }
+    }
+}
+
+fn form(choices: &mut Choices, depth: usize, out: &mut String) {
+    match choices.pick(if depth == 0 { 4 } else { 8 }) {
+        0 => out.push_str(ATOMS[choices.pick(ATOMS.len())]),
+        1 => out.push_str(&(choices.pick(256) as i64 - 128).to_string()),
+        2 => out.push_str(&format!("{:?}", choices.pick(256) as f64 / 16.0)),
+        3 => out.push_str(&format!("\"{}\"", "ab\\n{}".repeat(choices.pick(3)))),
+        4 => {
+            out.push('[');
+            for _ in 0..choices.pick(4) {
+                form(choices, depth - 1, out);
+                out.push(' ');
+            }
+            out.push(']');
+        }
+        _ => {
+            out.push('(');
+            out.push_str(CALLS[choices.pick(CALLS.len())]);
+            for _ in 0..choices.pick(4) {
+                out.push(' ');
+                form(choices, depth - 1, out);
+            }
+            out.push(')');
+        }
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut choices = Choices(data);
+    let mut program = String::from("(def x 1) (def y [1 2 3]) (defn f [a] a) ");
+    for _ in 0..1 + choices.pick(4) {
+        form(&mut choices, DEPTH, &mut program);
+        program.push(' ');
+    }
+
+    telescope::set_fuel(Some(FUEL));
+    let _ = Interpreter::sandboxed().eval_str(&program);
+    telescope::set_fuel(None);
+});
@@ -0,0 +1,19 @@
+//! Arbitrary text through the reader. Reading should fail with an error rather
+//! than panic, and whatever it reads should print as text that reads back the
+//! same.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate telescope;
+
+fuzz_target!(|data: &[u8]| {
+    let source = String::from_utf8_lossy(data);
+    if let Ok(forms) = telescope::parse(&source) {
+        for form in forms {
+            let printed = form.to_string();
+            let read = telescope::parse(&printed).expect("printed form doesn't read back");
+            assert_eq!(vec![form], read, "{} doesn't read back the same", printed);
+        }
+    }
+});
@@ -0,0 +1,51 @@
+/* The C API for Telescope, from the library built with the `ffi` feature.
+ * See src/ffi.rs for the details of who owns what. Passing a null value is an
+ * error, which telescope_error() reports like any other. */
+
+#ifndef TELESCOPE_H
+#define TELESCOPE_H
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct Interpreter telescope_t;
+typedef struct Value telescope_value_t;
+
+enum {
+    TELESCOPE_NIL = 0,
+    TELESCOPE_BOOL = 1,
+    TELESCOPE_INT = 2,
+    TELESCOPE_FLOAT = 3,
+    TELESCOPE_STRING = 4,
+    TELESCOPE_LIST = 5,
+    TELESCOPE_OTHER = 6
+};
+
+telescope_t *telescope_new(void);
+void telescope_free(telescope_t *interpreter);
+
+/* Returns null on failure, and telescope_error() says why. */
+telescope_value_t *telescope_eval(const telescope_t *interpreter, const char *code);
+const char *telescope_error(void);
+
+void telescope_value_free(telescope_value_t *value);
+int telescope_value_kind(const telescope_value_t *value);
+bool telescope_value_bool(const telescope_value_t *value);
+int telescope_value_int(const telescope_value_t *value, int64_t *out);
+int telescope_value_float(const telescope_value_t *value, double *out);
+/* Free the result with telescope_string_free. */
+char *telescope_value_string(const telescope_value_t *value);
+int64_t telescope_value_len(const telescope_value_t *value);
+telescope_value_t *telescope_value_get(const telescope_value_t *value, int64_t index);
+
+void telescope_string_free(char *text);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
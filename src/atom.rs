@@ -0,0 +1,171 @@
+//! Atoms, mutable references for state shared between functions and futures.
+//! Everything else is immutable, so atoms are the only thing `deep-copy` needs to
+//! duplicate and `freeze` can lock.
+
+use std::any::Any;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use error::*;
+use types::{Expr, Handle, List, Record, Resource, Vector};
+use util::lock;
+
+#[derive(Debug)]
+pub struct Atom {
+    // The value and how many times it's been changed, so swap! can tell
+    // whether another thread got there first
+    state: Mutex<(u64, Expr)>,
+    frozen: AtomicBool,
+}
+
+impl Atom {
+    pub fn new(value: Expr) -> Handle {
+        Handle::new(Arc::new(Atom {
+            state: Mutex::new((0, value)),
+            frozen: AtomicBool::new(false),
+        }))
+    }
+
+    pub fn get(&self) -> Expr {
+        lock(&self.state).1.clone()
+    }
+
+    pub fn set(&self, value: Expr) -> Result<()> {
+        self.ensure_thawed()?;
+        let mut state = lock(&self.state);
+        *state = (state.0 + 1, value);
+        Ok(())
+    }
+
+    /// Replace the value with `f` of it. `f` isn't called with the lock held,
+    /// and is called again if the value changes before it returns.
+    pub fn swap<F>(&self, f: F) -> Result<Expr>
+    where
+        F: Fn(Expr) -> Result<Expr>,
+    {
+        loop {
+            self.ensure_thawed()?;
+            let (version, old) = lock(&self.state).clone();
+            let new = f(old)?;
+            let mut state = lock(&self.state);
+            if state.0 == version {
+                *state = (version + 1, new.clone());
+                return Ok(new);
+            }
+        }
+    }
+
+    pub fn freeze(&self) {
+        self.frozen.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen.load(Ordering::SeqCst)
+    }
+
+    fn ensure_thawed(&self) -> Result<()> {
+        ensure!(!self.is_frozen(), "can't change a frozen atom");
+        Ok(())
+    }
+}
+
+impl Resource for Atom {
+    fn type_name(&self) -> &'static str {
+        "atom"
+    }
+
+    fn as_any(&self) -> &Any {
+        self
+    }
+}
+
+/// A deep copy of `value`, with every atom in it replaced by a new atom
+/// holding a copy of its value. The copies aren't frozen.
+pub fn copy(value: &Expr) -> Expr {
+    match *value {
+        Expr::Handle(ref handle) => match handle.downcast_ref::<Atom>() {
+            Some(atom) => Expr::Handle(Atom::new(copy(&atom.get()))),
+            None => value.clone(),
+        },
+        Expr::List(ref list) => Expr::List(List(list.0.iter().map(copy).collect(), list.1.clone())),
+        Expr::Vector(ref vec) => Expr::Vector(Vector(vec.0.iter().map(copy).collect(), vec.1.clone())),
+        Expr::Map(ref map) => {
+            let mut copied = map.clone();
+            for (key, value) in map.iter() {
+                copied.insert(key.clone(), copy(value));
+            }
+            Expr::Map(copied)
+        }
+        Expr::Record(ref record) => {
+            let values = record.values.iter().map(copy).collect();
+            Expr::Record(Record::new(record.rtype.clone(), values))
+        }
+        _ => value.clone(),
+    }
+}
+
+/// Freeze every atom in `value`, and the atoms in their values.
+pub fn freeze(value: &Expr) {
+    match *value {
+        Expr::Handle(ref handle) => {
+            if let Some(atom) = handle.downcast_ref::<Atom>() {
+                atom.freeze();
+                freeze(&atom.get());
+            }
+        }
+        Expr::List(List(ref items, _)) | Expr::Vector(Vector(ref items, _)) => {
+            for item in items {
+                freeze(item);
+            }
+        }
+        Expr::Map(ref map) => {
+            for (_, value) in map.iter() {
+                freeze(value);
+            }
+        }
+        Expr::Record(ref record) => {
+            for value in &record.values {
+                freeze(value);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Whether `value` is a frozen atom.
+pub fn is_frozen(value: &Expr) -> bool {
+    match *value {
+        Expr::Handle(ref handle) => handle.downcast_ref::<Atom>().map_or(false, Atom::is_frozen),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn atom(value: &Expr) -> &Atom {
+        match *value {
+            Expr::Handle(ref handle) => handle.downcast_ref::<Atom>().unwrap(),
+            _ => panic!("not an atom: {}", value),
+        }
+    }
+
+    #[test]
+    fn copy_and_freeze() {
+        let inner = Expr::Handle(Atom::new(Expr::from(1)));
+        let outer = Expr::Vector(Vector::new(vec![inner.clone(), Expr::from("x")]));
+        let copied = copy(&outer);
+        let copied_inner = copied.vector().unwrap().0[0].clone();
+        assert_ne!(inner, copied_inner);
+
+        atom(&copied_inner).set(Expr::from(2)).unwrap();
+        assert_eq!(Expr::from(1), atom(&inner).get());
+
+        freeze(&outer);
+        assert!(is_frozen(&inner) && !is_frozen(&copied_inner));
+        assert!(atom(&inner).set(Expr::from(3)).is_err());
+        assert!(atom(&inner).swap(|_| Ok(Expr::Nil)).is_err());
+        assert_eq!(Expr::from(3), atom(&copied_inner).swap(|n| Ok(Expr::from(n.int().unwrap() + 1))).unwrap());
+    }
+}
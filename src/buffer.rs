@@ -4,21 +4,43 @@ use std::mem;
 use rustyline;
 use rustyline::error::ReadlineError as RLError;
 
+use editor::Helper;
+
 #[derive(Debug)]
 pub struct Readline {
-    rl: rustyline::Editor<()>,
+    rl: rustyline::Editor<Helper>,
     prompt: String,
     buffer: String,
+    last_line: String,
 }
 
 impl Readline {
     pub fn new(prompt: &str) -> Self {
+        let mut rl = rustyline::Editor::new();
+        rl.set_helper(Some(Helper));
         Readline {
-            rl: rustyline::Editor::new(),
+            rl: rl,
             prompt: prompt.to_owned(),
             buffer: String::with_capacity(128),
+            last_line: String::new(),
         }
     }
+
+    /// Peek at the next line of input without consuming it.
+    pub fn peek_line(&mut self) -> io::Result<&str> {
+        self.fill_buf()?;
+        Ok(&self.buffer)
+    }
+
+    /// Discard the rest of the current line.
+    pub fn skip_line(&mut self) {
+        self.buffer.clear();
+    }
+
+    /// The most recent line entered, for pointing at errors within it.
+    pub fn last_line(&self) -> &str {
+        &self.last_line
+    }
 }
 
 impl Read for Readline {
@@ -26,20 +48,14 @@ impl Read for Readline {
         if self.buffer.is_empty() {
             match self.rl.readline(&self.prompt) {
                 Ok(line) => {
-                    self.rl.add_history_entry(&line);
+                    self.rl.add_history_entry(line.as_str());
                     self.buffer.push_str(&line);
                     self.buffer.push('\n');
+                    self.last_line = line;
                 },
                 Err(RLError::Io(err)) => return Err(err),
                 Err(RLError::Eof) | Err(RLError::Interrupted) => return Ok(0),
-                #[cfg(unix)]
-                Err(RLError::Char(_)) => return Ok(0),
-                #[cfg(unix)]
-                Err(RLError::Errno(err)) => return Err(io::Error::from(err)),
-                #[cfg(windows)]
-                Err(RLError::WindowResize) => return Err(io::ErrorKind::Other.into()),
-                #[cfg(windows)]
-                Err(RLError::Decode(_)) => return Err(io::ErrorKind::InvalidData.into()),
+                Err(err) => return Err(io::Error::new(io::ErrorKind::Other, err.to_string())),
             };
         }
 
@@ -61,20 +77,14 @@ impl io::BufRead for Readline {
         if self.buffer.is_empty() {
             match self.rl.readline(&self.prompt) {
                 Ok(line) => {
-                    self.rl.add_history_entry(&line);
+                    self.rl.add_history_entry(line.as_str());
                     self.buffer.push_str(&line);
                     self.buffer.push('\n');
+                    self.last_line = line;
                 },
                 Err(RLError::Io(err)) => return Err(err),
                 Err(RLError::Eof) | Err(RLError::Interrupted) => (),
-                #[cfg(unix)]
-                Err(RLError::Char(_)) => (),
-                #[cfg(unix)]
-                Err(RLError::Errno(err)) => return Err(io::Error::from(err)),
-                #[cfg(windows)]
-                Err(RLError::WindowResize) => return Err(io::ErrorKind::Other.into()),
-                #[cfg(windows)]
-                Err(RLError::Decode(_)) => return Err(io::ErrorKind::InvalidData.into()),
+                Err(err) => return Err(io::Error::new(io::ErrorKind::Other, err.to_string())),
             }
         }
 
@@ -0,0 +1,134 @@
+//! Bundling a program and the modules it requires into a single file.
+//!
+//! Each `(require name)` at the top level of a file is replaced by the
+//! contents of that module, the first time it's required, so the bundle
+//! evaluates in the same order as the original program. Top-level forms
+//! marked `^:dev`, or that define a name marked `^:dev`, are left out.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use error::*;
+use fmt;
+use module::Manifest;
+use types::{Expr, Key, Map};
+
+/// Bundle the program starting at `entry`, resolving modules with `manifest`.
+pub fn bundle(entry: &Path, manifest: &Manifest) -> Result<String> {
+    let mut bundler = Bundler {
+        manifest: manifest,
+        done: HashSet::new(),
+        loading: Vec::new(),
+        out: String::new(),
+    };
+    bundler.module(entry)?;
+    Ok(bundler.out)
+}
+
+struct Bundler<'a> {
+    manifest: &'a Manifest,
+    done: HashSet<PathBuf>,
+    // Modules being bundled, innermost last, for catching cycles
+    loading: Vec<PathBuf>,
+    out: String,
+}
+
+impl<'a> Bundler<'a> {
+    fn module(&mut self, path: &Path) -> Result<()> {
+        let key = path.canonicalize()
+            .map_err(|err| format!("cannot bundle {}: {}", path.display(), err))?;
+        if self.done.contains(&key) {
+            return Ok(());
+        }
+        ensure!(!self.loading.contains(&key), "{} requires itself", path.display());
+        self.loading.push(key.clone());
+
+        let mut source = String::new();
+        fs::File::open(path)?.read_to_string(&mut source)?;
+        let forms = fmt::split(&source).map_err(|err| format!("{}: {}", path.display(), err))?;
+
+        if !self.out.is_empty() {
+            self.out.push('\n');
+        }
+        self.out.push_str(&format!(";; {}\n", path.display()));
+        for (form, text) in forms {
+            if is_dev(&form) {
+                continue;
+            }
+            match required(&form) {
+                Some(name) => {
+                    let path = self.manifest.resolve(name);
+                    self.module(&path)?;
+                }
+                None => {
+                    self.out.push_str(&text);
+                    self.out.push('\n');
+                }
+            }
+        }
+
+        self.loading.pop();
+        self.done.insert(key);
+        Ok(())
+    }
+}
+
+/// The module a `(require name)` form loads.
+pub(crate) fn required(form: &Expr) -> Option<&str> {
+    let list = &form.list()?.0;
+    match (list.get(0).and_then(Expr::sym), list.get(1).and_then(Expr::sym)) {
+        (Some(head), Some(name)) if head.0 == "require" && list.len() == 2 => Some(&name.0),
+        _ => None,
+    }
+}
+
+fn is_dev(form: &Expr) -> bool {
+    let dev = |meta: Option<&Map>| {
+        meta.and_then(|meta| meta.get(&Key::Keyword("dev".to_owned())))
+            .map_or(false, Expr::truthiness)
+    };
+    // ^:dev (form), or (def ^:dev name ...)
+    dev(form.meta()) || form.list()
+        .and_then(|list| list.0.get(1))
+        .and_then(Expr::sym)
+        .map_or(false, |name| dev(name.1.get()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+
+    fn write(dir: &Path, name: &str, source: &str) {
+        fs::File::create(dir.join(name)).unwrap().write_all(source.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn inlines_requires() {
+        let dir = env::temp_dir().join("telescope-bundle-test");
+        fs::create_dir_all(dir.join("util")).unwrap();
+        write(&dir, "main.tl", "(require util/a)\n(require b)\n^:dev (print \"testing\")\n(print (f (g 1)))\n");
+        write(&dir, "util/a.tl", "; helpers\n(require b)\n(defn f [x] (+ x 1))\n");
+        write(&dir, "b.tl", "(defn g [x]\n  (* x 2))\n(def ^:dev test-value 1)\n");
+
+        let manifest = Manifest { dir: dir.clone(), deps: Vec::new() };
+        let bundle = bundle(&dir.join("main.tl"), &manifest).unwrap();
+        let code = bundle.lines()
+            .filter(|line| !line.is_empty() && !line.starts_with(";;"))
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["(defn g [x] (* x 2))", "(defn f [x] (+ x 1))", "(print (f (g 1)))"], code);
+    }
+
+    #[test]
+    fn cycles() {
+        let dir = env::temp_dir().join("telescope-bundle-cycle");
+        fs::create_dir_all(&dir).unwrap();
+        write(&dir, "a.tl", "(require b)");
+        write(&dir, "b.tl", "(require a)");
+
+        let manifest = Manifest { dir: dir.clone(), deps: Vec::new() };
+        assert!(bundle(&dir.join("a.tl"), &manifest).is_err());
+    }
+}
@@ -0,0 +1,73 @@
+use std::any::Any;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::time::Duration;
+
+use error::*;
+use interrupt;
+use types::{Expr, Handle, Resource};
+use util::lock;
+
+// How often a waiting recv! checks for Ctrl-C, in milliseconds
+const POLL_INTERVAL: u64 = 100;
+
+/// An unbounded queue of values, for passing messages between futures.
+/// Sending never blocks, and receiving blocks until a value arrives or the
+/// channel is closed.
+#[derive(Debug)]
+pub struct Channel {
+    // The mpsc ends aren't Sync, so each sits behind its own lock to let
+    // senders proceed while a receiver waits. Closing drops the sender.
+    sender: Mutex<Option<Sender<Expr>>>,
+    receiver: Mutex<Receiver<Expr>>,
+}
+
+impl Channel {
+    pub fn new() -> Handle {
+        let (sender, receiver) = mpsc::channel();
+        Handle::new(Arc::new(Channel {
+            sender: Mutex::new(Some(sender)),
+            receiver: Mutex::new(receiver),
+        }))
+    }
+
+    pub fn send(&self, value: Expr) -> Result<()> {
+        match *lock(&self.sender) {
+            Some(ref sender) => sender.send(value).map_err(|_| closed("send!")),
+            None => Err(closed("send!")),
+        }
+    }
+
+    /// The next value, waiting for one if there are none yet. Once the
+    /// channel is closed, the values already sent can still be received.
+    pub fn recv(&self) -> Result<Expr> {
+        let receiver = lock(&self.receiver);
+        loop {
+            match receiver.recv_timeout(Duration::from_millis(POLL_INTERVAL)) {
+                Ok(value) => return Ok(value),
+                Err(RecvTimeoutError::Timeout) => interrupt::check()?,
+                Err(RecvTimeoutError::Disconnected) => return Err(closed("recv!")),
+            }
+        }
+    }
+
+    /// Stop the channel taking any more values. Closing it again does
+    /// nothing.
+    pub fn close(&self) {
+        lock(&self.sender).take();
+    }
+}
+
+impl Resource for Channel {
+    fn type_name(&self) -> &'static str {
+        "chan"
+    }
+
+    fn as_any(&self) -> &Any {
+        self
+    }
+}
+
+fn closed(name: &str) -> Error {
+    format!("#[{}] channel is closed", name).into()
+}
@@ -0,0 +1,101 @@
+//! ANSI terminal colors for REPL output.
+
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use itertools::Itertools;
+use types::Expr;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Color {
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+}
+
+impl Color {
+    fn code(&self) -> u8 {
+        match *self {
+            Color::Red => 31,
+            Color::Green => 32,
+            Color::Yellow => 33,
+            Color::Blue => 34,
+            Color::Magenta => 35,
+            Color::Cyan => 36,
+        }
+    }
+}
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn paint<T: Display>(color: Color, text: T) -> String {
+    if enabled() {
+        format!("\x1b[{}m{}\x1b[0m", color.code(), text)
+    } else {
+        text.to_string()
+    }
+}
+
+pub fn underline<T: Display>(color: Color, text: T) -> String {
+    if enabled() {
+        format!("\x1b[4;{}m{}\x1b[0m", color.code(), text)
+    } else {
+        text.to_string()
+    }
+}
+
+pub fn bold<T: Display>(color: Color, text: T) -> String {
+    if enabled() {
+        format!("\x1b[1;{}m{}\x1b[0m", color.code(), text)
+    } else {
+        text.to_string()
+    }
+}
+
+pub fn dim<T: Display>(text: T) -> String {
+    if enabled() {
+        format!("\x1b[2m{}\x1b[0m", text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Render `expr` as it would be displayed, with syntax coloring.
+pub fn highlight(expr: &Expr) -> String {
+    match *expr {
+        Expr::Nil | Expr::Bool(_) => paint(Color::Magenta, expr),
+        Expr::Int(_) | Expr::Flt(_) => paint(Color::Cyan, expr),
+        Expr::Str(_) | Expr::Bytes(_) => paint(Color::Green, expr),
+        Expr::Keyword(_) => paint(Color::Yellow, expr),
+        Expr::Func(_) | Expr::Macro(_) => paint(Color::Blue, expr),
+        Expr::List(ref list) => format!("({})", list.0.iter().map(highlight).join(" ")),
+        Expr::Vector(ref vec) => format!("[{}]", vec.0.iter().map(highlight).join(" ")),
+        Expr::Map(ref map) => {
+            let pairs = map.iter()
+                .map(|(key, val)| format!("{}: {}", key, highlight(val)))
+                .join(", ");
+            format!("{{{}}}", pairs)
+        }
+        _ => expr.to_string(),
+    }
+}
+
+/// Echo `line` with the char at `column` underlined, to point at a syntax
+/// error.
+pub fn underline_span(line: &str, column: usize) -> String {
+    let before = line.chars().take(column).collect::<String>();
+    let at = line.chars().nth(column).unwrap_or(' ');
+    let after = line.chars().skip(column + 1).collect::<String>();
+    format!("{}{}{}", before, underline(Color::Red, at), after)
+}
@@ -0,0 +1,102 @@
+//! REPL meta-commands, e.g. `:load file`, handled before input reaches the
+//! parser.
+
+use error::*;
+
+pub const HELP: &'static str = "\
+:help          Show this message
+:load <file>   Evaluate a file in the current environment
+:type <expr>   Show the type of an expression's value
+:env           List all bindings in scope
+:reset         Start over with a fresh environment
+:set <opt> <v> Change a REPL setting, e.g. :set timing on
+:quit          Exit the REPL";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Help,
+    Load(String),
+    Type(String),
+    Env,
+    Reset,
+    Set(String, String),
+    Quit,
+}
+
+/// REPL settings changed with `:set`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Settings {
+    /// Report how long each evaluation took.
+    pub timing: bool,
+}
+
+impl Settings {
+    pub fn set(&mut self, option: &str, value: &str) -> Result<()> {
+        let flag = match value {
+            "on" | "true" => true,
+            "off" | "false" => false,
+            _ => bail!(":set {} expected on or off", option),
+        };
+        match option {
+            "timing" => self.timing = flag,
+            _ => bail!(":set unknown setting {}", option),
+        }
+        Ok(())
+    }
+}
+
+/// Parse `line` as a meta-command, returning `None` if it isn't one (so it can
+/// be read as an ordinary expression).
+pub fn parse(line: &str) -> Option<Command> {
+    let line = line.trim();
+    if !line.starts_with(':') {
+        return None;
+    }
+
+    let mut parts = line[1..].splitn(2, char::is_whitespace);
+    let name = parts.next().unwrap_or("");
+    let arg = parts.next().unwrap_or("").trim().to_owned();
+    match name {
+        "help" | "h" | "?" => Some(Command::Help),
+        "load" | "l" => Some(Command::Load(arg)),
+        "type" | "t" => Some(Command::Type(arg)),
+        "env" => Some(Command::Env),
+        "reset" => Some(Command::Reset),
+        "set" => {
+            let mut words = arg.split_whitespace();
+            let option = words.next().unwrap_or("").to_owned();
+            let value = words.next().unwrap_or("").to_owned();
+            Some(Command::Set(option, value))
+        }
+        "quit" | "q" => Some(Command::Quit),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!(Some(Command::Help), parse(":help"));
+        assert_eq!(Some(Command::Load("a b.tl".into())), parse("  :load  a b.tl \n"));
+        assert_eq!(Some(Command::Type("(+ 1 2)".into())), parse(":t (+ 1 2)"));
+        assert_eq!(Some(Command::Quit), parse(":q"));
+        assert_eq!(None, parse(":unknown"));
+        assert_eq!(None, parse("(+ 1 2)"));
+    }
+
+    #[test]
+    fn settings() {
+        let mut settings = Settings::default();
+        assert_eq!(
+            Some(Command::Set("timing".into(), "on".into())),
+            parse(":set timing on")
+        );
+        settings.set("timing", "on").unwrap();
+        assert!(settings.timing);
+        assert!(settings.set("timing", "maybe").is_err());
+        assert!(settings.set("nothing", "on").is_err());
+    }
+}
@@ -0,0 +1,127 @@
+//! Reading and writing CSV, as described by RFC 4180: fields are separated by
+//! a delimiter, and quoted fields may hold delimiters, newlines, and doubled
+//! quotes.
+
+use error::*;
+
+/// The punctuation of a CSV file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Dialect {
+    pub delimiter: char,
+    pub quote: char,
+}
+
+impl Default for Dialect {
+    fn default() -> Self {
+        Dialect { delimiter: ',', quote: '"' }
+    }
+}
+
+/// The rows of `text`. A trailing newline doesn't start another row.
+pub fn parse(text: &str, dialect: Dialect) -> Result<Vec<Vec<String>>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+
+    while let Some(c) = chars.next() {
+        if c == dialect.quote && field.is_empty() {
+            // A quoted field runs to the next lone quote
+            let start = line;
+            loop {
+                match chars.next() {
+                    Some(c) if c == dialect.quote => {
+                        if chars.peek() == Some(&dialect.quote) {
+                            chars.next();
+                            field.push(c);
+                        } else {
+                            break;
+                        }
+                    }
+                    Some(c) => {
+                        if c == '\n' {
+                            line += 1;
+                        }
+                        field.push(c);
+                    }
+                    None => bail!("unclosed quote in field starting on line {}", start),
+                }
+            }
+            match chars.peek() {
+                None | Some(&'\r') | Some(&'\n') => (),
+                Some(&c) if c == dialect.delimiter => (),
+                Some(&c) => bail!("unexpected {:?} after quoted field on line {}", c, line),
+            }
+        } else if c == dialect.delimiter {
+            row.push(field.split_off(0));
+        } else if c == '\r' && chars.peek() == Some(&'\n') {
+            continue;
+        } else if c == '\n' {
+            row.push(field.split_off(0));
+            rows.push(row.split_off(0));
+            line += 1;
+        } else {
+            field.push(c);
+        }
+    }
+
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+/// `rows` as CSV, quoting the fields that need it. Each row ends in a newline.
+pub fn write(rows: &[Vec<String>], dialect: Dialect) -> String {
+    let mut text = String::new();
+    for row in rows {
+        let fields = row.iter().map(|field| quote(field, dialect)).collect::<Vec<_>>();
+        text.push_str(&fields.join(&dialect.delimiter.to_string()));
+        text.push('\n');
+    }
+    text
+}
+
+fn quote(field: &str, dialect: Dialect) -> String {
+    let special = |c: char| c == dialect.delimiter || c == dialect.quote || c == '\r' || c == '\n';
+    if !field.contains(special) {
+        return field.to_owned();
+    }
+    let quote = dialect.quote.to_string();
+    let doubled = field.replace(&quote, &format!("{0}{0}", quote));
+    format!("{0}{1}{0}", quote, doubled)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rows(rows: &[&[&str]]) -> Vec<Vec<String>> {
+        rows.iter().map(|row| row.iter().map(|&field| field.to_owned()).collect()).collect()
+    }
+
+    #[test]
+    fn parse_fields() {
+        let dialect = Dialect::default();
+        assert_eq!(rows(&[&["a", "b"], &["1", ""]]), parse("a,b\n1,\n", dialect).unwrap());
+        assert_eq!(rows(&[&["a", "b"], &["1", "2"]]), parse("a,b\r\n1,2", dialect).unwrap());
+        assert_eq!(
+            rows(&[&["x, y", "say \"hi\"", "two\nlines"]]),
+            parse("\"x, y\",\"say \"\"hi\"\"\",\"two\nlines\"\n", dialect).unwrap()
+        );
+        assert!(parse("\"open", dialect).is_err());
+        assert!(parse("\"a\"b", dialect).is_err());
+        assert_eq!(Vec::<Vec<String>>::new(), parse("", dialect).unwrap());
+    }
+
+    #[test]
+    fn round_trip() {
+        let dialect = Dialect { delimiter: ';', quote: '\'' };
+        let data = rows(&[&["plain", "semi;colon", "it's"], &["", "new\nline", "\"double\""]]);
+        let text = write(&data, dialect);
+        assert_eq!("plain;'semi;colon';'it''s'\n;'new\nline';\"double\"\n", text);
+        assert_eq!(data, parse(&text, dialect).unwrap());
+    }
+}
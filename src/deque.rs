@@ -0,0 +1,92 @@
+//! A double-ended queue, which adds and removes items at either end in
+//! constant time. Vectors only do that at the end, so a queue of work, as in
+//! a breadth-first search, would otherwise copy everything left in it each
+//! time an item is taken off the front.
+
+use std::any::Any;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use types::{Expr, Handle, Resource};
+use util::lock;
+
+#[derive(Debug)]
+pub struct Deque {
+    items: Mutex<VecDeque<Expr>>,
+}
+
+impl Deque {
+    pub fn new(items: &[Expr]) -> Handle {
+        let items = items.iter().cloned().collect();
+        Handle::new(Arc::new(Deque { items: Mutex::new(items) }))
+    }
+
+    pub fn push_front(&self, item: Expr) {
+        lock(&self.items).push_front(item)
+    }
+
+    pub fn push_back(&self, item: Expr) {
+        lock(&self.items).push_back(item)
+    }
+
+    pub fn pop_front(&self) -> Option<Expr> {
+        lock(&self.items).pop_front()
+    }
+
+    pub fn pop_back(&self) -> Option<Expr> {
+        lock(&self.items).pop_back()
+    }
+
+    pub fn front(&self) -> Option<Expr> {
+        lock(&self.items).front().cloned()
+    }
+
+    pub fn back(&self) -> Option<Expr> {
+        lock(&self.items).back().cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        lock(&self.items).len()
+    }
+
+    /// The items, front to back.
+    pub fn to_vec(&self) -> Vec<Expr> {
+        lock(&self.items).iter().cloned().collect()
+    }
+}
+
+impl Resource for Deque {
+    fn type_name(&self) -> &'static str {
+        "deque"
+    }
+
+    fn as_any(&self) -> &Any {
+        self
+    }
+
+    fn describe(&self) -> String {
+        format!("#<deque {}>", Expr::new_vector(self.to_vec()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn both_ends() {
+        let handle = Deque::new(&[Expr::from(2)]);
+        let deque = handle.downcast_ref::<Deque>().unwrap();
+        deque.push_front(Expr::from(1));
+        deque.push_back(Expr::from(3));
+        assert_eq!(3, deque.len());
+        assert_eq!((Some(Expr::from(1)), Some(Expr::from(3))), (deque.front(), deque.back()));
+        assert_eq!("#<deque [1 2 3]>", Expr::Handle(handle.clone()).to_string());
+
+        assert_eq!(Some(Expr::from(3)), deque.pop_back());
+        assert_eq!(Some(Expr::from(1)), deque.pop_front());
+        assert_eq!(Some(Expr::from(2)), deque.pop_front());
+        assert_eq!(None, deque.pop_back());
+        assert_eq!(None, deque.front());
+    }
+}
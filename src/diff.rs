@@ -0,0 +1,123 @@
+//! Structural comparison of values, reporting each place where two nested
+//! values differ rather than just whether they're equal.
+
+use std::fmt;
+
+use itertools::Itertools;
+
+use types::{Expr, Key, Map, Vector};
+
+/// One place two values differ. `path` holds the indices and keys leading to
+/// it from the top, and a side is None when the other has an item it lacks.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Difference {
+    pub path: Vec<Expr>,
+    pub left: Option<Expr>,
+    pub right: Option<Expr>,
+}
+
+impl Difference {
+    /// The difference as a map of `:path`, `:left`, and `:right`, leaving out
+    /// a missing side.
+    pub fn to_expr(&self) -> Expr {
+        let mut map = Map::new();
+        map.insert(key("path"), Expr::Vector(Vector::new(self.path.clone())));
+        for &(name, side) in &[("left", &self.left), ("right", &self.right)] {
+            if let Some(ref value) = *side {
+                map.insert(key(name), value.clone());
+            }
+        }
+        Expr::Map(map)
+    }
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let side = |value: &Option<Expr>| value.as_ref().map_or("nothing".to_owned(), |v| v.to_string());
+        write!(f, "at [{}]: {} vs {}", self.path.iter().join(" "), side(&self.left), side(&self.right))
+    }
+}
+
+/// Where `left` and `right` differ, in order, or nothing if they're equal.
+pub fn diff(left: &Expr, right: &Expr) -> Vec<Difference> {
+    let mut found = Vec::new();
+    walk(&mut Vec::new(), left, right, &mut found);
+    found
+}
+
+fn walk(path: &mut Vec<Expr>, left: &Expr, right: &Expr, found: &mut Vec<Difference>) {
+    if left == right {
+        return;
+    }
+    match (left, right) {
+        (&Expr::List(ref a), &Expr::List(ref b)) => items(path, &a.0, &b.0, found),
+        (&Expr::Vector(ref a), &Expr::Vector(ref b)) => items(path, &a.0, &b.0, found),
+        (&Expr::Map(ref a), &Expr::Map(ref b)) => {
+            // Sorted, so the order doesn't depend on hashing
+            let mut keys = a.iter().map(|(k, _)| k).chain(b.iter().map(|(k, _)| k)).collect::<Vec<_>>();
+            keys.sort_by_key(|k| k.to_string());
+            keys.dedup();
+            for k in keys {
+                at(path, k.to_expr(), a.get(k), b.get(k), found);
+            }
+        }
+        (&Expr::Record(ref a), &Expr::Record(ref b)) if b.is_a(&a.rtype) => {
+            for (field, (x, y)) in a.rtype.fields.iter().zip(a.values.iter().zip(&b.values)) {
+                at(path, Expr::Sym(field.clone()), Some(x), Some(y), found);
+            }
+        }
+        _ => found.push(Difference {
+            path: path.clone(),
+            left: Some(left.clone()),
+            right: Some(right.clone()),
+        }),
+    }
+}
+
+fn items(path: &mut Vec<Expr>, left: &[Expr], right: &[Expr], found: &mut Vec<Difference>) {
+    for i in 0..left.len().max(right.len()) {
+        at(path, Expr::from(i as i64), left.get(i), right.get(i), found);
+    }
+}
+
+// Compare the items at one more step along the path
+fn at(path: &mut Vec<Expr>, step: Expr, left: Option<&Expr>, right: Option<&Expr>, found: &mut Vec<Difference>) {
+    path.push(step);
+    match (left, right) {
+        (Some(left), Some(right)) => walk(path, left, right, found),
+        (left, right) => found.push(Difference {
+            path: path.clone(),
+            left: left.cloned(),
+            right: right.cloned(),
+        }),
+    }
+    path.pop();
+}
+
+fn key(name: &str) -> Key {
+    Key::Keyword(name.to_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use parser;
+
+    fn differences(left: &str, right: &str) -> Vec<String> {
+        let (left, right) = (parser::parse_expr(left).unwrap(), parser::parse_expr(right).unwrap());
+        diff(&left, &right).iter().map(Difference::to_string).collect()
+    }
+
+    #[test]
+    fn nested() {
+        assert!(differences("[1 {:a 2}]", "[1 {:a 2}]").is_empty());
+        assert_eq!(vec!["at []: 1 vs 2"], differences("1", "2"));
+        assert_eq!(vec!["at [1 :a]: 2 vs 3"], differences("[1 {:a 2}]", "[1 {:a 3}]"));
+        assert_eq!(
+            vec!["at [:a]: 1 vs nothing", "at [:b]: nothing vs 2"],
+            differences("{:a 1}", "{:b 2}")
+        );
+        assert_eq!(vec!["at [2]: nothing vs 3"], differences("(1 2)", "(1 2 3)"));
+        assert_eq!(vec!["at []: (1) vs [1]"], differences("(1)", "[1]"));
+    }
+}
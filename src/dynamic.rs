@@ -0,0 +1,72 @@
+//! Dynamically-scoped variables, declared with `def-dynamic` and rebound for
+//! the extent of a `binding` form.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+use error::*;
+use types::Expr;
+
+/// The dynamic variables declared on a thread, and their bindings.
+#[derive(Clone, Debug, Default)]
+pub struct Bindings {
+    declared: HashSet<String>,
+    frames: Vec<HashMap<String, Expr>>,
+}
+
+thread_local! {
+    static BINDINGS: RefCell<Bindings> = RefCell::new(Bindings::default());
+}
+
+/// Mark `symbol` as dynamic, allowing it to be rebound with `binding`.
+pub fn declare(symbol: &str) {
+    BINDINGS.with(|b| {
+        b.borrow_mut().declared.insert(symbol.to_owned());
+    });
+}
+
+pub fn is_dynamic(symbol: &str) -> bool {
+    BINDINGS.with(|b| b.borrow().declared.contains(symbol))
+}
+
+/// Look up the innermost active binding of `symbol`, if any.
+pub fn lookup(symbol: &str) -> Option<Expr> {
+    BINDINGS.with(|b| {
+        b.borrow()
+            .frames
+            .iter()
+            .rev()
+            .filter_map(|frame| frame.get(symbol))
+            .next()
+            .cloned()
+    })
+}
+
+/// This thread's dynamic variables and bindings, for another thread to use.
+pub fn current() -> Bindings {
+    BINDINGS.with(|b| b.borrow().clone())
+}
+
+/// Run `body` with `bindings` in place of the thread's own, putting them back
+/// afterwards.
+pub fn with_inherited<F, T>(bindings: Bindings, body: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    let own = BINDINGS.with(|b| ::std::mem::replace(&mut *b.borrow_mut(), bindings));
+    let result = body();
+    BINDINGS.with(|b| *b.borrow_mut() = own);
+    result
+}
+
+/// Run `body` with `frame` pushed onto the binding stack, popping it afterwards
+/// even if `body` fails.
+pub fn with_bindings<F>(frame: HashMap<String, Expr>, body: F) -> Result<Expr>
+where
+    F: FnOnce() -> Result<Expr>,
+{
+    BINDINGS.with(|b| b.borrow_mut().frames.push(frame));
+    let result = body();
+    BINDINGS.with(|b| b.borrow_mut().frames.pop());
+    result
+}
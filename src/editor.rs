@@ -0,0 +1,264 @@
+//! Line editor support for the REPL: syntax highlighting as you type, and
+//! bracket validation and hinting.
+
+use std::borrow::Cow;
+
+use rustyline;
+use rustyline::Context;
+use rustyline::completion::Completer;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+
+use color::{self, Color};
+
+const DEPTH_COLORS: [Color; 5] = [Color::Yellow, Color::Magenta, Color::Cyan, Color::Green, Color::Blue];
+
+#[derive(Debug, Default)]
+pub struct Helper;
+
+impl rustyline::Helper for Helper {}
+
+impl Completer for Helper {
+    type Candidate = String;
+}
+
+impl Hinter for Helper {
+    fn hint(&self, line: &str, pos: usize, _ctx: &Context) -> Option<String> {
+        // Only hint at the end of the line, where the closers would go
+        if pos < line.len() {
+            return None;
+        }
+        match unclosed(line) {
+            Ok(ref open) if !open.is_empty() => {
+                Some(open.iter().rev().map(|&c| closing(c)).collect())
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Validator for Helper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        Ok(match unclosed(ctx.input()) {
+            Ok(ref open) if open.is_empty() => ValidationResult::Valid(None),
+            Ok(_) => ValidationResult::Incomplete,
+            Err(i) => {
+                let bracket = ctx.input().as_bytes()[i] as char;
+                ValidationResult::Invalid(Some(format!("  <- unmatched {}", bracket)))
+            }
+        })
+    }
+}
+
+impl Highlighter for Helper {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        if color::enabled() {
+            Cow::Owned(highlight(line, pos))
+        } else {
+            Cow::Borrowed(line)
+        }
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        if color::enabled() {
+            Cow::Owned(color::dim(hint))
+        } else {
+            Cow::Borrowed(hint)
+        }
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize) -> bool {
+        // Cursor movement changes which bracket is matched
+        color::enabled()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Span {
+    Plain(usize, usize),
+    Str(usize, usize),
+    Comment(usize, usize),
+    Open(usize),
+    Close(usize),
+}
+
+/// Split `line` into brackets, strings, comments and everything else, by byte
+/// offset.
+fn scan(line: &str) -> Vec<Span> {
+    let mut spans = Vec::new();
+    let mut plain_start = 0;
+    let mut chars = line.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        let span = match c {
+            '(' | '[' | '{' => Span::Open(i),
+            ')' | ']' | '}' => Span::Close(i),
+            ';' => {
+                let mut end = line.len();
+                for (j, d) in chars.by_ref() {
+                    if d == '\n' {
+                        end = j;
+                        break;
+                    }
+                }
+                Span::Comment(i, end)
+            }
+            '"' => {
+                let mut end = line.len();
+                let mut escaped = false;
+                for (j, d) in chars.by_ref() {
+                    match d {
+                        '\\' if !escaped => escaped = true,
+                        '"' if !escaped => {
+                            end = j + 1;
+                            break;
+                        }
+                        _ => escaped = false,
+                    }
+                }
+                Span::Str(i, end)
+            }
+            _ => continue,
+        };
+
+        if plain_start < i {
+            spans.push(Span::Plain(plain_start, i));
+        }
+        plain_start = match span {
+            Span::Open(j) | Span::Close(j) => j + 1,
+            Span::Str(_, end) | Span::Comment(_, end) => end,
+            Span::Plain(..) => unreachable!(),
+        };
+        spans.push(span);
+    }
+
+    if plain_start < line.len() {
+        spans.push(Span::Plain(plain_start, line.len()));
+    }
+    spans
+}
+
+/// The byte offsets of the bracket under (or just before) the cursor and its
+/// partner, if both exist.
+fn matching_bracket(spans: &[Span], pos: usize) -> Option<(usize, usize)> {
+    let mut stack = Vec::new();
+    let mut pairs = Vec::new();
+    for span in spans {
+        match *span {
+            Span::Open(i) => stack.push(i),
+            Span::Close(j) => if let Some(i) = stack.pop() {
+                pairs.push((i, j));
+            },
+            _ => (),
+        }
+    }
+
+    let at_cursor = |&&(i, j): &&(usize, usize)| i == pos || j == pos;
+    let before_cursor = |&&(i, j): &&(usize, usize)| pos > 0 && (i == pos - 1 || j == pos - 1);
+    pairs.iter().find(at_cursor).or_else(|| pairs.iter().find(before_cursor)).cloned()
+}
+
+fn closing(open: char) -> char {
+    match open {
+        '(' => ')',
+        '[' => ']',
+        _ => '}',
+    }
+}
+
+/// The brackets left open at the end of `line`, or the byte offset of the
+/// first closing bracket without a partner.
+fn unclosed(line: &str) -> ::std::result::Result<Vec<char>, usize> {
+    let mut stack = Vec::new();
+    for span in scan(line) {
+        match span {
+            Span::Open(i) => stack.push(line.as_bytes()[i] as char),
+            Span::Close(i) => {
+                let close = line.as_bytes()[i] as char;
+                match stack.pop() {
+                    Some(open) if closing(open) == close => (),
+                    _ => return Err(i),
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(stack)
+}
+
+/// Color brackets by nesting depth, emphasize the pair matching the cursor,
+/// and dim comments.
+pub fn highlight(line: &str, pos: usize) -> String {
+    let spans = scan(line);
+    let matched = matching_bracket(&spans, pos);
+    let is_matched = |i: usize| matched.map_or(false, |(open, close)| i == open || i == close);
+
+    let mut depth = 0;
+    let mut out = String::with_capacity(line.len() * 2);
+    for span in spans {
+        let piece = match span {
+            Span::Plain(start, end) => line[start..end].to_owned(),
+            Span::Str(start, end) => color::paint(Color::Green, &line[start..end]),
+            Span::Comment(start, end) => color::dim(&line[start..end]),
+            Span::Open(i) => {
+                let color = DEPTH_COLORS[depth % DEPTH_COLORS.len()];
+                depth += 1;
+                bracket(&line[i..i + 1], color, is_matched(i))
+            }
+            Span::Close(i) => {
+                depth = if depth > 0 { depth - 1 } else { 0 };
+                let color = DEPTH_COLORS[depth % DEPTH_COLORS.len()];
+                bracket(&line[i..i + 1], color, is_matched(i))
+            }
+        };
+        out.push_str(&piece);
+    }
+    out
+}
+
+fn bracket(text: &str, color: Color, matched: bool) -> String {
+    if matched {
+        color::bold(color, text)
+    } else {
+        color::paint(color, text)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scan_spans() {
+        assert_eq!(
+            vec![
+                Span::Open(0),
+                Span::Plain(1, 7),
+                Span::Str(7, 12),
+                Span::Close(12),
+                Span::Plain(13, 14),
+                Span::Comment(14, 18),
+            ],
+            scan(r#"(print "(a)") ; hi"#)
+        );
+    }
+
+    #[test]
+    fn matching() {
+        let spans = scan("(a [b] c)");
+        assert_eq!(Some((0, 8)), matching_bracket(&spans, 0));
+        assert_eq!(Some((3, 5)), matching_bracket(&spans, 6));
+        assert_eq!(Some((0, 8)), matching_bracket(&spans, 9));
+        assert_eq!(None, matching_bracket(&spans, 2));
+        assert_eq!(None, matching_bracket(&scan("(a"), 0));
+    }
+
+    #[test]
+    fn unclosed_brackets() {
+        assert_eq!(Ok(vec![]), unclosed("(a [b] {c d})"));
+        assert_eq!(Ok(vec!['(', '[']), unclosed("(a [b \"]\""));
+        assert_eq!(Err(5), unclosed("(a [b) c"));
+        assert_eq!(Ok(vec!['(']), unclosed("(a ; )\n b"));
+    }
+}
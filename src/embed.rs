@@ -0,0 +1,120 @@
+//! Support for the `tl!` macro in the telescope-macros crate, which parses code
+//! while Rust compiles it. At compile time the macro calls `parse` and
+//! `to_rust`, and the code it expands to calls the constructors below to build
+//! the forms, so nothing is parsed when the program runs.
+//!
+//! None of this is meant to be used directly.
+
+use error::*;
+use parser;
+use types::{Expr, Key, List, Map, Symbol, Vector};
+
+/// The forms in `source`, as one form: a single form as it is, or several in
+/// a `do`.
+pub fn parse(source: &str) -> Result<Expr> {
+    let mut forms = parser::parse_program(source)?;
+    if forms.len() == 1 {
+        return Ok(forms.remove(0));
+    }
+    forms.insert(0, sym("do"));
+    Ok(list(forms))
+}
+
+/// Rust code for an expression that builds `form`.
+pub fn to_rust(form: &Expr) -> Result<String> {
+    let code = match *form {
+        Expr::Nil => "::telescope::Expr::Nil".to_owned(),
+        Expr::Bool(b) => format!("::telescope::Expr::Bool({})", b),
+        Expr::Int(i) => format!("::telescope::Expr::Int({}i64)", i),
+        Expr::Flt(f) if f.is_nan() => "::telescope::Expr::Flt(::std::f64::NAN)".to_owned(),
+        Expr::Flt(f) if f.is_infinite() => format!(
+            "::telescope::Expr::Flt(::std::f64::{})",
+            if f > 0.0 { "INFINITY" } else { "NEG_INFINITY" }
+        ),
+        // Debug prints enough digits to read the same float back
+        Expr::Flt(f) => format!("::telescope::Expr::Flt({:?}f64)", f),
+        Expr::Str(ref s) => format!("::telescope::Expr::Str({:?}.to_owned())", s),
+        Expr::Keyword(ref k) => format!("::telescope::Expr::Keyword({:?}.to_owned())", k),
+        Expr::Bytes(ref bytes) => format!("::telescope::Expr::Bytes(vec!{:?})", bytes),
+        Expr::Sym(ref sym) => format!("::telescope::embed::sym({:?})", sym.0),
+        Expr::List(ref list) => format!("::telescope::embed::list({})", items(&list.0)?),
+        Expr::Vector(ref vec) => format!("::telescope::embed::vector({})", items(&vec.0)?),
+        Expr::Map(ref map) => format!("::telescope::embed::map({})", entries(map)?),
+        ref other => bail!("cannot embed a {} in Rust code", other.type_name()),
+    };
+    // Metadata, like ^:private or where the form was in the macro's source
+    match form.meta() {
+        Some(meta) => Ok(format!("::telescope::embed::with_meta({}, {})", code, entries(meta)?)),
+        None => Ok(code),
+    }
+}
+
+fn items(forms: &[Expr]) -> Result<String> {
+    let items = forms.iter().map(to_rust).collect::<Result<Vec<_>>>()?;
+    Ok(format!("vec![{}]", items.join(", ")))
+}
+
+fn entries(map: &Map) -> Result<String> {
+    let entries = map.iter()
+        .map(|(key, value)| Ok(format!("({}, {})", to_rust(&key.to_expr())?, to_rust(value)?)))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(format!("vec![{}]", entries.join(", ")))
+}
+
+pub fn sym(name: &str) -> Expr {
+    Expr::Sym(Symbol::new(name))
+}
+
+pub fn list(items: Vec<Expr>) -> Expr {
+    Expr::List(List::new(items))
+}
+
+pub fn vector(items: Vec<Expr>) -> Expr {
+    Expr::Vector(Vector::new(items))
+}
+
+pub fn map(entries: Vec<(Expr, Expr)>) -> Expr {
+    Expr::Map(to_map(entries))
+}
+
+pub fn with_meta(form: Expr, meta: Vec<(Expr, Expr)>) -> Expr {
+    form.with_meta(to_map(meta)).expect("only forms that can have metadata are given it")
+}
+
+fn to_map(entries: Vec<(Expr, Expr)>) -> Map {
+    let mut map = Map::new();
+    for (key, value) in entries {
+        map.insert(Key::try_from(&key).expect("keys come from a parsed map"), value);
+    }
+    map
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn generating_code() {
+        assert_eq!("::telescope::Expr::Int(-3i64)", to_rust(&parse("-3").unwrap()).unwrap());
+        assert_eq!("::telescope::Expr::Flt(0.1f64)", to_rust(&parse("0.1").unwrap()).unwrap());
+        assert_eq!(
+            r#"::telescope::Expr::Str("say \"hi\"\n".to_owned())"#,
+            to_rust(&parse(r#""say \"hi\"\n""#).unwrap()).unwrap()
+        );
+
+        let form = parse("(+ 1 2) [a :b]").unwrap();
+        assert_eq!(parse("(do (+ 1 2) [a :b])").unwrap(), form);
+        let code = to_rust(&form).unwrap();
+        let start = r#"::telescope::embed::list(vec![::telescope::embed::sym("do"), "#;
+        assert!(code.starts_with(start));
+        assert!(code.contains("::telescope::embed::with_meta(::telescope::embed::list("));
+        assert!(code.contains(r#"::telescope::Expr::Keyword("b".to_owned())"#));
+
+        // What the generated code calls rebuilds the same form
+        let line = (Expr::Keyword("line".to_owned()), Expr::Int(1));
+        let built = with_meta(list(vec![sym("+"), Expr::Int(1)]), vec![line]);
+        assert_eq!(parse("(+ 1)").unwrap(), built);
+        let meta = built.meta().unwrap();
+        assert_eq!(Some(&Expr::Int(1)), meta.get(&Key::Keyword("line".to_owned())));
+    }
+}
@@ -0,0 +1,222 @@
+//! Converting between strings and bytes, and the binary-to-text encodings
+//! base64, hex, and URL percent-encoding.
+
+use error::*;
+
+/// The bytes of `string` in a text encoding: utf-8, ascii, or latin-1.
+pub fn encode(string: &str, encoding: &str) -> Result<Vec<u8>> {
+    match encoding {
+        "utf-8" => Ok(string.as_bytes().to_vec()),
+        "ascii" | "latin-1" => {
+            let max = if encoding == "ascii" { 0x7f } else { 0xff };
+            string.chars()
+                .map(|c| if c as u32 <= max {
+                    Ok(c as u8)
+                } else {
+                    Err(format!("{:?} can't be encoded as {}", c, encoding).into())
+                })
+                .collect()
+        }
+        _ => bail!("unknown encoding {}", encoding),
+    }
+}
+
+/// The string `bytes` encode in a text encoding: utf-8, ascii, or latin-1.
+pub fn decode(bytes: &[u8], encoding: &str) -> Result<String> {
+    match encoding {
+        "utf-8" => String::from_utf8(bytes.to_vec()).map_err(|err| err.to_string().into()),
+        "ascii" => {
+            if let Some(byte) = bytes.iter().find(|&&byte| byte > 0x7f) {
+                bail!("byte {:#x} isn't ascii", byte);
+            }
+            Ok(bytes.iter().map(|&byte| byte as char).collect())
+        }
+        // Latin-1 is the first 256 code points
+        "latin-1" => Ok(bytes.iter().map(|&byte| byte as char).collect()),
+        _ => bail!("unknown encoding {}", encoding),
+    }
+}
+
+/// The bytes of a `#b"..."` literal, whose characters each stand for one byte.
+pub fn byte_literal(string: &str) -> Result<Vec<u8>> {
+    encode(string, "latin-1")
+        .map_err(|_| "byte strings can only hold characters up to \\xff".into())
+}
+
+/// `string` written as a string literal, with quotes and newlines escaped.
+/// Other control characters are written as `\xNN`.
+pub fn escape_str(string: &str) -> String {
+    let mut escaped = String::with_capacity(string.len() + 2);
+    escaped.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            '\u{0}'...'\u{1f}' | '\u{7f}' => escaped.push_str(&format!("\\x{:02x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// `bytes` written as a `#b"..."` literal.
+pub fn escape_bytes(bytes: &[u8]) -> String {
+    let mut escaped = String::from("#b\"");
+    for &byte in bytes {
+        match byte {
+            b'"' => escaped.push_str("\\\""),
+            b'\\' => escaped.push_str("\\\\"),
+            b'\n' => escaped.push_str("\\n"),
+            b'\r' => escaped.push_str("\\r"),
+            b'\t' => escaped.push_str("\\t"),
+            0x20...0x7e => escaped.push(byte as char),
+            _ => escaped.push_str(&format!("\\x{:02x}", byte)),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Standard base64, with padding.
+pub fn base64_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Decode standard base64. Padding is optional.
+pub fn base64_decode(text: &str) -> Result<Vec<u8>> {
+    let text = text.trim_right_matches('=');
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let mut bits = 0u32;
+    let mut count = 0;
+    for c in text.chars() {
+        let value = match BASE64.iter().position(|&b| b as char == c) {
+            Some(value) => value as u32,
+            None => bail!("invalid base64 character {:?}", c),
+        };
+        bits = (bits << 6) | value;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+        }
+    }
+    ensure!(count < 6, "truncated base64");
+    Ok(bytes)
+}
+
+/// Lowercase hex, two digits per byte.
+pub fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Decode hex, in either case.
+pub fn hex_decode(text: &str) -> Result<Vec<u8>> {
+    let digits = text.chars()
+        .map(|c| c.to_digit(16).ok_or_else(|| format!("invalid hex digit {:?}", c)))
+        .collect::<::std::result::Result<Vec<_>, _>>()?;
+    ensure!(digits.len() % 2 == 0, "hex must have an even number of digits");
+    Ok(digits.chunks(2).map(|pair| (pair[0] * 16 + pair[1]) as u8).collect())
+}
+
+/// Percent-encode every byte except letters, digits, and `-_.~`.
+pub fn url_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len());
+    for &byte in bytes {
+        match byte {
+            b'A'...b'Z' | b'a'...b'z' | b'0'...b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Decode `%XX` escapes. `+` is left alone, rather than read as a space.
+pub fn url_decode(text: &str) -> Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes().iter();
+    while let Some(&byte) = rest.next() {
+        if byte == b'%' {
+            let high = rest.next().and_then(|&c| (c as char).to_digit(16));
+            let low = rest.next().and_then(|&c| (c as char).to_digit(16));
+            match (high, low) {
+                (Some(high), Some(low)) => bytes.push((high * 16 + low) as u8),
+                _ => bail!("invalid escape in {:?}", text),
+            }
+        } else {
+            bytes.push(byte);
+        }
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn text_encodings() {
+        assert_eq!(vec![0xc3, 0xa9], encode("é", "utf-8").unwrap());
+        assert_eq!(vec![0xe9], encode("é", "latin-1").unwrap());
+        assert!(encode("é", "ascii").is_err());
+        assert!(encode("é", "ebcdic").is_err());
+
+        assert_eq!("é", decode(&[0xc3, 0xa9], "utf-8").unwrap());
+        assert_eq!("Ã©", decode(&[0xc3, 0xa9], "latin-1").unwrap());
+        assert!(decode(&[0xe9], "utf-8").is_err());
+        assert!(decode(&[0xe9], "ascii").is_err());
+    }
+
+    #[test]
+    fn literals() {
+        assert_eq!(r#"#b"a\"\\\n\x00\xff""#, escape_bytes(b"a\"\\\n\x00\xff"));
+        assert_eq!(vec![0, 0xff], byte_literal("\u{0}\u{ff}").unwrap());
+        assert!(byte_literal("🦀").is_err());
+    }
+
+    #[test]
+    fn base64() {
+        let cases = [("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v"), ("foobar", "Zm9vYmFy")];
+        for &(plain, text) in &cases {
+            assert_eq!(text, base64_encode(plain.as_bytes()));
+            assert_eq!(plain.as_bytes(), &base64_decode(text).unwrap()[..]);
+        }
+        assert_eq!("//4=", base64_encode(&[0xff, 0xfe]));
+        assert_eq!(vec![0xff, 0xfe], base64_decode("//4=").unwrap());
+        assert_eq!(b"fo".to_vec(), base64_decode("Zm8").unwrap());
+        assert!(base64_decode("Zm9v!").is_err());
+        assert!(base64_decode("Z").is_err());
+    }
+
+    #[test]
+    fn hex_and_url() {
+        assert_eq!("00ff10", hex_encode(&[0, 0xff, 0x10]));
+        assert_eq!(vec![0, 0xff, 0x10], hex_decode("00FF10").unwrap());
+        assert!(hex_decode("abc").is_err());
+        assert!(hex_decode("zz").is_err());
+
+        assert_eq!("a%20b%2Bc~%C3%A9", url_encode("a b+c~é".as_bytes()));
+        assert_eq!("a b+c~é".as_bytes(), &url_decode("a%20b+c~%c3%a9").unwrap()[..]);
+        assert!(url_decode("100%").is_err());
+        assert!(url_decode("%g0").is_err());
+    }
+}
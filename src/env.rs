@@ -1,6 +1,6 @@
-use std::collections::HashMap;
-use std::cell::{Ref, RefCell};
-use std::rc::Rc;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 use types::{Expr, Symbol};
 
@@ -8,21 +8,41 @@ use types::{Expr, Symbol};
 struct EnvImpl {
     symbols: HashMap<String, Expr>,
     parent: Option<Env>,
+    // The canonical paths of the modules required into this scope, which is
+    // only ever the root
+    modules: HashSet<PathBuf>,
+    // Whether code in this scope is kept from reaching outside the
+    // interpreter, which is also only set on the root
+    sandboxed: bool,
 }
 
+/// A scope of bindings. Environments are shared between threads, so values
+/// can be handed to futures without copying.
 #[derive(Clone, Debug)]
-pub struct Env(Rc<RefCell<EnvImpl>>);
+pub struct Env(Arc<RwLock<EnvImpl>>);
 
 impl Env {
     pub fn new(symbols: HashMap<String, Expr>, parent: Option<Env>) -> Self {
-        Env( Rc::new( RefCell::new( EnvImpl {
+        Env( Arc::new( RwLock::new( EnvImpl {
             symbols: symbols,
             parent: parent,
+            modules: HashSet::new(),
+            sandboxed: false,
         })))
     }
 
+    // A panic while holding the lock leaves the bindings intact, so poisoning
+    // is ignored
+    fn read(&self) -> RwLockReadGuard<EnvImpl> {
+        self.0.read().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<EnvImpl> {
+        self.0.write().unwrap_or_else(|err| err.into_inner())
+    }
+
     pub fn lookup(&self, symbol: &str) -> Option<Expr> {
-        let borrowed: Ref<EnvImpl> = (*self.0).borrow();
+        let borrowed = self.read();
         let self_lookup = borrowed.symbols.get(symbol).cloned();
         match self_lookup {
             Some(value) => Some(value),
@@ -33,9 +53,58 @@ impl Env {
         }
     }
 
+    /// All symbols visible from this scope.
+    pub fn names(&self) -> Vec<String> {
+        let borrowed = self.read();
+        let mut names = borrowed.symbols.keys().cloned().collect::<Vec<_>>();
+        if let Some(ref parent) = borrowed.parent {
+            names.extend(parent.names());
+        }
+        names
+    }
+
+    /// The bindings made directly in this scope, not its parents.
+    pub fn bindings(&self) -> Vec<(String, Expr)> {
+        self.read().symbols.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+    }
+
+    /// Whether both refer to the same scope.
+    pub fn is_same(&self, other: &Env) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// The outermost scope, where top-level definitions live.
+    pub fn root(&self) -> Env {
+        match self.read().parent {
+            Some(ref parent) => parent.root(),
+            None => self.clone(),
+        }
+    }
+
+    /// Run `f` on the set of modules loaded into the root scope, by canonical
+    /// path. Each global environment has its own, so every interpreter loads a
+    /// module for itself.
+    pub fn with_modules<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce(&mut HashSet<PathBuf>) -> T,
+    {
+        f(&mut self.root().write().modules)
+    }
+
+    /// Whether the global environment belongs to a sandboxed interpreter.
+    pub fn is_sandboxed(&self) -> bool {
+        self.root().read().sandboxed
+    }
+
+    /// Keep code in the global environment, and every scope under it, from
+    /// loading modules or starting threads.
+    pub fn sandbox(&self) {
+        self.root().write().sandboxed = true;
+    }
+
     pub fn define(&self, symbol: &str, value: Expr) -> Symbol {
-        (*self.0).borrow_mut().symbols.insert(symbol.to_string(), value);
-        Symbol(symbol.to_string())
+        self.write().symbols.insert(symbol.to_string(), value);
+        Symbol::new(symbol.to_string())
     }
 }
 
@@ -1,16 +1,28 @@
 use combine;
 // use std::fs;
+use std::fmt;
 use std::io;
 use stream::{StringStream, TokenStream};
+use types::{Arity, Expr, Symbol};
 
 #[derive(Debug, ErrorChain)]
 pub enum ErrorKind {
     Msg(String),
 
-    // #[error_chain(custom)]
-    // #[error_chain(description = r#"|_| "undefined symbol""#)]
-    // #[error_chain(display = r#"|t| write!(f, "undefined symbol {}", t)"#)]
-    // UndefinedSymbol(Symbol),
+    #[error_chain(custom)]
+    #[error_chain(description = r#"|_, _, _| "wrong number of args""#)]
+    #[error_chain(display = r#"|name, expected, got| write!(f, "wrong number of args ({}) passed to: {} (expected {})", got, name, expected)"#)]
+    Arity { name: String, expected: Arity, got: usize },
+
+    #[error_chain(custom)]
+    #[error_chain(description = r#"|_, _, _| "type error""#)]
+    #[error_chain(display = r##"|name, expected, got| write!(f, "#[{}] expected {}, got {}", name, expected, got)"##)]
+    Type { name: String, expected: String, got: String },
+
+    #[error_chain(custom)]
+    #[error_chain(description = r#"|_, _| "undefined symbol""#)]
+    #[error_chain(display = "display_undefined")]
+    Undefined(Symbol, Vec<String>),
 
     #[error_chain(foreign)]
     Io(io::Error),
@@ -27,8 +39,33 @@ pub enum ErrorKind {
     #[error_chain(custom)]
     Exit(i32),
 
-    // #[error_chain(custom)]
-    // #[error_chain(description = r#"|_, _| "type error""#)]
-    // #[error_chain(display = r#"|f, value, type| write!(f, "type error: received {}, expected {}", value, type)"#)]
-    // Type(Expr, String),
+    /// Calling an escape continuation, which call/ec returns the value of.
+    #[error_chain(custom)]
+    #[error_chain(description = r#"|_, _| "escape""#)]
+    #[error_chain(display = r#"|_, _| write!(f, "escape continuation called after call/ec returned")"#)]
+    Escape(usize, Expr),
+
+    /// `(return value)`, which the innermost user function returns the value of.
+    #[error_chain(custom)]
+    #[error_chain(description = r#"|_| "return""#)]
+    #[error_chain(display = r#"|_| write!(f, "return outside a function")"#)]
+    Return(Expr),
+
+    #[error_chain(custom)]
+    #[error_chain(description = r#"|| "interrupted""#)]
+    #[error_chain(display = r#"|| write!(f, "interrupted")"#)]
+    Interrupted,
+
+    #[error_chain(custom)]
+    #[error_chain(description = r#"|| "out of fuel""#)]
+    #[error_chain(display = r#"|| write!(f, "ran out of fuel")"#)]
+    OutOfFuel,
+}
+
+fn display_undefined(f: &mut fmt::Formatter, sym: &Symbol, suggestions: &Vec<String>) -> fmt::Result {
+    if suggestions.is_empty() {
+        write!(f, "undefined symbol: {}", sym)
+    } else {
+        write!(f, "undefined symbol: {} (did you mean {}?)", sym, suggestions.join(", "))
+    }
 }
@@ -1,17 +1,56 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering, ATOMIC_BOOL_INIT, ATOMIC_USIZE_INIT};
+
+use dynamic;
 use env::Env;
 use error::*;
 use forms;
+use interrupt;
+use location;
+use typecheck;
 use types::*;
 use util::*;
+use warning;
+
+// Off rather than on, so the default is to check
+static CONTRACTS_OFF: AtomicBool = ATOMIC_BOOL_INIT;
+
+/// Whether user functions check their :pre and :post conditions.
+pub fn contracts_enabled() -> bool {
+    !CONTRACTS_OFF.load(Ordering::SeqCst)
+}
+
+pub fn set_contracts(enabled: bool) {
+    CONTRACTS_OFF.store(!enabled, Ordering::SeqCst);
+}
+
+static ESCAPES: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// A new id for the target of an `ErrorKind::Escape`, such as a block.
+pub fn escape_id() -> usize {
+    ESCAPES.fetch_add(1, Ordering::SeqCst)
+}
+
+static DEBUG: AtomicBool = ATOMIC_BOOL_INIT;
+
+/// Whether user functions check the types their parameters and results are
+/// annotated with.
+pub fn debug_enabled() -> bool {
+    DEBUG.load(Ordering::SeqCst)
+}
+
+pub fn set_debug(enabled: bool) {
+    DEBUG.store(enabled, Ordering::SeqCst);
+}
 
 impl Expr {
     pub fn eval(&self, env: Env) -> Result<Expr> {
         match *self {
             Expr::List(ref lst) => lst.eval(env),
             Expr::Sym(ref symbol) => {
-                env.lookup(&symbol.0).ok_or_else(||
-                    format!("undefined symbol: {}", symbol.0).into()
-                )
+                dynamic::lookup(&symbol.0)
+                    .or_else(|| env.lookup(&symbol.0))
+                    .ok_or_else(|| undefined(symbol, &env))
             }
             _ => Ok(self.clone()),
         }
@@ -30,21 +69,47 @@ impl Expr {
     }
 }
 
+fn undefined(symbol: &Symbol, env: &Env) -> Error {
+    let candidates = env.names()
+        .into_iter()
+        .chain(forms::names().into_iter().map(String::from));
+    ErrorKind::Undefined(symbol.clone(), suggest(&symbol.0, candidates)).into()
+}
+
 impl List {
     pub fn eval(&self, env: Env) -> Result<Expr> {
+        // Every call passes through here, so it's where Ctrl-C takes effect
+        interrupt::check()?;
+
+        // Track where this form is, leaving it as the current location on error
+        let outer = location::enter(&self.1);
+        let result = self.eval_form(env);
+        if result.is_ok() {
+            location::leave(outer);
+        }
+        result
+    }
+
+    fn eval_form(&self, env: Env) -> Result<Expr> {
         if let Some((first, rest)) = self.0.split_first() {
             let sym = first.sym().ok_or("expected function call")?;
 
             if forms::is_special_form(sym) {
-                forms::eval(sym, rest, env)
-            } else if let Ok(Expr::Func(ref func)) = first.eval(env.clone()) {
-                // Eval all arguments, returning if any errors
-                let evaled_args = List::eval_args(rest, env.clone())?;
-                func.apply(&evaled_args, env.clone())
-            } else if let Ok(Expr::Macro(ref mac)) = first.eval(env.clone()) {
-                mac.apply(rest, env.clone())?.eval(env.clone())
-            } else {
-                Err(format!("could not find symbol {}", first).into())
+                return forms::eval(sym, rest, env);
+            }
+
+            match first.eval(env.clone())? {
+                Expr::Func(ref func) => {
+                    // By the name it's called by, which may be an alias
+                    if let Some(note) = func.deprecation() {
+                        warn_deprecated(&sym.0, note)?;
+                    }
+                    // Eval all arguments, returning if any errors
+                    let evaled_args = List::eval_args(rest, env.clone())?;
+                    func.apply(&evaled_args, env.clone())
+                }
+                Expr::Macro(ref mac) => mac.apply(rest, env.clone())?.eval(env.clone()),
+                ref other => Err(type_error(&sym.0, "fn", other)),
             }
 
         } else {
@@ -60,31 +125,116 @@ impl List {
 }
 
 impl Function {
-    #[cfg_attr(rustfmt, rustfmt_skip)]
     pub fn apply(&self, args: &[Expr], call_env: Env) -> Result<Expr> {
+        self.apply_with(args, call_env, debug_enabled())
+    }
+
+    // Whether to check types is an argument so tests can turn it on for one
+    // call, instead of for every other test running at the same time
+    #[cfg_attr(rustfmt, rustfmt_skip)]
+    pub(crate) fn apply_with(&self, args: &[Expr], call_env: Env, debug: bool) -> Result<Expr> {
+        // Builtins may rely on their declared arity having been checked
+        ensure_arity(self.name(), args, self.arity())?;
+
         match *self {
             Function::Builtin { ref func, .. } => (func)(args, call_env),
-            Function::User { ref name, ref params, ref body, ref env } => {
-                let name = if let &Some(ref n) = name { n.as_str() } else { "fn" };
-                ensure_args(name, args, params.len())?;
-                if args.len() != params.len() {
-                    return Err(format!("fn expected {} args", params.len()).into());
-                }
+            Function::Multi { ref dispatch, .. } => {
+                let value = dispatch.apply(args, call_env.clone())?;
+                let method = self.method(&value).ok_or_else(|| {
+                    format!("#[{}] no method for dispatch value {}", self.name(), value)
+                })?;
+                method.apply(args, call_env)
+            }
+            Function::Record { ref rtype, op, .. } => match op {
+                RecordOp::New => Ok(Expr::Record(Record::new(rtype.clone(), args.to_vec()))),
+                RecordOp::Is => match args[0] {
+                    Expr::Record(ref record) => Ok(Expr::from(record.is_a(rtype))),
+                    _ => Ok(Expr::from(false)),
+                },
+                RecordOp::Get(i) => match args[0] {
+                    Expr::Record(ref record) if record.is_a(rtype) => Ok(record.values[i].clone()),
+                    ref other => Err(type_error(self.name(), &rtype.name, other)),
+                },
+            },
+            Function::User { ref env, .. } => {
+                // Multi-arity functions may not cover every count in their range
+                let clause = self.clause(args.len()).ok_or_else(|| {
+                    Error::from(ErrorKind::Arity {
+                        name: self.name().to_owned(),
+                        expected: self.arity(),
+                        got: args.len(),
+                    })
+                })?;
 
                 // Create new env with arguments, eval body with new env
-                let bound_params = params
+                let bound_params = clause.params
                     .iter()
                     .map(|x| x.0.to_owned())
                     .zip(args.to_owned())
                     .collect();
 
+                if debug {
+                    for (param, arg) in clause.params.iter().zip(args) {
+                        match typecheck::tag(param) {
+                            Some(tag) if !typecheck::is_a(tag, arg) => {
+                                let expected = format!("{} for {}", tag, param);
+                                return Err(type_error(self.name(), &expected, arg));
+                            }
+                            _ => (),
+                        }
+                    }
+                }
+
                 let fn_env = Env::new(bound_params, Some(env.clone()));
-                Expr::eval_all(body, fn_env)
+                let check = contracts_enabled();
+                if check {
+                    for condition in &clause.pre {
+                        ensure!(
+                            condition.eval(fn_env.clone())?.truthiness(),
+                            "#[{}] precondition failed: {}", self.name(), condition
+                        );
+                    }
+                }
+
+                // (return value) ends the body early
+                let result = match Expr::eval_all(&clause.body, fn_env.clone()) {
+                    Err(err) => returned(err)?,
+                    result => result?,
+                };
+                if check && !clause.post.is_empty() {
+                    // Postconditions see the result as %
+                    let mut frame = HashMap::new();
+                    frame.insert("%".to_owned(), result.clone());
+                    let post_env = Env::new(frame, Some(fn_env));
+                    for condition in &clause.post {
+                        ensure!(
+                            condition.eval(post_env.clone())?.truthiness(),
+                            "#[{}] postcondition failed: {}", self.name(), condition
+                        );
+                    }
+                }
+                match clause.returns {
+                    Some(ref returns) if debug && !typecheck::is_a(&returns.0, &result) => {
+                        let expected = format!("return value of type {}", returns);
+                        Err(type_error(self.name(), &expected, &result))
+                    }
+                    _ => Ok(result),
+                }
             }
         }
     }
 }
 
+// Warn the first time a deprecated function is called
+fn warn_deprecated(name: &str, note: &str) -> Result<()> {
+    let message = if note.is_empty() {
+        format!("{} is deprecated", name)
+    } else {
+        format!("{} is deprecated: {}", name, note)
+    };
+    warning::warn_once(&message)
+}
+
 impl Macro {
     pub fn apply(&self, args: &[Expr], env: Env) -> Result<Expr> {
         let name = if let Some(ref n) = self.name { n.as_str() } else { "macro" };
@@ -102,3 +252,12 @@ impl Macro {
         Expr::eval_all(&self.body, fn_env)
     }
 }
+
+// The value of a (return value), or the error if it's anything else
+fn returned(err: Error) -> Result<Expr> {
+    let value = match *err.kind() {
+        ErrorKind::Return(ref value) => Some(value.clone()),
+        _ => None,
+    };
+    value.ok_or(err)
+}
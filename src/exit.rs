@@ -0,0 +1,64 @@
+//! Cleanup functions registered with `at-exit`, run when the program quits.
+
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+use env::Env;
+use error::*;
+use types::Function;
+use util::lock;
+
+type Hooks = Mutex<Vec<(Arc<Function>, Env)>>;
+
+lazy_static! {
+    static ref HOOKS: Hooks = Mutex::new(Vec::new());
+}
+
+/// Register `func` to be called with no arguments on exit.
+pub fn at_exit(func: Arc<Function>, env: Env) {
+    lock(&*HOOKS).push((func, env));
+}
+
+/// Call the registered functions, most recently registered first, and
+/// forget them. Every function is called even if an earlier one fails, and
+/// the errors are returned in the order they happened.
+pub fn run_hooks() -> Vec<Error> {
+    run_hooks_in(&HOOKS)
+}
+
+// The registry is an argument so tests can use their own, instead of running
+// the hooks of every other test running at the same time
+fn run_hooks_in(hooks: &Hooks) -> Vec<Error> {
+    let hooks = mem::replace(&mut *lock(hooks), Vec::new());
+    hooks.into_iter()
+        .rev()
+        .filter_map(|(func, env)| func.apply(&[], env).err())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ops;
+    use parser;
+    use types::Expr;
+
+    #[test]
+    fn hooks_run_once_in_reverse() {
+        let env = ops::env();
+        let eval = |source: &str| {
+            Expr::eval_all(&parser::parse_program(source).unwrap(), env.clone()).unwrap()
+        };
+        eval("(def order (chan))");
+        let hooks = Mutex::new(Vec::new());
+        for body in &["(send! order 1)", "(undefined-fn)", "(send! order 2)"] {
+            let func = eval(&format!("(fn [] {})", body)).func().unwrap();
+            lock(&hooks).push((func, env.clone()));
+        }
+
+        let errors = run_hooks_in(&hooks);
+        assert_eq!(1, errors.len());
+        assert_eq!("(2 1)", eval("(list (recv! order) (recv! order))").to_string());
+        assert!(run_hooks_in(&hooks).is_empty());
+    }
+}
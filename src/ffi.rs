@@ -0,0 +1,336 @@
+//! A C API, for driving the interpreter from C, C++, Python, or anything else
+//! that can call C. The declarations are in `include/telescope.h`.
+//!
+//! Interpreters and values are opaque pointers, each freed with its own
+//! `_free` function. Strings passed in are NUL-terminated UTF-8, and strings
+//! handed back are owned by the caller, who frees them with
+//! `telescope_string_free`. When a call fails it returns null, and
+//! `telescope_error` describes why.
+//!
+//! Every function is unsafe in the usual C way: pointers must be null or ones
+//! this API returned and that haven't been freed. A null value is an error,
+//! reported through `telescope_error` like any other.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use error::*;
+use types::Expr;
+use Interpreter;
+
+pub const TELESCOPE_NIL: c_int = 0;
+pub const TELESCOPE_BOOL: c_int = 1;
+pub const TELESCOPE_INT: c_int = 2;
+pub const TELESCOPE_FLOAT: c_int = 3;
+pub const TELESCOPE_STRING: c_int = 4;
+pub const TELESCOPE_LIST: c_int = 5;
+pub const TELESCOPE_OTHER: c_int = 6;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+/// A value handed out to C.
+pub struct Value(Expr);
+
+#[no_mangle]
+pub extern "C" fn telescope_new() -> *mut Interpreter {
+    Box::into_raw(Box::new(Interpreter::new()))
+}
+
+/// Free an interpreter from `telescope_new`. Null is ignored.
+///
+/// # Safety
+///
+/// `interpreter` must be null or a pointer from `telescope_new` that hasn't
+/// been freed yet, and mustn't be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn telescope_free(interpreter: *mut Interpreter) {
+    if !interpreter.is_null() {
+        drop(Box::from_raw(interpreter));
+    }
+}
+
+/// Evaluate the forms in `code`, returning the value of the last, or null if
+/// they fail. A panic in the interpreter is a failure too, rather than
+/// unwinding into C.
+///
+/// # Safety
+///
+/// `interpreter` must be null or a live pointer from `telescope_new`, and
+/// `code` null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn telescope_eval(
+    interpreter: *const Interpreter,
+    code: *const c_char,
+) -> *mut Value {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        ensure!(!interpreter.is_null() && !code.is_null(), "null pointer passed to telescope_eval");
+        let code = CStr::from_ptr(code).to_str().map_err(|_| "code is not valid UTF-8")?;
+        (*interpreter).eval_str(code)
+    }));
+    match result.unwrap_or_else(|_| Err("telescope_eval panicked".into())) {
+        Ok(value) => Box::into_raw(Box::new(Value(value))),
+        Err(err) => {
+            set_error(&err.to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Why the last call on this thread that failed did so, or null if none has.
+/// The string belongs to the API, and lasts until the next failure.
+#[no_mangle]
+pub extern "C" fn telescope_error() -> *const c_char {
+    LAST_ERROR.with(|error| error.borrow().as_ref().map_or(ptr::null(), |error| error.as_ptr()))
+}
+
+/// Free a value. Null is ignored.
+///
+/// # Safety
+///
+/// `value` must be null or a pointer from this API that hasn't been freed
+/// yet, and mustn't be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn telescope_value_free(value: *mut Value) {
+    if !value.is_null() {
+        drop(Box::from_raw(value));
+    }
+}
+
+/// Which of the `TELESCOPE_*` kinds `value` is, or `TELESCOPE_OTHER` if it's
+/// null. Vectors count as lists.
+///
+/// # Safety
+///
+/// `value` must be null or a live pointer from this API.
+#[no_mangle]
+pub unsafe extern "C" fn telescope_value_kind(value: *const Value) -> c_int {
+    match deref("telescope_value_kind", value) {
+        Some(&Expr::Nil) => TELESCOPE_NIL,
+        Some(&Expr::Bool(_)) => TELESCOPE_BOOL,
+        Some(&Expr::Int(_)) => TELESCOPE_INT,
+        Some(&Expr::Flt(_)) => TELESCOPE_FLOAT,
+        Some(&Expr::Str(_)) => TELESCOPE_STRING,
+        Some(&Expr::List(_)) | Some(&Expr::Vector(_)) => TELESCOPE_LIST,
+        _ => TELESCOPE_OTHER,
+    }
+}
+
+/// Whether `value` is truthy: anything but nil and false. Null is false.
+///
+/// # Safety
+///
+/// `value` must be null or a live pointer from this API.
+#[no_mangle]
+pub unsafe extern "C" fn telescope_value_bool(value: *const Value) -> bool {
+    deref("telescope_value_bool", value).map_or(false, Expr::truthiness)
+}
+
+/// Store `value` in `out` and return 1 if it's an int, or return 0.
+///
+/// # Safety
+///
+/// `value` must be null or a live pointer from this API, and `out` null or
+/// valid to write an `int64_t` to.
+#[no_mangle]
+pub unsafe extern "C" fn telescope_value_int(value: *const Value, out: *mut i64) -> c_int {
+    match (deref("telescope_value_int", value), out_ptr("telescope_value_int", out)) {
+        (Some(&Expr::Int(i)), Some(out)) => {
+            *out = i;
+            1
+        }
+        _ => 0,
+    }
+}
+
+/// Store `value` in `out` and return 1 if it's a number, or return 0.
+///
+/// # Safety
+///
+/// `value` must be null or a live pointer from this API, and `out` null or
+/// valid to write a `double` to.
+#[no_mangle]
+pub unsafe extern "C" fn telescope_value_float(value: *const Value, out: *mut f64) -> c_int {
+    match (deref("telescope_value_float", value), out_ptr("telescope_value_float", out)) {
+        (Some(&Expr::Int(i)), Some(out)) => *out = i as f64,
+        (Some(&Expr::Flt(f)), Some(out)) => *out = f,
+        _ => return 0,
+    }
+    1
+}
+
+/// The contents of a string, or how any other value prints. Null if `value`
+/// is null, or has a NUL in it.
+///
+/// # Safety
+///
+/// `value` must be null or a live pointer from this API.
+#[no_mangle]
+pub unsafe extern "C" fn telescope_value_string(value: *const Value) -> *mut c_char {
+    let text = match deref("telescope_value_string", value) {
+        Some(&Expr::Str(ref s)) => s.clone(),
+        Some(other) => other.to_string(),
+        None => return ptr::null_mut(),
+    };
+    match CString::new(text) {
+        Ok(text) => text.into_raw(),
+        Err(_) => {
+            set_error("string contains a NUL byte");
+            ptr::null_mut()
+        }
+    }
+}
+
+/// The number of items in a list or vector, or -1 for anything else,
+/// including null.
+///
+/// # Safety
+///
+/// `value` must be null or a live pointer from this API.
+#[no_mangle]
+pub unsafe extern "C" fn telescope_value_len(value: *const Value) -> i64 {
+    let items = deref("telescope_value_len", value).and_then(Expr::items);
+    items.map_or(-1, |items| items.len() as i64)
+}
+
+/// Item `index` of a list or vector, or null if there isn't one.
+///
+/// # Safety
+///
+/// `value` must be null or a live pointer from this API.
+#[no_mangle]
+pub unsafe extern "C" fn telescope_value_get(value: *const Value, index: i64) -> *mut Value {
+    let value = match deref("telescope_value_get", value) {
+        Some(value) => value,
+        None => return ptr::null_mut(),
+    };
+    let item = value.items().and_then(|items| {
+        if index < 0 { None } else { items.get(index as usize) }
+    });
+    match item {
+        Some(item) => Box::into_raw(Box::new(Value(item.clone()))),
+        None => {
+            set_error(&format!("no item {} in {}", index, value));
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Free a string from `telescope_value_string`. Null is ignored.
+///
+/// # Safety
+///
+/// `text` must be null or a string from `telescope_value_string` that hasn't
+/// been freed yet, and mustn't be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn telescope_string_free(text: *mut c_char) {
+    if !text.is_null() {
+        drop(CString::from_raw(text));
+    }
+}
+
+// The expression behind `value`, or None with the error set if it's null
+unsafe fn deref<'a>(name: &str, value: *const Value) -> Option<&'a Expr> {
+    if value.is_null() {
+        set_error(&format!("null value passed to {}", name));
+        None
+    } else {
+        Some(&(*value).0)
+    }
+}
+
+// `out` as a reference, or None with the error set if it's null
+unsafe fn out_ptr<'a, T>(name: &str, out: *mut T) -> Option<&'a mut T> {
+    if out.is_null() {
+        set_error(&format!("null out pointer passed to {}", name));
+        None
+    } else {
+        Some(&mut *out)
+    }
+}
+
+fn set_error(message: &str) {
+    let message = CString::new(message.replace('\0', "\\0")).unwrap_or_default();
+    LAST_ERROR.with(|error| *error.borrow_mut() = Some(message));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn text(ptr: *const c_char) -> String {
+        unsafe { CStr::from_ptr(ptr).to_string_lossy().into_owned() }
+    }
+
+    fn eval(interpreter: *const Interpreter, code: &str) -> *mut Value {
+        let code = CString::new(code).unwrap();
+        unsafe { telescope_eval(interpreter, code.as_ptr()) }
+    }
+
+    #[test]
+    fn embedding() {
+        unsafe {
+            let interpreter = telescope_new();
+            telescope_value_free(eval(interpreter, "(defn double [x] (* 2 x))"));
+
+            let value = eval(interpreter, r#"[(double 21) 1.5 "hi"]"#);
+            assert_eq!(TELESCOPE_LIST, telescope_value_kind(value));
+            assert_eq!(3, telescope_value_len(value));
+
+            let first = telescope_value_get(value, 0);
+            let mut int = 0;
+            assert_eq!(1, telescope_value_int(first, &mut int));
+            assert_eq!(42, int);
+            let mut float = 0.0;
+            let second = telescope_value_get(value, 1);
+            assert_eq!(0, telescope_value_int(second, &mut int));
+            assert_eq!(1, telescope_value_float(second, &mut float));
+            assert_eq!(1.5, float);
+
+            let third = telescope_value_get(value, 2);
+            let string = telescope_value_string(third);
+            assert_eq!("hi", text(string));
+            telescope_string_free(string);
+            assert!(telescope_value_get(value, 3).is_null());
+
+            for value in vec![first, second, third, value] {
+                telescope_value_free(value);
+            }
+
+            assert!(eval(interpreter, "(undefined-function)").is_null());
+            assert!(text(telescope_error()).contains("undefined-function"));
+            telescope_free(interpreter);
+        }
+    }
+
+    #[test]
+    fn null_pointers() {
+        unsafe {
+            let null = ptr::null();
+            assert_eq!(TELESCOPE_OTHER, telescope_value_kind(null));
+            assert_eq!("null value passed to telescope_value_kind", text(telescope_error()));
+            assert!(!telescope_value_bool(null));
+            let mut int = 7;
+            assert_eq!(0, telescope_value_int(null, &mut int));
+            assert_eq!(7, int);
+            let mut float = 0.0;
+            assert_eq!(0, telescope_value_float(null, &mut float));
+            assert!(telescope_value_string(null).is_null());
+            assert_eq!(-1, telescope_value_len(null));
+            assert!(telescope_value_get(null, 0).is_null());
+            assert_eq!("null value passed to telescope_value_get", text(telescope_error()));
+
+            let interpreter = telescope_new();
+            let value = eval(interpreter, "1");
+            assert_eq!(0, telescope_value_int(value, ptr::null_mut()));
+            assert_eq!("null out pointer passed to telescope_value_int", text(telescope_error()));
+            assert!(telescope_eval(ptr::null(), ptr::null()).is_null());
+            telescope_value_free(value);
+            telescope_free(interpreter);
+        }
+    }
+}
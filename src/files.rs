@@ -0,0 +1,213 @@
+//! The filesystem builtins' work: I/O errors that name the path involved,
+//! temporary files, and glob patterns.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+use error::*;
+
+/// An I/O error about `path`, still an `ErrorKind::Io` but mentioning the path.
+pub fn io_error<P: AsRef<Path>>(path: P, err: io::Error) -> Error {
+    let message = format!("{}: {}", path.as_ref().display(), err);
+    Error::from(io::Error::new(err.kind(), message))
+}
+
+/// The names in directory `path`, sorted.
+pub fn list(path: &str) -> Result<Vec<String>> {
+    let mut names = fs::read_dir(path)
+        .map_err(|err| io_error(path, err))?
+        .map(|entry| {
+            let entry = entry.map_err(|err| io_error(path, err))?;
+            Ok(entry.file_name().to_string_lossy().into_owned())
+        })
+        .collect::<Result<Vec<_>>>()?;
+    names.sort();
+    Ok(names)
+}
+
+/// Remove a file or an empty directory.
+pub fn remove(path: &str) -> Result<()> {
+    let metadata = fs::symlink_metadata(path).map_err(|err| io_error(path, err))?;
+    let result = if metadata.is_dir() {
+        fs::remove_dir(path)
+    } else {
+        fs::remove_file(path)
+    };
+    result.map_err(|err| io_error(path, err))
+}
+
+static TEMP_COUNT: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Create a new, empty file in the system's temporary directory.
+pub fn temp_file() -> Result<PathBuf> {
+    create_temp(|path| fs::OpenOptions::new().write(true).create_new(true).open(path).map(|_| ()))
+}
+
+/// Create a new, empty directory in the system's temporary directory.
+pub fn temp_dir() -> Result<PathBuf> {
+    create_temp(|path| fs::create_dir(path))
+}
+
+fn create_temp<F>(create: F) -> Result<PathBuf>
+where
+    F: Fn(&Path) -> io::Result<()>,
+{
+    // Names are unique within this process, but another process may have
+    // left a file behind, so keep counting until one is free
+    loop {
+        let count = TEMP_COUNT.fetch_add(1, Ordering::SeqCst);
+        let path = env::temp_dir().join(format!("telescope-{}-{}", process::id(), count));
+        match create(&path) {
+            Ok(()) => return Ok(path),
+            Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(io_error(&path, err)),
+        }
+    }
+}
+
+/// Remove a file, or a directory and everything in it. It's not an error if
+/// it's already gone.
+pub fn remove_all(path: &Path) -> Result<()> {
+    let result = match fs::symlink_metadata(path) {
+        Ok(ref metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(err) => Err(err),
+    };
+    match result {
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result.map_err(|err| io_error(path, err)),
+    }
+}
+
+/// The existing paths matching `pattern`, sorted. In each component of the
+/// pattern `*` matches any run of characters and `?` any one character, and
+/// a `**` component matches any number of directories. Wildcards don't match
+/// names starting with a dot unless the pattern does too.
+pub fn glob(pattern: &str) -> Vec<String> {
+    let root = if pattern.starts_with('/') { PathBuf::from("/") } else { PathBuf::new() };
+    let mut paths = vec![root];
+
+    for component in pattern.split('/').filter(|c| !c.is_empty()) {
+        let mut next = Vec::new();
+        for dir in &paths {
+            if component == "**" {
+                next.push(dir.clone());
+                descendants(dir, &mut next);
+            } else if !component.contains(|c: char| c == '*' || c == '?') {
+                next.push(dir.join(component));
+            } else {
+                let pattern = component.chars().collect::<Vec<_>>();
+                for name in children(dir) {
+                    let hidden = name.starts_with('.') && !component.starts_with('.');
+                    if !hidden && matches(&pattern, &name.chars().collect::<Vec<_>>()) {
+                        next.push(dir.join(name));
+                    }
+                }
+            }
+        }
+        paths = next;
+    }
+
+    let mut found = paths.into_iter()
+        .filter(|path| !path.as_os_str().is_empty() && path.exists())
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    found.sort();
+    found.dedup();
+    found
+}
+
+// The names in a directory, or none if it can't be read
+fn children(dir: &Path) -> Vec<String> {
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+// Every directory below `dir`, skipping hidden ones
+fn descendants(dir: &Path, found: &mut Vec<PathBuf>) {
+    for name in children(dir) {
+        let path = dir.join(&name);
+        if !name.starts_with('.') && path.is_dir() {
+            found.push(path.clone());
+            descendants(&path, found);
+        }
+    }
+}
+
+fn matches(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some(&'*') => (0..name.len() + 1).any(|skip| matches(&pattern[1..], &name[skip..])),
+        Some(&'?') => !name.is_empty() && matches(&pattern[1..], &name[1..]),
+        Some(c) => name.first() == Some(c) && matches(&pattern[1..], &name[1..]),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+
+    fn chars(s: &str) -> Vec<char> {
+        s.chars().collect()
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(matches(&chars("*.tl"), &chars("main.tl")));
+        assert!(matches(&chars("m??n.*"), &chars("main.tl")));
+        assert!(matches(&chars("*"), &chars("")));
+        assert!(!matches(&chars("*.tl"), &chars("main.rs")));
+        assert!(!matches(&chars("?"), &chars("")));
+    }
+
+    #[test]
+    fn temp_files() {
+        let (file, dir) = (temp_file().unwrap(), temp_dir().unwrap());
+        assert_ne!(file, dir);
+        assert!(file.is_file() && dir.is_dir());
+        fs::File::create(dir.join("inner")).unwrap();
+        remove_all(&file).unwrap();
+        remove_all(&dir).unwrap();
+        assert!(!file.exists() && !dir.exists());
+        remove_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn glob_files() {
+        let dir = env::temp_dir().join(format!("telescope-glob-{}", ::std::process::id()));
+        fs::create_dir_all(dir.join("a/b")).unwrap();
+        for file in &["x.tl", "a/y.tl", "a/b/z.tl", "a/b/z.rs", ".hidden.tl"] {
+            fs::File::create(dir.join(file)).unwrap();
+        }
+        let base = dir.to_string_lossy().into_owned();
+        let found = |pattern: &str| -> Vec<String> {
+            glob(&format!("{}/{}", base, pattern))
+                .into_iter()
+                .map(|path| path[base.len() + 1..].to_owned())
+                .collect()
+        };
+
+        assert_eq!(vec!["x.tl"], found("*.tl"));
+        assert_eq!(vec!["a/b/z.tl", "a/y.tl", "x.tl"], found("**/*.tl"));
+        assert_eq!(vec!["a/b/z.rs", "a/b/z.tl"], found("a/*/z.*"));
+        assert_eq!(vec![".hidden.tl"], found(".*.tl"));
+        assert_eq!(Vec::<String>::new(), found("nope/*"));
+
+        assert_eq!(vec![".hidden.tl", "a", "x.tl"], list(&base).unwrap());
+        assert!(remove(&format!("{}/a", base)).is_err());
+        let err = list(&format!("{}/missing", base)).unwrap_err();
+        assert!(err.to_string().contains("missing"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,385 @@
+//! A source formatter. Code is reprinted with canonical indentation, and
+//! forms too long for one line are broken across lines. Comments and single
+//! blank lines between forms are kept, and atoms are printed as written.
+
+use error::*;
+use parser;
+use types::Expr;
+
+const WIDTH: usize = 80;
+
+/// Forms whose body is indented by two spaces, with how many arguments stay
+/// on the first line with the name.
+const BODY_FORMS: &[(&str, usize)] = &[
+    ("def", 1),
+    ("def-dynamic", 1),
+    ("defn", 2),
+    ("defmulti", 1),
+    ("defmethod", 2),
+    ("defrecord", 1),
+    ("defmacro", 2),
+    ("macro", 1),
+    ("fn", 1),
+    ("let", 1),
+    ("let-values", 1),
+    ("binding", 1),
+    ("if", 1),
+    ("when", 1),
+    ("unless", 1),
+    ("case", 1),
+    ("match", 1),
+    ("do", 0),
+    ("future", 0),
+    ("with-out-str", 0),
+    ("with-in-str", 1),
+];
+
+/// Reformat `source`, failing without changes if it doesn't read.
+pub fn format(source: &str) -> Result<String> {
+    parser::parse_program(source)?;
+
+    let mut printer = Printer::default();
+    printer.top_level(&tree(&scan(source)));
+    Ok(printer.out)
+}
+
+/// Each top-level form of `source`, paired with its formatted text. Comments
+/// between forms are dropped.
+pub fn split(source: &str) -> Result<Vec<(Expr, String)>> {
+    let forms = parser::parse_program(source)?;
+    let texts = tree(&scan(source))
+        .into_iter()
+        .filter(|node| match *node {
+            Node::Comment(..) | Node::Blank => false,
+            _ => true,
+        })
+        .map(|node| {
+            let mut printer = Printer::default();
+            printer.node(&node);
+            printer.out
+        });
+    Ok(forms.into_iter().zip(texts).collect())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Item {
+    Open(char),
+    Close(char),
+    Atom(String),
+    Prefix(char),
+    // Whether the comment follows other code on its line
+    Comment(String, bool),
+    Blank,
+}
+
+/// Split source into brackets, atoms, and comments, noting blank lines.
+fn scan(source: &str) -> Vec<Item> {
+    let mut items = Vec::new();
+    let mut chars = source.chars().peekable();
+    let mut newlines = 0;
+    let mut seen_code = false;
+
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            if c == '\n' {
+                newlines += 1;
+            }
+            continue;
+        }
+        if newlines >= 2 && !items.is_empty() {
+            items.push(Item::Blank);
+        }
+        let trailing = newlines == 0 && seen_code;
+        newlines = 0;
+
+        let item = match c {
+            '(' | '[' | '{' => Item::Open(c),
+            ')' | ']' | '}' => Item::Close(c),
+            '\'' | '^' => Item::Prefix(c),
+            ';' => {
+                let mut text = c.to_string();
+                while chars.peek().map_or(false, |&c| c != '\n') {
+                    text.push(chars.next().unwrap());
+                }
+                items.push(Item::Comment(text.trim_right().to_owned(), trailing));
+                continue;
+            }
+            '"' => {
+                let mut text = c.to_string();
+                while let Some(c) = chars.next() {
+                    text.push(c);
+                    match c {
+                        '\\' => text.extend(chars.next()),
+                        '"' => break,
+                        _ => (),
+                    }
+                }
+                Item::Atom(text)
+            }
+            _ => {
+                let mut text = c.to_string();
+                while chars.peek().map_or(false, |&c| !ends_atom(c)) {
+                    text.push(chars.next().unwrap());
+                }
+                Item::Atom(text)
+            }
+        };
+        seen_code = true;
+        items.push(item);
+    }
+    items
+}
+
+fn ends_atom(c: char) -> bool {
+    c.is_whitespace() || "()[]{}\";".contains(c)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Atom(String),
+    Comment(String, bool),
+    Blank,
+    Prefixed(char, Box<Node>),
+    Coll(char, char, Vec<Node>),
+}
+
+impl Node {
+    /// The node on a single line, unless it holds comments or blank lines.
+    fn flat(&self) -> Option<String> {
+        match *self {
+            Node::Atom(ref text) => Some(text.clone()),
+            Node::Comment(..) | Node::Blank => None,
+            Node::Prefixed(prefix, ref node) => node.flat().map(|flat| format!("{}{}", prefix, flat)),
+            Node::Coll(open, close, ref nodes) => {
+                let flat = nodes.iter().map(Node::flat).collect::<Option<Vec<_>>>()?;
+                Some(format!("{}{}{}", open, flat.join(" "), close))
+            }
+        }
+    }
+}
+
+/// Nest the scanned items. The source has already been read successfully,
+/// so brackets are balanced.
+fn tree(items: &[Item]) -> Vec<Node> {
+    let mut stack: Vec<(char, Vec<Node>)> = vec![(' ', Vec::new())];
+    let mut prefixes: Vec<Vec<char>> = vec![Vec::new()];
+
+    for item in items {
+        let node = match *item {
+            Item::Open(open) => {
+                stack.push((open, Vec::new()));
+                prefixes.push(Vec::new());
+                continue;
+            }
+            Item::Prefix(prefix) => {
+                prefixes.last_mut().unwrap().push(prefix);
+                continue;
+            }
+            Item::Comment(ref text, trailing) => {
+                stack.last_mut().unwrap().1.push(Node::Comment(text.clone(), trailing));
+                continue;
+            }
+            Item::Blank => {
+                stack.last_mut().unwrap().1.push(Node::Blank);
+                continue;
+            }
+            Item::Atom(ref text) => Node::Atom(text.clone()),
+            Item::Close(close) => {
+                let (open, nodes) = stack.pop().unwrap();
+                prefixes.pop();
+                Node::Coll(open, close, nodes)
+            }
+        };
+
+        // A prefix applies to the form after it
+        let prefixed = prefixes.last_mut().unwrap()
+            .drain(..)
+            .rev()
+            .fold(node, |node, prefix| Node::Prefixed(prefix, Box::new(node)));
+        stack.last_mut().unwrap().1.push(prefixed);
+    }
+    stack.pop().unwrap().1
+}
+
+#[derive(Debug, Default)]
+struct Printer {
+    out: String,
+    column: usize,
+}
+
+impl Printer {
+    fn push(&mut self, text: &str) {
+        self.out.push_str(text);
+        match text.rfind('\n') {
+            Some(i) => self.column = text[i + 1..].chars().count(),
+            None => self.column += text.chars().count(),
+        }
+    }
+
+    fn newline(&mut self, indent: usize, blank: bool) {
+        let trimmed = self.out.trim_right_matches(' ').len();
+        self.out.truncate(trimmed);
+        self.out.push('\n');
+        if blank {
+            self.out.push('\n');
+        }
+        self.out.extend((0..indent).map(|_| ' '));
+        self.column = indent;
+    }
+
+    fn top_level(&mut self, nodes: &[Node]) {
+        let mut blank = false;
+        for (i, node) in nodes.iter().enumerate() {
+            match *node {
+                Node::Blank => blank = true,
+                Node::Comment(ref text, true) if i > 0 => {
+                    self.push(" ");
+                    self.push(text);
+                }
+                _ => {
+                    if i > 0 {
+                        self.newline(0, blank);
+                    }
+                    blank = false;
+                    self.node(node);
+                }
+            }
+        }
+        if !self.out.is_empty() {
+            self.out.push('\n');
+        }
+    }
+
+    fn node(&mut self, node: &Node) {
+        match *node {
+            Node::Atom(ref text) | Node::Comment(ref text, _) => self.push(text),
+            Node::Blank => (),
+            Node::Prefixed(prefix, ref node) => {
+                self.push(&prefix.to_string());
+                self.node(node);
+            }
+            Node::Coll(open, close, ref nodes) => {
+                match node.flat() {
+                    Some(ref flat) if self.column + flat.chars().count() <= WIDTH => self.push(flat),
+                    _ => self.broken(open, close, nodes),
+                }
+            }
+        }
+    }
+
+    /// Print a collection across lines. The first line holds the opening
+    /// bracket and the first `inline` forms, and the remaining forms go
+    /// `per_line` to a line at `indent`.
+    fn broken(&mut self, open: char, close: char, nodes: &[Node]) {
+        let start = self.column;
+        self.push(&open.to_string());
+
+        let head = match nodes.first() {
+            Some(&Node::Atom(ref head)) if open == '(' => Some(head),
+            _ => None,
+        };
+        let (inline, per_line, indent) = match head {
+            Some(head) => match BODY_FORMS.iter().find(|&&(name, _)| name == head.as_str()) {
+                Some(&(_, args)) => (1 + args, 1, start + 2),
+                // Calls line their arguments up after the function
+                None => (2, 1, start + head.chars().count() + 2),
+            },
+            None if open == '{' => (2, 2, start + 1),
+            None => (1, 1, start + 1),
+        };
+
+        let mut placed = 0;
+        let mut blank = false;
+        let mut break_next = false;
+        for node in nodes {
+            match *node {
+                Node::Blank => blank = true,
+                Node::Comment(ref text, trailing) => {
+                    if !trailing || placed == 0 {
+                        self.newline(indent, blank);
+                    } else {
+                        self.push(" ");
+                    }
+                    self.push(text);
+                    blank = false;
+                    break_next = true;
+                }
+                _ => {
+                    let same_line = placed < inline || (placed - inline) % per_line != 0;
+                    if same_line && !break_next {
+                        if placed > 0 {
+                            self.push(" ");
+                        }
+                    } else {
+                        self.newline(indent, blank);
+                    }
+                    self.node(node);
+                    placed += 1;
+                    blank = false;
+                    break_next = false;
+                }
+            }
+        }
+
+        // A comment runs to the end of the line, so the bracket can't follow it
+        if break_next {
+            self.newline(indent, false);
+        }
+        self.push(&close.to_string());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_formats(expected: &str, source: &str) {
+        assert_eq!(expected, format(source).unwrap());
+        // Formatting is idempotent
+        assert_eq!(expected, format(expected).unwrap());
+    }
+
+    #[test]
+    fn short_forms_stay_on_one_line() {
+        assert_formats("(+ 1 2)\n", "(+   1\n  2 )");
+        assert_formats("(def x [1 2 3])\n(print x)\n", "(def x [1\n2 3])   (print x)");
+    }
+
+    #[test]
+    fn body_forms_indent_two() {
+        let source = "(defn long-function-name [first-argument second-argument] (+ first-argument second-argument))";
+        let expected = "(defn long-function-name [first-argument second-argument]\n  (+ first-argument second-argument))\n";
+        assert_formats(expected, source);
+    }
+
+    #[test]
+    fn calls_align_arguments() {
+        let source = "(some-function \"a fairly long string argument\" \"and another long string argument\")";
+        let expected = "(some-function \"a fairly long string argument\"\n               \"and another long string argument\")\n";
+        assert_formats(expected, source);
+    }
+
+    #[test]
+    fn maps_keep_pairs() {
+        let source = "{:name \"a fairly long string value\" :description \"and another long string value\"}";
+        let expected = "{:name \"a fairly long string value\"\n :description \"and another long string value\"}\n";
+        assert_formats(expected, source);
+    }
+
+    #[test]
+    fn comments_and_blank_lines() {
+        let source = "; header\n\n\n(def x 1) ; one\n(defn f [x]\n  ; double it\n  (* 2 x))\n";
+        let expected = "; header\n\n(def x 1) ; one\n(defn f [x]\n  ; double it\n  (* 2 x))\n";
+        assert_formats(expected, source);
+    }
+
+    #[test]
+    fn prefixes_and_strings() {
+        assert_formats("(def ^:private x '(a \"b ; c\" d))\n", "(def  ^:private x '( a \"b ; c\"  d ))");
+    }
+
+    #[test]
+    fn invalid_source_is_rejected() {
+        assert!(format("(def x").is_err());
+    }
+}
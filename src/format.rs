@@ -0,0 +1,293 @@
+//! Format strings, for `format`, `printf`, and log messages. A placeholder is
+//! written `{arg:spec}`, where `arg` is empty for the next argument, an index,
+//! or a name looked up in a map, and `spec` is `[[fill]align][width][.precision]`
+//! with `<`, `^`, or `>` for the alignment:
+//!
+//! ```text
+//! (format "{:>8.2}|{name:*^7}" 1.23456 {:name "ada"})  =>  "    1.23|**ada**"
+//! ```
+//!
+//! Precision is the number of decimals for numbers and the maximum length for
+//! strings. Numbers are aligned right by default, and everything else left.
+//! `{{` and `}}` stand for literal braces.
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use error::*;
+use types::{Expr, Key, Map};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Arg {
+    Next,
+    Index(usize),
+    Name(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Spec {
+    fill: char,
+    align: Option<Align>,
+    width: usize,
+    precision: Option<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Piece {
+    Text(String),
+    Field(Arg, Spec),
+}
+
+/// A parsed format string.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template(Vec<Piece>);
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Template> {
+        let mut pieces = Vec::new();
+        let mut text = String::new();
+        let mut chars = source.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' | '}' if chars.peek() == Some(&c) => {
+                    chars.next();
+                    text.push(c);
+                }
+                '{' => {
+                    if !text.is_empty() {
+                        pieces.push(Piece::Text(text.split_off(0)));
+                    }
+                    pieces.push(field(&mut chars)?);
+                }
+                '}' => bail!("unmatched }} in format string {:?}", source),
+                _ => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            pieces.push(Piece::Text(text));
+        }
+        Ok(Template(pieces))
+    }
+
+    /// How many positional arguments the placeholders refer to.
+    pub fn positional(&self) -> usize {
+        let mut next = 0;
+        let mut count = 0;
+        for piece in &self.0 {
+            match *piece {
+                Piece::Field(Arg::Next, _) => {
+                    next += 1;
+                    count = count.max(next);
+                }
+                Piece::Field(Arg::Index(i), _) => count = count.max(i + 1),
+                _ => (),
+            }
+        }
+        count
+    }
+
+    /// Fill in the placeholders from `args`, and names from `named`.
+    pub fn render(&self, args: &[Expr], named: Option<&Map>) -> Result<String> {
+        let mut out = String::new();
+        let mut next = 0;
+        for piece in &self.0 {
+            let (arg, spec) = match *piece {
+                Piece::Text(ref text) => {
+                    out.push_str(text);
+                    continue;
+                }
+                Piece::Field(ref arg, ref spec) => (arg, spec),
+            };
+            let value = match *arg {
+                Arg::Next => {
+                    next += 1;
+                    positional(args, next - 1)?
+                }
+                Arg::Index(i) => positional(args, i)?,
+                Arg::Name(ref name) => named
+                    .and_then(|map| {
+                        let keyword = map.get(&Key::Keyword(name.clone()));
+                        keyword.or_else(|| map.get(&Key::Str(name.clone())))
+                    })
+                    .ok_or_else(|| format!("no value for {{{}}} in format string", name))?,
+            };
+            pad(&mut out, &render(value, spec.precision), spec, is_number(value));
+        }
+        Ok(out)
+    }
+}
+
+/// `template` filled in from `args`. If the last argument is a map, named
+/// placeholders are looked up in it.
+pub fn format(template: &str, args: &[Expr]) -> Result<String> {
+    let named = match args.last() {
+        Some(&Expr::Map(ref map)) => Some(map),
+        _ => None,
+    };
+    Template::parse(template)?.render(args, named)
+}
+
+// A placeholder, after its opening brace
+fn field(chars: &mut Peekable<Chars>) -> Result<Piece> {
+    let mut inside = String::new();
+    loop {
+        match chars.next() {
+            Some('}') => break,
+            Some(c) => inside.push(c),
+            None => bail!("unclosed {{ in format string"),
+        }
+    }
+    let (arg, spec) = match inside.find(':') {
+        Some(colon) => (&inside[..colon], &inside[colon + 1..]),
+        None => (&inside[..], ""),
+    };
+    let arg = if arg.is_empty() {
+        Arg::Next
+    } else if let Ok(i) = arg.parse() {
+        Arg::Index(i)
+    } else {
+        Arg::Name(arg.to_owned())
+    };
+    Ok(Piece::Field(arg, parse_spec(spec)?))
+}
+
+fn parse_spec(source: &str) -> Result<Spec> {
+    let mut spec = Spec { fill: ' ', align: None, width: 0, precision: None };
+    let mut chars = source.chars().peekable();
+
+    // A fill character is only there if an alignment follows it
+    let mut lookahead = source.chars();
+    match (lookahead.next(), lookahead.next().and_then(align)) {
+        (Some(fill), Some(alignment)) => {
+            spec.fill = fill;
+            spec.align = Some(alignment);
+            chars.next();
+            chars.next();
+        }
+        (Some(c), None) if align(c).is_some() => {
+            spec.align = align(c);
+            chars.next();
+        }
+        _ => (),
+    }
+
+    spec.width = number(&mut chars).unwrap_or(0);
+    if chars.peek() == Some(&'.') {
+        chars.next();
+        spec.precision = number(&mut chars);
+        ensure!(spec.precision.is_some(), "missing precision in {{:{}}}", source);
+    }
+    ensure!(chars.next().is_none(), "invalid format spec {{:{}}}", source);
+    Ok(spec)
+}
+
+fn align(c: char) -> Option<Align> {
+    match c {
+        '<' => Some(Align::Left),
+        '^' => Some(Align::Center),
+        '>' => Some(Align::Right),
+        _ => None,
+    }
+}
+
+fn number(chars: &mut Peekable<Chars>) -> Option<usize> {
+    let mut digits = String::new();
+    while let Some(&c) = chars.peek() {
+        if !c.is_digit(10) {
+            break;
+        }
+        digits.push(c);
+        chars.next();
+    }
+    digits.parse().ok()
+}
+
+fn positional(args: &[Expr], index: usize) -> Result<&Expr> {
+    args.get(index).ok_or_else(|| {
+        let count = args.len();
+        format!("format string refers to argument {}, but there are only {}", index, count).into()
+    })
+}
+
+fn is_number(value: &Expr) -> bool {
+    match *value {
+        Expr::Int(_) | Expr::Flt(_) => true,
+        _ => false,
+    }
+}
+
+fn render(value: &Expr, precision: Option<usize>) -> String {
+    match (value, precision) {
+        (&Expr::Int(i), Some(p)) => format!("{:.*}", p, i as f64),
+        (&Expr::Flt(f), Some(p)) => format!("{:.*}", p, f),
+        (&Expr::Str(ref s), Some(p)) => s.graphemes(true).take(p).collect(),
+        (&Expr::Str(ref s), None) => s.clone(),
+        (other, _) => other.to_string(),
+    }
+}
+
+fn pad(out: &mut String, text: &str, spec: &Spec, number: bool) {
+    let len = text.graphemes(true).count();
+    let padding = spec.width.saturating_sub(len);
+    let default = if number { Align::Right } else { Align::Left };
+    let (before, after) = match spec.align.unwrap_or(default) {
+        Align::Left => (0, padding),
+        Align::Center => (padding / 2, padding - padding / 2),
+        Align::Right => (padding, 0),
+    };
+    out.extend((0..before).map(|_| spec.fill));
+    out.push_str(text);
+    out.extend((0..after).map(|_| spec.fill));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn named(pairs: &[(&str, Expr)]) -> Expr {
+        let mut map = Map::new();
+        for &(name, ref value) in pairs {
+            map.insert(Key::Keyword(name.to_owned()), value.clone());
+        }
+        Expr::Map(map)
+    }
+
+    #[test]
+    fn placeholders() {
+        let args = [Expr::from(1), Expr::from("two")];
+        assert_eq!("1 two", format("{} {}", &args).unwrap());
+        assert_eq!("two 1 two", format("{1} {0} {}", &args).unwrap());
+        assert_eq!("{1}", format("{{{}}}", &args).unwrap());
+        let names = named(&[("name", Expr::from("ada"))]);
+        assert_eq!("hi ada", format("hi {name}", &[names]).unwrap());
+        assert!(format("{} {} {}", &args).is_err());
+        assert!(format("{name}", &args).is_err());
+        assert!(format("{", &args).is_err());
+        assert!(format("}", &args).is_err());
+        assert_eq!(2, Template::parse("{} {} {0}").unwrap().positional());
+        assert_eq!(3, Template::parse("{2} {name}").unwrap().positional());
+    }
+
+    #[test]
+    fn specs() {
+        assert_eq!("    1.23", format("{:>8.2}", &[Expr::from(1.23456)]).unwrap());
+        assert_eq!("     1", format("{:6}", &[Expr::from(1)]).unwrap());
+        assert_eq!("ab    |", format("{:6}|", &[Expr::from("ab")]).unwrap());
+        assert_eq!("**ab***", format("{:*^7}", &[Expr::from("ab")]).unwrap());
+        assert_eq!("2.00", format("{:.2}", &[Expr::from(2)]).unwrap());
+        assert_eq!("caf", format("{:.3}", &[Expr::from("café")]).unwrap());
+        assert_eq!("e\u{301}--", format("{:-<3}", &[Expr::from("e\u{301}")]).unwrap());
+        assert_eq!("  :a", format("{:>4}", &[Expr::Keyword("a".to_owned())]).unwrap());
+        assert!(format("{:x}", &[Expr::from(1)]).is_err());
+        assert!(format("{:.}", &[Expr::from(1)]).is_err());
+    }
+}
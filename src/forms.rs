@@ -1,23 +1,64 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
-use itertools::Itertools;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
 
 use env::Env;
 use error::*;
-use types::{Expr, Function, Macro, Symbol, Lambda};
+use types::{Clause, Expr, Function, Key, List, Macro, RecordOp, RecordType, Symbol, Lambda};
+use dynamic;
+use eval;
+use files;
+use future::{Future, Task};
+use generator::Generator;
+use location;
+use module;
+use pattern;
+use port;
+use reader_macro;
+use typecheck;
+use warning;
 use util::*;
 
 lazy_static! {
     static ref SPECIAL_FORMS: HashMap<&'static str, Lambda> = {
         let forms: Vec<(&'static str, Lambda)> = vec![
             ("def", def_form),
+            ("def-dynamic", def_dynamic_form),
+            ("binding", binding_form),
             ("if",  if_form),
             ("let", let_form),
+            ("let-values", let_values_form),
             ("do",  do_form),
             ("fn",  fn_form),
+            ("defn", defn_form),
+            ("defmulti", defmulti_form),
+            ("defmethod", defmethod_form),
+            ("defrecord", defrecord_form),
             ("macro", macro_form),
             ("quote", quote_form),
             ("and", and_form),
             ("or", or_form),
+            ("when", when_form),
+            ("unless", unless_form),
+            ("case", case_form),
+            ("->", thread_first_form),
+            ("->>", thread_last_form),
+            ("match", match_form),
+            ("for", for_form),
+            ("doseq", doseq_form),
+            ("with-out-str", with_out_str_form),
+            ("with-in-str", with_in_str_form),
+            ("with-temp-file", with_temp_file_form),
+            ("with-temp-dir", with_temp_dir_form),
+            ("with-handler", with_handler_form),
+            ("block", block_form),
+            ("return-from", return_from_form),
+            ("future", future_form),
+            ("generator", generator_form),
+            ("require", require_form),
+            ("reload", reload_form),
         ];
         forms.into_iter().collect()
     };
@@ -27,6 +68,10 @@ pub fn is_special_form(form: &Symbol) -> bool {
     SPECIAL_FORMS.contains_key(form.0.as_str())
 }
 
+pub fn names() -> Vec<&'static str> {
+    SPECIAL_FORMS.keys().cloned().collect()
+}
+
 pub fn eval(form: &Symbol, args: &[Expr], env: Env) -> Result<Expr> {
     debug_assert!(is_special_form(form));
     (SPECIAL_FORMS.get(form.0.as_str()))
@@ -36,9 +81,24 @@ pub fn eval(form: &Symbol, args: &[Expr], env: Env) -> Result<Expr> {
 
 fn def_impl(args: &[Expr], env: Env) -> Result<Expr> {
     let sym = ensure_sym("def", &args[0])?;
-    args[1].eval(env.clone())
-        .map(|expr| env.define(&sym.0, expr))
-        .map(Expr::from)
+    let mut value = args[1].eval(env.clone())?;
+    // Metadata on the name, as in (def ^{:doc "..."} x ...), goes on the value
+    if let Some(meta) = sym.1.get() {
+        value = value.merge_meta(meta)?;
+    }
+    define_global(&env, &sym.0, value)
+}
+
+/// Define `name`, warning if that replaces a builtin function.
+fn define_global(env: &Env, name: &str, value: Expr) -> Result<Expr> {
+    if let Some(Expr::Func(ref func)) = env.lookup(name) {
+        if let Function::Builtin { name: ref builtin, .. } = **func {
+            if builtin == name {
+                warning::warn(&format!("{} replaces the builtin function of the same name", name))?;
+            }
+        }
+    }
+    Ok(Expr::from(env.define(name, value)))
 }
 
 // (def symbol init)
@@ -47,6 +107,30 @@ fn def_form(args: &[Expr], env: Env) -> Result<Expr> {
     def_impl(&args, env)
 }
 
+// (def-dynamic symbol init)
+fn def_dynamic_form(args: &[Expr], env: Env) -> Result<Expr> {
+    ensure_args("def-dynamic", args, 2)?;
+    let sym = ensure_sym("def-dynamic", &args[0])?;
+    dynamic::declare(&sym.0);
+    def_impl(&args, env)
+}
+
+// (binding [symbol value ...] exprs*)
+fn binding_form(args: &[Expr], env: Env) -> Result<Expr> {
+    ensure_min_args("binding", args, 1)?;
+    let bindings = ensure_vector("binding", &args[0])?;
+    ensure!(bindings.0.len() % 2 == 0, "#[binding] expected symbol-value pairs");
+
+    let mut frame = HashMap::new();
+    for binding in bindings.0.chunks(2) {
+        let sym = ensure_sym("binding", &binding[0])?;
+        ensure!(dynamic::is_dynamic(&sym.0), "#[binding] {} is not dynamic", sym);
+        frame.insert(sym.0.clone(), binding[1].eval(env.clone())?);
+    }
+
+    dynamic::with_bindings(frame, || Expr::eval_all(&args[1..], env.clone()))
+}
+
 // (if cond then else?)
 fn if_form(args: &[Expr], env: Env) -> Result<Expr> {
     ensure_range_args("if", args, 2, 3)?;
@@ -64,16 +148,146 @@ fn do_form(args: &[Expr], env: Env) -> Result<Expr> {
 
 // (let [bindings*] exprs*)
 fn let_form(args: &[Expr], env: Env) -> Result<Expr> {
+    let_impl("let", args, env)
+}
+
+// (let-values [[patterns*] init ...] exprs*)
+fn let_values_form(args: &[Expr], env: Env) -> Result<Expr> {
+    let_impl("let-values", args, env)
+}
+
+fn let_impl(name: &str, args: &[Expr], env: Env) -> Result<Expr> {
+    ensure_min_args(name, args, 1)?;
     let let_env = Env::new(HashMap::new(), Some(env));
-    let bindings = ensure_vector("let", &args[0])?;
+    let bindings = ensure_vector(name, &args[0])?;
+    ensure!(bindings.0.len() % 2 == 0, "#[{}] expected pattern-init pairs", name);
+    if let Some(at) = location::current() {
+        if warning::first_check(&at) {
+            warn_unused(&bindings.0, &args[1..])?;
+        }
+    }
 
-    for i in (0..bindings.0.len()).step(2) {
-        def_impl(&(bindings.0)[i..i+2], let_env.clone())?;
+    // Each binding can destructure its init, e.g. the result of (values ...)
+    for binding in bindings.0.chunks(2) {
+        let value = binding[1].eval(let_env.clone())?;
+        define_pattern(name, &binding[0], &value, &let_env)?;
     }
 
     Expr::eval_all(&args[1..], let_env.clone())
 }
 
+// Warn about let bindings that nothing after them mentions. Names starting
+// with _ are meant to be unused.
+fn warn_unused(bindings: &[Expr], body: &[Expr]) -> Result<()> {
+    for (i, binding) in bindings.chunks(2).enumerate() {
+        let later = bindings[2 * i + 2..].iter().chain(body);
+        let used = |name: &str| later.clone().any(|form| mentions(form, name));
+        for name in pattern::symbols(&binding[0]) {
+            if !name.starts_with('_') && !used(&name) {
+                warning::warn(&format!("{} is never used", name))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Whether `name` appears anywhere in `form`
+fn mentions(form: &Expr, name: &str) -> bool {
+    match *form {
+        Expr::Sym(ref sym) => sym.0 == name,
+        Expr::List(ref list) => list.0.iter().any(|form| mentions(form, name)),
+        Expr::Vector(ref vec) => vec.0.iter().any(|form| mentions(form, name)),
+        Expr::Map(ref map) => map.iter().any(|(_, form)| mentions(form, name)),
+        _ => false,
+    }
+}
+
+/// Define the symbols `pattern` binds when matched against `value`.
+fn define_pattern(name: &str, pattern: &Expr, value: &Expr, env: &Env) -> Result<()> {
+    let mut bound = HashMap::new();
+    ensure!(
+        pattern::bind(pattern, value, &mut bound)?,
+        "#[{}] cannot bind {} to {}", name, value, pattern
+    );
+    for (symbol, value) in bound {
+        env.define(&symbol, value);
+    }
+    Ok(())
+}
+
+// (for [pattern coll modifiers* ...] exprs*)
+fn for_form(args: &[Expr], env: Env) -> Result<Expr> {
+    ensure_min_args("for", args, 1)?;
+    let bindings = ensure_vector("for", &args[0])?;
+    ensure!(bindings.0.len() % 2 == 0, "#[for] expected pattern-coll pairs");
+
+    let mut results = Vec::new();
+    for_each("for", &bindings.0, env, &mut |env| {
+        results.push(Expr::eval_all(&args[1..], env)?);
+        Ok(())
+    })?;
+    Ok(Expr::List(List::new(results)))
+}
+
+// (doseq [pattern coll modifiers* ...] exprs*)
+fn doseq_form(args: &[Expr], env: Env) -> Result<Expr> {
+    ensure_min_args("doseq", args, 1)?;
+    let bindings = ensure_vector("doseq", &args[0])?;
+    ensure!(bindings.0.len() % 2 == 0, "#[doseq] expected pattern-coll pairs");
+
+    // Like for, but the results are thrown away as they come
+    for_each("doseq", &bindings.0, env, &mut |env| Expr::eval_all(&args[1..], env).map(|_| ()))?;
+    Ok(Expr::Nil)
+}
+
+/// Call `body` with an env for each combination of the items `bindings` bind,
+/// the last binding varying fastest. Between them, `:when test` skips items,
+/// `:while test` stops the loop it follows, and `:let [bindings]` binds more
+/// names. Returns false if a `:while` stopped the loop.
+fn for_each(name: &str, bindings: &[Expr], env: Env, body: &mut FnMut(Env) -> Result<()>) -> Result<bool> {
+    let (target, init, rest) = match bindings.len() {
+        0 => return body(env).map(|()| true),
+        _ => (&bindings[0], &bindings[1], &bindings[2..]),
+    };
+
+    match *target {
+        Expr::Keyword(ref modifier) if modifier == "when" => {
+            if init.eval(env.clone())?.truthiness() {
+                return for_each(name, rest, env, body);
+            }
+            Ok(true)
+        }
+        Expr::Keyword(ref modifier) if modifier == "while" => {
+            if !init.eval(env.clone())?.truthiness() {
+                return Ok(false);
+            }
+            for_each(name, rest, env, body)
+        }
+        Expr::Keyword(ref modifier) if modifier == "let" => {
+            let let_env = Env::new(HashMap::new(), Some(env));
+            let lets = ensure_vector(name, init)?;
+            ensure!(lets.0.len() % 2 == 0, "#[{}] expected pattern-init pairs after :let", name);
+            for binding in lets.0.chunks(2) {
+                let value = binding[1].eval(let_env.clone())?;
+                define_pattern(name, &binding[0], &value, &let_env)?;
+            }
+            for_each(name, rest, let_env, body)
+        }
+        Expr::Keyword(ref modifier) => bail!("#[{}] unknown modifier :{}", name, modifier),
+        _ => {
+            let coll = init.eval(env.clone())?;
+            for item in ensure_seq(name, &coll)? {
+                let item_env = Env::new(HashMap::new(), Some(env.clone()));
+                define_pattern(name, target, item, &item_env)?;
+                if !for_each(name, rest, item_env, body)? {
+                    break;
+                }
+            }
+            Ok(true)
+        }
+    }
+}
+
 // (quote form)
 fn quote_form(args: &[Expr], _env: Env) -> Result<Expr> {
     ensure_args("quote", args, 1)?;
@@ -81,16 +295,151 @@ fn quote_form(args: &[Expr], _env: Env) -> Result<Expr> {
 }
 
 // (fn name? [params* ] exprs*)
+// (fn name? ([params* ] exprs*)+)
 fn fn_form(args: &[Expr], env: Env) -> Result<Expr> {
-    ensure_min_args("fn", args, 2)?;
+    ensure_min_args("fn", args, 1)?;
     let name = args[0].sym().cloned().map(|n| n.0);
-    let raw_params = if name.is_some() { &args[1] } else { &args[0] };
-    let params = ensure_vector("fn", raw_params)?
+    let rest = if name.is_some() { &args[1..] } else { args };
+    fn_impl("fn", name, rest, env, whole("fn", args))
+}
+
+// (defn name doc? [params* ] exprs*)
+// (defn name doc? ([params* ] exprs*)+)
+fn defn_form(args: &[Expr], env: Env) -> Result<Expr> {
+    ensure_min_args("defn", args, 2)?;
+    let sym = ensure_sym("defn", &args[0])?;
+    let (doc, rest) = match args[1] {
+        Expr::Str(ref doc) if args.len() > 2 => (Some(doc), &args[2..]),
+        _ => (None, &args[1..]),
+    };
+
+    let mut meta = sym.1.get().cloned().unwrap_or_default();
+    if let Some(doc) = doc {
+        meta.insert(Key::Keyword("doc".to_owned()), Expr::from(doc.clone()));
+    }
+    let source = whole("defn", args);
+    let func = fn_impl("defn", Some(sym.0.clone()), rest, env.clone(), source)?.merge_meta(&meta)?;
+    define_global(&env, &sym.0, func)
+}
+
+// (defmulti name dispatch-fn)
+fn defmulti_form(args: &[Expr], env: Env) -> Result<Expr> {
+    ensure_args("defmulti", args, 2)?;
+    let sym = ensure_sym("defmulti", &args[0])?;
+    let dispatch = args[1].eval(env.clone())?;
+    let dispatch = dispatch.func().ok_or_else(|| type_error("defmulti", "fn", &dispatch))?;
+    define_global(&env, &sym.0, Expr::from(Function::multi(sym.0.clone(), dispatch)))
+}
+
+// (defmethod name dispatch-value [params* ] exprs*)
+fn defmethod_form(args: &[Expr], env: Env) -> Result<Expr> {
+    ensure_min_args("defmethod", args, 3)?;
+    let sym = ensure_sym("defmethod", &args[0])?;
+    let multi = env.lookup(&sym.0)
+        .and_then(|expr| expr.func())
+        .ok_or_else(|| format!("#[defmethod] {} is not a generic function", sym))?;
+    let value = args[1].eval(env.clone())?;
+    let source = whole("defmethod", args);
+    let method = fn_impl("defmethod", Some(sym.0.clone()), &args[2..], env.clone(), source)?;
+    multi.add_method(value, method.func().unwrap())?;
+    Ok(Expr::from(sym.clone()))
+}
+
+// (defrecord Name [fields*])
+fn defrecord_form(args: &[Expr], env: Env) -> Result<Expr> {
+    ensure_args("defrecord", args, 2)?;
+    let name = ensure_sym("defrecord", &args[0])?.0.clone();
+    let fields = ensure_vector("defrecord", &args[1])?
         .0.iter()
-        .map(|x| ensure_sym("fn", x).map(|x| x.clone()))
+        .map(|x| ensure_sym("defrecord", x).map(|x| x.clone()))
         .collect::<Result<Vec<_>>>()?;
-    let body = if name.is_some() { args[2..].to_vec() } else { args[1..].to_vec() };
-    Ok(Expr::from(Function::User { name, params, body, env: env.clone() }))
+    let rtype = Arc::new(RecordType { name: name.clone(), fields: fields.clone() });
+    reader_macro::set_record(rtype.clone());
+
+    // Name constructs, Name? tests, and Name-field reads a field
+    let define = |fn_name: String, op: RecordOp| {
+        let func = Function::record(fn_name.clone(), rtype.clone(), op);
+        env.define(&fn_name, Expr::from(func));
+    };
+    define(name.clone(), RecordOp::New);
+    define(format!("{}?", name), RecordOp::Is);
+    for (i, field) in fields.iter().enumerate() {
+        define(format!("{}-{}", name, field), RecordOp::Get(i));
+    }
+    Ok(Expr::from(Symbol::new(name)))
+}
+
+fn fn_impl(
+    form: &str,
+    name: Option<String>,
+    args: &[Expr],
+    env: Env,
+    source: Expr,
+) -> Result<Expr> {
+    // A leading list means one clause per arity
+    let clauses = match args.first() {
+        Some(&Expr::List(_)) => {
+            args.iter()
+                .map(|arm| clause(form, &ensure_list(form, arm)?.0))
+                .collect::<Result<Vec<_>>>()?
+        }
+        _ => vec![clause(form, args)?],
+    };
+    for (i, c) in clauses.iter().enumerate() {
+        let count = c.params.len();
+        ensure!(
+            clauses[..i].iter().all(|other| other.params.len() != count),
+            "#[{}] more than one clause takes {} args",
+            form,
+            count
+        );
+    }
+    // Functions remember the location of the form that defined them
+    let meta = location::current_meta();
+    let source = Some(Arc::new(source));
+    Ok(Expr::from(Function::User { name, clauses, env: env.clone(), meta, source }))
+}
+
+// The form `(form args*)` being evaluated, put back together
+fn whole(form: &str, args: &[Expr]) -> Expr {
+    let mut items = vec![Expr::from(Symbol::new(form.to_owned()))];
+    items.extend_from_slice(args);
+    Expr::List(List::new(items))
+}
+
+fn clause(form: &str, args: &[Expr]) -> Result<Clause> {
+    let (params, body) = args.split_first()
+        .ok_or_else(|| format!("#[{}] expected a parameter vector", form))?;
+    let params = ensure_vector(form, params)?
+        .0.iter()
+        .map(|x| ensure_sym(form, x).map(|x| x.clone()))
+        .collect::<Result<Vec<_>>>()?;
+
+    let (returns, body) = typecheck::split_return(body);
+
+    // A map before the rest of the body may hold :pre and :post conditions
+    let keys = [Key::Keyword("pre".to_owned()), Key::Keyword("post".to_owned())];
+    let (map, body) = match body.first() {
+        Some(&Expr::Map(ref map)) if body.len() > 1 && keys.iter().any(|key| map.get(key).is_some()) => {
+            (Some(map), &body[1..])
+        }
+        _ => (None, body),
+    };
+    let conditions = |key: &Key| -> Result<Vec<Expr>> {
+        match map.and_then(|map| map.get(key)) {
+            Some(&Expr::Vector(ref vec)) => Ok(vec.0.clone()),
+            Some(other) => Err(type_error(form, "vector of conditions", other)),
+            None => Ok(Vec::new()),
+        }
+    };
+
+    Ok(Clause {
+        params,
+        returns,
+        pre: conditions(&keys[0])?,
+        post: conditions(&keys[1])?,
+        body: body.to_vec(),
+    })
 }
 
 // (macro name? [params* ] exprs*)
@@ -139,3 +488,753 @@ fn or_form(args: &[Expr], env: Env) -> Result<Expr> {
         Ok(Expr::from(false))
     }
 }
+
+// (when cond exprs*)
+fn when_form(args: &[Expr], env: Env) -> Result<Expr> {
+    ensure_min_args("when", args, 1)?;
+    if args[0].eval(env.clone())?.truthiness() {
+        Expr::eval_all(&args[1..], env.clone())
+    } else {
+        Ok(Expr::Nil)
+    }
+}
+
+// (unless cond exprs*)
+fn unless_form(args: &[Expr], env: Env) -> Result<Expr> {
+    ensure_min_args("unless", args, 1)?;
+    if args[0].eval(env.clone())?.truthiness() {
+        Ok(Expr::Nil)
+    } else {
+        Expr::eval_all(&args[1..], env.clone())
+    }
+}
+
+// (case expr [test then]* default?)
+fn case_form(args: &[Expr], env: Env) -> Result<Expr> {
+    ensure_min_args("case", args, 1)?;
+    let value = args[0].eval(env.clone())?;
+
+    for clause in args[1..].chunks(2) {
+        // A trailing unpaired expr is the default
+        if clause.len() == 1 {
+            return clause[0].eval(env.clone());
+        }
+
+        // Tests are not evaluated, and a list of tests matches any of them
+        let matched = match clause[0] {
+            Expr::List(ref tests) => tests.0.contains(&value),
+            ref test => *test == value,
+        };
+        if matched {
+            return clause[1].eval(env.clone());
+        }
+    }
+
+    Err(format!("#[case] no clause matching {}", value).into())
+}
+
+// (-> expr forms*)
+fn thread_first_form(args: &[Expr], env: Env) -> Result<Expr> {
+    ensure_min_args("->", args, 1)?;
+    thread_impl(args, false).eval(env)
+}
+
+// (->> expr forms*)
+fn thread_last_form(args: &[Expr], env: Env) -> Result<Expr> {
+    ensure_min_args("->>", args, 1)?;
+    thread_impl(args, true).eval(env)
+}
+
+// (match expr [pattern (if guard)? then]*)
+fn match_form(args: &[Expr], env: Env) -> Result<Expr> {
+    ensure_min_args("match", args, 1)?;
+    let value = args[0].eval(env.clone())?;
+
+    let mut clauses = &args[1..];
+    while !clauses.is_empty() {
+        let guarded = clauses.get(1).and_then(Expr::sym).map_or(false, |s| s.0 == "if");
+        let len = if guarded { 4 } else { 2 };
+        ensure!(clauses.len() >= len, "#[match] incomplete clause for {}", clauses[0]);
+
+        let mut bindings = HashMap::new();
+        if pattern::bind(&clauses[0], &value, &mut bindings)? {
+            let match_env = Env::new(bindings, Some(env.clone()));
+            if !guarded || clauses[2].eval(match_env.clone())?.truthiness() {
+                return clauses[len - 1].eval(match_env);
+            }
+        }
+        clauses = &clauses[len..];
+    }
+
+    Err(format!("#[match] no pattern matching {}", value).into())
+}
+
+// (with-temp-file [name] exprs*)
+fn with_temp_file_form(args: &[Expr], env: Env) -> Result<Expr> {
+    with_temp_impl("with-temp-file", args, env, files::temp_file)
+}
+
+// (with-temp-dir [name] exprs*)
+fn with_temp_dir_form(args: &[Expr], env: Env) -> Result<Expr> {
+    with_temp_impl("with-temp-dir", args, env, files::temp_dir)
+}
+
+fn with_temp_impl(
+    name: &str,
+    args: &[Expr],
+    env: Env,
+    create: fn() -> Result<PathBuf>,
+) -> Result<Expr> {
+    ensure_min_args(name, args, 1)?;
+    ensure_unsandboxed(name, &env)?;
+    let binding = ensure_vector(name, &args[0])?;
+    ensure!(binding.0.len() == 1, "#[{}] expected [name]", name);
+    let sym = ensure_sym(name, &binding.0[0])?;
+
+    let path = create()?;
+    let temp_env = Env::new(HashMap::new(), Some(env));
+    temp_env.define(&sym.0, Expr::from(path.to_string_lossy().into_owned()));
+    let result = Expr::eval_all(&args[1..], temp_env);
+
+    // Clean up even if the body failed, whose error matters more
+    let removed = files::remove_all(&path);
+    let value = result?;
+    removed?;
+    Ok(value)
+}
+
+// (with-out-str exprs*)
+fn with_out_str_form(args: &[Expr], env: Env) -> Result<Expr> {
+    port::capture(|| Expr::eval_all(args, env.clone())).map(Expr::from)
+}
+
+// (with-in-str string exprs*)
+fn with_in_str_form(args: &[Expr], env: Env) -> Result<Expr> {
+    ensure_min_args("with-in-str", args, 1)?;
+    let input = args[0].eval(env.clone())?;
+    let input = ensure_str("with-in-str", &input)?;
+    let source = Box::new(io::Cursor::new(input.as_bytes().to_vec()));
+    port::with_input(source, || Expr::eval_all(&args[1..], env.clone()))
+}
+
+// (with-handler pred handler exprs*)
+fn with_handler_form(args: &[Expr], env: Env) -> Result<Expr> {
+    ensure_min_args("with-handler", args, 2)?;
+    let pred = ensure_func("with-handler", &args[0].eval(env.clone())?)?;
+    let handler = ensure_func("with-handler", &args[1].eval(env.clone())?)?;
+
+    let outer = location::current_meta();
+    let err = match Expr::eval_all(&args[2..], env.clone()) {
+        Ok(value) => return Ok(value),
+        Err(err) => err,
+    };
+    // Quitting isn't an error to recover from, and escaping isn't an error
+    let passes = match *err.kind() {
+        ErrorKind::Exit(_) | ErrorKind::Interrupted | ErrorKind::OutOfFuel => true,
+        ErrorKind::Eof => true,
+        ErrorKind::Escape(..) | ErrorKind::Return(_) => true,
+        _ => false,
+    };
+    if passes {
+        return Err(err);
+    }
+
+    let condition = error_value(&err);
+    if !pred.apply(&[condition.clone()], env.clone())?.truthiness() {
+        return Err(err);
+    }
+    // Handled, so the failed form is no longer the current location
+    location::leave(Some(outer));
+    handler.apply(&[condition], env)
+}
+
+thread_local! {
+    // The names of the blocks being evaluated, innermost last, with the ids
+    // their escapes are addressed to
+    static BLOCKS: RefCell<Vec<(String, usize)>> = RefCell::new(Vec::new());
+}
+
+// (block name exprs*)
+fn block_form(args: &[Expr], env: Env) -> Result<Expr> {
+    ensure_min_args("block", args, 1)?;
+    let name = ensure_sym("block", &args[0])?.0.clone();
+    let id = eval::escape_id();
+
+    BLOCKS.with(|blocks| blocks.borrow_mut().push((name, id)));
+    let result = Expr::eval_all(&args[1..], env);
+    BLOCKS.with(|blocks| blocks.borrow_mut().pop());
+
+    let err = match result {
+        Err(err) => err,
+        result => return result,
+    };
+    let escaped = match *err.kind() {
+        ErrorKind::Escape(target, ref value) if target == id => Some(value.clone()),
+        _ => None,
+    };
+    escaped.ok_or(err)
+}
+
+// (return-from name value?)
+fn return_from_form(args: &[Expr], env: Env) -> Result<Expr> {
+    ensure_range_args("return-from", args, 1, 2)?;
+    let name = ensure_sym("return-from", &args[0])?;
+    let id = BLOCKS.with(|blocks| {
+        blocks.borrow().iter().rev().find(|&&(ref block, _)| *block == name.0).map(|&(_, id)| id)
+    });
+    let id = id.ok_or_else(|| format!("#[return-from] no block named {}", name))?;
+    let value = match args.get(1) {
+        Some(value) => value.eval(env)?,
+        None => Expr::Nil,
+    };
+    Err(ErrorKind::Escape(id, value).into())
+}
+
+/// An error as a value for handlers: a map of its :message and :kind, and
+/// where it happened as :file, :line, and :column if that's known.
+fn error_value(err: &Error) -> Expr {
+    let kind = match *err.kind() {
+        ErrorKind::Arity { .. } => "arity",
+        ErrorKind::Type { .. } => "type",
+        ErrorKind::Undefined(..) => "undefined",
+        ErrorKind::Io(_) => "io",
+        ErrorKind::Lex(_) | ErrorKind::Parse(_) => "syntax",
+        _ => "error",
+    };
+    let mut map = location::current().map(|location| location.to_meta()).unwrap_or_default();
+    map.insert(Key::Keyword("message".to_owned()), Expr::from(err.to_string()));
+    map.insert(Key::Keyword("kind".to_owned()), Expr::Keyword(kind.to_owned()));
+    Expr::Map(map)
+}
+
+// (future exprs*)
+fn future_form(args: &[Expr], env: Env) -> Result<Expr> {
+    ensure_unsandboxed("future", &env)?;
+    let mut body = vec![Expr::from(Symbol::new("do"))];
+    body.extend(args.iter().cloned());
+    let task = Task::Eval(Expr::List(List::new(body)), env);
+    Ok(Expr::Handle(Future::spawn(task)))
+}
+
+// (generator exprs*)
+fn generator_form(args: &[Expr], env: Env) -> Result<Expr> {
+    ensure_unsandboxed("generator", &env)?;
+    let mut body = vec![Expr::from(Symbol::new("do"))];
+    body.extend(args.iter().cloned());
+    Ok(Expr::Handle(Generator::spawn(Expr::List(List::new(body)), env)?))
+}
+
+// (reload name)
+fn reload_form(args: &[Expr], env: Env) -> Result<Expr> {
+    ensure_args("reload", args, 1)?;
+    ensure_unsandboxed("reload", &env)?;
+    // Quoting the name, as in (reload 'name), is allowed too
+    let quote = Expr::from(Symbol::new("quote".to_owned()));
+    let name = match args[0] {
+        Expr::List(ref list) if list.0.len() == 2 && list.0[0] == quote => &list.0[1],
+        ref name => name,
+    };
+    let name = ensure_sym("reload", name)?;
+    let names = module::reload(&name.0, env)?.into_iter().map(|name| Expr::from(Symbol::new(name)));
+    Ok(Expr::List(List::new(names.collect())))
+}
+
+// (require name)
+fn require_form(args: &[Expr], env: Env) -> Result<Expr> {
+    ensure_args("require", args, 1)?;
+    ensure_unsandboxed("require", &env)?;
+    let name = ensure_sym("require", &args[0])?;
+    Ok(Expr::from(module::require(&name.0, env)?))
+}
+
+/// Rewrite a threading form into nested calls, inserting each intermediate
+/// form as the first (or last) argument of the next.
+fn thread_impl(args: &[Expr], last: bool) -> Expr {
+    args[1..].iter().fold(args[0].clone(), |threaded, form| {
+        let mut call = match *form {
+            Expr::List(ref list) => list.0.clone(),
+            _ => vec![form.clone()],
+        };
+        if last || call.is_empty() {
+            call.push(threaded);
+        } else {
+            call.insert(1, threaded);
+        }
+        Expr::List(List::new(call))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use lexer;
+    use ops;
+    use parser;
+    use reader::Reader;
+    use stream::{StringStream, TokenStream};
+
+    fn eval_str(input: &str) -> Result<Expr> {
+        let (tokens, _) = lexer::lex(StringStream::new(input)).unwrap();
+        let (exprs, _) = parser::parse(TokenStream::new(tokens.into_iter())).unwrap();
+        Expr::eval_all(&exprs, ops::env())
+    }
+
+    // Evaluate `input` after the definitions in `defs`
+    fn eval_with(defs: &str, input: &str) -> Result<Expr> {
+        eval_str(&format!("{} {}", defs, input))
+    }
+
+    #[test]
+    fn when_unless() {
+        assert_eq!(Expr::from(2), eval_str("(when (= 1 1) 1 2)").unwrap());
+        assert_eq!(Expr::Nil, eval_str("(when (= 1 0) 1 2)").unwrap());
+        assert_eq!(Expr::from(2), eval_str("(unless (= 1 0) 1 2)").unwrap());
+        assert_eq!(Expr::Nil, eval_str("(unless (= 1 1) 1 2)").unwrap());
+    }
+
+    #[test]
+    fn case() {
+        assert_eq!(Expr::from("two"), eval_str(r#"(case (+ 1 1) 1 "one" 2 "two")"#).unwrap());
+        assert_eq!(Expr::from("small"), eval_str(r#"(case 3 (1 2 3) "small" "big")"#).unwrap());
+        assert_eq!(Expr::from("big"), eval_str(r#"(case 9 (1 2 3) "small" "big")"#).unwrap());
+        assert!(eval_str("(case 9 1 2)").is_err());
+    }
+
+    #[test]
+    fn threading() {
+        assert_eq!(Expr::from(1), eval_str("(-> 10 (- 4) (/ 3) (- 1))").unwrap());
+        assert_eq!(Expr::from(3), eval_str("(->> 10 (- 4) (/ 12) (- 1))").unwrap());
+        assert_eq!(Expr::from(-5), eval_str("(-> 5 -)").unwrap());
+    }
+
+    #[test]
+    fn match_patterns() {
+        let classify = r#"
+            (def classify (fn [x]
+                (match x
+                    0 "zero"
+                    n if (= n (- 1)) "negative"
+                    [a b] (+ a b)
+                    [a & rest] rest
+                    {"k" v} v
+                    'sym "quoted"
+                    _ "other")))
+        "#;
+        let run = |arg: &str| eval_with(classify, &format!("(classify {})", arg)).unwrap();
+        assert_eq!(Expr::from("zero"), run("0"));
+        assert_eq!(Expr::from("negative"), run("(- 1)"));
+        assert_eq!(Expr::from("other"), run("5"));
+        assert_eq!(Expr::from(3), run("'(1 2)"));
+        assert_eq!(eval_str("'[2 3]").unwrap(), run("'[1 2 3]"));
+        assert_eq!(Expr::from(7), run(r#"'{"k" 7}"#));
+        assert_eq!(Expr::from("quoted"), run("'sym"));
+    }
+
+    #[test]
+    fn let_values() {
+        assert_eq!(Expr::from(3), eval_str("(let [x 1 y (+ x 1)] (+ x y))").unwrap());
+        assert_eq!(
+            Expr::from(7),
+            eval_str("(let-values [[q r] (values 2 1)] (+ (* q 3) r))").unwrap()
+        );
+        assert!(eval_str("(let-values [[q r] (values 2)] q)").is_err());
+    }
+
+    #[test]
+    fn dynamic_binding() {
+        let defs = "(def-dynamic *x* 1) (def get-x (fn [] *x*))";
+        assert_eq!(
+            Expr::from(2),
+            eval_with(defs, "(binding [*x* 2] (get-x))").unwrap()
+        );
+        assert_eq!(
+            Expr::from(1),
+            eval_with(defs, "(binding [*x* 2] (get-x)) (get-x)").unwrap()
+        );
+        assert!(eval_str("(def y 1) (binding [y 2] y)").is_err());
+    }
+
+    #[test]
+    fn with_out_str() {
+        assert_eq!(
+            Expr::from("1\n\"a\"\n"),
+            eval_str(r#"(with-out-str (print 1) (print "a"))"#).unwrap()
+        );
+    }
+
+    #[test]
+    fn with_in_str() {
+        assert_eq!(
+            eval_str(r#"'("a" "b" nil)"#).unwrap(),
+            eval_str(r#"(with-in-str "a\nb" (list (read-line) (read-line) (read-line)))"#)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn multi_arity() {
+        let defs = "(defn greet ([] (greet \"world\")) ([name] name) ([a b] b))";
+        assert_eq!(Expr::from("world"), eval_with(defs, "(greet)").unwrap());
+        assert_eq!(Expr::from("tele"), eval_with(defs, "(greet \"tele\")").unwrap());
+        assert_eq!(Expr::from(2), eval_with(defs, "(greet 1 2)").unwrap());
+        assert!(eval_with(defs, "(greet 1 2 3)").is_err());
+
+        // Gaps in the arity range are still errors
+        let gap = "(def f (fn ([] 0) ([a b] 2)))";
+        assert_eq!(Expr::from(2), eval_with(gap, "(f 1 1)").unwrap());
+        assert!(eval_with(gap, "(f 1)").is_err());
+
+        assert!(eval_str("(fn ([x] 1) ([y] 2))").is_err());
+    }
+
+    #[test]
+    fn multimethods() {
+        let defs = "
+            (defmulti area (fn [shape] (first shape)))
+            (defmethod area 'square [s] (* (first (rest s)) (first (rest s))))
+            (defmethod area 'rect [r] (* (first (rest r)) (first (rest (rest r)))))";
+        assert_eq!(Expr::from(9), eval_with(defs, "(area '(square 3))").unwrap());
+        assert_eq!(Expr::from(6), eval_with(defs, "(area '(rect 2 3))").unwrap());
+        assert!(eval_with(defs, "(area '(blob))").is_err());
+
+        let fallback = "(defmethod area 'default [x] 0) (area '(blob))";
+        assert_eq!(Expr::from(0), eval_with(defs, fallback).unwrap());
+
+        // Redefining a method replaces it
+        let redef = "(defmethod area 'square [s] -1) (area '(square 3))";
+        assert_eq!(Expr::from(-1), eval_with(defs, redef).unwrap());
+    }
+
+    #[test]
+    fn records() {
+        let defs = "(defrecord Point [x y]) (def p (Point 1 2))";
+        assert_eq!(Expr::from(2), eval_with(defs, "(Point-y p)").unwrap());
+        assert_eq!(Expr::from(true), eval_with(defs, "(Point? p)").unwrap());
+        assert_eq!(Expr::from(false), eval_with(defs, "(Point? '(1 2))").unwrap());
+        assert_eq!(Expr::from(true), eval_with(defs, "(= p (Point 1 2))").unwrap());
+        assert_eq!(Expr::from(false), eval_with(defs, "(= p (Point 2 1))").unwrap());
+        assert_eq!("#Point{:x 1 :y 2}", eval_with(defs, "p").unwrap().to_string());
+        let read = "(= p (read-string (str p)))";
+        assert_eq!(Expr::from(true), eval_with(defs, read).unwrap());
+        assert!(eval_with(defs, "(Point 1)").is_err());
+        assert!(eval_with(defs, "(Point-x 1)").is_err());
+
+        // Same fields but a different type
+        let other = "(defrecord Pair [x y]) (= p (Pair 1 2))";
+        assert_eq!(Expr::from(false), eval_with(defs, other).unwrap());
+    }
+
+    #[test]
+    fn metadata() {
+        let doc = "(defn sq \"Squares x.\" [x] (* x x))";
+        assert_eq!(Expr::from(9), eval_with(doc, "(sq 3)").unwrap());
+        assert_eq!(
+            Expr::from("Squares x."),
+            eval_with(doc, "(get (meta sq) :doc)").unwrap()
+        );
+
+        let def = "(def ^{:doc \"origin\"} origin [0 0]) (get (meta origin) :doc)";
+        assert_eq!(Expr::from("origin"), eval_str(def).unwrap());
+
+        let flag = "(def ^:test t (fn [] 1)) (get (meta t) :test)";
+        assert_eq!(Expr::from(true), eval_str(flag).unwrap());
+
+        // Metadata doesn't affect equality
+        assert_eq!(Expr::from(true), eval_str("(= [1 2] (with-meta [1 2] {:a 1}))").unwrap());
+        assert_eq!(Expr::Nil, eval_str("(meta [1 2])").unwrap());
+        assert!(eval_str("(def ^:test x 1)").is_err());
+        assert!(eval_str("(with-meta 1 {:a 1})").is_err());
+    }
+
+    #[test]
+    fn error_locations() {
+        use std::io;
+        use location::{self, Location};
+        use reader::Reader;
+
+        let input = "(defn f [x]\n  (first x))\n(f 1)";
+        let forms = Reader::new(io::Cursor::new(input.as_bytes()))
+            .with_file("test.tl")
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        let env = ops::env();
+        forms[0].eval(env.clone()).unwrap();
+
+        // Functions record where they were defined
+        let f = env.lookup("f").unwrap();
+        assert_eq!(1, Location::of(&f).unwrap().line);
+
+        // Errors leave the failing form as the current location
+        assert!(forms[1].eval(env.clone()).is_err());
+        let location = location::current().unwrap();
+        assert_eq!((2, 3), (location.line, location.column));
+    }
+
+    #[test]
+    fn futures() {
+        assert_eq!(Expr::from(6), eval_str("(deref (future (+ 1 2) (* 2 3)))").unwrap());
+        // Futures close over their environment and may wait on each other
+        let nested = "(def x 20) (def a (future (+ x 1))) (deref (future (* (deref a) 2)))";
+        assert_eq!(Expr::from(42), eval_str(nested).unwrap());
+        assert!(eval_str("(deref (future (first 1)))").is_err());
+        assert!(eval_str("(deref 1)").is_err());
+
+        let squares = "(pmap (fn [x] (* x x)) [1 2 3 4])";
+        assert_eq!("(1 4 9 16)", eval_str(squares).unwrap().to_string());
+        assert_eq!(Expr::Nil, eval_str("(pmap (fn [x] x) [])").unwrap());
+        assert!(eval_str("(pmap (fn [x] (first x)) [[1] 2])").is_err());
+    }
+
+    #[test]
+    fn channels_between_futures() {
+        let program = "
+            (def c (chan))
+            (def results (chan))
+            (def worker (future (send! results (* 2 (recv! c)))))
+            (send! c 21)
+            (recv! results)";
+        assert_eq!(Expr::from(42), eval_str(program).unwrap());
+    }
+
+    #[test]
+    fn workers_inherit_context() {
+        // Futures and generators print where the thread that started them does
+        let printed = "(with-out-str (deref (future (print 1)))
+                                     (next! (generator (print 2) (yield 3))))";
+        assert_eq!(Expr::from("1\n2\n"), eval_str(printed).unwrap());
+
+        let bound = "(def-dynamic *x* 1) (binding [*x* 2] (deref (future *x*)))";
+        assert_eq!(Expr::from(2), eval_str(bound).unwrap());
+        let input = r#"(with-in-str "line" (deref (future (read-line))))"#;
+        assert_eq!(Expr::from("line"), eval_str(input).unwrap());
+    }
+
+    #[test]
+    fn tcp_echo() {
+        let program = r#"
+            (def server (tcp-listen "127.0.0.1:0"))
+            (def echo (future
+                (let [conn (tcp-accept server)]
+                  (tcp-write conn (tcp-read conn))
+                  (tcp-close conn))))
+            (def client (tcp-connect (tcp-addr server)))
+            (tcp-write client "hello\n")
+            [(tcp-read client) (tcp-read client)]"#;
+        assert_eq!("[\"hello\" nil]", eval_str(program).unwrap().to_string());
+        assert!(eval_str(r#"(tcp-read (chan))"#).is_err());
+    }
+
+    #[test]
+    fn redefine_operators() {
+        // Operators are ordinary symbols, so they can be rebound like any name
+        assert_eq!(Expr::from(6), eval_str("(let [+ *] (+ 2 3))").unwrap());
+        assert_eq!(
+            Expr::from(2),
+            eval_str("(def + (fn [a b] (- a b))) (+ 5 3)").unwrap()
+        );
+    }
+
+    #[test]
+    fn memoize() {
+        let fib = "(def fib (memoize (fn [n] (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))))
+                   (fib 80)";
+        assert_eq!(Expr::from(23416728348467685i64), eval_str(fib).unwrap());
+
+        let calls = "(def calls (chan))
+                     (def f (memoize (fn [x] (send! calls x) x)))
+                     (f 1) (f 1) (f [2]) (f [2])
+                     (list (recv! calls) (recv! calls) (recv! calls))";
+        assert_eq!("(1 [2] [2])", eval_str(calls).unwrap().to_string());
+    }
+
+    #[test]
+    fn temp_files() {
+        let program = r#"(with-temp-file [f] (write-bytes f #b"hi") (read-bytes f))"#;
+        assert_eq!(Expr::Bytes(b"hi".to_vec()), eval_str(program).unwrap());
+
+        let program = r#"
+            (def d (with-temp-dir [d] (write-bytes (path-join d "a") #b"") d))
+            (exists? d)"#;
+        assert_eq!(Expr::from(false), eval_str(program).unwrap());
+
+        // Cleaned up after an error too
+        let program = r#"
+            (def c (chan))
+            (def result (with-temp-file [f] (send! c f) (undefined-fn)))"#;
+        let env = ops::env();
+        let (tokens, _) = lexer::lex(StringStream::new(program)).unwrap();
+        let (exprs, _) = parser::parse(TokenStream::new(tokens.into_iter())).unwrap();
+        assert!(Expr::eval_all(&exprs, env.clone()).is_err());
+        let path = Expr::eval_all(&parser::parse_program("(recv! c)").unwrap(), env).unwrap();
+        assert!(!::std::path::Path::new(path.str().unwrap()).exists());
+    }
+
+    #[test]
+    fn with_handler() {
+        let parse = r#"
+            (defn parse-or-zero [s]
+              (with-handler (fn [e] #t) (fn [e] 0) (parse-int s)))
+            (list (parse-or-zero "12") (parse-or-zero "twelve"))"#;
+        assert_eq!("(12 0)", eval_str(parse).unwrap().to_string());
+
+        let kind = r#"(with-handler (fn [e] #t) (fn [e] (get e :kind)) (+ 1 "a"))"#;
+        assert_eq!(Expr::Keyword("type".to_owned()), eval_str(kind).unwrap());
+
+        // Errors the predicate rejects, and exits, pass through
+        let only_type = "(with-handler (fn [e] (= (get e :kind) :type)) (fn [e] 0) (nope))";
+        assert!(eval_str(only_type).is_err());
+        assert!(eval_str("(with-handler (fn [e] #t) (fn [e] 0) (exit 1))").is_err());
+    }
+
+    #[test]
+    fn conditions() {
+        let program = r#"
+            (defn safe-div [a b]
+              {:pre [(not (= b 0))] :post [(< % a)]}
+              (/ a b))
+            (safe-div 10 2)"#;
+        assert_eq!(Expr::from(5), eval_str(program).unwrap());
+
+        let pre = eval_with(program, "(safe-div 1 0)").unwrap_err();
+        assert!(pre.to_string().contains("precondition failed: (not (= b 0))"), "{}", pre);
+        let post = eval_with(program, "(safe-div -4 2)").unwrap_err();
+        assert!(post.to_string().contains("postcondition failed: (< % a)"), "{}", post);
+
+        // A map that's the whole body is the return value
+        assert_eq!("{:pre 1}", eval_str("((fn [] {:pre 1}))").unwrap().to_string());
+    }
+
+    #[test]
+    fn type_reflection() {
+        let kw = |name: &str| Expr::Keyword(name.to_owned());
+        assert_eq!(kw("int"), eval_str("(type 1)").unwrap());
+        assert_eq!(kw("list"), eval_str("(type '(1 2))").unwrap());
+        assert_eq!(kw("fn"), eval_str("(type first)").unwrap());
+        assert_eq!(kw("nil"), eval_str("(type nil)").unwrap());
+        let defs = "(defrecord Point [x y]) (def p (Point 1 2))";
+        assert_eq!(kw("record/Point"), eval_with(defs, "(type p)").unwrap());
+
+        let yes = Expr::from(true);
+        assert_eq!(yes, eval_str("(instance? :str \"a\")").unwrap());
+        assert_eq!(yes, eval_str("(instance? :num 1.5)").unwrap());
+        assert_eq!(Expr::from(false), eval_str("(instance? :int 1.5)").unwrap());
+        assert_eq!(yes, eval_with(defs, "(instance? :record/Point p)").unwrap());
+        assert_eq!(yes, eval_with(defs, "(instance? :record p)").unwrap());
+        assert!(eval_str("(instance? 'int 1)").is_err());
+    }
+
+    #[test]
+    fn atoms() {
+        let defs = "(def a (atom [1])) (def b (deep-copy a))";
+        assert_eq!(Expr::from(3), eval_str("(def a (atom 1)) (swap! a + 2) (deref a)").unwrap());
+        assert_eq!("[1]", eval_with(defs, "(reset! b [2]) (deref a)").unwrap().to_string());
+        assert!(eval_with(defs, "(freeze a) (reset! a nil)").is_err());
+        let copied = eval_with(defs, "(freeze a) (frozen? (deep-copy a))").unwrap();
+        assert_eq!(Expr::from(false), copied);
+    }
+
+    #[test]
+    fn generators() {
+        let defs = "(def log (chan))
+            (def g (generator (send! log 'started) (yield 1) (yield 2) (send! log 'finished)))";
+        assert_eq!("[1 2]", eval_with(defs, "(take 5 g)").unwrap().to_string());
+        assert_eq!(
+            "[1 #f 2 #t]",
+            eval_with(defs, "[(next! g) (done? g) (next! g) (done? g)]").unwrap().to_string()
+        );
+        // Nothing runs until a value is asked for
+        let lazy = "(send! log 'first) (next! g) (recv! log)";
+        assert_eq!("first", eval_with(defs, lazy).unwrap().to_string());
+
+        let endless = "(defn count-from [n] (yield n) (count-from (+ n 1)))
+            (take 3 (generator (count-from 10)))";
+        assert_eq!("[10 11 12]", eval_str(endless).unwrap().to_string());
+        assert_eq!(Expr::Nil, eval_str("(next! (generator))").unwrap());
+        assert!(eval_str("(next! (generator (yield 1) (frobnicate)))").is_ok());
+        assert!(eval_str("(def g (generator (frobnicate))) (next! g)").is_err());
+        assert!(eval_str("(yield 1)").is_err());
+    }
+
+    #[test]
+    fn escape_continuations() {
+        assert_eq!(Expr::from(2), eval_str("(call/ec (fn [k] (k 2) (frobnicate)))").unwrap());
+        assert_eq!(Expr::from(3), eval_str("(call/ec (fn [k] 3))").unwrap());
+        // An inner escape only leaves the inner call
+        let nested = "(call/ec (fn [outer] (+ 1 (call/ec (fn [inner] (inner 1))) (outer 10))))";
+        assert_eq!(Expr::from(10), eval_str(nested).unwrap());
+        let handled = "(call/ec (fn [k] (with-handler (fn [e] #t) (fn [e] 'caught) (k 'escaped))))";
+        assert_eq!("escaped", eval_str(handled).unwrap().to_string());
+        assert!(eval_str("(def saved (call/ec (fn [k] k))) (saved 1)").is_err());
+    }
+
+    #[test]
+    fn early_returns() {
+        let find = "(defn find-even [xs]
+            (match xs
+              [] nil
+              [x & rest] (do (when (= 0 (rem x 2)) (return x)) (find-even rest))))";
+        assert_eq!(Expr::from(4), eval_with(find, "(find-even [1 3 4 5 6])").unwrap());
+        assert_eq!(Expr::Nil, eval_with(find, "(find-even [1 3])").unwrap());
+        // return leaves only the innermost function
+        assert_eq!(Expr::from(3), eval_str("((fn [] (+ 1 ((fn [] (return 2) 5)))))").unwrap());
+        assert!(eval_str("(return 1)").is_err());
+
+        assert_eq!(Expr::from(1), eval_str("(block outer (block inner (return-from outer 1) 2) 3)").unwrap());
+        assert_eq!(Expr::from(3), eval_str("(block outer (block inner (return-from inner 1) 2) 3)").unwrap());
+        assert_eq!(Expr::Nil, eval_str("(block b (return-from b) 1)").unwrap());
+        assert!(eval_str("(return-from nowhere 1)").is_err());
+        assert!(eval_str("(block b 1) (return-from b 2)").is_err());
+    }
+
+    #[test]
+    fn for_comprehensions() {
+        assert_eq!("(0 4 16 36 64)", eval_str("(for [x (range 10) :when (even? x)] (* x x))").unwrap().to_string());
+        assert_eq!(
+            "([1 a] [1 b] [2 a] [2 b])",
+            eval_str("(for [x [1 2] y '(a b)] [x y])").unwrap().to_string()
+        );
+        assert_eq!(
+            "(0 1 2 10 11 12)",
+            eval_str("(for [x [0 10] y (range 5) :while (< y 3)] (+ x y))").unwrap().to_string()
+        );
+        assert_eq!("(2 4)", eval_str("(for [[a b] [[1 1] [2 2]] :let [s (+ a b)]] s)").unwrap().to_string());
+        assert_eq!("()", eval_str("(for [x []] x)").unwrap().to_string());
+        assert!(eval_str("(for [x 1] x)").is_err());
+        assert!(eval_str("(for [x [1] :unless #t] x)").is_err());
+    }
+
+    #[test]
+    fn doseq() {
+        let source = "(with-out-str (doseq [x [1 2 3] :when (odd? x)] (print x)))";
+        assert_eq!(Expr::from("1\n3\n"), eval_str(source).unwrap());
+        assert_eq!(Expr::Nil, eval_str("(doseq [x (range 3)] x)").unwrap());
+    }
+
+    #[test]
+    fn warnings() {
+        let source = "(defn list [] 1) (let [a 1 b a _c 2 [d e] [3 4]] (+ b d))";
+        let forms = Reader::new(io::Cursor::new(source.as_bytes()))
+            .with_file("warnings-test.tl")
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        let (result, warnings) = warning::record(|| Expr::eval_all(&forms, ops::env()));
+        assert_eq!(Expr::from(4), result.unwrap());
+        let expected = vec!["list replaces the builtin function of the same name", "e is never used"];
+        assert_eq!(expected, warnings);
+    }
+
+    #[test]
+    fn deprecation() {
+        let source = "
+            (defn ^{:deprecated \"use new-thing\"} old-thing [] 1)
+            (def ^:deprecated old-first first)
+            (+ (old-thing) (old-thing) (old-first [1]) (first [1]))";
+        let (result, warnings) = warning::record(|| eval_str(source));
+        assert_eq!(Expr::from(4), result.unwrap());
+        assert_eq!(vec!["old-thing is deprecated: use new-thing", "old-first is deprecated"], warnings);
+        assert_eq!(
+            Expr::from(true),
+            eval_str("(def ^:deprecated old-first first) (get (meta old-first) :deprecated)").unwrap()
+        );
+    }
+}
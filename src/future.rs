@@ -0,0 +1,165 @@
+use std::any::Any;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex};
+use threadpool::{self, ThreadPool};
+
+use env::Env;
+use error::*;
+use location;
+use types::{Expr, Function, Handle, Resource};
+use util::lock;
+use worker::{self, Inherited};
+
+lazy_static! {
+    static ref POOL: Mutex<ThreadPool> = Mutex::new(
+        threadpool::Builder::new()
+            .thread_name("future".into())
+            .thread_stack_size(worker::STACK_SIZE)
+            .build()
+    );
+}
+
+/// Work for a future to do.
+#[derive(Debug)]
+pub enum Task {
+    Eval(Expr, Env),
+    Apply(Arc<Function>, Vec<Expr>, Env),
+}
+
+impl Task {
+    fn run(self) -> Result<Expr> {
+        // Locations are per thread, so start fresh on the worker
+        location::reset();
+        match self {
+            Task::Eval(expr, env) => expr.eval(env),
+            Task::Apply(func, args, env) => func.apply(&args, env),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum State {
+    Queued(Task, Inherited),
+    Running,
+    // Errors are kept as messages, since they may be read by several threads
+    Done(::std::result::Result<Expr, String>),
+}
+
+/// The eventual result of a task running on the thread pool.
+#[derive(Debug)]
+pub struct Future {
+    state: Mutex<State>,
+    done: Condvar,
+}
+
+impl Future {
+    /// Queue `task` on the thread pool, returning a handle to its future. The
+    /// task sees this thread's dynamic bindings, output, and input.
+    pub fn spawn(task: Task) -> Handle {
+        let future = Arc::new(Future {
+            state: Mutex::new(State::Queued(task, Inherited::capture())),
+            done: Condvar::new(),
+        });
+        let worker = future.clone();
+        lock(&*POOL).execute(move || worker.run());
+        Handle::new(future)
+    }
+
+    /// Block until the task finishes, returning its result. A task that hasn't
+    /// started yet runs on the calling thread instead, so futures that wait
+    /// on other futures can't starve the pool.
+    pub fn wait(&self) -> Result<Expr> {
+        self.run();
+        let mut state = lock(&self.state);
+        loop {
+            if let State::Done(ref result) = *state {
+                return result.clone().map_err(Error::from);
+            }
+            state = self.done.wait(state).unwrap_or_else(|err| err.into_inner());
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        match *lock(&self.state) {
+            State::Done(_) => true,
+            _ => false,
+        }
+    }
+
+    // Run the task if no other thread has claimed it
+    fn run(&self) {
+        let (task, inherited) = {
+            let mut state = lock(&self.state);
+            match mem::replace(&mut *state, State::Running) {
+                State::Queued(task, inherited) => (task, inherited),
+                other => {
+                    *state = other;
+                    return;
+                }
+            }
+        };
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| inherited.run(|| task.run())))
+            .unwrap_or_else(|_| Err("future panicked".into()))
+            .map_err(|err| err.to_string());
+
+        *lock(&self.state) = State::Done(result);
+        self.done.notify_all();
+    }
+}
+
+impl Resource for Future {
+    fn type_name(&self) -> &'static str {
+        "future"
+    }
+
+    fn as_any(&self) -> &Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ops;
+    use types::{List, Symbol};
+    use util::ensure_resource;
+
+    fn future(handle: &Handle) -> &Future {
+        handle.downcast_ref::<Future>().unwrap()
+    }
+
+    #[test]
+    fn eval_on_pool() {
+        let env = ops::env();
+        let form = Expr::List(List::new(vec![
+            Expr::from(Symbol::new("+")),
+            Expr::from(1),
+            Expr::from(2),
+        ]));
+        let handle = Future::spawn(Task::Eval(form, env));
+        assert_eq!(Expr::from(3), future(&handle).wait().unwrap());
+        assert!(future(&handle).is_done());
+        // Waiting again returns the same result
+        assert_eq!(Expr::from(3), future(&handle).wait().unwrap());
+    }
+
+    #[test]
+    fn errors_are_kept() {
+        let env = ops::env();
+        let handle = Future::spawn(Task::Eval(Expr::from(Symbol::new("nope")), env));
+        let err = future(&handle).wait().unwrap_err();
+        assert!(err.to_string().contains("nope"));
+        assert!(future(&handle).wait().is_err());
+    }
+
+    #[test]
+    fn handle_type() {
+        let handle = Expr::Handle(Future::spawn(Task::Eval(Expr::Nil, Env::default())));
+        assert_eq!("future", handle.type_name());
+        assert_eq!("#<future>", handle.to_string());
+        assert!(ensure_resource::<Future>("deref", &handle, "future").is_ok());
+        assert!(ensure_resource::<Future>("deref", &Expr::Nil, "future").is_err());
+    }
+}
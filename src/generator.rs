@@ -0,0 +1,166 @@
+//! Generators: `(generator body*)` runs `body` lazily on a thread of its own,
+//! pausing at each `(yield value)` until the next value is asked for. The body
+//! doesn't start until the first value is wanted, and a generator that's
+//! dropped part way stops at its next `yield`.
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use env::Env;
+use error::*;
+use location;
+use types::{Expr, Handle, Resource};
+use util::lock;
+use worker::{self, Inherited};
+
+#[derive(Debug)]
+enum Event {
+    Yield(Expr),
+    Done(::std::result::Result<(), String>),
+}
+
+// The generator's side of the channels, kept by the thread running its body
+struct Yielder {
+    demand: Receiver<()>,
+    values: Sender<Event>,
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Yielder>> = RefCell::new(None);
+}
+
+#[derive(Debug)]
+struct State {
+    demand: Sender<()>,
+    values: Receiver<Event>,
+    // A value fetched by done? that next! hasn't returned yet
+    peeked: Option<Expr>,
+    finished: bool,
+}
+
+impl State {
+    fn advance(&mut self) -> Result<Option<Expr>> {
+        if self.finished {
+            return Ok(None);
+        }
+        let event = match self.demand.send(()) {
+            Ok(()) => self.values.recv().ok(),
+            Err(_) => None,
+        };
+        match event {
+            Some(Event::Yield(value)) => Ok(Some(value)),
+            Some(Event::Done(result)) => {
+                self.finished = true;
+                result.map(|()| None).map_err(Error::from)
+            }
+            None => {
+                self.finished = true;
+                Ok(None)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Generator {
+    state: Mutex<State>,
+}
+
+impl Generator {
+    /// Start a thread that will evaluate `body` in `env` once a value is
+    /// asked for, returning a handle to the generator. The body sees the
+    /// dynamic bindings, output, and input this thread has now.
+    pub fn spawn(body: Expr, env: Env) -> Result<Handle> {
+        let inherited = Inherited::capture();
+        let (demand, demanded) = mpsc::channel();
+        let (yielded, values) = mpsc::channel();
+        thread::Builder::new()
+            .name("generator".into())
+            .stack_size(worker::STACK_SIZE)
+            .spawn(move || {
+                if demanded.recv().is_err() {
+                    return;
+                }
+                location::reset();
+                let done = yielded.clone();
+                CURRENT.with(|current| {
+                    *current.borrow_mut() = Some(Yielder { demand: demanded, values: yielded });
+                });
+                let run = || inherited.run(|| body.eval(env));
+                let result = panic::catch_unwind(AssertUnwindSafe(run))
+                    .unwrap_or_else(|_| Err("generator panicked".into()))
+                    .map(|_| ())
+                    .map_err(|err| err.to_string());
+                let _ = done.send(Event::Done(result));
+            })?;
+
+        let state = State { demand, values, peeked: None, finished: false };
+        Ok(Handle::new(Arc::new(Generator { state: Mutex::new(state) })))
+    }
+
+    /// The next value, or None once the body has finished. An error in the
+    /// body is returned once, after which the generator is finished.
+    pub fn next(&self) -> Result<Option<Expr>> {
+        let mut state = lock(&self.state);
+        match state.peeked.take() {
+            Some(value) => Ok(Some(value)),
+            None => state.advance(),
+        }
+    }
+
+    /// Whether there are no more values, running the body up to its next
+    /// `yield` to find out.
+    pub fn is_done(&self) -> Result<bool> {
+        let mut state = lock(&self.state);
+        if state.peeked.is_none() {
+            state.peeked = state.advance()?;
+        }
+        Ok(state.peeked.is_none())
+    }
+
+    /// The remaining values, fetched as they're iterated over.
+    pub fn values(&self) -> Values {
+        Values(self)
+    }
+}
+
+pub struct Values<'a>(&'a Generator);
+
+impl<'a> Iterator for Values<'a> {
+    type Item = Result<Expr>;
+
+    fn next(&mut self) -> Option<Result<Expr>> {
+        match self.0.next() {
+            Ok(Some(value)) => Some(Ok(value)),
+            Ok(None) => None,
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+impl Resource for Generator {
+    fn type_name(&self) -> &'static str {
+        "generator"
+    }
+
+    fn as_any(&self) -> &Any {
+        self
+    }
+}
+
+/// Hand `value` to whoever asked the current generator for a value, then wait
+/// until another is asked for.
+pub fn yield_value(value: Expr) -> Result<()> {
+    CURRENT.with(|current| match *current.borrow() {
+        Some(ref yielder) => {
+            ensure!(yielder.values.send(Event::Yield(value)).is_ok(), "generator was abandoned");
+            ensure!(yielder.demand.recv().is_ok(), "generator was abandoned");
+            Ok(())
+        }
+        None => bail!("yield outside a generator"),
+    })
+}
@@ -0,0 +1,261 @@
+//! The structure of a program, read without running it: which modules require
+//! which, and which top-level definitions refer to which. `tele graph` prints
+//! it in Graphviz's DOT language:
+//!
+//! ```sh
+//! $ tele graph main.tl | dot -Tsvg > main.svg
+//! ```
+//!
+//! Each module is drawn as a box around its definitions, with an extra node
+//! for the module's top-level code that isn't part of any definition.
+//!
+//! `tele unused` uses the same graph to find definitions that nothing run from
+//! the entry point ever refers to.
+
+use std::collections::{BTreeSet, HashSet, VecDeque};
+use std::fs;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use bundle::required;
+use error::*;
+use lint::{self, Warning};
+use location::Location;
+use module::{self, Manifest};
+use reader::Reader;
+use types::Expr;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Module {
+    pub name: String,
+    pub path: PathBuf,
+    /// Top-level definitions, in order.
+    pub definitions: Vec<Definition>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Definition {
+    pub name: String,
+    pub location: Location,
+}
+
+/// A node of the graph: a definition, or a module's other top-level code.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Node {
+    Definition(String),
+    TopLevel(String),
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Graph {
+    /// The modules, starting with the entry point.
+    pub modules: Vec<Module>,
+    /// Which module requires which, by name.
+    pub requires: BTreeSet<(String, String)>,
+    /// Which node refers to which definition.
+    pub calls: BTreeSet<(Node, String)>,
+}
+
+impl Graph {
+    /// Read the program starting at `entry`, resolving modules with
+    /// `manifest`.
+    pub fn build(entry: &Path, manifest: &Manifest) -> Result<Graph> {
+        let mut graph = Graph::default();
+        let mut references = Vec::new();
+        let name = entry.file_stem().map_or("main".into(), |stem| stem.to_string_lossy());
+        let mut pending = VecDeque::new();
+        pending.push_back((name.into_owned(), entry.to_owned()));
+        let mut seen = HashSet::new();
+
+        while let Some((name, path)) = pending.pop_front() {
+            let key = path.canonicalize()
+                .map_err(|err| format!("cannot read {}: {}", path.display(), err))?;
+            if !seen.insert(key) {
+                continue;
+            }
+            let forms = read(&path)?;
+            for form in &forms {
+                if let Some(required) = required(form) {
+                    graph.requires.insert((name.clone(), required.to_owned()));
+                    pending.push_back((required.to_owned(), manifest.resolve(required)));
+                }
+            }
+            let file = Location::new(Some(path.to_string_lossy().into()), 0, 0);
+            let definitions = forms
+                .iter()
+                .filter_map(|form| {
+                    let location = Location::of(form).unwrap_or_else(|| file.clone());
+                    module::definition(form).map(|name| Definition { name, location })
+                })
+                .collect::<Vec<_>>();
+            references.push((name.clone(), lint::references(&forms)));
+            graph.modules.push(Module { name, path, definitions });
+        }
+
+        // Only calls between definitions in the program are drawn, not calls to
+        // builtins
+        let defined = graph.modules.iter()
+            .flat_map(|module| module.definitions.iter().map(|d| d.name.clone()))
+            .collect::<HashSet<_>>();
+        for (module, references) in references {
+            for (from, to) in references {
+                if defined.contains(&to) {
+                    let from = match from {
+                        Some(name) => Node::Definition(name),
+                        None => Node::TopLevel(module.clone()),
+                    };
+                    graph.calls.insert((from, to));
+                }
+            }
+        }
+        Ok(graph)
+    }
+
+    /// The graph in Graphviz's DOT language.
+    pub fn to_dot(&self) -> String {
+        let mut lines = vec!["digraph program {".to_owned(), "  compound=true;".to_owned()];
+        // A name defined in two modules is drawn once, in the first
+        let mut drawn = HashSet::new();
+        for (i, module) in self.modules.iter().enumerate() {
+            lines.push(format!("  subgraph cluster_{} {{", i));
+            lines.push(format!("    label={};", quote(&module.name)));
+            lines.push(format!(
+                "    {} [label=\"(top level)\" shape=box style=dashed];",
+                top(&module.name)
+            ));
+            for definition in &module.definitions {
+                if drawn.insert(&definition.name) {
+                    lines.push(format!("    {};", quote(&definition.name)));
+                }
+            }
+            lines.push("  }".to_owned());
+        }
+        for &(ref from, ref to) in &self.calls {
+            let from = match *from {
+                Node::Definition(ref name) => quote(name),
+                Node::TopLevel(ref module) => top(module),
+            };
+            lines.push(format!("  {} -> {};", from, quote(to)));
+        }
+        // Modules are clusters, so requires go between their top-level nodes
+        for &(ref from, ref to) in &self.requires {
+            lines.push(format!(
+                "  {} -> {} [style=dashed label=\"requires\" lhead={}];",
+                top(from),
+                top(to),
+                self.cluster(to).unwrap_or_default()
+            ));
+        }
+        lines.push("}".to_owned());
+        lines.join("\n")
+    }
+
+    /// Warnings for the definitions that can't be reached from the top-level
+    /// code of any module. Every module is loaded from the entry point, so
+    /// that's where running the program starts.
+    pub fn unused(&self) -> Vec<Warning> {
+        let mut used = HashSet::new();
+        let mut pending = self.modules
+            .iter()
+            .map(|module| Node::TopLevel(module.name.clone()))
+            .collect::<Vec<_>>();
+        while let Some(node) = pending.pop() {
+            for &(ref from, ref to) in &self.calls {
+                if *from == node && used.insert(to.clone()) {
+                    pending.push(Node::Definition(to.clone()));
+                }
+            }
+        }
+
+        let mut warnings = Vec::new();
+        for definition in self.modules.iter().flat_map(|module| &module.definitions) {
+            // Only the first of several forms for a name, like defmethods
+            if used.insert(definition.name.clone()) {
+                warnings.push(Warning {
+                    location: definition.location.clone(),
+                    kind: "unused-definition",
+                    message: format!("{} is never used", definition.name),
+                });
+            }
+        }
+        warnings
+    }
+
+    fn cluster(&self, module: &str) -> Option<String> {
+        let i = self.modules.iter().position(|m| m.name == module)?;
+        Some(format!("cluster_{}", i))
+    }
+}
+
+fn read(path: &Path) -> Result<Vec<Expr>> {
+    let mut source = String::new();
+    fs::File::open(path)?.read_to_string(&mut source)?;
+    Reader::from_read(source.as_bytes())
+        .with_file(path.to_string_lossy().into_owned())
+        .collect::<Result<Vec<_>>>()
+        .map_err(|err| format!("{}: {}", path.display(), err).into())
+}
+
+// The node for a module's top-level code
+fn top(module: &str) -> String {
+    quote(&format!("{} (top level)", module))
+}
+
+fn quote(id: &str) -> String {
+    format!("\"{}\"", id.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use files;
+
+    #[test]
+    fn modules_and_calls() {
+        let dir = files::temp_dir().unwrap();
+        let write = |name: &str, source: &str| {
+            fs::File::create(dir.join(name)).unwrap().write_all(source.as_bytes()).unwrap()
+        };
+        write("main.tl", "(require util) (defn run [] (twice 2)) (print (run))");
+        write("util.tl", "(defn twice [x] (* 2 x)) (defn unused [] (twice 1))");
+        let manifest = Manifest { dir: dir.clone(), deps: Vec::new() };
+        let graph = Graph::build(&dir.join("main.tl"), &manifest).unwrap();
+
+        let names = graph.modules.iter().map(|m| m.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(vec!["main", "util"], names);
+        assert!(graph.requires.contains(&("main".to_owned(), "util".to_owned())));
+        let calls = vec![
+            (Node::Definition("run".to_owned()), "twice".to_owned()),
+            (Node::Definition("unused".to_owned()), "twice".to_owned()),
+            (Node::TopLevel("main".to_owned()), "run".to_owned()),
+        ];
+        assert_eq!(calls, graph.calls.iter().cloned().collect::<Vec<_>>());
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph program {"));
+        assert!(dot.contains("\"run\" -> \"twice\";"));
+        assert!(dot.contains("\"main (top level)\" -> \"run\";"));
+        files::remove_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unused_definitions() {
+        let dir = files::temp_dir().unwrap();
+        let write = |name: &str, source: &str| {
+            fs::File::create(dir.join(name)).unwrap().write_all(source.as_bytes()).unwrap()
+        };
+        write("main.tl", "(require util)\n(defn run [] (twice 2))\n(run)");
+        write("util.tl", "(def base 2)\n(defn twice [x] (* base x))\n(defn helper [] (old))\n\
+                          (defn old [] (helper))\n(def table (twice 1))");
+        let manifest = Manifest { dir: dir.clone(), deps: Vec::new() };
+        let graph = Graph::build(&dir.join("main.tl"), &manifest).unwrap();
+
+        let unused = graph.unused();
+        let messages = unused.iter().map(|w| w.message.as_str()).collect::<Vec<_>>();
+        let expected = vec!["helper is never used", "old is never used", "table is never used"];
+        assert_eq!(expected, messages);
+        assert_eq!(3, unused[0].location.line);
+        assert!(unused[0].location.file.as_ref().unwrap().ends_with("util.tl"));
+        files::remove_all(&dir).unwrap();
+    }
+}
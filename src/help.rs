@@ -0,0 +1,315 @@
+//! `(help)`, for reading the documentation from the REPL. Builtins and special
+//! forms are described by their sections of the README, which is compiled in,
+//! and user functions by their metadata: the docstring, any `:examples`, and
+//! where they were defined.
+//!
+//! `(apropos "query")` finds names to ask about, by searching what's bound
+//! and the docstrings.
+
+use itertools::Itertools;
+
+use color::{self, Color};
+use env::Env;
+use error::*;
+use forms;
+use location::Location;
+use types::{Expr, Function, Key, Symbol};
+use util::edit_distance;
+
+const MANUAL: &str = include_str!("../README.md");
+
+const BUILTINS: &str = "Built-in Functions";
+const SPECIAL_FORMS: &str = "Special Forms";
+
+struct Section {
+    title: String,
+    body: String,
+}
+
+/// The topics `(help "topic")` knows about.
+pub fn topics() -> String {
+    let mut titles = sections(BUILTINS).into_iter().map(|s| s.title).collect::<Vec<_>>();
+    titles.push(SPECIAL_FORMS.to_owned());
+    format!(
+        "{}\n{}\n\n{}",
+        color::bold(Color::Cyan, "Topics:"),
+        titles.iter().map(|title| format!("  {}", title)).join("\n"),
+        "(help \"topic\") describes a topic, and (help 'name) a function or special form."
+    )
+}
+
+/// The section of the manual about `topic`, ignoring case.
+pub fn topic(topic: &str) -> Option<String> {
+    if topic.eq_ignore_ascii_case(SPECIAL_FORMS) {
+        let mut names = forms::names();
+        names.sort();
+        return Some(format!("{}\n{}", color::bold(Color::Cyan, SPECIAL_FORMS), names.join(" ")));
+    }
+    sections(BUILTINS)
+        .into_iter()
+        .find(|s| s.title.eq_ignore_ascii_case(topic))
+        .map(|s| format!("{}\n\n{}", color::bold(Color::Cyan, &s.title), s.body))
+}
+
+/// What's known about whatever `name` is bound to in `env`, or the special
+/// form called `name`.
+pub fn describe(name: &str, env: &Env) -> Result<String> {
+    if forms::is_special_form(&Symbol::new(name.to_owned())) {
+        return special_form(name).ok_or_else(|| format!("no help for {}", name).into());
+    }
+    match env.lookup(name) {
+        Some(Expr::Func(ref func)) => Ok(function(name, func)),
+        Some(value) => {
+            let mut text = format!("{} is a {}", color::bold(Color::Cyan, name), value.type_name());
+            if let Some(doc) = value.meta().and_then(|meta| meta.get(&key("doc"))) {
+                text.push_str(&format!("\n\n{}", plain(doc)));
+            }
+            Ok(text)
+        }
+        None => bail!("no help for {}, which isn't defined", name),
+    }
+}
+
+/// What's known about a function, called by `name`.
+pub fn function(name: &str, func: &Function) -> String {
+    let mut parts = Vec::new();
+    let meta = func.meta();
+    let doc = meta.and_then(|meta| meta.get(&key("doc"))).map(plain);
+
+    match *func {
+        Function::User { ref clauses, .. } => {
+            parts.push(color::bold(Color::Cyan, name));
+            let signatures = clauses.iter().map(|c| format!("  ({} {})", name, c.signature()));
+            parts.push(signatures.join("\n"));
+            parts.extend(doc);
+            if let Some(at) = meta.and_then(Location::from_meta) {
+                parts.push(color::dim(format!("Defined at {}", at)));
+            }
+        }
+        Function::Builtin { .. } => {
+            let arity = color::dim(format!("builtin, arity {}", func.arity()));
+            parts.push(format!("{} {}", color::bold(Color::Cyan, name), arity));
+            let (paragraphs, examples) = mentions(func.name());
+            match doc {
+                Some(doc) => parts.push(doc),
+                None if !paragraphs.is_empty() => parts.push(paragraphs),
+                None => (),
+            }
+            parts.extend(examples);
+        }
+        _ => parts.push(format!("{} is {}", color::bold(Color::Cyan, name), func)),
+    }
+
+    let examples = meta
+        .and_then(|meta| meta.get(&key("examples")))
+        .and_then(|examples| examples.vector().map(|v| v.0.clone()));
+    if let Some(examples) = examples {
+        let examples = examples.iter().map(|example| format!("  {}", plain(example))).join("\n");
+        parts.push(format!("{}\n{}", color::bold(Color::Cyan, "Examples:"), examples));
+    }
+    parts.join("\n\n")
+}
+
+/// The names bound in `env`, and the special forms, that match `query`, best
+/// first. Names containing it come first, then ones with its letters in order,
+/// then ones whose docstring mentions it, and last ones a typo away from it.
+pub fn apropos(query: &str, env: &Env) -> Vec<String> {
+    let query = query.to_lowercase();
+    let mut names = env.names();
+    names.extend(forms::names().into_iter().map(String::from));
+    let mut matches = names
+        .into_iter()
+        .filter_map(|name| {
+            let doc = env.lookup(&name)
+                .and_then(|value| value.meta().and_then(|meta| meta.get(&key("doc"))).map(plain));
+            score(&query, &name.to_lowercase(), doc).map(|score| (score, name))
+        })
+        .collect::<Vec<_>>();
+    matches.sort();
+    matches.dedup();
+    matches.into_iter().map(|(_, name)| name).collect()
+}
+
+// How well `name` matches `query`, lower being better, or None if it doesn't
+fn score(query: &str, name: &str, doc: Option<String>) -> Option<(usize, usize)> {
+    if let Some(at) = name.find(query) {
+        return Some((0, at));
+    }
+    if let Some(gaps) = subsequence(query, name) {
+        return Some((1, gaps));
+    }
+    if doc.map_or(false, |doc| doc.to_lowercase().contains(query)) {
+        return Some((2, 0));
+    }
+    let distance = edit_distance(query, name);
+    if distance <= (query.chars().count() / 3).max(1) {
+        return Some((3, distance));
+    }
+    None
+}
+
+// How many characters of `name` are skipped to find the characters of `query`
+// in order, if they're there
+fn subsequence(query: &str, name: &str) -> Option<usize> {
+    let mut chars = name.chars();
+    let mut gaps = 0;
+    for c in query.chars() {
+        loop {
+            match chars.next() {
+                Some(n) if n == c => break,
+                Some(_) => gaps += 1,
+                None => return None,
+            }
+        }
+    }
+    Some(gaps)
+}
+
+fn special_form(name: &str) -> Option<String> {
+    let (call, prefix) = (format!("({})", name), format!("({} ", name));
+    sections(SPECIAL_FORMS)
+        .into_iter()
+        .find(|s| s.title.split('`').any(|code| code == call || code.starts_with(&prefix)))
+        .map(|s| format!("{}\n\n{}", color::bold(Color::Cyan, s.title.replace('`', "")), s.body))
+}
+
+// The paragraphs of the builtin sections that mention `name`, and the
+// examples that call it
+fn mentions(name: &str) -> (String, Option<String>) {
+    let quoted = [format!("`{}`", name), format!("`({} ", name), format!("`({})`", name)];
+    let calls = [format!("({} ", name), format!("({})", name)];
+    let mut paragraphs = Vec::new();
+    let mut examples = Vec::new();
+    for section in sections(BUILTINS) {
+        for block in blocks(&section.body) {
+            if block.starts_with("```") {
+                if calls.iter().any(|call| block.contains(&**call)) {
+                    examples.push(block.lines().filter(|line| !line.starts_with("```")).join("\n"));
+                }
+            } else if quoted.iter().any(|code| block.contains(&**code)) {
+                paragraphs.push(block);
+            }
+        }
+    }
+    let examples = if examples.is_empty() {
+        None
+    } else {
+        Some(format!("{}\n{}", color::bold(Color::Cyan, "Examples:"), examples.join("\n\n")))
+    };
+    (paragraphs.join("\n\n"), examples)
+}
+
+// The subsections of the README section called `chapter`
+fn sections(chapter: &str) -> Vec<Section> {
+    let heading = format!("### {}", chapter);
+    let mut sections = Vec::new();
+    let mut current: Option<Section> = None;
+    let mut fenced = false;
+    for line in MANUAL.lines().skip_while(|line| *line != heading).skip(1) {
+        if line.starts_with("```") {
+            fenced = !fenced;
+        }
+        if fenced {
+            // Code can start with #, like a tagged literal
+        } else if line.starts_with("#### ") {
+            sections.extend(current.take());
+            current = Some(Section { title: line[5..].to_owned(), body: String::new() });
+            continue;
+        } else if line.starts_with("## ") || line.starts_with("### ") {
+            break;
+        }
+        if let Some(ref mut section) = current {
+            section.body.push_str(line);
+            section.body.push('\n');
+        }
+    }
+    sections.extend(current);
+    for section in &mut sections {
+        section.body = section.body.trim().to_owned();
+    }
+    sections
+}
+
+// Paragraphs and fenced code blocks, which can have blank lines inside them
+fn blocks(body: &str) -> Vec<String> {
+    let mut blocks = Vec::new();
+    let mut block = Vec::new();
+    let mut fenced = false;
+    for line in body.lines() {
+        if line.starts_with("```") {
+            fenced = !fenced;
+        }
+        if line.trim().is_empty() && !fenced {
+            if !block.is_empty() {
+                blocks.push(block.join("\n"));
+                block.clear();
+            }
+        } else {
+            block.push(line);
+        }
+    }
+    if !block.is_empty() {
+        blocks.push(block.join("\n"));
+    }
+    blocks
+}
+
+// Strings as their contents, and anything else as it prints
+fn plain(expr: &Expr) -> String {
+    match *expr {
+        Expr::Str(ref s) => s.clone(),
+        ref other => other.to_string(),
+    }
+}
+
+fn key(name: &str) -> Key {
+    Key::Keyword(name.to_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ops;
+    use parser;
+
+    #[test]
+    fn manual() {
+        assert!(topics().contains("Strings") && topics().contains(SPECIAL_FORMS));
+        assert!(topic("strings").unwrap().contains("sb-append!"));
+        assert!(topic(SPECIAL_FORMS).unwrap().contains("let"));
+        assert_eq!(None, topic("nonsense"));
+
+        let env = ops::env();
+        let map = describe("map", &env).unwrap();
+        assert!(map.contains("builtin, arity 2"));
+        assert!(map.contains("`(map f coll)` calls `f` on each item"));
+        assert!(describe("let", &env).unwrap().starts_with("(let [bindings*] exprs*)"));
+        assert!(describe("nonsense", &env).is_err());
+    }
+
+    #[test]
+    fn user_functions() {
+        let env = ops::env();
+        let source = "(defn ^{:examples [\"(sq 3) => 9\"]} sq \"Squares x.\" [x] (* x x))";
+        Expr::eval_all(&parser::parse_program(source).unwrap(), env.clone()).unwrap();
+        let sq = describe("sq", &env).unwrap();
+        assert!(sq.contains("(sq [x])") && sq.contains("Squares x."));
+        assert!(sq.contains("Examples:\n  (sq 3) => 9"));
+        assert!(sq.contains("Defined at <input>:1:1"));
+    }
+
+    #[test]
+    fn searching() {
+        let env = ops::env();
+        let source = "(defn tally \"Counts the ballots.\" [votes] (count votes))";
+        Expr::eval_all(&parser::parse_program(source).unwrap(), env.clone()).unwrap();
+
+        let found = apropos("STR", &env);
+        assert_eq!(Some("str"), found.first().map(String::as_str));
+        assert!(found.contains(&"compare-str".to_owned()));
+        assert!(apropos("sbap", &env).contains(&"sb-append!".to_owned()));
+        assert_eq!(vec!["tally".to_owned()], apropos("ballots", &env));
+        assert!(apropos("lett", &env).contains(&"let".to_owned()));
+        assert!(apropos("xyzzyq", &env).is_empty());
+    }
+}
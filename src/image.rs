@@ -0,0 +1,270 @@
+//! Saving a session's definitions as source code, to be loaded again later.
+//!
+//! An image is a file of top-level definitions that recreate the values
+//! bound in the global environment: `defrecord`s first, then generic
+//! functions and their methods, then everything else. Functions are saved as
+//! the source of their clauses. Builtins aren't saved, since every
+//! environment already has them, but closures made at runtime, like the
+//! result of `partial`, can't be saved at all.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io::prelude::*;
+use std::sync::Arc;
+use itertools::Itertools;
+
+use dynamic;
+use encoding;
+use env::Env;
+use error::*;
+use types::{Expr, Function, Key, Macro, Map};
+
+// The REPL's result history, and values every environment starts with
+const PREDEFINED: &[&str] = &["*1", "*2", "*3", "*e", "*telescope-version*"];
+
+/// Write the definitions in `env`'s global scope to `path`, returning how
+/// many were saved.
+pub fn save(env: &Env, path: &str) -> Result<usize> {
+    let (source, count) = image(env)?;
+    fs::File::create(path)?.write_all(source.as_bytes())?;
+    Ok(count)
+}
+
+/// The source of an image of `env`'s global scope, and how many definitions
+/// it holds.
+pub fn image(env: &Env) -> Result<(String, usize)> {
+    let root = env.root();
+    let mut bindings = root.bindings();
+    bindings.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut records = Vec::new();
+    let mut seen_records = HashSet::new();
+    let mut multis = Vec::new();
+    let mut defs = Vec::new();
+
+    for (name, value) in bindings {
+        if PREDEFINED.contains(&name.as_str()) {
+            continue;
+        }
+        let saved = match value.func() {
+            Some(ref func) => match **func {
+                Function::Builtin { name: ref builtin, .. } if *builtin == name => continue,
+                Function::Record { ref rtype, .. } => {
+                    // The constructor, predicate, and accessors come from one defrecord
+                    if seen_records.insert(&**rtype as *const _ as usize) {
+                        let fields = rtype.fields.iter().join(" ");
+                        records.push(format!("(defrecord {} [{}])", rtype.name, fields));
+                    }
+                    continue;
+                }
+                Function::Multi { name: ref multi_name, ref dispatch, .. } if *multi_name == name => {
+                    multis.push(format!("(defmulti {} {})", name, function(dispatch, &root)?));
+                    for (value, method) in func.methods() {
+                        let clauses = clauses(&method)
+                            .ok_or_else(|| format!("cannot save a method of {}", name))?;
+                        multis.push(format!("(defmethod {} {} {})", name, source(&value, &root)?, clauses));
+                    }
+                    continue;
+                }
+                _ => source(&value, &root),
+            },
+            None => source(&value, &root),
+        };
+
+        let saved = saved.map_err(|err| format!("cannot save {}: {}", name, err))?;
+        let def = if dynamic::is_dynamic(&name) { "def-dynamic" } else { "def" };
+        defs.push(format!("({} {} {})", def, name, saved));
+    }
+
+    let count = records.len() + multis.len() + defs.len();
+    let forms = records.into_iter().chain(multis).chain(defs).collect::<Vec<_>>();
+    Ok((forms.iter().map(|form| format!("{}\n", form)).collect(), count))
+}
+
+/// An expression that evaluates to `value`.
+fn source(value: &Expr, root: &Env) -> Result<String> {
+    let source = match *value {
+        Expr::Sym(ref sym) => format!("'{}", sym.0),
+        Expr::List(ref list) => match literal(value) {
+            Some(literal) => format!("'{}", literal),
+            None => format!("(list {})", sources(&list.0, root)?),
+        },
+        Expr::Vector(ref vec) => match literal(value) {
+            // Vector literals aren't evaluated, so only plain data can be one
+            Some(literal) => literal,
+            None => format!("(values {})", sources(&vec.0, root)?),
+        },
+        Expr::Record(ref record) => {
+            format!("({} {})", record.rtype.name, sources(&record.values, root)?)
+        }
+        Expr::Func(ref func) => function(func, root)?,
+        Expr::Macro(ref mac) => macro_source(mac)?,
+        Expr::Handle(ref handle) => bail!("a {} can't be saved", handle.type_name()),
+        _ => literal(value).ok_or_else(|| format!("{} can't be saved", value))?,
+    };
+
+    // Keep metadata, like docstrings
+    match value.meta().map(|meta| map_literal(meta)) {
+        Some(Some(meta)) => Ok(format!("(with-meta {} {})", source, meta)),
+        _ => Ok(source),
+    }
+}
+
+fn sources(values: &[Expr], root: &Env) -> Result<String> {
+    Ok(values.iter().map(|value| source(value, root)).collect::<Result<Vec<_>>>()?.join(" "))
+}
+
+fn function(func: &Arc<Function>, root: &Env) -> Result<String> {
+    match **func {
+        Function::User { ref name, ref env, .. } => {
+            ensure!(env.is_same(root), "it closes over local bindings");
+            let clauses = clauses(func).ok_or("its body can't be saved")?;
+            match *name {
+                Some(ref name) => Ok(format!("(fn {} {})", name, clauses)),
+                None => Ok(format!("(fn {})", clauses)),
+            }
+        }
+        Function::Builtin { ref name, .. } => match root.lookup(name) {
+            Some(Expr::Func(ref builtin)) if Arc::ptr_eq(builtin, func) => Ok(name.clone()),
+            _ => bail!("it's a closure made at runtime"),
+        },
+        // Defined separately, so refer to them by name
+        _ => Ok(func.name().to_owned()),
+    }
+}
+
+/// The clauses of a user function, as `([params*] body*)+`.
+fn clauses(func: &Function) -> Option<String> {
+    match *func {
+        Function::User { ref clauses, .. } => {
+            let clauses = clauses.iter()
+                .map(|clause| {
+                    let body = clause.conditions().iter().chain(&clause.body)
+                        .map(literal)
+                        .collect::<Option<Vec<_>>>()?;
+                    Some(format!("({} {})", clause.signature(), body.join(" ")))
+                })
+                .collect::<Option<Vec<_>>>()?;
+            Some(clauses.join(" "))
+        }
+        _ => None,
+    }
+}
+
+fn macro_source(mac: &Macro) -> Result<String> {
+    let body = mac.body.iter().map(literal).collect::<Option<Vec<_>>>()
+        .ok_or("its body can't be saved")?;
+    let params = mac.params.iter().join(" ");
+    match mac.name {
+        Some(ref name) => Ok(format!("(macro {} [{}] {})", name, params, body.join(" "))),
+        None => Ok(format!("(macro [{}] {})", params, body.join(" "))),
+    }
+}
+
+/// The literal syntax for plain data, which reads back as the same value.
+fn literal(value: &Expr) -> Option<String> {
+    match *value {
+        Expr::Nil => Some("()".to_owned()),
+        Expr::Bool(b) => Some(if b { "#t" } else { "#f" }.to_owned()),
+        Expr::Int(int) => Some(int.to_string()),
+        // Debug formatting keeps the decimal point on whole numbers
+        Expr::Flt(flt) if flt.is_finite() => Some(format!("{:?}", flt)),
+        Expr::Str(ref string) => Some(encoding::escape_str(string)),
+        Expr::Bytes(ref bytes) => Some(encoding::escape_bytes(bytes)),
+        Expr::Sym(ref sym) => Some(sym.0.clone()),
+        Expr::Keyword(ref name) => Some(format!(":{}", name)),
+        Expr::List(ref list) => Some(format!("({})", literals(&list.0)?)),
+        Expr::Vector(ref vec) => Some(format!("[{}]", literals(&vec.0)?)),
+        Expr::Map(ref map) => map_literal(map),
+        _ => None,
+    }
+}
+
+fn literals(values: &[Expr]) -> Option<String> {
+    Some(values.iter().map(literal).collect::<Option<Vec<_>>>()?.join(" "))
+}
+
+fn map_literal(map: &Map) -> Option<String> {
+    let mut pairs = map.iter()
+        .map(|(key, value)| Some(format!("{} {}", key_literal(key), literal(value)?)))
+        .collect::<Option<Vec<_>>>()?;
+    pairs.sort();
+    Some(format!("{{{}}}", pairs.join(" ")))
+}
+
+fn key_literal(key: &Key) -> String {
+    match *key {
+        Key::Nil => "()".to_owned(),
+        Key::Bool(b) => if b { "#t" } else { "#f" }.to_owned(),
+        Key::Int(int) => int.to_string(),
+        Key::Str(ref string) => encoding::escape_str(string),
+        Key::Keyword(ref name) => format!(":{}", name),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ops;
+    use parser;
+
+    fn eval_in(env: &Env, source: &str) -> Result<Expr> {
+        Expr::eval_all(&parser::parse_program(source)?, env.clone())
+    }
+
+    fn restore(env: &Env) -> Env {
+        let (image, _) = image(env).unwrap();
+        let restored = ops::env();
+        eval_in(&restored, &image).unwrap();
+        restored
+    }
+
+    #[test]
+    fn round_trip() {
+        let env = ops::env();
+        let session = r#"
+            (def n 1.0)
+            (def s "say \"hi\"\n")
+            (def data [1 :two 'three {:four (4)}])
+            (defn square "Squares x." [x] (* x x))
+            (def twice (fn twice ([x] (twice x 2)) ([x k] (* x k))))
+            (defrecord Point [x y])
+            (def p (Point 1 (list 2 square)))
+            (defmulti area (fn [shape] (first shape)))
+            (defmethod area 'square [s] (square (first (rest s))))"#;
+        eval_in(&env, session).unwrap();
+
+        let restored = restore(&env);
+        for check in &["n", "s", "data", "(square 3)", "(twice 4)", "(Point-x p)",
+                       "(get (meta square) :doc)", "(area '(square 5))"] {
+            assert_eq!(
+                eval_in(&env, check).unwrap(),
+                eval_in(&restored, check).unwrap(),
+                "{} differs after restoring",
+                check
+            );
+        }
+        assert_eq!(Expr::from(4), eval_in(&restored, "((first (rest (Point-y p))) 2)").unwrap());
+    }
+
+    #[test]
+    fn unsavable_values() {
+        let env = ops::env();
+        eval_in(&env, "(def adder (let [k 1] (fn [x] (+ x k))))").unwrap();
+        let err = image(&env).unwrap_err();
+        assert!(err.to_string().contains("adder"));
+
+        let env = ops::env();
+        eval_in(&env, "(def c (chan))").unwrap();
+        assert!(image(&env).is_err());
+
+        let env = ops::env();
+        eval_in(&env, "(def inc (partial + 1))").unwrap();
+        assert!(image(&env).unwrap_err().to_string().contains("inc"));
+
+        // Builtins under another name are saved as a reference to the builtin
+        let env = ops::env();
+        eval_in(&env, "(def plus +)").unwrap();
+        assert_eq!("(def plus +)\n", image(&env).unwrap().0);
+    }
+}
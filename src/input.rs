@@ -1,72 +1,249 @@
 use std::fs;
 use std::io;
 use std::io::prelude::*;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use {lexer, parser, types};
+use {color, command, fmt, interrupt, lint, location, ops, parser, types};
+use color::Color;
+use command::{Command, Settings};
 use types::Expr;
 use error::*;
 use env::Env;
-use token::Token;
 use buffer::Readline;
-use stream::{StringStream, TokenStream};
+use notebook::Notebook;
+use reader::Reader;
+
+/// The REPL's greeting: the version, and how the session was set up.
+pub fn banner(image: Option<&str>) -> String {
+    let build = if cfg!(debug_assertions) { "debug" } else { "release" };
+    let mut lines = vec![format!("telescope v{} ({} build)", env!("CARGO_PKG_VERSION"), build)];
+    if let Some(image) = image {
+        lines.push(color::dim(format!("image: {}", image)));
+    }
+    lines.push(color::dim(format!("color: {}", if color::enabled() { "on" } else { "off" })));
+    lines.push(color::dim("Type :help for a list of commands."));
+    lines.join("\n")
+}
+
+/// Run the program on stdin.
+pub fn stdin(env: Env) -> Result<()> {
+    let mut source = String::new();
+    io::stdin().read_to_string(&mut source)?;
+    for form in parser::parse_program(&source)? {
+        location::reset();
+        form.eval(env.clone())?;
+    }
+    Ok(())
+}
 
 pub fn file(path: &str, env: Env) -> Result<()> {
-    let file = fs::File::open(path)?;
-    let mut file_buf = io::BufReader::new(file);
+    for form in Reader::from_read(fs::File::open(path)?).with_file(path) {
+        location::reset();
+        form?.eval(env.clone())?;
+    }
+    Ok(())
+}
+
+// How often --watch checks whether the file has changed, in milliseconds
+const WATCH_INTERVAL: u64 = 250;
+
+/// Run the program in `path`, then run it again each time it changes, until
+/// the process is killed. Each run is in the environment `start` returns, so
+/// it decides whether definitions carry over from one run to the next. As a
+/// `notebook`, only the forms that changed run again, in the first
+/// environment.
+pub fn watch<F>(path: &str, notebook: bool, start: F) -> Result<()>
+where
+    F: Fn() -> Result<Env>,
+{
+    let mut notebook = if notebook { Some(Notebook::new(start()?)) } else { None };
+    let mut last_modified = None;
     loop {
-        let exprs = read(&mut file_buf)?;
-        eval(&exprs, env.clone())?;
+        // Editors often replace a file when saving it, so it can be missing
+        // for a moment
+        let modified = fs::metadata(path).and_then(|meta| meta.modified()).ok();
+        if modified.is_some() && modified != last_modified {
+            last_modified = modified;
+            println!("{}", color::dim(format!("running {}", path)));
+            let result = match notebook {
+                Some(ref mut notebook) => notebook.run_file(path).map(|run| {
+                    println!("{}", color::dim(format!("ran {} changed forms", run.len())))
+                }),
+                None => start().and_then(|env| file(path, env)),
+            };
+            if let Err(err) = result {
+                match *err.kind() {
+                    ErrorKind::Exit(code) => {
+                        println!("{}", color::dim(format!("exited with {}", code)))
+                    }
+                    _ => print_error(&err),
+                }
+            }
+            println!("{}", color::dim("watching for changes..."));
+        }
+        thread::sleep(Duration::from_millis(WATCH_INTERVAL));
+    }
+}
+
+/// Reformat each file in place, or with `check` only list the files that
+/// aren't formatted. Returns whether every file was already formatted.
+pub fn format_files(paths: &[&str], check: bool) -> Result<bool> {
+    let mut formatted = true;
+    for path in paths {
+        let mut source = String::new();
+        fs::File::open(path)?.read_to_string(&mut source)?;
+        let result = fmt::format(&source).map_err(|err| format!("{}: {}", path, err))?;
+        if result != source {
+            formatted = false;
+            if check {
+                println!("{}", path);
+            } else {
+                fs::File::create(path)?.write_all(result.as_bytes())?;
+            }
+        }
+    }
+    Ok(formatted || !check)
+}
+
+/// Print the warnings `check` (lint or the type checker) finds in each file,
+/// as text or as one JSON array. Returns whether there were no warnings.
+pub fn lint_files<F>(paths: &[&str], json: bool, check: F) -> Result<bool>
+where
+    F: Fn(&str, &str) -> Result<Vec<lint::Warning>>,
+{
+    let mut warnings = Vec::new();
+    for path in paths {
+        let mut source = String::new();
+        fs::File::open(path)?.read_to_string(&mut source)?;
+        warnings.extend(check(&source, path).map_err(|err| format!("{}: {}", path, err))?);
+    }
+    report(&warnings, json);
+    Ok(warnings.is_empty())
+}
+
+/// Print warnings one per line, or as a JSON array.
+pub fn report(warnings: &[lint::Warning], json: bool) {
+    if json {
+        let warnings = warnings.iter().map(lint::Warning::to_json).collect::<Vec<_>>();
+        println!("{}", ::serde_json::Value::Array(warnings));
+    } else {
+        for warning in &warnings {
+            println!("{}", warning);
+        }
     }
 }
 
 pub fn repl(env: Env) -> Result<i32> {
-    let mut rl = Readline::new("> ");
+    let mut env = env;
+    let mut settings = Settings::default();
+    let mut reader = Reader::new(Readline::new("> ")).with_file("<repl>");
+    init_history(&env);
+    // Without a handler, Ctrl-C still kills the REPL as before
+    if let Err(err) = interrupt::install() {
+        print_error(&err);
+    }
     loop {
-        let exprs = match read(&mut rl) {
-            Ok(x) => x,
+        // Meta-commands are handled before the line reaches the parser
+        if !reader.is_pending() {
+            if let Some(cmd) = command::parse(reader.get_mut().peek_line()?) {
+                reader.get_mut().skip_line();
+                location::reset();
+                interrupt::clear();
+                match run_command(cmd, &mut env, &mut settings) {
+                    Ok(Some(code)) => return Ok(code),
+                    Ok(None) => (),
+                    Err(err) => print_error(&err),
+                }
+                continue;
+            }
+        }
+
+        let form = match reader.read_form() {
+            Ok(Some(form)) => form,
+            Ok(None) => return Ok(0),
             Err(err) => {
-                println!("{}", err);
+                location::reset();
+                if let ErrorKind::Lex(ref lex) = *err.kind() {
+                    println!("{}", color::underline_span(reader.get_ref().last_line(), lex.position));
+                }
+                print_error(&err);
                 continue;
             },
         };
-        match eval(&exprs, env.clone()) {
-            Ok(val) => print(&val),
+        location::reset();
+        interrupt::clear();
+        let start = Instant::now();
+        let result = form.eval(env.clone());
+        if settings.timing {
+            println!("{}", color::dim(format!("; {}", format_duration(start.elapsed()))));
+        }
+
+        match result {
+            Ok(val) => {
+                print(&val);
+                record_result(&env, val);
+            }
             Err(err) => {
                 match *err.kind() {
                     ErrorKind::Eof => return Ok(0),
                     ErrorKind::Exit(code) => return Ok(code),
-                    _ => println!("{}", err),
+                    _ => {
+                        print_error(&err);
+                        env.define("*e", Expr::from(err.to_string()));
+                    }
                 }
             }
         };
     }
 }
 
-fn read<B: BufRead>(reader: &mut B) -> Result<Vec<Expr>> {
-    let mut token_buf: Vec<Token> = Vec::with_capacity(128);
-    let mut expr_buf: Vec<Expr> = Vec::with_capacity(16);
-    let mut lines = reader.lines();
-
-    loop {
-        let line = match lines.next() {
-            Some(Ok(l)) => l,
-            Some(Err(err)) => return Err(err.into()),
-            None => return Err(ErrorKind::Eof.into()),
-        };
-
-        let (tokens, _) = lexer::lex(StringStream::new(&line))?;
-        token_buf.extend(tokens);
-
-        let (exprs, unparsed) = parser::parse(TokenStream::new(token_buf.drain(..)))?;
+/// Bind the result history symbols: the last three results *1, *2, *3, and
+/// the last error message *e.
+fn init_history(env: &Env) {
+    for name in &["*1", "*2", "*3", "*e"] {
+        env.define(name, Expr::Nil);
+    }
+}
 
-        token_buf = unparsed.unwrap();
-        expr_buf.extend(exprs);
+fn record_result(env: &Env, value: Expr) {
+    let prev1 = env.lookup("*1").unwrap_or(Expr::Nil);
+    let prev2 = env.lookup("*2").unwrap_or(Expr::Nil);
+    env.define("*3", prev2);
+    env.define("*2", prev1);
+    env.define("*1", value);
+}
 
-        if token_buf.is_empty() {
-            break;
+/// Run a meta-command, returning an exit code if the REPL should quit.
+fn run_command(cmd: Command, env: &mut Env, settings: &mut Settings) -> Result<Option<i32>> {
+    match cmd {
+        Command::Help => println!("{}", command::HELP),
+        Command::Load(path) => {
+            ensure!(!path.is_empty(), ":load expected a file");
+            file(&path, env.clone())?;
+        }
+        Command::Type(source) => {
+            let exprs = parser::parse_program(&source)?;
+            println!("{}", eval(&exprs, env.clone())?.type_name());
         }
+        Command::Env => {
+            let mut names = env.names();
+            names.sort();
+            names.dedup();
+            for name in names {
+                if let Some(value) = env.lookup(&name) {
+                    println!("{} : {}", name, value.type_name());
+                }
+            }
+        }
+        Command::Reset => {
+            *env = ops::env();
+            init_history(env);
+        }
+        Command::Set(option, value) => settings.set(&option, &value)?,
+        Command::Quit => return Ok(Some(0)),
     }
-    Ok(expr_buf)
+    Ok(None)
 }
 
 fn eval(exprs: &[Expr], env: Env) -> Result<Expr> {
@@ -82,6 +259,26 @@ fn eval(exprs: &[Expr], env: Env) -> Result<Expr> {
 
 fn print(value: &Expr) {
     if value != &types::Expr::Nil {
-        println!("{}", value);
+        println!("{}", color::highlight(value));
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let millis = duration.as_secs() as f64 * 1e3 + duration.subsec_nanos() as f64 / 1e6;
+    if millis < 1e3 {
+        format!("{:.3} ms", millis)
+    } else {
+        format!("{:.3} s", millis / 1e3)
+    }
+}
+
+/// Print an error, along with where it happened if that's known.
+pub fn print_error(err: &Error) {
+    println!("{}", color::paint(Color::Red, err));
+    if let Some(location) = location::current() {
+        println!("{}", color::dim(format!("  at {}", location)));
+        if let Some(excerpt) = location.excerpt() {
+            println!("{}", excerpt);
+        }
     }
 }
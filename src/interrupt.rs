@@ -0,0 +1,92 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering, ATOMIC_BOOL_INIT, ATOMIC_USIZE_INIT};
+#[cfg(feature = "cli")]
+use ctrlc;
+
+use error::*;
+
+static INTERRUPTED: AtomicBool = ATOMIC_BOOL_INIT;
+// One more than the calls left before evaluation stops, or 0 for no limit
+static FUEL: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Catch Ctrl-C, so that it interrupts the running evaluation instead of
+/// killing the process.
+#[cfg(feature = "cli")]
+pub fn install() -> Result<()> {
+    ctrlc::set_handler(|| INTERRUPTED.store(true, Ordering::SeqCst))
+        .map_err(|err| format!("failed to install Ctrl-C handler: {}", err).into())
+}
+
+/// Forget any earlier interrupt, before starting a new evaluation.
+pub fn clear() {
+    INTERRUPTED.store(false, Ordering::SeqCst);
+}
+
+/// Stop evaluation, in every thread, after `steps` more function calls, or
+/// never for None. For running code that might not halt, like a fuzzer's.
+pub fn set_fuel(steps: Option<usize>) {
+    FUEL.store(steps.map_or(0, |steps| steps.saturating_add(1)), Ordering::SeqCst);
+}
+
+/// Fail with an interrupted error if Ctrl-C was pressed, or an out of fuel
+/// error if the fuel ran out. Every thread checks the same flag, so running
+/// futures are interrupted too.
+pub fn check() -> Result<()> {
+    check_with(&INTERRUPTED, &FUEL)
+}
+
+// The flag and fuel are arguments so tests can use their own, instead of
+// interrupting every other test running at the same time
+fn check_with(interrupted: &AtomicBool, fuel: &AtomicUsize) -> Result<()> {
+    if interrupted.load(Ordering::SeqCst) {
+        bail!(ErrorKind::Interrupted);
+    }
+    // Threads race to spend the fuel, so take it a step at a time, and never
+    // down to 0, which would lift the limit
+    let mut left = fuel.load(Ordering::SeqCst);
+    loop {
+        match left {
+            0 => return Ok(()),
+            1 => bail!(ErrorKind::OutOfFuel),
+            _ => match fuel.compare_exchange(left, left - 1, Ordering::SeqCst, Ordering::SeqCst) {
+                Ok(_) => return Ok(()),
+                Err(actual) => left = actual,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn flag() {
+        let interrupted = AtomicBool::new(false);
+        let fuel = AtomicUsize::new(0);
+        assert!(check_with(&interrupted, &fuel).is_ok());
+        interrupted.store(true, Ordering::SeqCst);
+        match check_with(&interrupted, &fuel) {
+            Err(Error(ErrorKind::Interrupted, _)) => (),
+            other => panic!("expected an interrupt, got {:?}", other),
+        }
+        interrupted.store(false, Ordering::SeqCst);
+        assert!(check_with(&interrupted, &fuel).is_ok());
+    }
+
+    #[test]
+    fn fuel() {
+        let interrupted = AtomicBool::new(false);
+        // Two steps, stored as one more
+        let fuel = AtomicUsize::new(3);
+        assert!(check_with(&interrupted, &fuel).is_ok());
+        assert!(check_with(&interrupted, &fuel).is_ok());
+        for _ in 0..2 {
+            match check_with(&interrupted, &fuel) {
+                Err(Error(ErrorKind::OutOfFuel, _)) => (),
+                other => panic!("expected to run out of fuel, got {:?}", other),
+            }
+        }
+        fuel.store(0, Ordering::SeqCst);
+        assert!(check_with(&interrupted, &fuel).is_ok());
+    }
+}
@@ -0,0 +1,61 @@
+//! Values as JSON, for handing them to other languages. Lists and vectors
+//! become arrays, and maps and records objects. Keywords and symbols become
+//! their names, and anything without a JSON counterpart, like a function, is
+//! written out as it would print.
+
+use serde_json::{Map as Object, Number, Value};
+
+use types::{Expr, Key};
+
+pub fn to_json(expr: &Expr) -> Value {
+    match *expr {
+        Expr::Nil => Value::Null,
+        Expr::Bool(b) => Value::Bool(b),
+        Expr::Int(i) => Value::from(i),
+        // JSON has no NaN or infinity
+        Expr::Flt(f) => Number::from_f64(f).map_or(Value::Null, Value::Number),
+        Expr::Str(ref s) => Value::String(s.clone()),
+        Expr::Bytes(ref bytes) => Value::Array(bytes.iter().map(|&b| Value::from(b)).collect()),
+        Expr::Sym(ref sym) => Value::String(sym.0.clone()),
+        Expr::Keyword(ref name) => Value::String(name.clone()),
+        Expr::List(ref list) => Value::Array(list.0.iter().map(to_json).collect()),
+        Expr::Vector(ref vec) => Value::Array(vec.0.iter().map(to_json).collect()),
+        Expr::Map(ref map) => {
+            let fields = map.iter().map(|(key, value)| (key_name(key), to_json(value)));
+            Value::Object(fields.collect())
+        }
+        Expr::Record(ref record) => {
+            let mut fields = Object::new();
+            for (field, value) in record.rtype.fields.iter().zip(&record.values) {
+                fields.insert(field.0.clone(), to_json(value));
+            }
+            Value::Object(fields)
+        }
+        ref other => Value::String(other.to_string()),
+    }
+}
+
+// Object keys have to be strings
+fn key_name(key: &Key) -> String {
+    match *key {
+        Key::Str(ref s) | Key::Keyword(ref s) => s.clone(),
+        ref other => other.to_expr().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use parser;
+
+    fn convert(source: &str) -> Value {
+        to_json(&parser::parse_expr(source).unwrap())
+    }
+
+    #[test]
+    fn conversion() {
+        assert_eq!(json!([1, 2.5, "a", true, null]), convert(r#"[1 2.5 "a" #t nil]"#));
+        assert_eq!(json!({"name": "ada", "1": ["x"]}), convert(r#"{:name "ada" 1 [x]}"#));
+        assert_eq!(json!("b"), convert(":b"));
+    }
+}
@@ -1,26 +1,56 @@
 use combine::{Parser, Stream, ParseError, ParseResult};
-use combine::{between, many, many1, one_of, optional, parser, satisfy, satisfy_map, try};
-use combine::char::{digit, char, spaces};
+use combine::{between, eof, many, many1, one_of, optional, parser, satisfy, satisfy_map, skip_many,
+              skip_many1, try};
+use combine::char::{digit, char, hex_digit, space, string};
 
-use token::{Literal, Token};
+use std::f64;
+
+use stream::position;
+use token::Token;
+use types::Expr;
 use unicode_xid::UnicodeXID;
 
 pub fn lex<I>(input: I) -> Result<(Vec<Token>, I), ParseError<I>>
 where
     I: Stream<Item = char>,
 {
-    between(spaces(),
-            spaces(),
-            many(spaces().with(parser(token))))
+    lex_positions(input)
+        .map(|(tokens, rest)| (tokens.into_iter().map(|(token, _)| token).collect(), rest))
+}
+
+/// Lex, pairing each token with the stream position it starts at.
+pub fn lex_positions<I>(input: I) -> Result<(Vec<(Token, I::Position)>, I), ParseError<I>>
+where
+    I: Stream<Item = char>,
+{
+    let positioned = (parser(position), parser(token)).map(|(position, token)| (token, position));
+    between(parser(whitespace),
+            parser(whitespace),
+            many(try(parser(whitespace).with(positioned))))
+        .skip(eof())
         .parse(input)
 }
 
+// Whitespace and ; line comments
+fn whitespace<I>(input: I) -> ParseResult<(), I>
+where
+    I: Stream<Item = char>,
+{
+    let comment = (char(';'), skip_many(satisfy(|c| c != '\n'))).map(|_| ());
+    skip_many(skip_many1(space()).or(comment)).parse_stream(input)
+}
+
 fn token<I>(input: I) -> ParseResult<Token, I>
 where
     I: Stream<Item = char>,
 {
-    parser(symbol)
-        .or(parser(literal))
+    // Literals go first so signed numbers like -5 aren't read as symbols; a
+    // lone sign backtracks into a symbol. Tags go before them, so #true isn't
+    // read as #t followed by rue.
+    try(parser(tag))
+        .or(try(parser(literal)))
+        .or(parser(symbol))
+        .or(parser(keyword))
         .or(parser(punctuation))
         .parse_stream(input)
 }
@@ -29,24 +59,29 @@ fn literal<I>(input: I) -> ParseResult<Token, I>
 where
     I: Stream<Item = char>,
 {
-    let sign = optional(char('-'));
-    let digits = many1::<String, _>(digit());
-    let integer =
-    (
-        sign.clone(),
-        digits.clone().and_then(|s| s.parse::<i64>())
-    )
-        .map(|(sign, num)| if sign.is_some() { -num } else { num })
-        .map(Literal::from);
-
-    let float = sign.clone()
-        .and(
-            (digits.clone(), char('.'), digits.clone())
-                .map(|(prefix, _, suffix)| format!("{}.{}", prefix, suffix))
-                .and_then(|num| num.parse::<f64>()),
-        )
-        .map(|(sign, num)| if sign.is_some() { -num } else { num })
-        .map(Literal::from);
+    let sign = optional(one_of("+-".chars()))
+        .map(|sign| if sign == Some('-') { "-" } else { "" });
+
+    // Digits may be separated by underscores, e.g. 1_000_000
+    let digits = (digit(), many::<String, _>(digit().or(char('_'))))
+        .map(|(first, rest)| format!("{}{}", first, rest.replace('_', "")));
+    let fraction = (char('.'), digits.clone()).map(|(_, digits)| format!(".{}", digits));
+    let exponent = (one_of("eE".chars()), optional(one_of("+-".chars())), digits.clone())
+        .map(|(_, sign, digits)| format!("e{}{}", sign.unwrap_or('+'), digits));
+
+    let integer = (sign.clone(), digits.clone())
+        .and_then(|(sign, digits)| format!("{}{}", sign, digits).parse::<i64>())
+        .map(Expr::from);
+
+    // 1.5, 1.5e10, 1e10, and .5
+    let mantissa = try((digits.clone(), fraction.clone(), optional(try(exponent.clone()))))
+        .map(|(int, frac, exp)| format!("{}{}{}", int, frac, exp.unwrap_or_default()))
+        .or(try((digits.clone(), exponent.clone())).map(|(int, exp)| format!("{}{}", int, exp)))
+        .or((fraction, optional(try(exponent)))
+            .map(|(frac, exp)| format!("0{}{}", frac, exp.unwrap_or_default())));
+    let float = (sign, mantissa)
+        .and_then(|(sign, num)| format!("{}{}", sign, num).parse::<f64>())
+        .map(Expr::from);
 
     let num = try(float).or(try(integer));
 
@@ -56,7 +91,7 @@ where
             'f' => Some(false),
             _ => None,
         }))
-        .map(Literal::from);
+        .map(Expr::from);
 
     let escaped = char('\\').with(satisfy_map(|c| match c {
         '\"' => Some('\"'),
@@ -65,11 +100,14 @@ where
         'r' => Some('\r'),
         't' => Some('\t'),
         _ => None,
-    }));
+    }).or(char('x').with((hex_digit(), hex_digit())).map(|(high, low)| {
+        // \xNN, the character with code point NN
+        (high.to_digit(16).unwrap() * 16 + low.to_digit(16).unwrap()) as u8 as char
+    })));
 
     let non_quote = try(escaped).or(satisfy(|c| c != '"'));
 
-    let string = between(char('"'), char('"'), many::<String, _>(non_quote)).map(Literal::from);
+    let string = between(char('"'), char('"'), many::<String, _>(non_quote)).map(Expr::from);
 
     boolean
         .or(num)
@@ -82,9 +120,11 @@ fn symbol<I>(input: I) -> ParseResult<Token, I>
 where
     I: Stream<Item = char>,
 {
-    let punctuation = one_of("_+-*/=<>!".chars());
-    let start = satisfy(UnicodeXID::is_xid_start).or(punctuation.clone());
-    let body = satisfy(UnicodeXID::is_xid_continue).or(punctuation.clone());
+    let punctuation = one_of("_+-*/=<>!%".chars());
+    // Other non-ASCII characters such as emoji and joiners are allowed too
+    let other = satisfy(|c: char| !c.is_ascii() && !c.is_whitespace() && !c.is_control());
+    let start = satisfy(UnicodeXID::is_xid_start).or(punctuation.clone()).or(other.clone());
+    let body = satisfy(UnicodeXID::is_xid_continue).or(punctuation.clone()).or(other);
     let rest = many::<String, _>(body);
     start
         .and(rest)
@@ -96,6 +136,39 @@ where
         .parse_stream(input)
 }
 
+// #name, the booleans #t and #f, or the floats ##Inf, ##-Inf, and ##NaN
+fn tag<I>(input: I) -> ParseResult<Token, I>
+where
+    I: Stream<Item = char>,
+{
+    let float = char('#')
+        .with(try(string("Inf")).map(|_| f64::INFINITY)
+            .or(try(string("-Inf")).map(|_| f64::NEG_INFINITY))
+            .or(string("NaN").map(|_| f64::NAN)))
+        .map(Token::from);
+    let name = parser(symbol).map(|sym| match sym {
+        Token::Symbol(ref name) if name == "t" => Token::from(true),
+        Token::Symbol(ref name) if name == "f" => Token::from(false),
+        Token::Symbol(name) => Token::Tag(name),
+        _ => unreachable!(),
+    });
+    char('#').with(float.or(name)).parse_stream(input)
+}
+
+// :name
+fn keyword<I>(input: I) -> ParseResult<Token, I>
+where
+    I: Stream<Item = char>,
+{
+    char(':')
+        .with(parser(symbol))
+        .map(|sym| match sym {
+            Token::Symbol(name) => Token::Keyword(name),
+            _ => unreachable!(),
+        })
+        .parse_stream(input)
+}
+
 fn punctuation<I>(input: I) -> ParseResult<Token, I>
 where
     I: Stream<Item = char>,
@@ -105,7 +178,10 @@ where
         ')' => Some(Token::RParen),
         '[' => Some(Token::LBracket),
         ']' => Some(Token::RBracket),
+        '{' => Some(Token::LBrace),
+        '}' => Some(Token::RBrace),
         '\'' => Some(Token::Quote),
+        '^' => Some(Token::Caret),
         _ => None,
     }).parse_stream(input)
 }
@@ -133,7 +209,7 @@ mod test {
 
         // Float case
         match parser(literal).parse("0.0") {
-            Ok((Token::Literal(Literal::Flt(flt)), _)) => assert!(flt.approx_eq_ulps(&0.0f64, 4)),
+            Ok((Token::Literal(Expr::Flt(flt)), _)) => assert!(flt.approx_eq_ulps(&0.0f64, 4)),
             Ok(x) => assert!(false, format!("0.0 parsed as {}", x.0)),
             Err(e) => assert!(false, format!("0.0 parsed as {}", e)),
         };
@@ -185,6 +261,32 @@ mod test {
             Ok((Token::RBracket, "")),
             parser(punctuation).parse("]")
         );
+
+        assert_eq!(
+            Ok((Token::LBrace, "")),
+            parser(punctuation).parse("{")
+        );
+
+        assert_eq!(
+            Ok((Token::RBrace, "")),
+            parser(punctuation).parse("}")
+        );
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(Ok((vec![], "")), lex("; nothing here"));
+        assert_eq!(
+            Ok((vec![Token::from(1), Token::from(2)], "")),
+            lex("1 ; one\n2 ; two")
+        );
+    }
+
+    #[test]
+    fn trailing_input() {
+        // Anything left over that isn't a token is an error, not dropped
+        assert!(lex("(a) \"unterminated").is_err());
+        assert_eq!(Ok((vec![Token::LParen, Token::RParen], "")), lex("() ; done\n"));
     }
 
     #[test]
@@ -220,7 +322,7 @@ mod test {
                 string.push_str(".0");
             }
 
-            if let Ok((Token::Literal(Literal::Flt(y)), _)) = parser(literal).parse(&*string) {
+            if let Ok((Token::Literal(Expr::Flt(y)), _)) = parser(literal).parse(&*string) {
                 x.approx_eq_ulps(&y, 4)
             } else {
                 false
@@ -237,4 +339,69 @@ mod test {
             Ok((Token::from(x.clone()), "")) == parser(literal).parse(&*string)
         }
     }
+
+    #[test]
+    fn signed_numbers() {
+        assert_eq!(Ok((vec![Token::from(-5)], "")), lex("-5"));
+        assert_eq!(Ok((vec![Token::from(3)], "")), lex("+3"));
+        assert_eq!(
+            Ok((vec![Token::Symbol("-".to_owned()), Token::from(5)], "")),
+            lex("- 5")
+        );
+        assert_eq!(Ok((vec![Token::Symbol("->".to_owned())], "")), lex("->"));
+        assert_eq!(Ok((vec![Token::Symbol("+".to_owned())], "")), lex("+"));
+    }
+
+    #[test]
+    fn scientific_notation() {
+        assert_eq!(Ok((Token::from(1e10), "")), parser(literal).parse("1e10"));
+        assert_eq!(Ok((Token::from(6.02e23), "")), parser(literal).parse("6.02e23"));
+        assert_eq!(Ok((Token::from(-2.5e-3), "")), parser(literal).parse("-2.5E-3"));
+        assert_eq!(Ok((Token::from(0.5), "")), parser(literal).parse(".5"));
+        assert_eq!(
+            Ok((vec![Token::from(1), Token::Symbol("e".to_owned())], "")),
+            lex("1e")
+        );
+    }
+
+    #[test]
+    fn digit_separators() {
+        assert_eq!(Ok((Token::from(1_000_000), "")), parser(literal).parse("1_000_000"));
+        assert_eq!(Ok((Token::from(1_000.5), "")), parser(literal).parse("1_000.5"));
+        assert_eq!(Ok((vec![Token::Symbol("_1".to_owned())], "")), lex("_1"));
+    }
+
+    #[test]
+    fn unicode_symbols() {
+        for name in &["λ", "日本語", "café", "🦀", "👩\u{200d}💻", "e\u{301}"] {
+            assert_eq!(Ok((vec![Token::Symbol(name.to_string())], "")), lex(*name));
+        }
+    }
+
+    #[test]
+    fn keywords_and_metadata() {
+        assert_eq!(Ok((vec![Token::Keyword("doc".to_owned())], "")), lex(":doc"));
+        assert_eq!(
+            Ok((vec![Token::Caret, Token::LBrace, Token::Keyword("a".to_owned()), Token::from(1),
+                     Token::RBrace, Token::Symbol("x".to_owned())], "")),
+            lex("^{:a 1} x")
+        );
+        assert!(lex(": x").is_err());
+    }
+
+    #[test]
+    fn tags() {
+        assert_eq!(
+            Ok((vec![Token::Tag("path".to_owned()), Token::from("a/b")], "")),
+            lex("#path \"a/b\"")
+        );
+        assert_eq!(Ok((vec![Token::Tag("true".to_owned())], "")), lex("#true"));
+        assert_eq!(Ok((vec![Token::from(true), Token::from(false)], "")), lex("#t #f"));
+    }
+
+    #[test]
+    fn hex_escapes() {
+        assert_eq!(Ok((Token::from("A\u{ff}"), "")), parser(literal).parse(r#""\x41\xfF""#));
+        assert!(parser(literal).parse(r#""\x4""#).is_err());
+    }
 }
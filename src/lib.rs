@@ -0,0 +1,239 @@
+//! Telescope, an elegant Lisp, for a more civilized age.
+//!
+//! The `tele` binary is built with the `cli` feature, which brings in the REPL,
+//! the command line, and the language server. Without it, the crate is just
+//! the interpreter, for embedding:
+//!
+//! ```ignore
+//! let interpreter = telescope::Interpreter::new();
+//! assert_eq!(telescope::Expr::from(3), interpreter.eval_str("(+ 1 2)")?);
+//! ```
+
+#![recursion_limit = "1024"]
+#[cfg(test)]
+extern crate float_cmp;
+
+#[cfg(test)]
+#[macro_use]
+extern crate quickcheck;
+
+#[macro_use]
+extern crate error_chain;
+#[macro_use]
+extern crate derive_error_chain;
+
+#[macro_use]
+extern crate lazy_static;
+
+#[macro_use]
+extern crate combine;
+extern crate conv;
+extern crate itertools;
+extern crate unicode_normalization;
+extern crate unicode_segmentation;
+extern crate unicode_xid;
+extern crate threadpool;
+
+#[cfg(feature = "cli")]
+extern crate ctrlc;
+#[cfg(feature = "cli")]
+extern crate rustyline;
+#[cfg(feature = "json")]
+#[macro_use]
+extern crate serde_json;
+#[cfg(feature = "wasm")]
+extern crate wasm_bindgen;
+
+#[macro_use]
+mod macros;
+
+mod atom;
+pub mod bundle;
+mod channel;
+mod types;
+mod encoding;
+pub mod eval;
+pub mod exit;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod files;
+pub mod fmt;
+mod format;
+mod forms;
+pub mod graph;
+mod lexer;
+pub mod lint;
+mod location;
+mod log;
+mod matrix;
+pub mod module;
+pub mod notebook;
+mod parser;
+mod reader;
+mod reader_macro;
+pub mod ops;
+mod token;
+pub mod typecheck;
+pub mod error;
+mod util;
+pub mod warning;
+mod image;
+mod interrupt;
+#[doc(hidden)]
+pub mod embed;
+mod env;
+mod stream;
+mod sorted;
+mod strings;
+mod transient;
+mod pattern;
+mod deque;
+mod diff;
+mod order;
+mod dynamic;
+mod future;
+mod generator;
+mod worker;
+mod help;
+mod port;
+pub mod color;
+mod csv;
+
+#[cfg(feature = "json")]
+pub mod json;
+#[cfg(feature = "net")]
+mod net;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(all(feature = "cli", feature = "net"))]
+pub mod server;
+
+// The command line, REPL, and language server
+#[cfg(feature = "cli")]
+mod buffer;
+#[cfg(feature = "cli")]
+mod command;
+#[cfg(feature = "cli")]
+mod editor;
+#[cfg(feature = "cli")]
+pub mod input;
+#[cfg(feature = "cli")]
+pub mod lsp;
+
+pub use env::Env;
+pub use error::{Error, ErrorKind, Result};
+pub use interrupt::set_fuel;
+pub use types::{Arity, Expr, Key, Map};
+
+/// Read the forms in `source`, without evaluating them.
+pub fn parse(source: &str) -> Result<Vec<Expr>> {
+    parser::parse_program(source)
+}
+
+/// An interpreter with its own global environment, holding the builtins and
+/// whatever the code it runs defines.
+#[derive(Clone, Debug)]
+pub struct Interpreter {
+    env: Env,
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        Interpreter { env: ops::env() }
+    }
+
+    /// An interpreter whose code can't touch files, processes, or the
+    /// network, load modules, or start threads. Calling a builtin or form
+    /// that would is an error instead.
+    pub fn sandboxed() -> Interpreter {
+        let interpreter = Interpreter::new();
+        interpreter.env.sandbox();
+        for name in ops::host_builtins() {
+            interpreter.define_builtin(name, Arity::at_least(0), move |_, _| {
+                bail!("{} isn't available in a sandbox", name)
+            });
+        }
+        interpreter
+    }
+
+    /// Define a builtin function, or replace one, such as to swap a builtin
+    /// that does I/O for one that works where the interpreter is embedded.
+    pub fn define_builtin<F>(&self, name: &str, arity: Arity, func: F)
+    where
+        F: Fn(&[Expr], Env) -> Result<Expr> + Send + Sync + 'static,
+    {
+        self.env.define(name, Expr::from(types::Function::builtin(name, arity, func)));
+    }
+
+    /// Evaluate each form in `source`, returning the value of the last.
+    pub fn eval_str(&self, source: &str) -> Result<Expr> {
+        let forms = parser::parse_program(source)?;
+        location::reset();
+        Expr::eval_all(&forms, self.env.clone())
+    }
+
+    /// Evaluate each form in `source` like `eval_str`, collecting what it
+    /// prints instead of writing it to stdout. The output up to an error is
+    /// kept too.
+    pub fn eval_str_captured(&self, source: &str) -> (Result<Expr>, String) {
+        let (result, bytes) = port::capture_all(|| self.eval_str(source));
+        (result, String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    /// Evaluate a form that's already been read, such as one built by `tl!`.
+    pub fn eval(&self, form: &Expr) -> Result<Expr> {
+        location::reset();
+        form.eval(self.env.clone())
+    }
+
+    /// The value `name` is bound to at the top level, if any.
+    pub fn lookup(&self, name: &str) -> Option<Expr> {
+        self.env.lookup(name)
+    }
+
+    /// The global environment, for evaluating parsed forms directly.
+    pub fn env(&self) -> &Env {
+        &self.env
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Interpreter {
+        Interpreter::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn embedding() {
+        let interpreter = Interpreter::new();
+        interpreter.eval_str("(defn double [x] (* 2 x))").unwrap();
+        assert_eq!(Expr::from(42), interpreter.eval_str("(double 21)").unwrap());
+        assert!(interpreter.lookup("double").is_some());
+        assert!(Interpreter::new().lookup("double").is_none());
+        assert!(interpreter.eval_str("(double").is_err());
+    }
+
+    #[test]
+    fn sandboxing() {
+        let interpreter = Interpreter::sandboxed();
+        let err = interpreter.eval_str(r#"(read-bytes "Cargo.toml")"#).unwrap_err();
+        assert_eq!("read-bytes isn't available in a sandbox", err.to_string());
+        assert_eq!(Expr::from(3), interpreter.eval_str("(+ 1 2)").unwrap());
+        for code in &["(require telescope)", "(future 1)", "(pmap first [[1]])", "(generator)"] {
+            assert!(interpreter.eval_str(code).is_err(), "{}", code);
+        }
+        let err = interpreter.eval_str("(let [x 1] (reload 'telescope))").unwrap_err();
+        assert_eq!("reload isn't available in a sandbox", err.to_string());
+        assert!(Interpreter::new().eval_str("(future 1)").is_ok());
+        // Copying a value in memory isn't copying a file
+        assert!(interpreter.eval_str("(deep-copy (atom 1))").is_ok());
+        assert!(interpreter.eval_str(r#"(copy "a" "b")"#).is_err());
+
+        interpreter.define_builtin("exists?", Arity::exact(1), |_, _| Ok(Expr::from(false)));
+        assert_eq!(Expr::from(false), interpreter.eval_str(r#"(exists? "Cargo.toml")"#).unwrap());
+    }
+}
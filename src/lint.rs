@@ -0,0 +1,534 @@
+//! Static checks on source files, which read code without evaluating it.
+//!
+//! Reports symbols that aren't defined anywhere, calls with the wrong number
+//! of arguments, local bindings that are never used, and bindings that shadow
+//! another name. Names starting with `_` are exempt from the last two.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+#[cfg(feature = "json")]
+use serde_json::Value;
+
+use {forms, module, ops, typecheck};
+use error::*;
+use location::Location;
+use reader::Reader;
+use types::{Arity, Expr};
+
+/// A problem found in a source file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Warning {
+    pub location: Location,
+    pub kind: &'static str,
+    pub message: String,
+}
+
+#[cfg(feature = "json")]
+impl Warning {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "file": self.location.file.as_ref().map(|file| &**file),
+            "line": self.location.line,
+            "column": self.location.column,
+            "kind": self.kind,
+            "message": self.message,
+        })
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.location, self.kind, self.message)
+    }
+}
+
+/// Check the source of `file`, failing only if it can't be read.
+pub fn lint(source: &str, file: &str) -> Result<Vec<Warning>> {
+    let forms = Reader::new(io::Cursor::new(source.as_bytes()))
+        .with_file(file)
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut linter = Linter::new(&forms);
+    linter.walk(&forms, &Location::new(Some(file.into()), 0, 0));
+    let mut warnings = linter.warnings;
+    warnings.sort_by(|a, b| a.location.cmp(&b.location));
+    Ok(warnings)
+}
+
+/// The global names each top-level form refers to, with the name the form
+/// defines, or None for code that isn't a definition. Names don't have to be
+/// defined in `forms`, so this finds references to other modules too.
+pub fn references(forms: &[Expr]) -> Vec<(Option<String>, String)> {
+    let mut linter = Linter::new(forms);
+    linter.walk(forms, &Location::new(None, 0, 0));
+    linter.references
+}
+
+#[derive(Debug)]
+struct Binding {
+    name: String,
+    used: bool,
+    location: Location,
+}
+
+#[derive(Debug)]
+struct Linter {
+    // Builtins and top-level definitions, with their arity when it's known
+    globals: HashMap<String, Option<Arity>>,
+    scopes: Vec<Vec<Binding>>,
+    warnings: Vec<Warning>,
+    // The name the top-level form being checked defines
+    current: Option<String>,
+    // Global names referred to, and by which definition
+    references: Vec<(Option<String>, String)>,
+}
+
+impl Linter {
+    fn new(forms: &[Expr]) -> Self {
+        let mut globals = HashMap::new();
+        for name in ops::env().names() {
+            let arity = ops::env().lookup(&name).and_then(|f| f.func()).map(|f| f.arity());
+            globals.insert(name, arity);
+        }
+        for form in forms {
+            define_globals(form, &mut globals);
+        }
+        Linter {
+            globals,
+            scopes: Vec::new(),
+            warnings: Vec::new(),
+            current: None,
+            references: Vec::new(),
+        }
+    }
+
+    fn walk(&mut self, forms: &[Expr], top: &Location) {
+        for form in forms {
+            self.current = module::definition(form);
+            self.form(form, top);
+        }
+    }
+
+    fn warn(&mut self, at: &Location, kind: &'static str, message: String) {
+        self.warnings.push(Warning { location: at.clone(), kind, message });
+    }
+
+    fn is_local(&self, name: &str) -> bool {
+        self.scopes.iter().any(|scope| scope.iter().any(|b| b.name == name))
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    fn pop_scope(&mut self) {
+        for binding in self.scopes.pop().unwrap_or_default() {
+            if !binding.used && !binding.name.starts_with('_') {
+                let message = format!("{} is never used", binding.name);
+                self.warn(&binding.location, "unused", message);
+            }
+        }
+    }
+
+    fn bind(&mut self, name: &str, at: &Location) {
+        if !name.starts_with('_') {
+            if self.is_local(name) {
+                self.warn(at, "shadowed", format!("{} shadows an outer binding", name));
+            } else if self.globals.contains_key(name) {
+                self.warn(at, "shadowed", format!("{} shadows a global definition", name));
+            }
+        }
+        let binding = Binding { name: name.to_owned(), used: false, location: at.clone() };
+        self.scopes.last_mut().expect("bind outside a scope").push(binding);
+    }
+
+    /// Bind the symbols a destructuring pattern introduces.
+    fn bind_pattern(&mut self, pattern: &Expr, at: &Location) {
+        match *pattern {
+            Expr::Sym(ref sym) if sym.0 == "_" || sym.0 == "&" => (),
+            Expr::Sym(ref sym) => self.bind(&sym.0, at),
+            Expr::Vector(ref patterns) => {
+                for pattern in &patterns.0 {
+                    self.bind_pattern(pattern, at);
+                }
+            }
+            Expr::Map(ref patterns) => {
+                for (_, pattern) in patterns.iter() {
+                    self.bind_pattern(pattern, at);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn resolve(&mut self, name: &str, at: &Location, what: &str) {
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(binding) = scope.iter_mut().rev().find(|b| b.name == name) {
+                binding.used = true;
+                return;
+            }
+        }
+        self.references.push((self.current.clone(), name.to_owned()));
+        if !self.globals.contains_key(name) && !forms::names().contains(&name) {
+            self.warn(at, "undefined", format!("unknown {} {}", what, name));
+        }
+    }
+
+    fn forms(&mut self, forms: &[Expr], at: &Location) {
+        for form in forms {
+            self.form(form, at);
+        }
+    }
+
+    fn form(&mut self, form: &Expr, at: &Location) {
+        match *form {
+            Expr::Sym(ref sym) => self.resolve(&sym.0, at, "symbol"),
+            Expr::Vector(ref vec) => self.forms(&vec.0, at),
+            Expr::Map(ref map) => {
+                for (_, value) in map.iter() {
+                    self.form(value, at);
+                }
+            }
+            Expr::List(ref list) => {
+                let at = Location::of(form).unwrap_or_else(|| at.clone());
+                if let Some((head, args)) = list.0.split_first() {
+                    match head.sym() {
+                        Some(sym) => self.call(&sym.0, args, &at),
+                        None => self.forms(&list.0, &at),
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn call(&mut self, name: &str, args: &[Expr], at: &Location) {
+        // A local binding takes precedence over everything
+        if self.is_local(name) {
+            self.resolve(name, at, "function");
+            return self.forms(args, at);
+        }
+
+        match name {
+            "quote" | "macro" | "defrecord" => (),
+            "def" | "def-dynamic" | "defmulti" => self.forms(skip(args, 1), at),
+            "defn" => {
+                let rest = match args.get(1) {
+                    Some(&Expr::Str(_)) if args.len() > 2 => &args[2..],
+                    _ => skip(args, 1),
+                };
+                self.clauses(rest, at);
+            }
+            "defmethod" => {
+                if let Some(value) = args.get(1) {
+                    self.form(value, at);
+                }
+                self.clauses(skip(args, 2), at);
+            }
+            "fn" => match args.first().and_then(Expr::sym) {
+                Some(name) => {
+                    // The name is in scope for recursion, but needn't be used
+                    self.push_scope();
+                    self.bind(&name.0, at);
+                    self.scopes.last_mut().unwrap()[0].used = true;
+                    self.clauses(&args[1..], at);
+                    self.pop_scope();
+                }
+                None => self.clauses(args, at),
+            },
+            "let" | "let-values" => {
+                self.push_scope();
+                if let Some(bindings) = args.first().and_then(Expr::vector) {
+                    for pair in bindings.0.chunks(2) {
+                        if let Some(init) = pair.get(1) {
+                            self.form(init, at);
+                        }
+                        self.bind_pattern(&pair[0], at);
+                    }
+                }
+                self.forms(skip(args, 1), at);
+                self.pop_scope();
+            }
+            "binding" => {
+                if let Some(bindings) = args.first().and_then(Expr::vector) {
+                    self.forms(&bindings.0, at);
+                }
+                self.forms(skip(args, 1), at);
+            }
+            "case" => {
+                if let Some(expr) = args.first() {
+                    self.form(expr, at);
+                }
+                // Tests aren't evaluated
+                for clause in skip(args, 1).chunks(2) {
+                    self.form(clause.last().unwrap(), at);
+                }
+            }
+            "match" => {
+                if let Some(expr) = args.first() {
+                    self.form(expr, at);
+                }
+                let mut clauses = skip(args, 1);
+                while !clauses.is_empty() {
+                    let guarded = clauses.get(1).and_then(Expr::sym).map_or(false, |s| s.0 == "if");
+                    let len = if guarded { 4 } else { 2 };
+                    if clauses.len() < len {
+                        break;
+                    }
+                    self.push_scope();
+                    self.bind_pattern(&clauses[0], at);
+                    if guarded {
+                        self.form(&clauses[2], at);
+                    }
+                    self.form(&clauses[len - 1], at);
+                    self.pop_scope();
+                    clauses = &clauses[len..];
+                }
+            }
+            "->" | "->>" => {
+                // Threaded calls are missing an argument, so skip arity checks
+                for arg in args {
+                    match arg.list() {
+                        Some(call) => {
+                            let at = Location::of(arg).unwrap_or_else(|| at.clone());
+                            self.forms(&call.0, &at)
+                        }
+                        None => self.form(arg, at),
+                    }
+                }
+            }
+            _ if forms::names().contains(&name) => self.forms(args, at),
+            _ => {
+                self.resolve(name, at, "function");
+                if let Some(&Some(arity)) = self.globals.get(name) {
+                    if !arity.contains(args.len()) {
+                        let message = format!(
+                            "{} takes {} args, but is called with {}",
+                            name,
+                            arity,
+                            args.len()
+                        );
+                        self.warn(at, "arity", message);
+                    }
+                }
+                self.forms(args, at);
+            }
+        }
+    }
+
+    // Either [params*] exprs*, or one ([params*] exprs*) per arity
+    fn clauses(&mut self, args: &[Expr], at: &Location) {
+        match args.first() {
+            Some(&Expr::List(_)) => {
+                for clause in args {
+                    let at = Location::of(clause).unwrap_or_else(|| at.clone());
+                    if let Some(clause) = clause.list() {
+                        self.clause(&clause.0, &at);
+                    }
+                }
+            }
+            _ => self.clause(args, at),
+        }
+    }
+
+    fn clause(&mut self, clause: &[Expr], at: &Location) {
+        self.push_scope();
+        if let Some(params) = clause.first().and_then(Expr::vector) {
+            for param in &params.0 {
+                self.bind_pattern(param, at);
+            }
+        }
+        let (_, body) = typecheck::split_return(skip(clause, 1));
+        match body.first() {
+            // A leading :pre/:post map, whose conditions can see the result as %
+            Some(conditions @ &Expr::Map(_)) if body.len() > 1 => {
+                self.push_scope();
+                // Only :post uses %, so don't warn when it's unused
+                self.bind("%", at);
+                self.resolve("%", at, "symbol");
+                self.form(conditions, at);
+                self.pop_scope();
+                self.forms(skip(body, 1), at);
+            }
+            _ => self.forms(body, at),
+        }
+        self.pop_scope();
+    }
+}
+
+/// The forms from `index` on, empty if there are none.
+fn skip(forms: &[Expr], index: usize) -> &[Expr] {
+    if index < forms.len() { &forms[index..] } else { &[] }
+}
+
+/// Record the names a top-level form defines.
+fn define_globals(form: &Expr, globals: &mut HashMap<String, Option<Arity>>) {
+    let items = match form.list() {
+        Some(list) => &list.0,
+        None => return,
+    };
+    let kind = match items.first().and_then(Expr::sym) {
+        Some(kind) => kind.0.as_str(),
+        None => return,
+    };
+    let name = match items.get(1).and_then(Expr::sym) {
+        Some(name) => name.0.clone(),
+        None => return,
+    };
+
+    match kind {
+        "def" | "def-dynamic" => {
+            // (def f (fn ...)) has a known arity too
+            let arity = items.get(2)
+                .and_then(Expr::list)
+                .and_then(|value| match value.0.split_first() {
+                    Some((head, args)) if head.sym().map_or(false, |s| s.0 == "fn") => {
+                        let args = if args.first().and_then(Expr::sym).is_some() { &args[1..] } else { args };
+                        clauses_arity(args)
+                    }
+                    _ => None,
+                });
+            globals.insert(name, arity);
+        }
+        "defn" => {
+            let rest = match items.get(2) {
+                Some(&Expr::Str(_)) if items.len() > 3 => &items[3..],
+                _ => &items[2..],
+            };
+            globals.insert(name, clauses_arity(rest));
+        }
+        "defmulti" => {
+            globals.insert(name, None);
+        }
+        "defrecord" => {
+            if let Some(fields) = items.get(2).and_then(Expr::vector) {
+                globals.insert(format!("{}?", name), Some(Arity::exact(1)));
+                for field in fields.0.iter().filter_map(Expr::sym) {
+                    globals.insert(format!("{}-{}", name, field.0), Some(Arity::exact(1)));
+                }
+                globals.insert(name, Some(Arity::exact(fields.0.len())));
+            }
+        }
+        _ => (),
+    }
+}
+
+/// The arity of a function's clauses, like `Function::arity`.
+fn clauses_arity(args: &[Expr]) -> Option<Arity> {
+    let counts = match args.first() {
+        Some(&Expr::List(_)) => args.iter()
+            .map(|clause| clause.list().and_then(|c| c.0.first()).and_then(Expr::vector).map(|p| p.0.len()))
+            .collect::<Option<Vec<_>>>()?,
+        _ => vec![args.first().and_then(Expr::vector)?.0.len()],
+    };
+    let min = *counts.iter().min()?;
+    let max = *counts.iter().max()?;
+    Some(Arity::range(min, max))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn kinds(source: &str) -> Vec<(&'static str, String)> {
+        lint(source, "test.tl")
+            .unwrap()
+            .into_iter()
+            .map(|w| (w.kind, w.message))
+            .collect()
+    }
+
+    #[test]
+    fn clean_code() {
+        let source = "
+            (defn square [x] (* x x))
+            (defrecord Point [x y])
+            (def origin (Point 0 0))
+            (let [[a b] (values 1 2)] (square (+ a b (Point-x origin))))
+            (match [1 2] [a _] a)";
+        assert_eq!(Vec::<(&str, String)>::new(), kinds(source));
+    }
+
+    #[test]
+    fn undefined_names() {
+        assert_eq!(
+            vec![("undefined", "unknown function frobnicate".to_owned())],
+            kinds("(frobnicate 1)")
+        );
+        assert_eq!(
+            vec![("undefined", "unknown symbol y".to_owned())],
+            kinds("(defn f [x] (+ x y))")
+        );
+    }
+
+    #[test]
+    fn arities() {
+        assert_eq!(
+            vec![("arity", "first takes 1 args, but is called with 2".to_owned())],
+            kinds("(first [1] [2])")
+        );
+        let source = "(defn f ([x] x) ([x y] (+ x y))) (f 1 2 3)";
+        assert_eq!(
+            vec![("arity", "f takes 1-2 args, but is called with 3".to_owned())],
+            kinds(source)
+        );
+        // Threading supplies an argument
+        assert!(kinds("(-> [1] first)").is_empty());
+    }
+
+    #[test]
+    fn unused_and_shadowed() {
+        assert_eq!(
+            vec![("unused", "y is never used".to_owned())],
+            kinds("(let [x 1 y 2] x)")
+        );
+        assert!(kinds("(fn [_ignored] 1)").is_empty());
+        assert_eq!(
+            vec![("shadowed", "x shadows an outer binding".to_owned())],
+            kinds("(fn [x] (let [x (+ x 1)] x))")
+        );
+        assert_eq!(
+            vec![("shadowed", "list shadows a global definition".to_owned())],
+            kinds("(fn [list] list)")
+        );
+    }
+
+    #[test]
+    fn locations() {
+        let warnings = lint("(def x 1)\n\n  (nope x)", "test.tl").unwrap();
+        assert_eq!(1, warnings.len());
+        assert_eq!("test.tl:3:3: undefined: unknown function nope", warnings[0].to_string());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json() {
+        let warnings = lint("(def x 1)\n\n  (nope x)", "test.tl").unwrap();
+        assert_eq!(json!(3), warnings[0].to_json()["line"]);
+    }
+
+    #[test]
+    fn conditions() {
+        assert!(kinds("(defn f [x] {:pre [(> x 0)] :post [(> % x)]} (+ x 1))").is_empty());
+        assert_eq!(
+            vec![("undefined", "unknown symbol %".to_owned())],
+            kinds("(defn f [x] %)")
+        );
+    }
+
+    #[test]
+    fn finding_references() {
+        let forms = ::parser::parse_program("(defn f [x] (g x (h))) (def y (f 1)) (print y)").unwrap();
+        let references = references(&forms);
+        let by = |name: Option<&str>| {
+            references.iter()
+                .filter(|&&(ref from, _)| from.as_ref().map(String::as_str) == name)
+                .map(|&(_, ref to)| to.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["g", "h"], by(Some("f")));
+        assert_eq!(vec!["f"], by(Some("y")));
+        assert_eq!(vec!["print", "y"], by(None));
+    }
+}
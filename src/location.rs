@@ -0,0 +1,172 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::mem;
+use std::sync::Arc;
+
+use types::{Expr, Key, Map, Meta};
+
+// Lines of source shown on each side of an error
+const CONTEXT: usize = 2;
+
+/// Where a form was read from. Lines and columns count from 1, and line 0
+/// means the location is unknown.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Location {
+    pub file: Option<Arc<str>>,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Location {
+    pub fn new(file: Option<Arc<str>>, line: usize, column: usize) -> Self {
+        Location { file, line, column }
+    }
+
+    pub fn is_known(&self) -> bool {
+        self.line > 0
+    }
+
+    /// The location of a form, from the metadata the reader attached to it.
+    pub fn of(expr: &Expr) -> Option<Location> {
+        expr.meta().and_then(Location::from_meta)
+    }
+
+    pub fn from_meta(meta: &Map) -> Option<Location> {
+        let line = meta.get(&key("line")).and_then(Expr::int)?;
+        let column = meta.get(&key("column")).and_then(Expr::int)?;
+        let file = meta.get(&key("file")).and_then(|file| file.str()).map(Arc::from);
+        Some(Location::new(file, line as usize, column as usize))
+    }
+
+    /// Metadata recording this location, as :file, :line, and :column.
+    pub fn to_meta(&self) -> Map {
+        let mut meta = Map::new();
+        if let Some(ref file) = self.file {
+            meta.insert(key("file"), Expr::from(&**file));
+        }
+        meta.insert(key("line"), Expr::from(self.line as i64));
+        meta.insert(key("column"), Expr::from(self.column as i64));
+        meta
+    }
+
+    /// The lines of the file around this location, or None if there's no
+    /// file to read them from.
+    pub fn excerpt(&self) -> Option<String> {
+        let file = self.file.as_ref()?;
+        let mut source = String::new();
+        fs::File::open(&**file).ok()?.read_to_string(&mut source).ok()?;
+        excerpt(&source, self.line, self.column)
+    }
+}
+
+/// The lines of `source` around `line`, numbered, with a caret under
+/// `column`. None if `source` has no such line.
+pub fn excerpt(source: &str, line: usize, column: usize) -> Option<String> {
+    let lines = source.lines().collect::<Vec<_>>();
+    if line == 0 || line > lines.len() {
+        return None;
+    }
+    let first = line.saturating_sub(CONTEXT).max(1);
+    let last = (line + CONTEXT).min(lines.len());
+    let width = last.to_string().len();
+
+    let mut excerpt = Vec::new();
+    for n in first..last + 1 {
+        excerpt.push(format!("{:>width$} | {}", n, lines[n - 1], width = width));
+        if n == line {
+            // Keep tabs, so the caret lines up however they're shown
+            let indent = lines[n - 1].chars()
+                .take(column.saturating_sub(1))
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect::<String>();
+            excerpt.push(format!("{:>width$} | {}^", "", indent, width = width));
+        }
+    }
+    Some(excerpt.join("\n"))
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let file = self.file.as_ref().map(|file| &**file).unwrap_or("<input>");
+        write!(f, "{}:{}:{}", file, self.line, self.column)
+    }
+}
+
+fn key(name: &str) -> Key {
+    Key::Keyword(name.to_owned())
+}
+
+/// Parser stream positions that may correspond to a place in the source.
+pub trait Locate {
+    fn locate(&self) -> Option<Location>;
+}
+
+impl Locate for Location {
+    fn locate(&self) -> Option<Location> {
+        if self.is_known() { Some(self.clone()) } else { None }
+    }
+}
+
+// Token slices, as used in tests, have no source positions
+impl Locate for usize {
+    fn locate(&self) -> Option<Location> {
+        None
+    }
+}
+
+thread_local! {
+    // Metadata of the innermost form being evaluated
+    static CURRENT: RefCell<Meta> = RefCell::new(Meta::default());
+}
+
+/// Mark a form's metadata as the current location while it's evaluated,
+/// returning the location to restore with `leave`. Only restore it if the
+/// evaluation succeeds, so that after an error `current` points at the form
+/// that failed.
+pub fn enter(meta: &Meta) -> Option<Meta> {
+    meta.get()?;
+    Some(CURRENT.with(|current| mem::replace(&mut *current.borrow_mut(), meta.clone())))
+}
+
+pub fn leave(outer: Option<Meta>) {
+    if let Some(outer) = outer {
+        CURRENT.with(|current| *current.borrow_mut() = outer);
+    }
+}
+
+/// Forget the current location, before evaluating a new top-level form.
+pub fn reset() {
+    CURRENT.with(|current| *current.borrow_mut() = Meta::default());
+}
+
+pub fn current() -> Option<Location> {
+    CURRENT.with(|current| current.borrow().get().and_then(Location::from_meta))
+}
+
+/// The metadata of the form being evaluated, to give to values it creates.
+pub fn current_meta() -> Meta {
+    CURRENT.with(|current| current.borrow().clone())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn excerpts() {
+        let source = "(def a 1)\n(def b 2)\n(+ a\n   \"b\")\n(def c 3)\n(def d 4)\n";
+        let expected = "
+2 | (def b 2)
+3 | (+ a
+4 |    \"b\")
+  |    ^
+5 | (def c 3)
+6 | (def d 4)";
+        assert_eq!(Some(&expected[1..]), excerpt(source, 4, 4).as_ref().map(|s| &**s));
+        let expected = "1 | (def a 1)\n  | ^\n2 | (def b 2)\n3 | (+ a";
+        assert_eq!(Some(expected.to_owned()), excerpt(source, 1, 1));
+        assert_eq!(None, excerpt(source, 7, 1));
+    }
+}
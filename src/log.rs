@@ -0,0 +1,155 @@
+//! Leveled logging for programs, to stderr or a file. Each line has a UTC
+//! timestamp, the level, the message, and any `key=value` pairs:
+//!
+//! ```text
+//! 2017-09-30T14:05:12Z WARN retrying user="ada" attempt=2
+//! ```
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use error::*;
+use files;
+use util::lock;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+const LEVELS: &[Level] = &[Level::Debug, Level::Info, Level::Warn, Level::Error];
+
+impl Level {
+    pub fn from_name(name: &str) -> Option<Level> {
+        LEVELS.iter().cloned().find(|level| level.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Level::Debug => "debug",
+            Level::Info => "info",
+            Level::Warn => "warn",
+            Level::Error => "error",
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name().to_uppercase())
+    }
+}
+
+/// Where log lines go.
+#[derive(Debug)]
+enum Target {
+    Stderr,
+    File(fs::File),
+}
+
+lazy_static! {
+    // The index in LEVELS of the least severe level that's logged
+    static ref LEVEL: AtomicUsize = AtomicUsize::new(Level::Info as usize);
+    static ref TARGET: Mutex<Target> = Mutex::new(Target::Stderr);
+}
+
+pub fn level() -> Level {
+    LEVELS[LEVEL.load(Ordering::SeqCst)]
+}
+
+/// Log only messages at least as severe as `level`.
+pub fn set_level(level: Level) {
+    LEVEL.store(level as usize, Ordering::SeqCst);
+}
+
+/// Log to stderr.
+pub fn to_stderr() {
+    *lock(&*TARGET) = Target::Stderr;
+}
+
+/// Log by appending to the file at `path`.
+pub fn to_file(path: &str) -> Result<()> {
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|err| files::io_error(path, err))?;
+    *lock(&*TARGET) = Target::File(file);
+    Ok(())
+}
+
+/// Log `message` with `fields`, if `level` is enabled.
+pub fn log(level: Level, message: &str, fields: &[(String, String)]) -> Result<()> {
+    if level < self::level() {
+        return Ok(());
+    }
+    let line = format_line(SystemTime::now(), level, message, fields);
+    match *lock(&*TARGET) {
+        Target::Stderr => writeln!(io::stderr(), "{}", line)?,
+        Target::File(ref mut file) => writeln!(file, "{}", line)?,
+    }
+    Ok(())
+}
+
+fn format_line(time: SystemTime, level: Level, message: &str, fields: &[(String, String)]) -> String {
+    let mut line = format!("{} {} {}", timestamp(time), level, message);
+    for &(ref key, ref value) in fields {
+        line.push_str(&format!(" {}={}", key, value));
+    }
+    line
+}
+
+// An RFC 3339 UTC timestamp, to the second
+fn timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, secs) = ((secs / 86400) as i64, secs % 86400);
+
+    // Civil date from days since 1970-01-01, after Howard Hinnant's algorithm
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, secs / 3600, secs / 60 % 60, secs % 60
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn timestamps() {
+        assert_eq!("1970-01-01T00:00:00Z", timestamp(UNIX_EPOCH));
+        let leap_day = UNIX_EPOCH + Duration::from_secs(951_782_400 + 3661);
+        assert_eq!("2000-02-29T01:01:01Z", timestamp(leap_day));
+        let time = UNIX_EPOCH + Duration::from_secs(1_506_780_312);
+        assert_eq!("2017-09-30T14:05:12Z", timestamp(time));
+    }
+
+    #[test]
+    fn lines() {
+        let fields = vec![("user".to_owned(), "\"ada\"".to_owned()), ("attempt".to_owned(), "2".to_owned())];
+        assert_eq!(
+            "1970-01-01T00:00:00Z WARN retrying user=\"ada\" attempt=2",
+            format_line(UNIX_EPOCH, Level::Warn, "retrying", &fields)
+        );
+        assert_eq!(Some(Level::Error), Level::from_name("error"));
+        assert_eq!(None, Level::from_name("loud"));
+    }
+}
@@ -0,0 +1,383 @@
+//! A Language Server Protocol server over stdio, for editor integration.
+//!
+//! Supports diagnostics for syntax errors, hover docs, and completion. Source
+//! files are only read, never evaluated, so definitions are found by looking
+//! for `def`-like forms at the top level. Columns are counted in characters.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Read, Write};
+use serde_json::{self, Value};
+
+use {forms, ops};
+use error::*;
+use location::{Locate, Location};
+use reader::Reader;
+use types::{Expr, Key};
+
+// LSP constants
+const SYNC_FULL: u8 = 1;
+const SEVERITY_ERROR: u8 = 1;
+const KIND_FUNCTION: u8 = 3;
+const KIND_VARIABLE: u8 = 6;
+const KIND_KEYWORD: u8 = 14;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// Serve requests from stdin until the client asks the server to exit.
+pub fn run() -> Result<()> {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut input = stdin.lock();
+    let mut server = Server::default();
+
+    while let Some(message) = read_message(&mut input)? {
+        for reply in server.handle(&message) {
+            write_message(&mut stdout.lock(), &reply)?;
+        }
+        if server.exit {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Read one message, framed by a Content-Length header.
+fn read_message<R: BufRead>(input: &mut R) -> Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.split(':').nth(1) {
+            if header.to_lowercase().starts_with("content-length") {
+                length = Some(value.trim().parse::<usize>().map_err(|err| err.to_string())?);
+            }
+        }
+    }
+
+    let length = length.ok_or("message without a Content-Length header")?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body).map_err(|err| err.to_string())?))
+}
+
+fn write_message<W: Write>(output: &mut W, message: &Value) -> Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()?;
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+struct Server {
+    documents: HashMap<String, String>,
+    exit: bool,
+}
+
+impl Server {
+    /// Handle a message, returning any responses and notifications to send.
+    fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let method = message["method"].as_str().unwrap_or("");
+        let params = &message["params"];
+        let id = message.get("id").cloned();
+
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": SYNC_FULL,
+                    "hoverProvider": true,
+                    "completionProvider": {},
+                }
+            }),
+            "shutdown" => Value::Null,
+            "exit" => {
+                self.exit = true;
+                return vec![];
+            }
+            "textDocument/didOpen" => {
+                let doc = &params["textDocument"];
+                return self.update(doc["uri"].as_str(), doc["text"].as_str());
+            }
+            "textDocument/didChange" => {
+                // Full sync, so the last change has the whole text
+                let text = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str());
+                return self.update(params["textDocument"]["uri"].as_str(), text);
+            }
+            "textDocument/didClose" => {
+                if let Some(uri) = params["textDocument"]["uri"].as_str() {
+                    self.documents.remove(uri);
+                }
+                return vec![];
+            }
+            "textDocument/hover" => self.hover(params),
+            "textDocument/completion" => self.completion(params),
+            _ => {
+                // Unknown notifications are ignored, but requests need an answer
+                return match id {
+                    Some(id) => vec![json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": {
+                            "code": METHOD_NOT_FOUND,
+                            "message": format!("unsupported method {}", method),
+                        },
+                    })],
+                    None => vec![],
+                };
+            }
+        };
+
+        match id {
+            Some(id) => vec![json!({ "jsonrpc": "2.0", "id": id, "result": result })],
+            None => vec![],
+        }
+    }
+
+    fn update(&mut self, uri: Option<&str>, text: Option<&str>) -> Vec<Value> {
+        match (uri, text) {
+            (Some(uri), Some(text)) => {
+                self.documents.insert(uri.to_owned(), text.to_owned());
+                vec![json!({
+                    "jsonrpc": "2.0",
+                    "method": "textDocument/publishDiagnostics",
+                    "params": { "uri": uri, "diagnostics": diagnostics(text) },
+                })]
+            }
+            _ => vec![],
+        }
+    }
+
+    fn document(&self, params: &Value) -> Option<&str> {
+        let uri = params["textDocument"]["uri"].as_str()?;
+        self.documents.get(uri).map(|text| text.as_str())
+    }
+
+    fn hover(&self, params: &Value) -> Value {
+        let text = match self.document(params) {
+            Some(text) => text,
+            None => return Value::Null,
+        };
+        let word = match word_at(text, &params["position"]) {
+            Some(word) => word,
+            None => return Value::Null,
+        };
+
+        let contents = if let Some(def) = definitions(text).get(&word) {
+            let mut contents = format!("`{}` defined with `{}` on line {}", word, def.kind, def.line);
+            if let Some(ref doc) = def.doc {
+                contents.push_str("\n\n");
+                contents.push_str(doc);
+            }
+            contents
+        } else if let Some(Expr::Func(func)) = ops::env().lookup(&word) {
+            format!("`{}` builtin function, taking {} args", word, func.arity())
+        } else if forms::names().contains(&word.as_str()) {
+            format!("`{}` special form", word)
+        } else {
+            return Value::Null;
+        };
+
+        json!({ "contents": { "kind": "markdown", "value": contents } })
+    }
+
+    fn completion(&self, params: &Value) -> Value {
+        let mut items = Vec::new();
+        if let Some(text) = self.document(params) {
+            for name in definitions(text).keys() {
+                items.push(json!({ "label": name, "kind": KIND_VARIABLE }));
+            }
+        }
+        for name in ops::env().names() {
+            items.push(json!({ "label": name, "kind": KIND_FUNCTION }));
+        }
+        for name in forms::names() {
+            items.push(json!({ "label": name, "kind": KIND_KEYWORD }));
+        }
+        Value::Array(items)
+    }
+}
+
+/// A syntax error diagnostic for each form that fails to read.
+fn diagnostics(text: &str) -> Vec<Value> {
+    let mut reader = Reader::new(io::Cursor::new(text.as_bytes()));
+    let mut diagnostics = Vec::new();
+    loop {
+        match reader.read_form() {
+            Ok(Some(_)) => (),
+            Ok(None) => break,
+            Err(err) => {
+                let (line, column) = error_position(&err, reader.line());
+                let start = json!({ "line": line - 1, "character": column - 1 });
+                let end = json!({ "line": line - 1, "character": column });
+                diagnostics.push(json!({
+                    "range": { "start": start, "end": end },
+                    "severity": SEVERITY_ERROR,
+                    "source": "telescope",
+                    "message": err.to_string(),
+                }));
+            }
+        }
+    }
+    diagnostics
+}
+
+/// Where a read error happened, as a 1-based line and column, defaulting to
+/// the start of the last line read.
+fn error_position(err: &Error, line: usize) -> (usize, usize) {
+    let line = line.max(1);
+    match *err.kind() {
+        ErrorKind::Lex(ref lex) => (line, lex.position + 1),
+        ErrorKind::Parse(ref parse) => parse.position
+            .locate()
+            .map(|location| (location.line, location.column))
+            .unwrap_or((line, 1)),
+        _ => (line, 1),
+    }
+}
+
+/// The symbol under an LSP position, if any.
+fn word_at(text: &str, position: &Value) -> Option<String> {
+    let line = text.lines().nth(position["line"].as_u64()? as usize)?;
+    let chars = line.chars().collect::<Vec<_>>();
+    let at = (position["character"].as_u64()? as usize).min(chars.len());
+
+    let is_symbol = |c: &char| !c.is_whitespace() && !"()[]{}\"';^".contains(*c);
+    let start = chars[..at].iter().rposition(|c| !is_symbol(c)).map_or(0, |i| i + 1);
+    let end = chars[at..].iter().position(|c| !is_symbol(c)).map_or(chars.len(), |i| at + i);
+    if start < end {
+        Some(chars[start..end].iter().collect())
+    } else {
+        None
+    }
+}
+
+#[derive(Debug)]
+struct Definition {
+    kind: String,
+    line: usize,
+    doc: Option<String>,
+}
+
+/// The names defined by top-level forms, skipping any that fail to read.
+fn definitions(text: &str) -> HashMap<String, Definition> {
+    let mut defs = HashMap::new();
+    for form in Reader::new(io::Cursor::new(text.as_bytes())).filter_map(|form| form.ok()) {
+        let items = match form.list() {
+            Some(list) => &list.0,
+            None => continue,
+        };
+        let kind = match items.first().and_then(Expr::sym) {
+            Some(sym) => &sym.0,
+            None => continue,
+        };
+        let name = match items.get(1).and_then(Expr::sym) {
+            Some(name) => name,
+            None => continue,
+        };
+        let doc = match kind.as_str() {
+            "def" | "def-dynamic" | "defmulti" | "defrecord" => name.1.get()
+                .and_then(|meta| meta.get(&Key::Keyword("doc".into())))
+                .and_then(|doc| doc.str())
+                .map(String::from),
+            // A docstring only counts if something follows it
+            "defn" if items.len() > 3 => items[2].str().map(String::from),
+            "defn" => None,
+            _ => continue,
+        };
+        let line = Location::of(&form).map_or(0, |location| location.line);
+        defs.insert(name.0.clone(), Definition { kind: kind.clone(), line, doc });
+    }
+    defs
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn request(id: u64, method: &str, params: Value) -> Value {
+        json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+    }
+
+    fn open(server: &mut Server, text: &str) -> Vec<Value> {
+        server.handle(&json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": "file:///a.tl", "text": text } },
+        }))
+    }
+
+    fn position(line: u64, character: u64) -> Value {
+        json!({ "textDocument": { "uri": "file:///a.tl" }, "position": { "line": line, "character": character } })
+    }
+
+    #[test]
+    fn framing() {
+        let mut out = Vec::new();
+        write_message(&mut out, &json!({ "id": 1 })).unwrap();
+        let mut input = io::Cursor::new(out);
+        assert_eq!(Some(json!({ "id": 1 })), read_message(&mut input).unwrap());
+        assert_eq!(None, read_message(&mut input).unwrap());
+    }
+
+    #[test]
+    fn syntax_diagnostics() {
+        let mut server = Server::default();
+        let replies = open(&mut server, "(def x 1)\n(+ x\n  \"oops)");
+        let diagnostics = replies[0]["params"]["diagnostics"].as_array().unwrap();
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(json!(SEVERITY_ERROR), diagnostics[0]["severity"]);
+
+        let replies = open(&mut server, "(def x 1)");
+        assert_eq!(json!([]), replies[0]["params"]["diagnostics"]);
+    }
+
+    #[test]
+    fn hover_docs() {
+        let mut server = Server::default();
+        open(&mut server, "(defn square \"Squares x.\" [x] (* x x))\n(square 2)");
+
+        let reply = server.handle(&request(1, "textDocument/hover", position(1, 3)));
+        let contents = reply[0]["result"]["contents"]["value"].as_str().unwrap();
+        assert!(contents.contains("Squares x."));
+
+        let reply = server.handle(&request(2, "textDocument/hover", position(0, 31)));
+        let contents = reply[0]["result"]["contents"]["value"].as_str().unwrap();
+        assert!(contents.contains("builtin"));
+
+        let reply = server.handle(&request(3, "textDocument/hover", position(1, 0)));
+        assert_eq!(Value::Null, reply[0]["result"]);
+    }
+
+    #[test]
+    fn completions() {
+        let mut server = Server::default();
+        open(&mut server, "(def answer 42)");
+        let reply = server.handle(&request(1, "textDocument/completion", position(0, 0)));
+        let labels = reply[0]["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["label"].as_str().unwrap().to_owned())
+            .collect::<Vec<_>>();
+        for name in &["answer", "first", "defn"] {
+            assert!(labels.contains(&name.to_string()), "missing {}", name);
+        }
+    }
+
+    #[test]
+    fn unknown_requests() {
+        let mut server = Server::default();
+        let reply = server.handle(&request(1, "workspace/symbol", json!({})));
+        assert_eq!(json!(METHOD_NOT_FOUND), reply[0]["error"]["code"]);
+        assert!(server.handle(&json!({ "method": "$/cancelRequest" })).is_empty());
+        server.handle(&json!({ "method": "exit" }));
+        assert!(server.exit);
+    }
+}
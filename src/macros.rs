@@ -0,0 +1,51 @@
+/// Build an `Expr` from Rust values, for host code:
+///
+/// ```ignore
+/// let config = expr!({
+///     :name => "telescope",
+///     :sizes => [1, 2, 3],
+///     :call => list [Expr::new_symbol("+"), x, -1],
+///     :missing => nil,
+/// });
+/// ```
+///
+/// `[...]` is a vector, `list [...]` a list, `{key => value, ...}` a map,
+/// `:name` a keyword, and `nil` is nil. Anything else is a Rust expression,
+/// converted with `Expr::from`. A map with a key that can't be a map key
+/// panics.
+#[macro_export]
+macro_rules! expr {
+    // Split the tokens on commas, and make each part an item or a map entry
+    (@items $kind:ident [$($done:expr,)*] [$($item:tt)+] , $($rest:tt)*) => {
+        expr!(@items $kind [$($done,)* expr!(@each $kind $($item)+),] [] $($rest)*)
+    };
+    (@items $kind:ident [$($done:expr,)*] [$($item:tt)*] $next:tt $($rest:tt)*) => {
+        expr!(@items $kind [$($done,)*] [$($item)* $next] $($rest)*)
+    };
+    (@items $kind:ident [$($done:expr,)*] [$($item:tt)+]) => {
+        vec![$($done,)* expr!(@each $kind $($item)+)]
+    };
+    // Typed, in case there are none
+    (@items item [$($done:expr,)*] []) => {{
+        let items: Vec<$crate::Expr> = vec![$($done),*];
+        items
+    }};
+    (@items entry [$($done:expr,)*] []) => {{
+        let entries: Vec<($crate::Expr, $crate::Expr)> = vec![$($done),*];
+        entries
+    }};
+    (@each item $($item:tt)+) => { expr!($($item)+) };
+    (@each entry $($entry:tt)+) => { expr!(@entry [] $($entry)+) };
+    (@entry [$($key:tt)+] => $($value:tt)+) => { (expr!($($key)+), expr!($($value)+)) };
+    (@entry [$($key:tt)*] $next:tt $($rest:tt)*) => { expr!(@entry [$($key)* $next] $($rest)*) };
+
+    (nil) => { $crate::Expr::Nil };
+    (: $name:ident) => { $crate::Expr::new_keyword(stringify!($name)) };
+    ([ $($items:tt)* ]) => { $crate::Expr::new_vector(expr!(@items item [] [] $($items)*)) };
+    (list [ $($items:tt)* ]) => { $crate::Expr::new_list(expr!(@items item [] [] $($items)*)) };
+    ({ $($entries:tt)* }) => {
+        $crate::Expr::new_map(expr!(@items entry [] [] $($entries)*))
+            .expect("map keys must be nil, bools, ints, strings, or keywords")
+    };
+    ($other:expr) => { $crate::Expr::from($other) };
+}
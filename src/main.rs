@@ -1,55 +1,62 @@
-#![recursion_limit = "1024"]
-#[cfg(test)]
-extern crate float_cmp;
+extern crate atty;
+extern crate clap;
+extern crate telescope;
 
-#[cfg(test)]
-#[macro_use]
-extern crate quickcheck;
+use clap::{App, Arg, SubCommand};
+use telescope::{bundle, color, error, eval, exit, input, lint, lsp, module, ops, server, typecheck};
+use telescope::{graph, warning, Env};
 
-#[macro_use]
-extern crate error_chain;
-#[macro_use]
-extern crate derive_error_chain;
+fn fetch_deps() -> error::Result<()> {
+    module::Manifest::load(&std::env::current_dir()?)?.fetch()
+}
 
-#[macro_use]
-extern crate lazy_static;
+fn bundle_program(entry: &str, output: Option<&str>) -> error::Result<()> {
+    use std::io::Write;
+    let manifest = module::Manifest::load(&std::env::current_dir()?)?;
+    let bundle = bundle::bundle(std::path::Path::new(entry), &manifest)?;
+    match output {
+        Some(path) => std::fs::File::create(path)?.write_all(bundle.as_bytes())?,
+        None => print!("{}", bundle),
+    }
+    Ok(())
+}
 
-extern crate clap;
+fn graph_program(entry: &str, output: Option<&str>) -> error::Result<()> {
+    use std::io::Write;
+    let manifest = module::Manifest::load(&std::env::current_dir()?)?;
+    let dot = graph::Graph::build(std::path::Path::new(entry), &manifest)?.to_dot();
+    match output {
+        Some(path) => writeln!(std::fs::File::create(path)?, "{}", dot)?,
+        None => println!("{}", dot),
+    }
+    Ok(())
+}
 
-#[macro_use]
-extern crate combine;
-extern crate conv;
-extern crate itertools;
-extern crate unicode_xid;
-extern crate rustyline;
-
-mod buffer;
-mod types;
-mod eval;
-mod forms;
-mod lexer;
-mod parser;
-mod ops;
-mod token;
-mod error;
-mod util;
-mod input;
-mod env;
-mod stream;
-
-use clap::{App, Arg};
+fn unused_definitions(entry: &str, json: bool) -> error::Result<bool> {
+    let manifest = module::Manifest::load(&std::env::current_dir()?)?;
+    let warnings = graph::Graph::build(std::path::Path::new(entry), &manifest)?.unused();
+    input::report(&warnings, json);
+    Ok(warnings.is_empty())
+}
 
-fn main() {
-    use std::sync::Arc;
-    println!("Expr: {}", ::std::mem::size_of::<types::Expr>());
-    println!("String: {}", ::std::mem::size_of::<String>());
-    println!("Symbol: {}", ::std::mem::size_of::<types::Symbol>());
-    println!("Arc<Fn>: {}", ::std::mem::size_of::<Arc<types::Function>>());
-    println!("Arc<Macro>: {}", ::std::mem::size_of::<Arc<types::Macro>>());
-    println!("List: {}", ::std::mem::size_of::<types::List>());
-    println!("Vector: {}", ::std::mem::size_of::<types::Vector>());
-    println!("Map: {}", ::std::mem::size_of::<types::Map>());
+/// A new global environment, with the definitions from `image` if given.
+fn start(image: Option<&str>) -> error::Result<Env> {
+    let env = ops::env();
+    if let Some(image) = image {
+        input::file(image, env.clone())?;
+    }
+    Ok(env)
+}
+
+/// Run the `at-exit` functions, then exit with `code`.
+fn finish(code: i32) -> ! {
+    for err in exit::run_hooks() {
+        input::print_error(&err);
+    }
+    ::std::process::exit(code)
+}
 
+fn main() {
     let matches = App::new(env!("CARGO_PKG_NAME"))
         .version(env!("CARGO_PKG_VERSION"))
         .author(env!("CARGO_PKG_AUTHORS"))
@@ -57,33 +64,210 @@ fn main() {
         .arg(Arg::from_usage(
             "-i --interactive 'Run in interactive mode'",
         ))
+        .arg(Arg::from_usage(
+            "--no-color 'Disable colored output'",
+        ))
+        .arg(Arg::from_usage(
+            "--debug 'Check type annotations when functions are called'",
+        ))
+        .arg(Arg::from_usage(
+            "--werror 'Treat warnings as errors'",
+        ))
+        .arg(Arg::from_usage(
+            "--image [file] 'Restore definitions saved with save-image'",
+        ))
+        .arg(Arg::from_usage(
+            "--listen [addr] 'Serve the REPL over TCP, e.g. on 127.0.0.1:7777'",
+        ))
+        .arg(Arg::from_usage(
+            "-w --watch 'Run the input file again each time it changes'",
+        ).requires("input"))
+        .arg(Arg::from_usage(
+            "--fresh 'With --watch, start each run from a fresh environment'",
+        ).requires("watch"))
+        .arg(Arg::from_usage(
+            "--notebook 'With --watch, only run the forms that changed, and those that use them'",
+        ).requires("watch").conflicts_with("fresh"))
         .arg(Arg::from_usage(
             "[input] 'Read program from file (- for stdin)'",
         ))
+        .subcommand(SubCommand::with_name("lsp")
+            .about("Run a Language Server Protocol server over stdio"))
+        .subcommand(SubCommand::with_name("fmt")
+            .about("Reformat source files in place")
+            .arg(Arg::from_usage("--check 'List files that need formatting, without changing them'"))
+            .arg(Arg::from_usage("<files>... 'Files to format'")))
+        .subcommand(SubCommand::with_name("bundle")
+            .about("Combine a program and the modules it requires into one file")
+            .arg(Arg::from_usage("-o --output [file] 'Write the bundle here instead of to stdout'"))
+            .arg(Arg::from_usage("<entry> 'The program to bundle'")))
+        .subcommand(SubCommand::with_name("graph")
+            .about("Print which functions call which, and which modules require which, as DOT")
+            .arg(Arg::from_usage("-o --output [file] 'Write the graph here instead of to stdout'"))
+            .arg(Arg::from_usage("<entry> 'The program to draw'")))
+        .subcommand(SubCommand::with_name("unused")
+            .about("List top-level definitions that the program never uses")
+            .arg(Arg::from_usage("--json 'Print warnings as a JSON array'"))
+            .arg(Arg::from_usage("<entry> 'The program to check'")))
+        .subcommand(SubCommand::with_name("deps")
+            .about("Manage the dependencies listed in deps.tl")
+            .subcommand(SubCommand::with_name("fetch")
+                .about("Clone or update git dependencies into .deps")))
+        .subcommand(SubCommand::with_name("lint")
+            .about("Check source files for likely mistakes, without running them")
+            .arg(Arg::from_usage("--json 'Print warnings as a JSON array'"))
+            .arg(Arg::from_usage("<files>... 'Files to check'")))
+        .subcommand(SubCommand::with_name("check")
+            .about("Check calls and returns against type annotations, without running them")
+            .arg(Arg::from_usage("--json 'Print warnings as a JSON array'"))
+            .arg(Arg::from_usage("<files>... 'Files to check'")))
         .get_matches();
 
-    let env = ops::env();
+    // The protocol owns stdout, so nothing else may print
+    if matches.subcommand_matches("lsp").is_some() {
+        if let Err(err) = lsp::run() {
+            eprintln!("{}", err);
+            ::std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("fmt") {
+        let files = matches.values_of("files").unwrap().collect::<Vec<_>>();
+        match input::format_files(&files, matches.is_present("check")) {
+            Ok(true) => return,
+            Ok(false) => ::std::process::exit(1),
+            Err(err) => {
+                eprintln!("{}", err);
+                ::std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("bundle") {
+        let entry = matches.value_of("entry").unwrap();
+        if let Err(err) = bundle_program(entry, matches.value_of("output")) {
+            eprintln!("{}", err);
+            ::std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("graph") {
+        let entry = matches.value_of("entry").unwrap();
+        if let Err(err) = graph_program(entry, matches.value_of("output")) {
+            eprintln!("{}", err);
+            ::std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("unused") {
+        let entry = matches.value_of("entry").unwrap();
+        match unused_definitions(entry, matches.is_present("json")) {
+            Ok(true) => return,
+            Ok(false) => ::std::process::exit(1),
+            Err(err) => {
+                eprintln!("{}", err);
+                ::std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("deps") {
+        let result = match matches.subcommand_name() {
+            Some("fetch") => fetch_deps(),
+            _ => Err("expected a deps command, such as fetch".into()),
+        };
+        if let Err(err) = result {
+            eprintln!("{}", err);
+            ::std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("lint") {
+        let files = matches.values_of("files").unwrap().collect::<Vec<_>>();
+        match input::lint_files(&files, matches.is_present("json"), lint::lint) {
+            Ok(true) => return,
+            Ok(false) => ::std::process::exit(1),
+            Err(err) => {
+                eprintln!("{}", err);
+                ::std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("check") {
+        let files = matches.values_of("files").unwrap().collect::<Vec<_>>();
+        match input::lint_files(&files, matches.is_present("json"), typecheck::check) {
+            Ok(true) => return,
+            Ok(false) => ::std::process::exit(1),
+            Err(err) => {
+                eprintln!("{}", err);
+                ::std::process::exit(1);
+            }
+        }
+    }
+
+    color::set_enabled(!matches.is_present("no-color") && atty::is(atty::Stream::Stdout));
+    eval::set_debug(matches.is_present("debug"));
+    warning::set_werror(matches.is_present("werror"));
+
+    let image = matches.value_of("image");
+    let env = match start(image) {
+        Ok(env) => env,
+        Err(err) => {
+            input::print_error(&err);
+            ::std::process::exit(1);
+        }
+    };
+
+    if matches.is_present("watch") {
+        let file = matches.value_of("input").unwrap();
+        let fresh = matches.is_present("fresh");
+        let notebook = matches.is_present("notebook");
+        let result = input::watch(file, notebook, || {
+            if fresh { start(image) } else { Ok(env.clone()) }
+        });
+        if let Err(err) = result {
+            input::print_error(&err);
+        }
+        finish(1);
+    }
 
     if let Some(file) = matches.value_of("input") {
-        if file == "-" {
-            
+        let result = if file == "-" {
+            input::stdin(env.clone())
         } else {
-            match input::file(file, env.clone()) {
-                Ok(_) => (),
-                Err(err) => println!("{}", err),
+            input::file(file, env.clone())
+        };
+        if let Err(err) = result {
+            match *err.kind() {
+                error::ErrorKind::Exit(code) => finish(code),
+                _ => input::print_error(&err),
             }
         }
     }
 
+    if let Some(addr) = matches.value_of("listen") {
+        if let Err(err) = server::serve(addr, env.clone()) {
+            input::print_error(&err);
+        }
+        finish(0);
+    }
+
     if !matches.is_present("input") {
-        println!("telescope v{}", env!("CARGO_PKG_VERSION"));
+        println!("{}", input::banner(matches.value_of("image")));
     }
 
     // Run REPL if -i flag supplied or no arguments
+    let mut code = 0;
     if matches.is_present("interactive") || !matches.is_present("input") {
         match input::repl(env.clone()) {
-            Ok(_) => (),
+            Ok(exit) => code = exit,
             Err(err) => println!("{}", err),
         }
     }
+    finish(code)
 }
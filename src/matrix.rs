@@ -0,0 +1,246 @@
+//! Numeric arrays: a flat buffer of floats with a shape, so arithmetic on
+//! tables of numbers doesn't go through nested vectors. Arrays can have any
+//! number of dimensions, but `matmul` and `transpose` need two.
+
+use std::any::Any;
+use std::sync::Arc;
+
+use itertools::Itertools;
+
+use error::*;
+use types::{Expr, Handle, Resource, Vector};
+
+/// The most elements a matrix can have, so that a mistyped size is an error
+/// rather than an allocation that takes down the process.
+pub const MAX_SIZE: usize = 1 << 24;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Matrix {
+    shape: Vec<usize>,
+    // Row-major, so the last index varies fastest
+    data: Vec<f64>,
+}
+
+impl Matrix {
+    pub fn new(shape: Vec<usize>, data: Vec<f64>) -> Result<Matrix> {
+        let size = size(&shape)?;
+        ensure!(
+            size == data.len(),
+            "a {} matrix needs {} elements, not {}", shape_name(&shape), size, data.len()
+        );
+        Ok(Matrix { shape, data })
+    }
+
+    pub fn zeros(shape: Vec<usize>) -> Result<Matrix> {
+        let size = size(&shape)?;
+        Ok(Matrix { shape, data: vec![0.0; size] })
+    }
+
+    pub fn identity(n: usize) -> Result<Matrix> {
+        let mut matrix = Matrix::zeros(vec![n, n])?;
+        for i in 0..n {
+            matrix.data[i * n + i] = 1.0;
+        }
+        Ok(matrix)
+    }
+
+    /// A matrix from nested vectors or lists of numbers, which must be
+    /// rectangular.
+    pub fn from_expr(expr: &Expr) -> Result<Matrix> {
+        let mut shape = Vec::new();
+        let mut level = expr;
+        while let Some(items) = seq(level) {
+            shape.push(items.len());
+            match items.first() {
+                Some(first) => level = first,
+                None => break,
+            }
+        }
+        let mut data = Vec::new();
+        flatten(expr, &shape, &mut data)?;
+        Matrix::new(shape, data)
+    }
+
+    /// The matrix as nested vectors.
+    pub fn to_expr(&self) -> Expr {
+        fn nest(shape: &[usize], data: &[f64]) -> Expr {
+            match shape.split_first() {
+                None => Expr::from(data[0]),
+                Some((&n, rest)) => {
+                    let stride = rest.iter().product::<usize>();
+                    let items = (0..n).map(|i| nest(rest, &data[i * stride..(i + 1) * stride])).collect();
+                    Expr::Vector(Vector::new(items))
+                }
+            }
+        }
+        nest(&self.shape, &self.data)
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    pub fn get(&self, index: &[usize]) -> Result<f64> {
+        ensure!(
+            index.len() == self.shape.len() && index.iter().zip(&self.shape).all(|(i, n)| i < n),
+            "index [{}] is out of bounds for a {} matrix", index.iter().join(" "), shape_name(&self.shape)
+        );
+        let offset = index.iter().zip(&self.shape).fold(0, |offset, (i, n)| offset * n + i);
+        Ok(self.data[offset])
+    }
+
+    pub fn transpose(&self) -> Result<Matrix> {
+        let (rows, cols) = self.dims("transpose")?;
+        let mut data = Vec::with_capacity(self.data.len());
+        for j in 0..cols {
+            for i in 0..rows {
+                data.push(self.data[i * cols + j]);
+            }
+        }
+        Ok(Matrix { shape: vec![cols, rows], data })
+    }
+
+    pub fn matmul(&self, other: &Matrix) -> Result<Matrix> {
+        let ((n, m), (m2, p)) = (self.dims("matmul")?, other.dims("matmul")?);
+        ensure!(
+            m == m2,
+            "can't multiply a {} matrix by a {} matrix", shape_name(&self.shape), shape_name(&other.shape)
+        );
+        let mut product = Matrix::zeros(vec![n, p])?;
+        for i in 0..n {
+            for k in 0..m {
+                let a = self.data[i * m + k];
+                for j in 0..p {
+                    product.data[i * p + j] += a * other.data[k * p + j];
+                }
+            }
+        }
+        Ok(product)
+    }
+
+    /// `op` applied to each pair of elements, of two matrices with the same
+    /// shape.
+    pub fn zip_with<F: Fn(f64, f64) -> f64>(&self, other: &Matrix, op: F) -> Result<Matrix> {
+        ensure!(
+            self.shape == other.shape,
+            "shapes {} and {} don't match", shape_name(&self.shape), shape_name(&other.shape)
+        );
+        let data = self.data.iter().zip(&other.data).map(|(&a, &b)| op(a, b)).collect();
+        Ok(Matrix { shape: self.shape.clone(), data })
+    }
+
+    /// `op` applied to each element.
+    pub fn map<F: Fn(f64) -> f64>(&self, op: F) -> Matrix {
+        Matrix { shape: self.shape.clone(), data: self.data.iter().map(|&x| op(x)).collect() }
+    }
+
+    pub fn into_handle(self) -> Handle {
+        Handle::new(Arc::new(self))
+    }
+
+    fn dims(&self, name: &str) -> Result<(usize, usize)> {
+        ensure!(
+            self.shape.len() == 2,
+            "#[{}] expected a 2-dimensional matrix, got {}", name, shape_name(&self.shape)
+        );
+        Ok((self.shape[0], self.shape[1]))
+    }
+}
+
+impl Resource for Matrix {
+    fn type_name(&self) -> &'static str {
+        "matrix"
+    }
+
+    fn as_any(&self) -> &Any {
+        self
+    }
+
+    fn describe(&self) -> String {
+        format!("#matrix{}", self.to_expr())
+    }
+}
+
+fn seq(expr: &Expr) -> Option<&[Expr]> {
+    match *expr {
+        Expr::List(ref list) => Some(&list.0),
+        Expr::Vector(ref vec) => Some(&vec.0),
+        _ => None,
+    }
+}
+
+fn flatten(expr: &Expr, shape: &[usize], data: &mut Vec<f64>) -> Result<()> {
+    match (shape.split_first(), seq(expr)) {
+        (None, None) => match *expr {
+            Expr::Int(i) => data.push(i as f64),
+            Expr::Flt(f) => data.push(f),
+            _ => bail!("matrices can only hold numbers, not {}", expr),
+        },
+        (Some((&n, rest)), Some(items)) if items.len() == n => {
+            for item in items {
+                flatten(item, rest, data)?;
+            }
+        }
+        _ => bail!("matrix rows must all be the same shape"),
+    }
+    Ok(())
+}
+
+// The number of elements in a matrix of `shape`, if it's no more than MAX_SIZE
+fn size(shape: &[usize]) -> Result<usize> {
+    let size = shape.iter().fold(Some(1), |size: Option<usize>, &n| {
+        size.and_then(|size| size.checked_mul(n))
+    });
+    match size {
+        Some(size) if size <= MAX_SIZE => Ok(size),
+        _ => bail!("a {} matrix has more than {} elements", shape_name(shape), MAX_SIZE),
+    }
+}
+
+fn shape_name(shape: &[usize]) -> String {
+    shape.iter().join("x")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use parser;
+
+    fn matrix(source: &str) -> Matrix {
+        Matrix::from_expr(&parser::parse_expr(source).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn construction() {
+        let m = matrix("[[1 2 3] [4 5 6]]");
+        assert_eq!(&[2, 3], m.shape());
+        assert_eq!(6.0, m.get(&[1, 2]).unwrap());
+        assert!(m.get(&[2, 0]).is_err());
+        assert_eq!(parser::parse_expr("[[1.0 2.0 3.0] [4.0 5.0 6.0]]").unwrap(), m.to_expr());
+        assert_eq!(&[0], matrix("[]").shape());
+        assert!(Matrix::from_expr(&parser::parse_expr("[[1 2] [3]]").unwrap()).is_err());
+        assert!(Matrix::from_expr(&parser::parse_expr("[[1 :a]]").unwrap()).is_err());
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(6, Matrix::zeros(vec![2, 3]).unwrap().data.len());
+        assert!(Matrix::zeros(vec![100_000, 100_000]).is_err());
+        assert!(Matrix::zeros(vec![usize::max_value(), 2]).is_err());
+        assert!(Matrix::identity(1 << 16).is_err());
+        let wide = Matrix::zeros(vec![1 << 20, 1]).unwrap();
+        assert!(wide.matmul(&wide.transpose().unwrap()).is_err());
+    }
+
+    #[test]
+    fn arithmetic() {
+        let m = matrix("[[1 2] [3 4]]");
+        assert_eq!(matrix("[[1 3] [2 4]]"), m.transpose().unwrap());
+        assert_eq!(matrix("[[7 10] [15 22]]"), m.matmul(&m).unwrap());
+        assert_eq!(m, m.matmul(&Matrix::identity(2).unwrap()).unwrap());
+        assert_eq!(matrix("[[2 4] [6 8]]"), m.zip_with(&m, |a, b| a + b).unwrap());
+        assert_eq!(matrix("[[2 4] [6 8]]"), m.map(|x| x * 2.0));
+        assert!(m.matmul(&matrix("[[1 2 3]]")).is_err());
+        assert!(matrix("[1 2]").transpose().is_err());
+    }
+}
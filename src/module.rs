@@ -0,0 +1,294 @@
+//! Loading code from other files with `require`, and fetching the libraries
+//! listed in a project's `deps.tl` manifest.
+//!
+//! The manifest is a single map from module names to where they come from,
+//! either a local path or a git repository:
+//!
+//! ```clojure
+//! {:strings "vendor/strings.tl"
+//!  :json {:git "https://github.com/someone/json.tl" :rev "v1.0"}}
+//! ```
+//!
+//! Git dependencies are cloned into `.deps/<name>` by `tele deps fetch`. A
+//! dependency that's a directory is loaded from `<name>.tl` inside it. Modules
+//! not in the manifest are loaded from `<name>.tl` in the current directory.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use env::Env;
+use error::*;
+use reader::Reader;
+use types::{Expr, Key, Map};
+
+pub const MANIFEST: &str = "deps.tl";
+pub const DEPS_DIR: &str = ".deps";
+
+// The top-level forms that reload evaluates again
+const DEFINITIONS: &[&str] = &[
+    "def", "def-dynamic", "defn", "defmacro", "defmulti", "defmethod", "defrecord",
+];
+
+/// Where a dependency comes from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Source {
+    Path(PathBuf),
+    Git { url: String, rev: Option<String> },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dependency {
+    pub name: String,
+    pub source: Source,
+}
+
+/// The dependencies declared by a project.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Manifest {
+    pub dir: PathBuf,
+    pub deps: Vec<Dependency>,
+}
+
+impl Manifest {
+    /// Read `deps.tl` from `dir`, or an empty manifest if there isn't one.
+    pub fn load(dir: &Path) -> Result<Manifest> {
+        let path = dir.join(MANIFEST);
+        if !path.exists() {
+            return Ok(Manifest { dir: dir.to_owned(), deps: Vec::new() });
+        }
+        let forms = Reader::from_read(fs::File::open(&path)?)
+            .with_file(path.to_string_lossy().into_owned())
+            .collect::<Result<Vec<_>>>()?;
+        Manifest::parse(dir, &forms)
+    }
+
+    fn parse(dir: &Path, forms: &[Expr]) -> Result<Manifest> {
+        let deps = match forms.first() {
+            None => Vec::new(),
+            Some(&Expr::Map(ref map)) if forms.len() == 1 => {
+                let mut deps = map.iter()
+                    .map(|(key, source)| dependency(key, source))
+                    .collect::<Result<Vec<_>>>()?;
+                deps.sort_by(|a, b| a.name.cmp(&b.name));
+                deps
+            }
+            _ => bail!("{} should contain a single map", MANIFEST),
+        };
+        Ok(Manifest { dir: dir.to_owned(), deps })
+    }
+
+    /// The file a module is loaded from.
+    pub fn resolve(&self, name: &str) -> PathBuf {
+        let dir = match self.deps.iter().find(|dep| dep.name == name) {
+            Some(&Dependency { source: Source::Path(ref path), .. }) => self.dir.join(path),
+            Some(&Dependency { source: Source::Git { .. }, .. }) => {
+                self.dir.join(DEPS_DIR).join(name)
+            }
+            None => return self.dir.join(format!("{}.tl", name)),
+        };
+        if dir.is_dir() {
+            dir.join(format!("{}.tl", module_file(name)))
+        } else {
+            dir
+        }
+    }
+
+    /// Clone or update every git dependency.
+    pub fn fetch(&self) -> Result<()> {
+        for dep in &self.deps {
+            if let Source::Git { ref url, ref rev } = dep.source {
+                let target = self.dir.join(DEPS_DIR).join(&dep.name);
+                if target.exists() {
+                    println!("updating {}", dep.name);
+                    git(&target, &["fetch", "--quiet", "origin"])?;
+                } else {
+                    println!("fetching {} from {}", dep.name, url);
+                    fs::create_dir_all(self.dir.join(DEPS_DIR))?;
+                    let target = target.to_string_lossy().into_owned();
+                    // A url like --upload-pack=... would be read as an option
+                    git(&self.dir, &["clone", "--quiet", "--", url, &target])?;
+                }
+                let rev = rev.as_ref().map(String::as_str).unwrap_or("origin/HEAD");
+                git(&target, &["checkout", "--quiet", rev])?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn dependency(key: &Key, source: &Expr) -> Result<Dependency> {
+    let name = match *key {
+        Key::Keyword(ref name) => name.clone(),
+        _ => bail!("dependency names should be keywords, got {:?}", key),
+    };
+    let source = match *source {
+        Expr::Str(ref path) => Source::Path(PathBuf::from(path)),
+        Expr::Map(ref map) => {
+            let url = string(map, "git")
+                .ok_or_else(|| format!("dependency {} needs a path or :git url", name))?;
+            let rev = string(map, "rev");
+            // Revs are passed to git checkout, which would read these as options
+            if let Some(ref rev) = rev {
+                ensure!(!rev.starts_with('-'), "dependency {} has a bad :rev {:?}", name, rev);
+            }
+            Source::Git { url, rev }
+        }
+        ref other => bail!("dependency {} should be a path or a map, got {}", name, other),
+    };
+    Ok(Dependency { name, source })
+}
+
+fn string(map: &Map, key: &str) -> Option<String> {
+    map.get(&Key::Keyword(key.to_owned())).and_then(Expr::str).map(String::from)
+}
+
+// The last part of a module name like util/strings
+fn module_file(name: &str) -> &str {
+    name.rsplit('/').next().unwrap_or(name)
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<()> {
+    let status = Command::new("git").args(args).current_dir(dir).status()?;
+    ensure!(status.success(), "git {} failed", args.join(" "));
+    Ok(())
+}
+
+/// Load a module into the top-level environment, unless it's already been
+/// loaded there. Returns whether it was loaded now.
+pub fn require(name: &str, env: Env) -> Result<bool> {
+    let (path, key) = locate(name)?;
+    if !env.with_modules(|modules| modules.insert(key.clone())) {
+        return Ok(false);
+    }
+    let env = env.root();
+    let result = Reader::from_read(fs::File::open(&path)?)
+        .with_file(path.to_string_lossy().into_owned())
+        .map(|form| form.and_then(|form| form.eval(env.clone())))
+        .collect::<Result<Vec<_>>>();
+    if result.is_err() {
+        // Let a fixed module be required again
+        env.with_modules(|modules| modules.remove(&key));
+    }
+    result.map(|_| true)
+}
+
+/// Evaluate the definitions in a module that's been loaded again, after its
+/// file has changed, and return the names they define. Code that refers to
+/// them by name uses the new versions from then on. Other top-level forms, like
+/// ones that print or start a server, aren't run again, and nothing is run if
+/// the file doesn't read.
+pub fn reload(name: &str, env: Env) -> Result<Vec<String>> {
+    let (path, key) = locate(name)?;
+    ensure!(
+        env.with_modules(|modules| modules.contains(&key)),
+        "module {} hasn't been required, so there's nothing to reload", name
+    );
+    reload_file(&path, env)
+}
+
+fn reload_file(path: &Path, env: Env) -> Result<Vec<String>> {
+    let forms = Reader::from_read(fs::File::open(path)?)
+        .with_file(path.to_string_lossy().into_owned())
+        .collect::<Result<Vec<_>>>()?;
+    let env = env.root();
+    let mut names = Vec::new();
+    for form in forms {
+        if let Some(name) = definition(&form) {
+            form.eval(env.clone())?;
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// The name a top-level form defines, if it's a definition.
+pub(crate) fn definition(form: &Expr) -> Option<String> {
+    let items = &form.list()?.0;
+    let head = items.first()?.sym()?;
+    if !DEFINITIONS.contains(&head.0.as_str()) {
+        return None;
+    }
+    items.get(1)?.sym().map(|name| name.0.clone())
+}
+
+// The file a module is loaded from, and its canonical path, which identifies it
+fn locate(name: &str) -> Result<(PathBuf, PathBuf)> {
+    let dir = ::std::env::current_dir()?;
+    let path = Manifest::load(&dir)?.resolve(name);
+    let key = path.canonicalize().map_err(|err| -> Error {
+        match err.kind() {
+            io::ErrorKind::NotFound => format!("module {} not found at {}", name, path.display()).into(),
+            _ => err.into(),
+        }
+    })?;
+    Ok((path, key))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use parser;
+
+    fn manifest(source: &str) -> Result<Manifest> {
+        Manifest::parse(Path::new("project"), &parser::parse_program(source)?)
+    }
+
+    #[test]
+    fn parse_manifest() {
+        let deps = manifest(r#"{:strings "vendor/strings.tl" :json {:git "https://example.com/json" :rev "v1"}}"#)
+            .unwrap()
+            .deps;
+        assert_eq!(
+            vec![
+                Dependency {
+                    name: "json".into(),
+                    source: Source::Git { url: "https://example.com/json".into(), rev: Some("v1".into()) },
+                },
+                Dependency { name: "strings".into(), source: Source::Path("vendor/strings.tl".into()) },
+            ],
+            deps
+        );
+        assert!(manifest("").unwrap().deps.is_empty());
+        assert!(manifest("{:json {:rev \"v1\"}}").is_err());
+        assert!(manifest("{:json {:git \"u\" :rev \"--orphan=x\"}}").is_err());
+        assert!(manifest("[1 2]").is_err());
+    }
+
+    #[test]
+    fn resolve_modules() {
+        let manifest = manifest(r#"{:strings "vendor/strings.tl" :json {:git "u"}}"#).unwrap();
+        assert_eq!(Path::new("project/vendor/strings.tl"), manifest.resolve("strings"));
+        assert_eq!(Path::new("project/.deps/json"), manifest.resolve("json"));
+        assert_eq!(Path::new("project/util/text.tl"), manifest.resolve("util/text"));
+    }
+
+    #[test]
+    fn reload_definitions() {
+        use std::io::Write;
+        use files;
+        use ops;
+
+        let path = files::temp_file().unwrap();
+        let write = |source: &str| {
+            fs::File::create(&path).unwrap().write_all(source.as_bytes()).unwrap()
+        };
+        let env = ops::env();
+        let eval = |source: &str| Expr::eval_all(&parser::parse_program(source).unwrap(), env.clone());
+
+        eval("(def calls (atom 0)) (defn f [] 1) (def x 2)").unwrap();
+        write("(defn f [] 10) (swap! calls + 1) (def y 3) (defn f [] 11)");
+        assert_eq!(vec!["f", "y"], reload_file(&path, env.clone()).unwrap());
+        assert_eq!(Expr::from(11), eval("(f)").unwrap());
+        assert_eq!(Expr::from(0), eval("(deref calls)").unwrap());
+        assert_eq!(Expr::from(2), eval("x").unwrap());
+
+        write("(defn f [] 12) (defn broken");
+        assert!(reload_file(&path, env.clone()).is_err());
+        assert_eq!(Expr::from(11), eval("(f)").unwrap());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,161 @@
+use std::any::Any;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+
+use env::Env;
+use error::*;
+use types::{Arity, Expr, Handle, Lambda, Resource};
+use util::*;
+
+/// A connected TCP stream. Reads are buffered by line, and reading and
+/// writing can happen on different threads at once.
+#[derive(Debug)]
+pub struct Socket {
+    reader: Mutex<BufReader<TcpStream>>,
+    writer: Mutex<TcpStream>,
+}
+
+impl Socket {
+    pub fn connect(addr: &str) -> Result<Handle> {
+        Socket::wrap(TcpStream::connect(addr)?)
+    }
+
+    fn wrap(stream: TcpStream) -> Result<Handle> {
+        let writer = stream.try_clone()?;
+        Ok(Handle::new(Arc::new(Socket {
+            reader: Mutex::new(BufReader::new(stream)),
+            writer: Mutex::new(writer),
+        })))
+    }
+
+    /// Read a line, without its line ending, or None once the peer has
+    /// closed the connection.
+    pub fn read_line(&self) -> Result<Option<String>> {
+        let mut line = String::new();
+        if lock(&self.reader).read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        if line.ends_with('\n') {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+        Ok(Some(line))
+    }
+
+    pub fn write(&self, data: &str) -> Result<()> {
+        let mut writer = lock(&self.writer);
+        writer.write_all(data.as_bytes())?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn close(&self) -> Result<()> {
+        lock(&self.writer).shutdown(Shutdown::Both)?;
+        Ok(())
+    }
+}
+
+impl Resource for Socket {
+    fn type_name(&self) -> &'static str {
+        "socket"
+    }
+
+    fn as_any(&self) -> &Any {
+        self
+    }
+}
+
+/// A TCP server socket waiting for connections.
+#[derive(Debug)]
+pub struct Listener(TcpListener);
+
+impl Listener {
+    pub fn bind(addr: &str) -> Result<Handle> {
+        Ok(Handle::new(Arc::new(Listener(TcpListener::bind(addr)?))))
+    }
+
+    /// The address actually bound, which tells the port when binding port 0.
+    pub fn local_addr(&self) -> Result<String> {
+        Ok(self.0.local_addr()?.to_string())
+    }
+
+    /// Wait for the next connection.
+    pub fn accept(&self) -> Result<Handle> {
+        let (stream, _) = self.0.accept()?;
+        Socket::wrap(stream)
+    }
+}
+
+impl Resource for Listener {
+    fn type_name(&self) -> &'static str {
+        "listener"
+    }
+
+    fn as_any(&self) -> &Any {
+        self
+    }
+}
+
+/// The builtins for TCP sockets, for `ops::env`.
+pub fn builtins() -> Vec<(&'static str, Arity, Lambda)> {
+    vec![
+        ("tcp-connect", Arity::exact(1), tcp_connect),
+        ("tcp-listen", Arity::exact(1), tcp_listen),
+        ("tcp-accept", Arity::exact(1), tcp_accept),
+        ("tcp-addr", Arity::exact(1), tcp_addr),
+        ("tcp-read", Arity::exact(1), tcp_read),
+        ("tcp-write", Arity::exact(2), tcp_write),
+        ("tcp-close", Arity::exact(1), tcp_close),
+    ]
+}
+
+// (tcp-connect "host:port")
+fn tcp_connect(args: &[Expr], _env: Env) -> Result<Expr> {
+    let addr = Args::new("tcp-connect", args).str(0)?;
+    Ok(Expr::Handle(Socket::connect(addr)?))
+}
+
+// (tcp-listen "host:port")
+fn tcp_listen(args: &[Expr], _env: Env) -> Result<Expr> {
+    let addr = Args::new("tcp-listen", args).str(0)?;
+    Ok(Expr::Handle(Listener::bind(addr)?))
+}
+
+// (tcp-accept listener)
+fn tcp_accept(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("tcp-accept", args);
+    let listener = ensure_resource::<Listener>("tcp-accept", args.get(0)?, "listener")?;
+    Ok(Expr::Handle(listener.accept()?))
+}
+
+// (tcp-addr listener)
+fn tcp_addr(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("tcp-addr", args);
+    let listener = ensure_resource::<Listener>("tcp-addr", args.get(0)?, "listener")?;
+    Ok(Expr::from(listener.local_addr()?))
+}
+
+// (tcp-read socket)
+fn tcp_read(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("tcp-read", args);
+    let socket = ensure_resource::<Socket>("tcp-read", args.get(0)?, "socket")?;
+    Ok(socket.read_line()?.map(Expr::from).unwrap_or(Expr::Nil))
+}
+
+// (tcp-write socket string)
+fn tcp_write(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("tcp-write", args);
+    let socket = ensure_resource::<Socket>("tcp-write", args.get(0)?, "socket")?;
+    socket.write(args.str(1)?)?;
+    Ok(Expr::Nil)
+}
+
+// (tcp-close socket)
+fn tcp_close(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("tcp-close", args);
+    ensure_resource::<Socket>("tcp-close", args.get(0)?, "socket")?.close()?;
+    Ok(Expr::Nil)
+}
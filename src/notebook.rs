@@ -0,0 +1,198 @@
+//! Notebook mode, for `--watch --notebook`: when a file changes, only the
+//! top-level forms that changed are evaluated again, along with the forms that
+//! use what they define, and the forms after them that use those, and so on.
+//! Everything else keeps the value it had, so a slow form at the top of a
+//! script doesn't run every time something below it is edited.
+//!
+//! Forms are told apart by a hash of their contents, ignoring layout and
+//! comments, so moving a form or reformatting it doesn't count as a change.
+
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::Read;
+
+use env::Env;
+use error::*;
+use location;
+use module;
+use reader::Reader;
+use types::Expr;
+
+// A top-level form from the last run
+#[derive(Clone, Debug)]
+struct Cell {
+    // None if the form hasn't run successfully, so it has to run again
+    hash: Option<u64>,
+    defines: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Notebook {
+    env: Env,
+    cells: Vec<Cell>,
+}
+
+impl Notebook {
+    pub fn new(env: Env) -> Notebook {
+        Notebook { env, cells: Vec::new() }
+    }
+
+    pub fn run_file(&mut self, path: &str) -> Result<Vec<usize>> {
+        let mut source = String::new();
+        fs::File::open(path)?.read_to_string(&mut source)?;
+        self.run(&source, Some(path))
+    }
+
+    /// Evaluate the forms in `source` that are new or changed since the last
+    /// run, and the ones depending on them, returning their indices. If one
+    /// fails, it and the forms after it run again next time.
+    pub fn run(&mut self, source: &str, file: Option<&str>) -> Result<Vec<usize>> {
+        let mut reader = Reader::from_read(source.as_bytes());
+        if let Some(file) = file {
+            reader = reader.with_file(file);
+        }
+        let forms = reader.collect::<Result<Vec<_>>>()?;
+
+        // Forms can appear more than once, so count how many of each there were
+        let mut unchanged = HashMap::new();
+        for hash in self.cells.iter().filter_map(|cell| cell.hash) {
+            *unchanged.entry(hash).or_insert(0) += 1;
+        }
+        let mut cells = forms
+            .iter()
+            .map(|form| Cell { hash: Some(digest(form)), defines: module::definition(form) })
+            .collect::<Vec<_>>();
+
+        // Names whose definitions changed or went away
+        let mut stale = HashSet::new();
+        for cell in &self.cells {
+            if cell.hash.map_or(true, |hash| !cells.iter().any(|c| c.hash == Some(hash))) {
+                stale.extend(cell.defines.clone());
+            }
+        }
+
+        let mut run = Vec::new();
+        let mut failure = None;
+        for (i, (form, cell)) in forms.iter().zip(&cells).enumerate() {
+            let hash = cell.hash.unwrap_or_default();
+            let seen = match unchanged.get_mut(&hash) {
+                Some(count) if *count > 0 => {
+                    *count -= 1;
+                    true
+                }
+                _ => false,
+            };
+            if seen && !uses_any(form, &stale) {
+                continue;
+            }
+            stale.extend(cell.defines.clone());
+            run.push(i);
+            location::reset();
+            if let Err(err) = form.eval(self.env.clone()) {
+                failure = Some((i, err));
+                break;
+            }
+        }
+
+        if let Some((i, err)) = failure {
+            for cell in &mut cells[i..] {
+                cell.hash = None;
+            }
+            self.cells = cells;
+            return Err(err);
+        }
+        self.cells = cells;
+        Ok(run)
+    }
+}
+
+// Whether `form` refers to any of `names`
+fn uses_any(form: &Expr, names: &HashSet<String>) -> bool {
+    match *form {
+        Expr::Sym(ref sym) => names.contains(&sym.0),
+        Expr::List(ref list) => list.0.iter().any(|form| uses_any(form, names)),
+        Expr::Vector(ref vec) => vec.0.iter().any(|form| uses_any(form, names)),
+        Expr::Map(ref map) => map.iter().any(|(_, form)| uses_any(form, names)),
+        _ => false,
+    }
+}
+
+// A hash of a form's contents. Unlike its printed form, this tells 1 from 1.0,
+// and it leaves out metadata, like where the form is.
+fn digest(form: &Expr) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    feed(form, &mut hasher);
+    hasher.finish()
+}
+
+fn feed<H: Hasher>(form: &Expr, hasher: &mut H) {
+    form.type_name().hash(hasher);
+    match *form {
+        Expr::Bool(b) => b.hash(hasher),
+        Expr::Int(i) => i.hash(hasher),
+        Expr::Flt(f) => f.to_bits().hash(hasher),
+        Expr::Str(ref s) | Expr::Keyword(ref s) => s.hash(hasher),
+        Expr::Sym(ref sym) => sym.0.hash(hasher),
+        Expr::Bytes(ref bytes) => bytes.hash(hasher),
+        Expr::List(ref list) => feed_all(&list.0, hasher),
+        Expr::Vector(ref vec) => feed_all(&vec.0, hasher),
+        // Entries come in any order, so combine them in a way that doesn't
+        // depend on it
+        Expr::Map(ref map) => {
+            let entries = map.iter().fold(0u64, |sum, (key, value)| {
+                let mut entry = DefaultHasher::new();
+                feed(&key.to_expr(), &mut entry);
+                feed(value, &mut entry);
+                sum.wrapping_add(entry.finish())
+            });
+            entries.hash(hasher);
+        }
+        ref other => other.to_string().hash(hasher),
+    }
+}
+
+fn feed_all<H: Hasher>(forms: &[Expr], hasher: &mut H) {
+    forms.len().hash(hasher);
+    for form in forms {
+        feed(form, hasher);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ops;
+    use parser;
+
+    fn eval(notebook: &Notebook, source: &str) -> Expr {
+        parser::parse_expr(source).unwrap().eval(notebook.env.clone()).unwrap()
+    }
+
+    #[test]
+    fn rerunning() {
+        let mut notebook = Notebook::new(ops::env());
+        let source = "(def calls (atom 0))\n(defn slow [] (swap! calls + 1) 10)\n(def a (slow))\n\
+                      (def b 2)\n(def total (+ a b))";
+        assert_eq!(vec![0, 1, 2, 3, 4], notebook.run(source, None).unwrap());
+
+        // Reformatting and comments aren't changes
+        let reformatted = source.replace("(def b 2)", "; two\n(def   b\n  2)");
+        assert!(notebook.run(&reformatted, None).unwrap().is_empty());
+
+        // Only b, and total, which uses it, run again
+        let changed = source.replace("(def b 2)", "(def b 3)");
+        assert_eq!(vec![3, 4], notebook.run(&changed, None).unwrap());
+        assert_eq!(Expr::from(13), eval(&notebook, "total"));
+        assert_eq!(Expr::from(1), eval(&notebook, "(deref calls)"));
+
+        // A failing form, and the ones after it, run again next time
+        let failing = changed.replace("(def b 3)", "(def b (undefined))");
+        assert!(notebook.run(&failing, None).is_err());
+        assert_eq!(vec![3, 4], notebook.run(&changed, None).unwrap());
+
+        assert_eq!(digest(&Expr::from(1)), digest(&Expr::from(1)));
+        assert!(digest(&Expr::from(1)) != digest(&Expr::from(1.0)));
+    }
+}
@@ -1,229 +1,793 @@
+use std::cmp::Ordering;
 use std::collections::HashMap;
-use std::ops::{Sub, Div};
-use itertools::Itertools;
+use std::fs;
+use std::io::{Read, Write};
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::ops::{Add, Sub, Mul, Div};
+use std::sync::Mutex;
+use unicode_segmentation::UnicodeSegmentation;
 use error::*;
 use env::Env;
-use types::{Expr, List, Vector, Function, Lambda};
+use types::{Arity, Expr, Key, List, Map, Vector, Function, Lambda, Symbol};
+use atom::{self, Atom};
+use channel::Channel;
+use future::{Future, Task};
+use generator::{self, Generator};
+#[cfg(feature = "net")]
+use net;
+use csv;
+use deque::Deque;
+use diff;
+use encoding;
+use eval;
+use exit;
+use files;
+use fmt;
+use format::{self, Template};
+use help;
+use image;
+use log;
+use sorted::{PriorityQueue, SortedMap};
+use strings::{self, StringBuilder};
+use transient::Transient;
+use matrix::Matrix;
+use order;
+use parser;
+use reader_macro;
+use typecheck;
+use port;
 use util::*;
 
 pub fn env() -> Env {
-    let table: Vec<(&str, Lambda)> = vec![
-        ("not", not),
-        ("+", add),
-        ("-", sub),
-        ("*", mul),
-        ("/", div),
-        ("=", equal),
-        ("<", less),
-        ("<=", less_eq),
-        (">", greater),
-        (">=", greater_eq),
-        ("first", first),
-        ("rest", rest),
-        ("cons", cons),
-        ("list", list),
-        ("print", print),
-        ("debug", debug),
-        ("eval", eval),
-        ("exit", exit),
+    let table: Vec<(&str, Arity, Lambda)> = vec![
+        ("not", Arity::exact(1), not),
+        ("+", Arity::at_least(0), add),
+        ("-", Arity::at_least(1), sub),
+        ("*", Arity::at_least(0), mul),
+        ("/", Arity::at_least(1), div),
+        ("quot", Arity::exact(2), quot),
+        ("rem", Arity::exact(2), rem),
+        ("mod", Arity::exact(2), modulo),
+        ("checked-add", Arity::exact(2), checked_add),
+        ("checked-sub", Arity::exact(2), checked_sub),
+        ("checked-mul", Arity::exact(2), checked_mul),
+        ("wrapping-add", Arity::exact(2), wrapping_add),
+        ("wrapping-sub", Arity::exact(2), wrapping_sub),
+        ("wrapping-mul", Arity::exact(2), wrapping_mul),
+        ("saturating-add", Arity::exact(2), saturating_add),
+        ("saturating-sub", Arity::exact(2), saturating_sub),
+        ("saturating-mul", Arity::exact(2), saturating_mul),
+        ("=", Arity::exact(2), equal),
+        ("<", Arity::exact(2), less),
+        ("<=", Arity::exact(2), less_eq),
+        (">", Arity::exact(2), greater),
+        (">=", Arity::exact(2), greater_eq),
+        ("first", Arity::exact(1), first),
+        ("rest", Arity::exact(1), rest),
+        ("cons", Arity::exact(2), cons),
+        ("list", Arity::at_least(0), list),
+        ("values", Arity::at_least(0), values),
+        ("range", Arity::range(1, 3), range),
+        ("even?", Arity::exact(1), even),
+        ("sum", Arity::exact(1), sum),
+        ("product", Arity::exact(1), product),
+        ("mean", Arity::exact(1), mean),
+        ("median", Arity::exact(1), median),
+        ("stddev", Arity::exact(1), stddev),
+        ("matrix", Arity::exact(1), matrix),
+        ("matrix/zeros", Arity::at_least(1), matrix_zeros),
+        ("matrix/identity", Arity::exact(1), matrix_identity),
+        ("matrix/shape", Arity::exact(1), matrix_shape),
+        ("matrix/get", Arity::at_least(1), matrix_get),
+        ("matrix/to-vector", Arity::exact(1), matrix_to_vector),
+        ("matrix/add", Arity::exact(2), matrix_add),
+        ("matrix/sub", Arity::exact(2), matrix_sub),
+        ("matrix/mul", Arity::exact(2), matrix_mul),
+        ("matmul", Arity::exact(2), matmul),
+        ("transpose", Arity::exact(1), transpose),
+        ("map", Arity::exact(2), map),
+        ("filter", Arity::exact(2), filter),
+        ("remove", Arity::exact(2), remove),
+        ("keep", Arity::exact(2), keep),
+        ("some", Arity::exact(2), some),
+        ("every?", Arity::exact(2), every),
+        ("not-any?", Arity::exact(2), not_any),
+        ("group-by", Arity::exact(2), group_by),
+        ("frequencies", Arity::exact(1), frequencies),
+        ("partition", Arity::range(2, 3), partition),
+        ("partition-by", Arity::exact(2), partition_by),
+        ("interleave", Arity::at_least(1), interleave),
+        ("zip", Arity::at_least(1), zip),
+        ("zipmap", Arity::exact(2), zipmap),
+        ("distinct", Arity::exact(1), distinct),
+        ("flatten", Arity::exact(1), flatten),
+        ("odd?", Arity::exact(1), odd),
+        ("nan?", Arity::exact(1), is_nan),
+        ("infinite?", Arity::exact(1), is_infinite),
+        ("get", Arity::range(2, 3), get),
+        ("get-in", Arity::range(2, 3), get_in),
+        ("assoc", Arity::exact(3), assoc),
+        ("assoc-in", Arity::exact(3), assoc_in),
+        ("update", Arity::at_least(3), update),
+        ("update-in", Arity::at_least(3), update_in),
+        ("merge", Arity::at_least(0), merge),
+        ("merge-with", Arity::at_least(1), merge_with),
+        ("select-keys", Arity::exact(2), select_keys),
+        ("rename-keys", Arity::exact(2), rename_keys),
+        ("transient", Arity::exact(1), transient),
+        ("conj!", Arity::exact(2), conj_transient),
+        ("assoc!", Arity::exact(3), assoc_transient),
+        ("persistent!", Arity::exact(1), persistent),
+        ("deque", Arity::at_least(0), deque),
+        ("push-front!", Arity::exact(2), push_front),
+        ("push-back!", Arity::exact(2), push_back),
+        ("pop-front!", Arity::exact(1), pop_front),
+        ("pop-back!", Arity::exact(1), pop_back),
+        ("peek-front", Arity::exact(1), peek_front),
+        ("peek-back", Arity::exact(1), peek_back),
+        ("deque-len", Arity::exact(1), deque_len),
+        ("deque->vector", Arity::exact(1), deque_to_vector),
+        ("sorted-map", Arity::at_least(0), sorted_map),
+        ("sorted-map/entries", Arity::exact(1), sorted_map_entries),
+        ("sorted-map/first", Arity::exact(1), sorted_map_first),
+        ("sorted-map/last", Arity::exact(1), sorted_map_last),
+        ("sorted-map/range", Arity::exact(3), sorted_map_range),
+        ("priority-queue", Arity::at_least(0), priority_queue),
+        ("insert!", Arity::exact(2), insert),
+        ("peek-min", Arity::exact(1), peek_min),
+        ("pop-min!", Arity::exact(1), pop_min),
+        ("priority-queue-len", Arity::exact(1), priority_queue_len),
+        ("meta", Arity::exact(1), meta),
+        ("type", Arity::exact(1), type_of),
+        ("instance?", Arity::exact(2), instance),
+        ("with-meta", Arity::exact(2), with_meta),
+        ("str", Arity::at_least(0), str),
+        ("join", Arity::range(1, 2), join),
+        ("sb", Arity::range(0, 1), sb),
+        ("sb-append!", Arity::at_least(1), sb_append),
+        ("sb-str", Arity::exact(1), sb_str),
+        ("normalize", Arity::range(1, 2), normalize),
+        ("casefold", Arity::exact(1), casefold),
+        ("compare-str", Arity::exact(2), compare_str),
+        ("compare", Arity::exact(2), compare),
+        ("sort", Arity::exact(1), sort),
+        ("sort-by", Arity::exact(2), sort_by),
+        ("min", Arity::at_least(1), min),
+        ("max", Arity::at_least(1), max),
+        ("min-key", Arity::exact(2), min_key),
+        ("max-key", Arity::exact(2), max_key),
+        ("argmin", Arity::exact(1), argmin),
+        ("argmax", Arity::exact(1), argmax),
+        ("str-len", Arity::exact(1), str_len),
+        ("substr", Arity::range(2, 3), substr),
+        ("parse-int", Arity::range(1, 2), parse_int),
+        ("bytes", Arity::at_least(0), bytes),
+        ("bytes-len", Arity::exact(1), bytes_len),
+        ("bytes-slice", Arity::range(2, 3), bytes_slice),
+        ("bytes->str", Arity::range(1, 2), bytes_to_str),
+        ("str->bytes", Arity::range(1, 2), str_to_bytes),
+        ("base64-encode", Arity::exact(1), base64_encode),
+        ("base64-decode", Arity::exact(1), base64_decode),
+        ("hex-encode", Arity::exact(1), hex_encode),
+        ("hex-decode", Arity::exact(1), hex_decode),
+        ("url-encode", Arity::exact(1), url_encode),
+        ("url-decode", Arity::exact(1), url_decode),
+        ("csv/parse", Arity::range(1, 2), csv_parse),
+        ("csv/write", Arity::range(1, 2), csv_write),
+        ("read-bytes", Arity::exact(1), read_bytes),
+        ("path-join", Arity::at_least(1), path_join),
+        ("basename", Arity::exact(1), basename),
+        ("dirname", Arity::exact(1), dirname),
+        ("exists?", Arity::exact(1), exists),
+        ("dir-list", Arity::exact(1), dir_list),
+        ("mkdir", Arity::exact(1), mkdir),
+        ("rm", Arity::exact(1), rm),
+        ("copy", Arity::exact(2), copy),
+        ("glob", Arity::exact(1), glob),
+        ("temp-dir", Arity::exact(0), temp_dir),
+        ("write-bytes", Arity::exact(2), write_bytes),
+        ("to-string", Arity::range(1, 2), to_string),
+        ("print", Arity::exact(1), print),
+        ("printf", Arity::at_least(1), printf),
+        ("format", Arity::at_least(1), format_str),
+        ("read-line", Arity::exact(0), read_line),
+        ("debug", Arity::exact(1), debug),
+        ("diff", Arity::exact(2), diff),
+        ("assert-eq", Arity::exact(2), assert_equal),
+        ("eval", Arity::exact(1), eval),
+        ("read-string", Arity::exact(1), read_string),
+        ("set-reader-macro!", Arity::exact(2), set_reader_macro),
+        ("save-image", Arity::exact(1), save_image),
+        ("deref", Arity::exact(1), deref),
+        ("yield", Arity::exact(1), yield_value),
+        ("next!", Arity::exact(1), next),
+        ("done?", Arity::exact(1), done),
+        ("take", Arity::exact(2), take),
+        ("atom", Arity::exact(1), atom),
+        ("reset!", Arity::exact(2), reset),
+        ("swap!", Arity::at_least(2), swap),
+        ("deep-copy", Arity::exact(1), deep_copy),
+        ("freeze", Arity::exact(1), freeze),
+        ("frozen?", Arity::exact(1), frozen),
+        ("pmap", Arity::exact(2), pmap),
+        ("call/ec", Arity::exact(1), call_ec),
+        ("partial", Arity::at_least(1), partial),
+        ("memoize", Arity::exact(1), memoize),
+        ("chan", Arity::exact(0), chan),
+        ("send!", Arity::exact(2), send),
+        ("recv!", Arity::exact(1), recv),
+        ("close!", Arity::exact(1), close),
+        ("log/debug", Arity::at_least(1), log_debug),
+        ("log/info", Arity::at_least(1), log_info),
+        ("log/warn", Arity::at_least(1), log_warn),
+        ("log/error", Arity::at_least(1), log_error),
+        ("log/set-level!", Arity::exact(1), log_set_level),
+        ("log/set-target!", Arity::exact(1), log_set_target),
+        ("set-contracts!", Arity::exact(1), set_contracts),
+        ("set-debug!", Arity::exact(1), set_debug),
+        ("exit", Arity::range(0, 1), exit),
+        ("at-exit", Arity::exact(1), at_exit),
+        ("version", Arity::exact(0), version),
+        ("help", Arity::range(0, 1), help),
+        ("source", Arity::exact(1), source),
+        ("apropos", Arity::exact(1), apropos),
     ];
+    #[cfg(feature = "net")]
+    let table = table.into_iter().chain(net::builtins()).collect::<Vec<_>>();
 
-    let builtins = table
+    let mut builtins = table
         .into_iter()
-        .map(|(symbol, f)| {
+        .map(|(symbol, arity, f)| {
             (
                 String::from(symbol),
-                Expr::from(Function::builtin(symbol, f)),
+                Expr::from(Function::builtin(symbol, arity, f)),
             )
         })
         .collect::<HashMap<_, _>>();
+    builtins.insert(
+        String::from("*telescope-version*"),
+        Expr::from(env!("CARGO_PKG_VERSION")),
+    );
 
     Env::new(builtins, None)
 }
 
-fn numeric_op<F, G>(name: &str, args: &[Expr], fn_int: F, fn_flt: G) -> Result<Expr>
+/// The names of the builtins that reach outside the interpreter, to files, the
+/// process, or the network. A sandboxed interpreter swaps these out.
+pub fn host_builtins() -> Vec<&'static str> {
+    let names = vec![
+        "read-bytes", "write-bytes", "exists?", "dir-list", "mkdir", "rm", "copy", "glob",
+        "temp-dir", "save-image", "log/set-target!", "exit", "at-exit",
+    ];
+    #[cfg(feature = "net")]
+    let names = names.into_iter()
+        .chain(net::builtins().into_iter().map(|(name, _, _)| name))
+        .collect::<Vec<_>>();
+    names
+}
+
+/// Folds `fn_int` or `fn_flt` over the arguments from left to right, or
+/// returns `empty` if there are none. If any argument is a float, all of them
+/// are promoted to float.
+fn numeric_op<F, G>(name: &str, args: &[Expr], empty: i64, fn_int: F, fn_flt: G) -> Result<Expr>
 where
-    F: Fn(&[i64]) -> Result<i64>,
-    G: Fn(&[f64]) -> Result<f64>,
+    F: Fn(i64, i64) -> Result<i64>,
+    G: Fn(f64, f64) -> f64,
 {
     // Check all arguments are numeric
-    if args.iter().all(Expr::is_num) {
-        if args.iter().any(Expr::is_flt) {
-            // If any are float, promote to float
-            let floats = args.iter()
-                .map(|x| match *x {
-                    Expr::Int(y) => y as f64,
-                    Expr::Flt(y) => y,
-                    _ => unreachable!(),
-                })
-                .collect::<Vec<_>>();
-            fn_flt(&floats).map(Expr::from)
-        } else {
-            // Otherwise perform integer operation
-            let ints = args.iter()
-                .map(|x| match *x {
-                    Expr::Int(y) => y,
-                    _ => unreachable!(),
-                })
-                .collect::<Vec<_>>();
-            fn_int(&ints).map(Expr::from)
-        }
+    if let Some(arg) = args.iter().find(|x| !x.is_num()) {
+        return Err(type_error(name, "number", arg));
+    }
+
+    let (first, rest) = match args.split_first() {
+        Some(split) => split,
+        None => return Ok(Expr::from(empty)),
+    };
+
+    if args.iter().any(Expr::is_flt) {
+        let float = |x: &Expr| x.flt().unwrap_or_else(|| x.int().unwrap() as f64);
+        Ok(Expr::from(rest.iter().fold(float(first), |acc, x| fn_flt(acc, float(x)))))
     } else {
-        Err(format!("#[{}] expected numeric", name).into())
+        let int = |x: &Expr| x.int().unwrap();
+        rest.iter()
+            .fold(Ok(int(first)), |acc, x| acc.and_then(|acc| fn_int(acc, int(x))))
+            .map(Expr::from)
     }
 }
 
+// Int arithmetic is checked, so overflow is an error in every build rather
+// than a panic in debug builds and wrapping in release builds
+fn overflow(name: &str, result: Option<i64>) -> Result<i64> {
+    result.ok_or_else(|| format!("#[{}] integer overflow", name).into())
+}
+
 fn add(args: &[Expr], _env: Env) -> Result<Expr> {
-    numeric_op("+", args,
-        |ints| Ok(ints.iter().sum::<i64>()),
-        |floats| Ok(floats.iter().sum::<f64>())
-    )
+    numeric_op("+", args, 0, |a, b| overflow("+", a.checked_add(b)), Add::add)
 }
 
 fn sub(args: &[Expr], _env: Env) -> Result<Expr> {
-    ensure_min_args("-", args, 1)?;
+    let args = Args::new("-", args);
 
     // If one argument, negate and return
     if args.len() == 1 {
-        return match args[0] {
-            Expr::Int(x) => Ok(Expr::from(-x)),
+        return match *args.get(0)? {
+            Expr::Int(x) => overflow("-", x.checked_neg()).map(Expr::from),
             Expr::Flt(x) => Ok(Expr::from(-x)),
-            _ => Err("invalid type".into())
+            ref x => Err(type_error("-", "number", x)),
         }
     }
 
-    numeric_op("-", args,
-        |ints| Ok(ints[1..].iter().fold(ints[0], Sub::sub)),
-        |floats| Ok(floats[1..].iter().fold(floats[0], Sub::sub))
-    )
+    numeric_op("-", args.all(), 0, |a, b| overflow("-", a.checked_sub(b)), Sub::sub)
 }
 
 fn mul(args: &[Expr], _env: Env) -> Result<Expr> {
-    numeric_op("*", args,
-        |ints| Ok(ints.iter().product::<i64>()),
-        |floats| Ok(floats.iter().product::<f64>())
-    )
+    numeric_op("*", args, 1, |a, b| overflow("*", a.checked_mul(b)), Mul::mul)
 }
 
 fn div(args: &[Expr], _env: Env) -> Result<Expr> {
-    ensure_min_args("/", args, 1)?;
+    let args = Args::new("/", args);
 
     // If one argument, invert and return
     if args.len() == 1 {
-        return match args[0] {
+        return match *args.get(0)? {
             Expr::Int(x) => Ok(Expr::from((x as f64).recip())),
             Expr::Flt(x) => Ok(Expr::from(x.recip())),
-            _ => Err("invalid type".into())
+            ref x => Err(type_error("/", "number", x)),
         }
     }
 
-    let int_div = |ints: &[i64]| {
-        ints[1..].iter()
-            .map(|&x| if x == 0i64 { Err("division by zero".into()) } else { Ok(x) })
-            .fold_results(ints[0], Div::div)
+    // One float makes the whole operation floating point
+    if args.all().iter().any(|x| !x.is_num() || x.is_flt()) {
+        return numeric_op("/", args.all(), 1, |a, b| Ok(a / b), Div::div);
+    }
+
+    // Ints stay ints while they divide exactly, and become a float when they
+    // don't, rather than silently truncating
+    let mut quotient = args.get(0)?.clone();
+    for arg in args.rest(1) {
+        let divisor = arg.int().unwrap();
+        quotient = match quotient {
+            Expr::Int(_) if divisor == 0 => bail!("#[/] division by zero"),
+            Expr::Int(a) if a.checked_rem(divisor) == Some(0) => Expr::from(a / divisor),
+            Expr::Int(a) => Expr::from(a as f64 / divisor as f64),
+            Expr::Flt(a) => Expr::from(a / divisor as f64),
+            _ => unreachable!(),
+        };
+    }
+    Ok(quotient)
+}
+
+// (quot a b), rounding toward zero
+fn quot(args: &[Expr], _env: Env) -> Result<Expr> {
+    let int_quot = |a: i64, b: i64| {
+        a.checked_div(b).ok_or_else(|| Error::from("#[quot] integer overflow"))
+    };
+    division("quot", args, int_quot, |a, b| (a / b).trunc())
+}
+
+// (rem a b), the remainder of quot, with the sign of a
+fn rem(args: &[Expr], _env: Env) -> Result<Expr> {
+    division("rem", args, |a, b| Ok(a.wrapping_rem(b)), |a, b| a % b)
+}
+
+// (mod a b), the Euclidean remainder, which is never negative
+fn modulo(args: &[Expr], _env: Env) -> Result<Expr> {
+    let euclid = |rem: f64, b: f64| if rem < 0.0 { rem + b.abs() } else { rem };
+    let int_mod = |a: i64, b: i64| {
+        let rem = a.wrapping_rem(b);
+        Ok(if rem >= 0 { rem } else if b > 0 { rem + b } else { rem - b })
     };
+    division("mod", args, int_mod, |a, b| euclid(a % b, b))
+}
+
+// (checked-add a b), nil if it overflows
+fn checked_add(args: &[Expr], _env: Env) -> Result<Expr> {
+    checked_op("checked-add", args, i64::checked_add)
+}
+
+// (checked-sub a b)
+fn checked_sub(args: &[Expr], _env: Env) -> Result<Expr> {
+    checked_op("checked-sub", args, i64::checked_sub)
+}
+
+// (checked-mul a b)
+fn checked_mul(args: &[Expr], _env: Env) -> Result<Expr> {
+    checked_op("checked-mul", args, i64::checked_mul)
+}
+
+// (wrapping-add a b), wrapping around at the edges of a 64-bit int
+fn wrapping_add(args: &[Expr], _env: Env) -> Result<Expr> {
+    int_op("wrapping-add", args, i64::wrapping_add)
+}
+
+// (wrapping-sub a b)
+fn wrapping_sub(args: &[Expr], _env: Env) -> Result<Expr> {
+    int_op("wrapping-sub", args, i64::wrapping_sub)
+}
+
+// (wrapping-mul a b)
+fn wrapping_mul(args: &[Expr], _env: Env) -> Result<Expr> {
+    int_op("wrapping-mul", args, i64::wrapping_mul)
+}
+
+// (saturating-add a b), stopping at the largest or smallest int
+fn saturating_add(args: &[Expr], _env: Env) -> Result<Expr> {
+    int_op("saturating-add", args, i64::saturating_add)
+}
+
+// (saturating-sub a b)
+fn saturating_sub(args: &[Expr], _env: Env) -> Result<Expr> {
+    int_op("saturating-sub", args, i64::saturating_sub)
+}
+
+// (saturating-mul a b)
+fn saturating_mul(args: &[Expr], _env: Env) -> Result<Expr> {
+    int_op("saturating-mul", args, i64::saturating_mul)
+}
+
+fn checked_op(name: &str, args: &[Expr], op: fn(i64, i64) -> Option<i64>) -> Result<Expr> {
+    let args = Args::new(name, args);
+    Ok(op(args.int(0)?, args.int(1)?).map_or(Expr::Nil, Expr::from))
+}
+
+fn int_op(name: &str, args: &[Expr], op: fn(i64, i64) -> i64) -> Result<Expr> {
+    let args = Args::new(name, args);
+    Ok(Expr::from(op(args.int(0)?, args.int(1)?)))
+}
 
-    numeric_op("/", args,
-        int_div,
-        |floats| Ok(floats[1..].iter().fold(floats[0], Div::div))
-    )
+fn division<F, G>(name: &str, args: &[Expr], fn_int: F, fn_flt: G) -> Result<Expr>
+where
+    F: Fn(i64, i64) -> Result<i64>,
+    G: Fn(f64, f64) -> f64,
+{
+    let args = Args::new(name, args);
+    if let (&Expr::Int(_), &Expr::Int(0)) = (args.get(0)?, args.get(1)?) {
+        bail!("#[{}] division by zero", name);
+    }
+    numeric_op(name, args.all(), 0, fn_int, fn_flt)
 }
 
 fn equal(args: &[Expr], _env: Env) -> Result<Expr> {
-    ensure_args("=", args, 2)?;
-    Ok(Expr::from(args[0] == args[1]))
+    let args = Args::new("=", args);
+    Ok(Expr::from(args.get(0)? == args.get(1)?))
 }
 
 fn less(args: &[Expr], _env: Env) -> Result<Expr> {
-    ensure_args("<", args, 2)?;
-    match (&args[0], &args[1]) {
-        (&Expr::Int(ref a), &Expr::Int(ref b)) => Ok(Expr::from(a < b)),
-        (&Expr::Flt(ref a), &Expr::Flt(ref b)) => Ok(Expr::from(a < b)),
-        (&Expr::Str(ref a), &Expr::Str(ref b)) => Ok(Expr::from(a < b)),
-        _ => Err(
-            format!("comparison undefined for: {}, {}", args[0], args[1]).into(),
-        ),
-    }
+    comparison("<", args, |ordering| ordering == Ordering::Less)
 }
 
 fn less_eq(args: &[Expr], _env: Env) -> Result<Expr> {
-    ensure_args("<=", args, 2)?;
-    match (&args[0], &args[1]) {
-        (&Expr::Int(ref a), &Expr::Int(ref b)) => Ok(Expr::from(a <= b)),
-        (&Expr::Flt(ref a), &Expr::Flt(ref b)) => Ok(Expr::from(a <= b)),
-        (&Expr::Str(ref a), &Expr::Str(ref b)) => Ok(Expr::from(a <= b)),
-        _ => Err(
-            format!("comparison undefined for: {}, {}", args[0], args[1]).into(),
-        ),
-    }
+    comparison("<=", args, |ordering| ordering != Ordering::Greater)
 }
 
 fn greater(args: &[Expr], _env: Env) -> Result<Expr> {
-    ensure_args(">", args, 2)?;
-    match (&args[0], &args[1]) {
-        (&Expr::Int(ref a), &Expr::Int(ref b)) => Ok(Expr::from(a > b)),
-        (&Expr::Flt(ref a), &Expr::Flt(ref b)) => Ok(Expr::from(a > b)),
-        (&Expr::Str(ref a), &Expr::Str(ref b)) => Ok(Expr::from(a > b)),
-        _ => Err(
-            format!("comparison undefined for: {}, {}", args[0], args[1]).into(),
-        ),
-    }
+    comparison(">", args, |ordering| ordering == Ordering::Greater)
 }
 
 fn greater_eq(args: &[Expr], _env: Env) -> Result<Expr> {
-    ensure_args(">=", args, 2)?;
-    match (&args[0], &args[1]) {
-        (&Expr::Int(ref a), &Expr::Int(ref b)) => Ok(Expr::from(a >= b)),
-        (&Expr::Flt(ref a), &Expr::Flt(ref b)) => Ok(Expr::from(a >= b)),
-        (&Expr::Str(ref a), &Expr::Str(ref b)) => Ok(Expr::from(a >= b)),
-        _ => Err(
-            format!("comparison undefined for: {}, {}", args[0], args[1]).into(),
-        ),
+    comparison(">=", args, |ordering| ordering != Ordering::Less)
+}
+
+// Every comparison with NaN is false
+fn comparison(name: &str, args: &[Expr], test: fn(Ordering) -> bool) -> Result<Expr> {
+    let args = Args::new(name, args);
+    let ordering = order::compare(name, args.get(0)?, args.get(1)?)?;
+    Ok(Expr::from(ordering.map_or(false, test)))
+}
+
+// (compare a b), -1, 0, or 1 as a goes before, with, or after b in sorted order
+fn compare(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("compare", args);
+    Ok(Expr::from(match order::total(args.get(0)?, args.get(1)?) {
+        Ordering::Less => -1,
+        Ordering::Equal => 0,
+        Ordering::Greater => 1,
+    }))
+}
+
+// (sort coll)
+fn sort(args: &[Expr], _env: Env) -> Result<Expr> {
+    let mut items = Args::new("sort", args).seq(0)?.to_vec();
+    items.sort_by(order::total);
+    Ok(vector(items))
+}
+
+// (sort-by f coll), by what f gives for each item
+fn sort_by(args: &[Expr], env: Env) -> Result<Expr> {
+    let args = Args::new("sort-by", args);
+    let func = args.func(0)?;
+    let mut keyed = args.seq(1)?
+        .iter()
+        .map(|item| Ok((func.apply(&[item.clone()], env.clone())?, item.clone())))
+        .collect::<Result<Vec<_>>>()?;
+    keyed.sort_by(|a, b| order::total(&a.0, &b.0));
+    Ok(vector(keyed.into_iter().map(|(_, item)| item).collect()))
+}
+
+// (min xs+), or (min coll) for the least item of a list or vector
+fn min(args: &[Expr], _env: Env) -> Result<Expr> {
+    let items = spread(args);
+    let best = extreme("min", items, Ordering::Less)?;
+    Ok(best.map_or(Expr::Nil, |i| items[i].clone()))
+}
+
+// (max xs+), or (max coll)
+fn max(args: &[Expr], _env: Env) -> Result<Expr> {
+    let items = spread(args);
+    let best = extreme("max", items, Ordering::Greater)?;
+    Ok(best.map_or(Expr::Nil, |i| items[i].clone()))
+}
+
+// A lone list or vector argument stands for its items
+fn spread(args: &[Expr]) -> &[Expr] {
+    match args.first().and_then(Expr::items) {
+        Some(items) if args.len() == 1 => items,
+        _ => args,
+    }
+}
+
+// (min-key f coll), the item for which f gives the least value
+fn min_key(args: &[Expr], env: Env) -> Result<Expr> {
+    by_key("min-key", args, env, Ordering::Less)
+}
+
+// (max-key f coll)
+fn max_key(args: &[Expr], env: Env) -> Result<Expr> {
+    by_key("max-key", args, env, Ordering::Greater)
+}
+
+fn by_key(name: &str, args: &[Expr], env: Env, wanted: Ordering) -> Result<Expr> {
+    let args = Args::new(name, args);
+    let func = args.func(0)?;
+    let items = args.seq(1)?;
+    let keys = items.iter()
+        .map(|item| func.apply(&[item.clone()], env.clone()))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(extreme(name, &keys, wanted)?.map_or(Expr::Nil, |i| items[i].clone()))
+}
+
+// (argmin coll), the index of the least item
+fn argmin(args: &[Expr], _env: Env) -> Result<Expr> {
+    let items = Args::new("argmin", args).seq(0)?;
+    Ok(extreme("argmin", items, Ordering::Less)?.map_or(Expr::Nil, |i| Expr::from(i as i64)))
+}
+
+// (argmax coll)
+fn argmax(args: &[Expr], _env: Env) -> Result<Expr> {
+    let items = Args::new("argmax", args).seq(0)?;
+    Ok(extreme("argmax", items, Ordering::Greater)?.map_or(Expr::Nil, |i| Expr::from(i as i64)))
+}
+
+// The index of the value that compares as `wanted` against all the others,
+// the first of any ties, or None if there are no values
+fn extreme(name: &str, values: &[Expr], wanted: Ordering) -> Result<Option<usize>> {
+    let mut best = None;
+    for (i, value) in values.iter().enumerate() {
+        best = match best {
+            Some(b) if order::compare(name, value, &values[b])? != Some(wanted) => Some(b),
+            _ => Some(i),
+        };
+    }
+    Ok(best)
+}
+
+// (sum coll)
+fn sum(args: &[Expr], _env: Env) -> Result<Expr> {
+    let items = Args::new("sum", args).seq(0)?;
+    let add = |a: i64, b: i64| -> Result<i64> { a.checked_add(b).ok_or_else(|| "#[sum] integer overflow".into()) };
+    numeric_op("sum", items, 0, add, Add::add)
+}
+
+// (product coll)
+fn product(args: &[Expr], _env: Env) -> Result<Expr> {
+    let items = Args::new("product", args).seq(0)?;
+    let mul = |a: i64, b: i64| -> Result<i64> { a.checked_mul(b).ok_or_else(|| "#[product] integer overflow".into()) };
+    numeric_op("product", items, 1, mul, Mul::mul)
+}
+
+// The items of a sequence of numbers as floats
+fn floats(name: &str, items: &[Expr]) -> Result<Vec<f64>> {
+    items.iter()
+        .map(|x| match *x {
+            Expr::Int(i) => Ok(i as f64),
+            Expr::Flt(f) => Ok(f),
+            _ => Err(type_error(name, "number", x)),
+        })
+        .collect()
+}
+
+// (mean coll)
+fn mean(args: &[Expr], _env: Env) -> Result<Expr> {
+    let xs = floats("mean", Args::new("mean", args).seq(0)?)?;
+    // There's no mean of nothing
+    if xs.is_empty() {
+        return Ok(Expr::Nil);
+    }
+    Ok(Expr::from(xs.iter().sum::<f64>() / xs.len() as f64))
+}
+
+// (median coll)
+fn median(args: &[Expr], _env: Env) -> Result<Expr> {
+    let items = Args::new("median", args).seq(0)?;
+    let xs = floats("median", items)?;
+    let mut order = (0..items.len()).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| xs[a].partial_cmp(&xs[b]).unwrap_or(::std::cmp::Ordering::Equal));
+
+    let mid = order.len() / 2;
+    match order.len() {
+        0 => Ok(Expr::Nil),
+        // The middle item itself, so ints stay ints
+        n if n % 2 == 1 => Ok(items[order[mid]].clone()),
+        _ => Ok(Expr::from((xs[order[mid - 1]] + xs[order[mid]]) / 2.0)),
+    }
+}
+
+// (stddev coll)
+fn stddev(args: &[Expr], _env: Env) -> Result<Expr> {
+    let xs = floats("stddev", Args::new("stddev", args).seq(0)?)?;
+    if xs.is_empty() {
+        return Ok(Expr::Nil);
     }
+    // The population standard deviation
+    let n = xs.len() as f64;
+    let mean = xs.iter().sum::<f64>() / n;
+    let variance = xs.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / n;
+    Ok(Expr::from(variance.sqrt()))
+}
+
+// Non-negative ints, as matrix sizes or indices
+fn sizes(name: &str, items: &[Expr]) -> Result<Vec<usize>> {
+    items.iter()
+        .map(|x| match *x {
+            Expr::Int(i) if i >= 0 => Ok(i as usize),
+            _ => Err(type_error(name, "non-negative int", x)),
+        })
+        .collect()
+}
+
+// (matrix rows)
+fn matrix(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("matrix", args);
+    Ok(Expr::Handle(Matrix::from_expr(args.get(0)?)?.into_handle()))
+}
+
+// (matrix/zeros sizes+)
+fn matrix_zeros(args: &[Expr], _env: Env) -> Result<Expr> {
+    let shape = sizes("matrix/zeros", args)?;
+    Ok(Expr::Handle(Matrix::zeros(shape)?.into_handle()))
+}
+
+// (matrix/identity n)
+fn matrix_identity(args: &[Expr], _env: Env) -> Result<Expr> {
+    let n = sizes("matrix/identity", args)?[0];
+    Ok(Expr::Handle(Matrix::identity(n)?.into_handle()))
+}
+
+// (matrix/shape m)
+fn matrix_shape(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("matrix/shape", args);
+    let m = ensure_resource::<Matrix>("matrix/shape", args.get(0)?, "matrix")?;
+    Ok(vector(m.shape().iter().map(|&n| Expr::from(n as i64)).collect()))
+}
+
+// (matrix/get m indices*)
+fn matrix_get(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("matrix/get", args);
+    let m = ensure_resource::<Matrix>("matrix/get", args.get(0)?, "matrix")?;
+    Ok(Expr::from(m.get(&sizes("matrix/get", args.rest(1))?)?))
+}
+
+// (matrix/to-vector m)
+fn matrix_to_vector(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("matrix/to-vector", args);
+    Ok(ensure_resource::<Matrix>("matrix/to-vector", args.get(0)?, "matrix")?.to_expr())
+}
+
+// An element-wise operation on two matrices of the same shape, or on a
+// matrix and a number
+fn elementwise<F: Fn(f64, f64) -> f64>(name: &str, args: &[Expr], op: F) -> Result<Expr> {
+    let args = Args::new(name, args);
+    let m = ensure_resource::<Matrix>(name, args.get(0)?, "matrix")?;
+    let result = match *args.get(1)? {
+        Expr::Int(i) => m.map(|x| op(x, i as f64)),
+        Expr::Flt(f) => m.map(|x| op(x, f)),
+        ref other => m.zip_with(ensure_resource::<Matrix>(name, other, "matrix or number")?, op)?,
+    };
+    Ok(Expr::Handle(result.into_handle()))
+}
+
+// (matrix/add m other)
+fn matrix_add(args: &[Expr], _env: Env) -> Result<Expr> {
+    elementwise("matrix/add", args, Add::add)
+}
+
+// (matrix/sub m other)
+fn matrix_sub(args: &[Expr], _env: Env) -> Result<Expr> {
+    elementwise("matrix/sub", args, Sub::sub)
+}
+
+// (matrix/mul m other)
+fn matrix_mul(args: &[Expr], _env: Env) -> Result<Expr> {
+    elementwise("matrix/mul", args, Mul::mul)
+}
+
+// (matmul a b)
+fn matmul(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("matmul", args);
+    let a = ensure_resource::<Matrix>("matmul", args.get(0)?, "matrix")?;
+    let b = ensure_resource::<Matrix>("matmul", args.get(1)?, "matrix")?;
+    Ok(Expr::Handle(a.matmul(b)?.into_handle()))
+}
+
+// (transpose m)
+fn transpose(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("transpose", args);
+    let m = ensure_resource::<Matrix>("transpose", args.get(0)?, "matrix")?;
+    Ok(Expr::Handle(m.transpose()?.into_handle()))
 }
 
 // (not expr)
 fn not(args: &[Expr], _env: Env) -> Result<Expr> {
-    ensure_args("[not]", args, 1)?;
-    Ok(Expr::from(!args[0].truthiness()))
+    let args = Args::new("not", args);
+    Ok(Expr::from(!args.get(0)?.truthiness()))
 }
 
 // (print expr)
 // TODO: lift one-argument restriction
 // TODO: create print, println versions
 fn print(args: &[Expr], _env: Env) -> Result<Expr> {
-    ensure_args("print", args, 1)?;
-    println!("{}", args[0]);
+    let expr = Args::new("print", args).get(0)?;
+    port::with_output(|out| writeln!(out, "{}", expr))?;
+    Ok(Expr::Nil)
+}
+
+// (printf template args*)
+fn printf(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("printf", args);
+    let text = format::format(args.str(0)?, args.rest(1))?;
+    port::with_output(|out| write!(out, "{}", text))?;
     Ok(Expr::Nil)
 }
 
+// (format template args*)
+fn format_str(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("format", args);
+    Ok(Expr::from(format::format(args.str(0)?, args.rest(1))?))
+}
+
+// (read-line)
+fn read_line(_args: &[Expr], _env: Env) -> Result<Expr> {
+    Ok(port::read_line()?.map(Expr::from).unwrap_or(Expr::Nil))
+}
+
 // (debug expr)
 // TODO: lift one-argument restriction
 fn debug(args: &[Expr], _env: Env) -> Result<Expr> {
-    ensure_args("debug", args, 1)?;
-    println!("{:?}", args[0]);
+    let expr = Args::new("debug", args).get(0)?;
+    port::with_output(|out| writeln!(out, "{:?}", expr))?;
     Ok(Expr::Nil)
 }
 
 // (first seq)
 fn first(args: &[Expr], _env: Env) -> Result<Expr> {
-    ensure_args("first", args, 1)?;
-    match args[0] {
+    let args = Args::new("first", args);
+    match *args.get(0)? {
         Expr::List(ref l) => Ok(l.0.first().cloned().unwrap_or(Expr::Nil)),
         Expr::Vector(ref q) => Ok(q.0.first().cloned().unwrap_or(Expr::Nil)),
-        _ => Err("#[first] expected list".into()),
+        ref x => Err(type_error("first", "list", x)),
     }
 }
 
 // (rest seq)
 fn rest(args: &[Expr], _env: Env) -> Result<Expr> {
-    ensure_args("first", args, 1)?;
-    match args[0] {
+    let args = Args::new("rest", args);
+    match *args.get(0)? {
         Expr::List(ref l) => {
             Ok(
                 l.0
                     .split_first()
-                    .map(|(_, rest)| Expr::List(List(rest.to_vec())))
+                    .map(|(_, rest)| Expr::List(List::new(rest.to_vec())))
                     .unwrap_or(Expr::Nil),
             )
         }
@@ -231,30 +795,32 @@ fn rest(args: &[Expr], _env: Env) -> Result<Expr> {
             Ok(
                 v.0
                     .split_first()
-                    .map(|(_, rest)| Expr::Vector(Vector(rest.to_vec())))
+                    .map(|(_, rest)| Expr::Vector(Vector::new(rest.to_vec())))
                     .unwrap_or(Expr::Nil),
             )
         }
-        _ => Err("#[rest] expected list".into()),
+        ref x => Err(type_error("rest", "list", x)),
     }
 }
 
 // (cons item seq)
 fn cons(args: &[Expr], _env: Env) -> Result<Expr> {
-    ensure_args("cons", args, 2)?;
+    let args = Args::new("cons", args);
+    let item = args.get(0)?;
 
-    match args[2] {
+    match *args.get(1)? {
         Expr::List(ref l) => {
-            let mut new = l.clone();
-            new.0.insert(0, args[1].clone());
+            let new = iter::once(item.clone()).chain(l.iter().cloned()).collect::<List>();
             Ok(Expr::List(new))
         }
         Expr::Vector(ref v) => {
             let mut new = v.clone();
-            new.0.push(args[1].clone());
+            new.0.push(item.clone());
             Ok(Expr::Vector(new))
         }
-        _ => Err("#[cons] expected list".into()),
+        // Consing onto nil starts a new list
+        Expr::Nil => Ok(Expr::List(List::new(vec![item.clone()]))),
+        ref x => Err(type_error("cons", "list", x)),
     }
 }
 
@@ -263,17 +829,1966 @@ fn list(args: &[Expr], _env: Env) -> Result<Expr> {
     if args.is_empty() {
         Ok(Expr::Nil)
     } else {
-        Ok(Expr::List(List(args.to_vec())))
+        Ok(Expr::List(List::new(args.to_vec())))
     }
 }
 
-// (eval form)
-fn eval(args: &[Expr], env: Env) -> Result<Expr> {
-    ensure_args("eval", args, 1)?;
-    args[0].eval(env)
+// (values exprs*)
+fn values(args: &[Expr], _env: Env) -> Result<Expr> {
+    Ok(Expr::Vector(Vector::new(args.to_vec())))
+}
+
+// (range end), (range start end step?)
+fn range(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("range", args);
+    let (start, end) = match args.len() {
+        1 => (0, args.int(0)?),
+        _ => (args.int(0)?, args.int(1)?),
+    };
+    let step = if args.len() == 3 { args.int(2)? } else { 1 };
+    ensure!(step != 0, "#[range] step can't be 0");
+
+    let mut items = Vec::new();
+    let mut i = start;
+    while (step > 0 && i < end) || (step < 0 && i > end) {
+        items.push(Expr::from(i));
+        i = match i.checked_add(step) {
+            Some(next) => next,
+            None => break,
+        };
+    }
+    Ok(Expr::List(List::new(items)))
+}
+
+// (even? n)
+fn even(args: &[Expr], _env: Env) -> Result<Expr> {
+    Ok(Expr::from(Args::new("even?", args).int(0)? % 2 == 0))
+}
+
+// (odd? n)
+fn odd(args: &[Expr], _env: Env) -> Result<Expr> {
+    Ok(Expr::from(Args::new("odd?", args).int(0)? % 2 != 0))
+}
+
+// (nan? x)
+fn is_nan(args: &[Expr], _env: Env) -> Result<Expr> {
+    float_test("nan?", args, f64::is_nan)
+}
+
+// (infinite? x)
+fn is_infinite(args: &[Expr], _env: Env) -> Result<Expr> {
+    float_test("infinite?", args, f64::is_infinite)
+}
+
+// A test of a float, which is false for every int
+fn float_test(name: &str, args: &[Expr], test: fn(f64) -> bool) -> Result<Expr> {
+    match *Args::new(name, args).get(0)? {
+        Expr::Int(_) => Ok(Expr::from(false)),
+        Expr::Flt(x) => Ok(Expr::from(test(x))),
+        ref x => Err(type_error(name, "number", x)),
+    }
+}
+
+fn vector(items: Vec<Expr>) -> Expr {
+    Expr::Vector(Vector::new(items))
+}
+
+// (map f coll)
+fn map(args: &[Expr], env: Env) -> Result<Expr> {
+    let args = Args::new("map", args);
+    let func = args.func(0)?;
+    let results = args.seq(1)?.iter()
+        .map(|item| func.apply(&[item.clone()], env.clone()))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Expr::List(List::new(results)))
+}
+
+// (filter pred coll)
+fn filter(args: &[Expr], env: Env) -> Result<Expr> {
+    select("filter", args, env, true)
+}
+
+// (remove pred coll)
+fn remove(args: &[Expr], env: Env) -> Result<Expr> {
+    select("remove", args, env, false)
+}
+
+// The items for which pred's truthiness is `keep`
+fn select(name: &str, args: &[Expr], env: Env, keep: bool) -> Result<Expr> {
+    let args = Args::new(name, args);
+    let pred = args.func(0)?;
+    let mut selected = Vec::new();
+    for item in args.seq(1)? {
+        if pred.apply(&[item.clone()], env.clone())?.truthiness() == keep {
+            selected.push(item.clone());
+        }
+    }
+    Ok(Expr::List(List::new(selected)))
+}
+
+// (keep f coll)
+fn keep(args: &[Expr], env: Env) -> Result<Expr> {
+    let args = Args::new("keep", args);
+    let func = args.func(0)?;
+    let mut kept = Vec::new();
+    for item in args.seq(1)? {
+        match func.apply(&[item.clone()], env.clone())? {
+            Expr::Nil => (),
+            value => kept.push(value),
+        }
+    }
+    Ok(Expr::List(List::new(kept)))
+}
+
+// (some pred coll)
+fn some(args: &[Expr], env: Env) -> Result<Expr> {
+    let args = Args::new("some", args);
+    let pred = args.func(0)?;
+    // Stops at the first truthy result, which is returned
+    for item in args.seq(1)? {
+        let value = pred.apply(&[item.clone()], env.clone())?;
+        if value.truthiness() {
+            return Ok(value);
+        }
+    }
+    Ok(Expr::Nil)
+}
+
+// (every? pred coll)
+fn every(args: &[Expr], env: Env) -> Result<Expr> {
+    let args = Args::new("every?", args);
+    let pred = args.func(0)?;
+    for item in args.seq(1)? {
+        if !pred.apply(&[item.clone()], env.clone())?.truthiness() {
+            return Ok(Expr::from(false));
+        }
+    }
+    Ok(Expr::from(true))
+}
+
+// (not-any? pred coll)
+fn not_any(args: &[Expr], env: Env) -> Result<Expr> {
+    let found = some(args, env)?;
+    Ok(Expr::from(!found.truthiness()))
+}
+
+// (group-by f coll)
+fn group_by(args: &[Expr], env: Env) -> Result<Expr> {
+    let args = Args::new("group-by", args);
+    let func = args.func(0)?;
+    let mut groups: Vec<(Key, Vec<Expr>)> = Vec::new();
+    for item in args.seq(1)? {
+        let key = Key::try_from(&func.apply(&[item.clone()], env.clone())?)?;
+        match groups.iter().position(|&(ref k, _)| *k == key) {
+            Some(i) => groups[i].1.push(item.clone()),
+            None => groups.push((key, vec![item.clone()])),
+        }
+    }
+    let mut map = Map::new();
+    for (key, items) in groups {
+        map.insert(key, vector(items));
+    }
+    Ok(Expr::Map(map))
+}
+
+// (frequencies coll)
+fn frequencies(args: &[Expr], _env: Env) -> Result<Expr> {
+    let mut counts = HashMap::new();
+    for item in Args::new("frequencies", args).seq(0)? {
+        *counts.entry(Key::try_from(item)?).or_insert(0) += 1;
+    }
+    let mut map = Map::new();
+    for (key, count) in counts {
+        map.insert(key, Expr::from(count as i64));
+    }
+    Ok(Expr::Map(map))
+}
+
+// (partition n step? coll)
+fn partition(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("partition", args);
+    let n = args.int(0)?;
+    let (step, items) = match args.len() {
+        3 => (args.int(1)?, args.seq(2)?),
+        _ => (n, args.seq(1)?),
+    };
+    ensure!(n > 0 && step > 0, "#[partition] sizes must be positive");
+    let (n, step) = (n as usize, step as usize);
+
+    // Only whole partitions, so leftover items at the end are dropped
+    let mut parts = Vec::new();
+    let mut start = 0;
+    while start + n <= items.len() {
+        parts.push(vector(items[start..start + n].to_vec()));
+        start += step;
+    }
+    Ok(vector(parts))
+}
+
+// (partition-by f coll)
+fn partition_by(args: &[Expr], env: Env) -> Result<Expr> {
+    let args = Args::new("partition-by", args);
+    let func = args.func(0)?;
+    let mut parts: Vec<Vec<Expr>> = Vec::new();
+    let mut last = None;
+    for item in args.seq(1)? {
+        // A new partition starts whenever f's value changes
+        let value = func.apply(&[item.clone()], env.clone())?;
+        if last.as_ref() != Some(&value) {
+            parts.push(Vec::new());
+        }
+        parts.last_mut().expect("a partition was started").push(item.clone());
+        last = Some(value);
+    }
+    Ok(vector(parts.into_iter().map(vector).collect()))
+}
+
+// (interleave colls*)
+fn interleave(args: &[Expr], _env: Env) -> Result<Expr> {
+    let colls = Args::new("interleave", args).all().iter()
+        .map(|coll| ensure_seq("interleave", coll))
+        .collect::<Result<Vec<_>>>()?;
+    let len = colls.iter().map(|coll| coll.len()).min().unwrap_or(0);
+    Ok(vector((0..len).flat_map(|i| colls.iter().map(move |coll| coll[i].clone())).collect()))
+}
+
+// (zip colls*)
+fn zip(args: &[Expr], _env: Env) -> Result<Expr> {
+    let colls = Args::new("zip", args).all().iter()
+        .map(|coll| ensure_seq("zip", coll))
+        .collect::<Result<Vec<_>>>()?;
+    let len = colls.iter().map(|coll| coll.len()).min().unwrap_or(0);
+    Ok(vector((0..len).map(|i| vector(colls.iter().map(|coll| coll[i].clone()).collect())).collect()))
+}
+
+// (zipmap keys values)
+fn zipmap(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("zipmap", args);
+    let mut map = Map::new();
+    for (key, value) in args.seq(0)?.iter().zip(args.seq(1)?) {
+        map.insert(Key::try_from(key)?, value.clone());
+    }
+    Ok(Expr::Map(map))
+}
+
+// (distinct coll)
+fn distinct(args: &[Expr], _env: Env) -> Result<Expr> {
+    let mut items: Vec<Expr> = Vec::new();
+    for item in Args::new("distinct", args).seq(0)? {
+        if !items.contains(item) {
+            items.push(item.clone());
+        }
+    }
+    Ok(vector(items))
+}
+
+// (flatten coll)
+fn flatten(args: &[Expr], _env: Env) -> Result<Expr> {
+    fn walk(items: &[Expr], flat: &mut Vec<Expr>) {
+        for item in items {
+            match *item {
+                Expr::List(ref list) => walk(&list.0, flat),
+                Expr::Vector(ref vec) => walk(&vec.0, flat),
+                _ => flat.push(item.clone()),
+            }
+        }
+    }
+    let mut flat = Vec::new();
+    walk(Args::new("flatten", args).seq(0)?, &mut flat);
+    Ok(vector(flat))
+}
+
+// (get coll key default?)
+fn get(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("get", args);
+    let found = lookup("get", args.get(0)?, args.get(1)?)?;
+    Ok(found.or_else(|| args.opt(2).cloned()).unwrap_or(Expr::Nil))
+}
+
+// The value under `key` in a map, or the item at index `key` of a vector or
+// bytes, if there is one. Nil holds nothing.
+fn lookup(name: &str, coll: &Expr, key: &Expr) -> Result<Option<Expr>> {
+    Ok(match *coll {
+        Expr::Map(ref map) => map.get(&Key::try_from(key)?).cloned(),
+        Expr::Vector(ref vec) => key.int()
+            .and_then(|i| if i >= 0 { vec.0.get(i as usize).cloned() } else { None }),
+        Expr::Bytes(ref bytes) => key.int()
+            .and_then(|i| if i >= 0 { bytes.get(i as usize).map(|&b| Expr::from(i64::from(b))) } else { None }),
+        Expr::Nil => None,
+        ref x => match x.handle().and_then(|handle| handle.downcast_ref::<SortedMap>()) {
+            Some(map) => map.get(key).cloned(),
+            None => return Err(type_error(name, "map", x)),
+        },
+    })
+}
+
+// A copy of `coll` with `value` under `key`. A vector can be given a new value
+// at any index up to its length, which adds to the end, and nil becomes a map.
+fn put(name: &str, coll: &Expr, key: &Expr, value: Expr) -> Result<Expr> {
+    match *coll {
+        Expr::Map(ref map) => {
+            let mut map = map.clone();
+            map.insert(Key::try_from(key)?, value);
+            Ok(Expr::Map(map))
+        }
+        Expr::Vector(ref vec) => {
+            let mut vec = vec.clone();
+            match key.int() {
+                Some(i) if i >= 0 && (i as usize) < vec.0.len() => vec.0[i as usize] = value,
+                Some(i) if i >= 0 && i as usize == vec.0.len() => vec.0.push(value),
+                Some(i) => bail!("#[{}] index {} out of range for length {}", name, i, vec.0.len()),
+                None => return Err(type_error(name, "int", key)),
+            }
+            Ok(Expr::Vector(vec))
+        }
+        Expr::Nil => put(name, &Expr::Map(Map::new()), key, value),
+        ref x => match x.handle().and_then(|handle| handle.downcast_ref::<SortedMap>()) {
+            Some(map) => {
+                let mut map = map.clone();
+                map.insert(key.clone(), value);
+                Ok(Expr::Handle(map.into_handle()))
+            }
+            None => Err(type_error(name, "map", x)),
+        },
+    }
+}
+
+// (get-in coll path default?), following a sequence of keys into nested
+// collections
+fn get_in(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("get-in", args);
+    let mut found = Some(args.get(0)?.clone());
+    for key in args.seq(1)? {
+        found = match found {
+            Some(coll) => lookup("get-in", &coll, key)?,
+            None => break,
+        };
+    }
+    Ok(found.or_else(|| args.opt(2).cloned()).unwrap_or(Expr::Nil))
+}
+
+// (assoc coll key value)
+fn assoc(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("assoc", args);
+    put("assoc", args.get(0)?, args.get(1)?, args.get(2)?.clone())
+}
+
+// (assoc-in coll path value), making maps for keys that are missing
+fn assoc_in(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("assoc-in", args);
+    let value = args.get(2)?.clone();
+    put_in("assoc-in", args.get(0)?, args.seq(1)?, &mut |_| Ok(value.clone()))
+}
+
+// (update coll key f args*), putting (f old args*) in place of the old value
+fn update(args: &[Expr], env: Env) -> Result<Expr> {
+    let args = Args::new("update", args);
+    let func = args.func(2)?;
+    let old = lookup("update", args.get(0)?, args.get(1)?)?.unwrap_or(Expr::Nil);
+    let new = func.apply(&with_first(old, args.rest(3)), env)?;
+    put("update", args.get(0)?, args.get(1)?, new)
+}
+
+// (update-in coll path f args*)
+fn update_in(args: &[Expr], env: Env) -> Result<Expr> {
+    let args = Args::new("update-in", args);
+    let func = args.func(2)?;
+    let extra = args.rest(3);
+    put_in("update-in", args.get(0)?, args.seq(1)?, &mut |old| {
+        func.apply(&with_first(old, extra), env.clone())
+    })
+}
+
+// A copy of `coll` with the value at the end of `path` replaced by what `f`
+// makes of it
+fn put_in<F>(name: &str, coll: &Expr, path: &[Expr], f: &mut F) -> Result<Expr>
+where
+    F: FnMut(Expr) -> Result<Expr>,
+{
+    let (key, rest) = match path.split_first() {
+        Some(split) => split,
+        None => return f(coll.clone()),
+    };
+    let inner = lookup(name, coll, key)?.unwrap_or(Expr::Nil);
+    let value = put_in(name, &inner, rest, f)?;
+    put(name, coll, key, value)
+}
+
+// (merge maps*), with the last value for keys in more than one. Nils are
+// skipped, and merging only nils gives nil.
+fn merge(args: &[Expr], _env: Env) -> Result<Expr> {
+    let mut merged: Option<Map> = None;
+    for arg in args.iter().filter(|arg| **arg != Expr::Nil) {
+        merged.get_or_insert_with(Map::new).merge(ensure_map("merge", arg)?);
+    }
+    Ok(merged.map_or(Expr::Nil, Expr::Map))
+}
+
+// (merge-with f maps*), with (f old new) for keys in more than one
+fn merge_with(args: &[Expr], env: Env) -> Result<Expr> {
+    let args = Args::new("merge-with", args);
+    let func = args.func(0)?;
+    let mut merged: Option<Map> = None;
+    for arg in args.rest(1).iter().filter(|arg| **arg != Expr::Nil) {
+        let map = merged.get_or_insert_with(Map::new);
+        for (key, value) in ensure_map("merge-with", arg)?.iter() {
+            let value = match map.get(key) {
+                Some(old) => func.apply(&[old.clone(), value.clone()], env.clone())?,
+                None => value.clone(),
+            };
+            map.insert(key.clone(), value);
+        }
+    }
+    Ok(merged.map_or(Expr::Nil, Expr::Map))
+}
+
+// (select-keys map keys), the entries of map with those keys
+fn select_keys(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("select-keys", args);
+    let map = args.map(0)?;
+    let mut selected = Map::new();
+    for key in args.seq(1)? {
+        let key = Key::try_from(key)?;
+        if let Some(value) = map.get(&key) {
+            selected.insert(key, value.clone());
+        }
+    }
+    Ok(Expr::Map(selected))
+}
+
+// (rename-keys map renames), moving the value under each key of renames to
+// the key it maps to
+fn rename_keys(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("rename-keys", args);
+    let mut renamed = args.map(0)?.clone();
+    // Every value is taken out before any is put back, so keys can swap
+    let moved = args.map(1)?
+        .iter()
+        .filter_map(|(from, to)| renamed.remove(from).map(|value| (to, value)))
+        .collect::<Vec<_>>();
+    for (to, value) in moved {
+        renamed.insert(Key::try_from(to)?, value);
+    }
+    Ok(Expr::Map(renamed))
+}
+
+// (transient coll), a vector or map that can be changed in place
+fn transient(args: &[Expr], _env: Env) -> Result<Expr> {
+    let coll = Args::new("transient", args).get(0)?;
+    match *coll {
+        Expr::Vector(_) | Expr::Map(_) => Ok(Expr::Handle(Transient::new(coll.clone()))),
+        ref x => Err(type_error("transient", "vector or map", x)),
+    }
+}
+
+// (conj! transient item)
+fn conj_transient(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("conj!", args);
+    ensure_resource::<Transient>("conj!", args.get(0)?, "transient")?.conj(args.get(1)?)?;
+    Ok(args.get(0)?.clone())
+}
+
+// (assoc! transient key value)
+fn assoc_transient(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("assoc!", args);
+    let transient = ensure_resource::<Transient>("assoc!", args.get(0)?, "transient")?;
+    transient.assoc(args.get(1)?, args.get(2)?)?;
+    Ok(args.get(0)?.clone())
+}
+
+// (persistent! transient)
+fn persistent(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("persistent!", args);
+    ensure_resource::<Transient>("persistent!", args.get(0)?, "transient")?.persistent()
+}
+
+// (deque items*)
+fn deque(args: &[Expr], _env: Env) -> Result<Expr> {
+    Ok(Expr::Handle(Deque::new(args)))
 }
 
-// (exit)
-fn exit(_args: &[Expr], _env: Env) -> Result<Expr> {
-    Err(ErrorKind::Exit(0).into())
+// (push-front! deque item)
+fn push_front(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("push-front!", args);
+    ensure_deque("push-front!", args.get(0)?)?.push_front(args.get(1)?.clone());
+    Ok(args.get(0)?.clone())
+}
+
+// (push-back! deque item)
+fn push_back(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("push-back!", args);
+    ensure_deque("push-back!", args.get(0)?)?.push_back(args.get(1)?.clone());
+    Ok(args.get(0)?.clone())
+}
+
+// (pop-front! deque), the item taken off the front, or nil if it's empty
+fn pop_front(args: &[Expr], _env: Env) -> Result<Expr> {
+    let deque = ensure_deque("pop-front!", Args::new("pop-front!", args).get(0)?)?;
+    Ok(deque.pop_front().unwrap_or(Expr::Nil))
+}
+
+// (pop-back! deque)
+fn pop_back(args: &[Expr], _env: Env) -> Result<Expr> {
+    let deque = ensure_deque("pop-back!", Args::new("pop-back!", args).get(0)?)?;
+    Ok(deque.pop_back().unwrap_or(Expr::Nil))
+}
+
+// (peek-front deque)
+fn peek_front(args: &[Expr], _env: Env) -> Result<Expr> {
+    let deque = ensure_deque("peek-front", Args::new("peek-front", args).get(0)?)?;
+    Ok(deque.front().unwrap_or(Expr::Nil))
+}
+
+// (peek-back deque)
+fn peek_back(args: &[Expr], _env: Env) -> Result<Expr> {
+    let deque = ensure_deque("peek-back", Args::new("peek-back", args).get(0)?)?;
+    Ok(deque.back().unwrap_or(Expr::Nil))
+}
+
+// (deque-len deque)
+fn deque_len(args: &[Expr], _env: Env) -> Result<Expr> {
+    let deque = ensure_deque("deque-len", Args::new("deque-len", args).get(0)?)?;
+    Ok(Expr::from(deque.len() as i64))
+}
+
+// (deque->vector deque)
+fn deque_to_vector(args: &[Expr], _env: Env) -> Result<Expr> {
+    let deque = ensure_deque("deque->vector", Args::new("deque->vector", args).get(0)?)?;
+    Ok(vector(deque.to_vec()))
+}
+
+fn ensure_deque<'a>(name: &str, arg: &'a Expr) -> Result<&'a Deque> {
+    ensure_resource::<Deque>(name, arg, "deque")
+}
+
+// (sorted-map [key value]*)
+fn sorted_map(args: &[Expr], _env: Env) -> Result<Expr> {
+    ensure!(args.len() % 2 == 0, "#[sorted-map] expected keys and values in pairs");
+    let mut map = SortedMap::new();
+    for pair in args.chunks(2) {
+        map.insert(pair[0].clone(), pair[1].clone());
+    }
+    Ok(Expr::Handle(map.into_handle()))
+}
+
+// (sorted-map/entries map), the [key value] entries in order
+fn sorted_map_entries(args: &[Expr], _env: Env) -> Result<Expr> {
+    let map = ensure_sorted_map("sorted-map/entries", args)?;
+    Ok(vector(map.entries()))
+}
+
+// (sorted-map/first map), the entry with the least key
+fn sorted_map_first(args: &[Expr], _env: Env) -> Result<Expr> {
+    let map = ensure_sorted_map("sorted-map/first", args)?;
+    Ok(map.first().unwrap_or(Expr::Nil))
+}
+
+// (sorted-map/last map), the entry with the greatest key
+fn sorted_map_last(args: &[Expr], _env: Env) -> Result<Expr> {
+    let map = ensure_sorted_map("sorted-map/last", args)?;
+    Ok(map.last().unwrap_or(Expr::Nil))
+}
+
+// (sorted-map/range map start end), the entries with keys from start up to
+// but not including end
+fn sorted_map_range(args: &[Expr], _env: Env) -> Result<Expr> {
+    let map = ensure_sorted_map("sorted-map/range", args)?;
+    Ok(vector(map.range(&args[1], &args[2])))
+}
+
+fn ensure_sorted_map<'a>(name: &str, args: &'a [Expr]) -> Result<&'a SortedMap> {
+    ensure_resource::<SortedMap>(name, Args::new(name, args).get(0)?, "sorted-map")
+}
+
+// (priority-queue items*)
+fn priority_queue(args: &[Expr], _env: Env) -> Result<Expr> {
+    Ok(Expr::Handle(PriorityQueue::new(args)))
+}
+
+// (insert! queue item)
+fn insert(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("insert!", args);
+    ensure_priority_queue("insert!", args.all())?.insert(args.get(1)?.clone());
+    Ok(args.get(0)?.clone())
+}
+
+// (peek-min queue), the least item, or nil if it's empty
+fn peek_min(args: &[Expr], _env: Env) -> Result<Expr> {
+    Ok(ensure_priority_queue("peek-min", args)?.peek_min().unwrap_or(Expr::Nil))
+}
+
+// (pop-min! queue), the least item, taken out
+fn pop_min(args: &[Expr], _env: Env) -> Result<Expr> {
+    Ok(ensure_priority_queue("pop-min!", args)?.pop_min().unwrap_or(Expr::Nil))
+}
+
+// (priority-queue-len queue)
+fn priority_queue_len(args: &[Expr], _env: Env) -> Result<Expr> {
+    let queue = ensure_priority_queue("priority-queue-len", args)?;
+    Ok(Expr::from(queue.len() as i64))
+}
+
+fn ensure_priority_queue<'a>(name: &str, args: &'a [Expr]) -> Result<&'a PriorityQueue> {
+    ensure_resource::<PriorityQueue>(name, Args::new(name, args).get(0)?, "priority-queue")
+}
+
+fn with_first(first: Expr, rest: &[Expr]) -> Vec<Expr> {
+    iter::once(first).chain(rest.iter().cloned()).collect()
+}
+
+// (meta expr)
+fn meta(args: &[Expr], _env: Env) -> Result<Expr> {
+    let expr = Args::new("meta", args).get(0)?;
+    Ok(expr.meta().cloned().map(Expr::Map).unwrap_or(Expr::Nil))
+}
+
+// (type expr)
+fn type_of(args: &[Expr], _env: Env) -> Result<Expr> {
+    let expr = Args::new("type", args).get(0)?;
+    Ok(Expr::Keyword(type_name(expr)))
+}
+
+// The name of a value's type, with records qualified by their record type
+fn type_name(expr: &Expr) -> String {
+    match *expr {
+        Expr::Record(ref record) => format!("record/{}", record.rtype.name),
+        _ => expr.type_name().to_owned(),
+    }
+}
+
+// (instance? :type expr)
+fn instance(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("instance?", args);
+    let expected = match *args.get(0)? {
+        Expr::Keyword(ref name) => name,
+        ref other => return Err(type_error("instance?", "keyword", other)),
+    };
+    // The same types as annotations, such as :num and :any, are accepted too
+    let value = args.get(1)?;
+    Ok(Expr::from(type_name(value) == *expected || typecheck::is_a(expected, value)))
+}
+
+// (set-reader-macro! 'tag f)
+fn set_reader_macro(args: &[Expr], env: Env) -> Result<Expr> {
+    let args = Args::new("set-reader-macro!", args);
+    reader_macro::set(&args.sym(0)?.0, args.func(1)?, env)?;
+    Ok(Expr::Nil)
+}
+
+// (version)
+fn version(_args: &[Expr], _env: Env) -> Result<Expr> {
+    let parts = [
+        ("major", env!("CARGO_PKG_VERSION_MAJOR")),
+        ("minor", env!("CARGO_PKG_VERSION_MINOR")),
+        ("patch", env!("CARGO_PKG_VERSION_PATCH")),
+    ];
+    let mut version = Map::new();
+    for &(name, part) in &parts {
+        let part = part.parse::<i64>().map_err(|err| err.to_string())?;
+        version.insert(Key::Keyword(name.to_owned()), Expr::from(part));
+    }
+    Ok(Expr::Map(version))
+}
+
+// (help topic?)
+fn help(args: &[Expr], env: Env) -> Result<Expr> {
+    let text = match Args::new("help", args).opt(0) {
+        None => help::topics(),
+        Some(&Expr::Sym(ref sym)) => help::describe(&sym.0, &env)?,
+        Some(&Expr::Str(ref topic)) => match help::topic(topic) {
+            Some(text) => text,
+            None => help::describe(topic, &env)?,
+        },
+        Some(&Expr::Func(ref func)) => help::function(func.name(), func),
+        Some(other) => bail!("no help for {}", other),
+    };
+    port::with_output(|out| writeln!(out, "{}", text))?;
+    Ok(Expr::Nil)
+}
+
+// (apropos query)
+fn apropos(args: &[Expr], env: Env) -> Result<Expr> {
+    let query = Args::new("apropos", args).str(0)?;
+    let names = help::apropos(query, &env).into_iter().map(|name| Expr::from(Symbol::new(name)));
+    Ok(Expr::List(List::new(names.collect())))
+}
+
+// (source f)
+fn source(args: &[Expr], _env: Env) -> Result<Expr> {
+    let arg = Args::new("source", args).get(0)?;
+    let func = arg.func().ok_or_else(|| type_error("source", "fn", arg))?;
+    let form = match func.source() {
+        Some(form) => form.to_string(),
+        None => bail!("#[source] {} wasn't defined by a form, so has no source", func),
+    };
+    let text = fmt::format(&form).unwrap_or(form);
+    port::with_output(|out| writeln!(out, "{}", text.trim_right()))?;
+    Ok(Expr::Nil)
+}
+
+// (with-meta expr map)
+fn with_meta(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("with-meta", args);
+    match *args.get(1)? {
+        Expr::Map(ref meta) => args.get(0)?.with_meta(meta.clone()),
+        ref x => Err(type_error("with-meta", "map", x)),
+    }
+}
+
+// (str exprs*)
+fn str(args: &[Expr], _env: Env) -> Result<Expr> {
+    Ok(Expr::from(strings::concat(args)))
+}
+
+// (join separator? coll)
+fn join(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("join", args);
+    let (separator, coll) = match args.len() {
+        1 => ("", args.get(0)?),
+        _ => (args.str(0)?, args.get(1)?),
+    };
+    let text = match coll.handle().and_then(|handle| handle.downcast_ref::<Generator>()) {
+        Some(generator) => strings::join(separator, generator.values())?,
+        None => strings::join(separator, ensure_seq("join", coll)?.iter().cloned().map(Ok))?,
+    };
+    Ok(Expr::from(text))
+}
+
+// (sb init?)
+fn sb(args: &[Expr], _env: Env) -> Result<Expr> {
+    let init = Args::new("sb", args).opt(0).map_or(String::new(), |init| strings::concat(&[init.clone()]));
+    Ok(Expr::Handle(StringBuilder::new(init)))
+}
+
+// (sb-append! builder exprs*)
+fn sb_append(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("sb-append!", args);
+    ensure_resource::<StringBuilder>("sb-append!", args.get(0)?, "string-builder")?.append(args.rest(1));
+    Ok(args.get(0)?.clone())
+}
+
+// (sb-str builder)
+fn sb_str(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("sb-str", args);
+    Ok(Expr::from(ensure_resource::<StringBuilder>("sb-str", args.get(0)?, "string-builder")?.text()))
+}
+
+// (normalize str form?)
+fn normalize(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("normalize", args);
+    let form = args.opt(1).map_or(Ok("nfc"), |arg| ensure_str("normalize", arg))?;
+    Ok(Expr::from(strings::normalize(args.str(0)?, form)?))
+}
+
+// (casefold str)
+fn casefold(args: &[Expr], _env: Env) -> Result<Expr> {
+    Ok(Expr::from(strings::casefold(Args::new("casefold", args).str(0)?)))
+}
+
+// (compare-str a b)
+fn compare_str(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("compare-str", args);
+    Ok(Expr::from(strings::compare(args.str(0)?, args.str(1)?) as i64))
+}
+
+// (str-len str)
+fn str_len(args: &[Expr], _env: Env) -> Result<Expr> {
+    let string = Args::new("str-len", args).str(0)?;
+    Ok(Expr::from(string.graphemes(true).count() as i64))
+}
+
+// (substr str start end?)
+fn substr(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("substr", args);
+    // Index by grapheme so characters are never split
+    let graphemes = args.str(0)?.graphemes(true).collect::<Vec<_>>();
+    let start = ensure_index("substr", args.get(1)?, graphemes.len())?;
+    let end = match args.opt(2) {
+        Some(arg) => ensure_index("substr", arg, graphemes.len())?,
+        None => graphemes.len(),
+    };
+    ensure!(start <= end, "#[substr] start {} is past end {}", start, end);
+    Ok(Expr::from(graphemes[start..end].concat()))
+}
+
+// (bytes ints*)
+fn bytes(args: &[Expr], _env: Env) -> Result<Expr> {
+    let bytes = args.iter()
+        .map(|arg| match *arg {
+            Expr::Int(int) if 0 <= int && int <= 255 => Ok(int as u8),
+            ref x => Err(type_error("bytes", "int from 0 to 255", x)),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Expr::Bytes(bytes))
+}
+
+// (bytes-len bytes)
+fn bytes_len(args: &[Expr], _env: Env) -> Result<Expr> {
+    Ok(Expr::from(Args::new("bytes-len", args).bytes(0)?.len() as i64))
+}
+
+// (bytes-slice bytes start end?)
+fn bytes_slice(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("bytes-slice", args);
+    let bytes = args.bytes(0)?;
+    let start = ensure_index("bytes-slice", args.get(1)?, bytes.len())?;
+    let end = match args.opt(2) {
+        Some(arg) => ensure_index("bytes-slice", arg, bytes.len())?,
+        None => bytes.len(),
+    };
+    ensure!(start <= end, "#[bytes-slice] start {} is past end {}", start, end);
+    Ok(Expr::Bytes(bytes[start..end].to_vec()))
+}
+
+// (bytes->str bytes encoding?)
+fn bytes_to_str(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("bytes->str", args);
+    let encoding = args.opt(1).map_or(Ok("utf-8"), |arg| ensure_str("bytes->str", arg))?;
+    Ok(Expr::from(encoding::decode(args.bytes(0)?, encoding)?))
+}
+
+// (str->bytes str encoding?)
+fn str_to_bytes(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("str->bytes", args);
+    let encoding = args.opt(1).map_or(Ok("utf-8"), |arg| ensure_str("str->bytes", arg))?;
+    Ok(Expr::Bytes(encoding::encode(args.str(0)?, encoding)?))
+}
+
+// (base64-encode str-or-bytes)
+fn base64_encode(args: &[Expr], _env: Env) -> Result<Expr> {
+    Ok(Expr::from(encoding::base64_encode(binary("base64-encode", args)?)))
+}
+
+// (base64-decode str)
+fn base64_decode(args: &[Expr], _env: Env) -> Result<Expr> {
+    let text = Args::new("base64-decode", args).str(0)?;
+    Ok(Expr::Bytes(encoding::base64_decode(text)?))
+}
+
+// (hex-encode str-or-bytes)
+fn hex_encode(args: &[Expr], _env: Env) -> Result<Expr> {
+    Ok(Expr::from(encoding::hex_encode(binary("hex-encode", args)?)))
+}
+
+// (hex-decode str)
+fn hex_decode(args: &[Expr], _env: Env) -> Result<Expr> {
+    let text = Args::new("hex-decode", args).str(0)?;
+    Ok(Expr::Bytes(encoding::hex_decode(text)?))
+}
+
+// (url-encode str-or-bytes)
+fn url_encode(args: &[Expr], _env: Env) -> Result<Expr> {
+    Ok(Expr::from(encoding::url_encode(binary("url-encode", args)?)))
+}
+
+// (url-decode str)
+fn url_decode(args: &[Expr], _env: Env) -> Result<Expr> {
+    let text = Args::new("url-decode", args).str(0)?;
+    Ok(Expr::from(encoding::decode(&encoding::url_decode(text)?, "utf-8")?))
+}
+
+// The data to encode: a string's utf-8 bytes, or bytes as they are
+fn binary<'a>(fn_name: &str, args: &'a [Expr]) -> Result<&'a [u8]> {
+    match *Args::new(fn_name, args).get(0)? {
+        Expr::Str(ref string) => Ok(string.as_bytes()),
+        Expr::Bytes(ref bytes) => Ok(bytes),
+        ref x => Err(type_error(fn_name, "str or bytes", x)),
+    }
+}
+
+// (csv/parse str opts?)
+fn csv_parse(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("csv/parse", args);
+    let (dialect, header) = csv_options("csv/parse", args.opt(1))?;
+    let mut rows = csv::parse(args.str(0)?, dialect)?.into_iter();
+
+    let rows = if header {
+        // Each row after the header becomes a map from column name to field
+        let columns = rows.next().unwrap_or_default();
+        rows.map(|row| {
+                let mut map = Map::new();
+                for (column, field) in columns.iter().zip(row) {
+                    map.insert(Key::Str(column.clone()), Expr::from(field));
+                }
+                Expr::Map(map)
+            })
+            .collect()
+    } else {
+        rows.map(|row| Expr::Vector(Vector::new(row.into_iter().map(Expr::from).collect())))
+            .collect()
+    };
+    Ok(Expr::Vector(Vector::new(rows)))
+}
+
+// (csv/write rows opts?)
+fn csv_write(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("csv/write", args);
+    let (dialect, _) = csv_options("csv/write", args.opt(1))?;
+    let rows = args.seq(0)?
+        .iter()
+        .map(|row| {
+            let fields = ensure_seq("csv/write", row)?;
+            // Strings are written as they are, without quotes
+            Ok(fields.iter().map(|field| match *field {
+                Expr::Str(ref string) => string.clone(),
+                ref other => other.to_string(),
+            }).collect())
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Expr::from(csv::write(&rows, dialect)))
+}
+
+// The dialect and whether there's a header row, from {:delimiter "," :quote "\"" :header #t}
+fn csv_options(fn_name: &str, opts: Option<&Expr>) -> Result<(csv::Dialect, bool)> {
+    let mut dialect = csv::Dialect::default();
+    let opts = match opts {
+        Some(&Expr::Map(ref opts)) => opts,
+        Some(&Expr::Nil) | None => return Ok((dialect, false)),
+        Some(other) => return Err(type_error(fn_name, "map", other)),
+    };
+
+    let char_option = |name: &str| -> Result<Option<char>> {
+        match opts.get(&Key::Keyword(name.to_owned())) {
+            Some(value) => {
+                let string = ensure_str(fn_name, value)?;
+                let mut chars = string.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Ok(Some(c)),
+                    _ => bail!("#[{}] :{} must be one character, not {:?}", fn_name, name, string),
+                }
+            }
+            None => Ok(None),
+        }
+    };
+    if let Some(delimiter) = char_option("delimiter")? {
+        dialect.delimiter = delimiter;
+    }
+    if let Some(quote) = char_option("quote")? {
+        dialect.quote = quote;
+    }
+    ensure!(dialect.delimiter != dialect.quote, "#[{}] the delimiter and quote must differ", fn_name);
+
+    let header = opts.get(&Key::Keyword("header".to_owned())).map_or(false, Expr::truthiness);
+    Ok((dialect, header))
+}
+
+// (read-bytes path)
+fn read_bytes(args: &[Expr], _env: Env) -> Result<Expr> {
+    let path = Args::new("read-bytes", args).str(0)?;
+    let mut bytes = Vec::new();
+    fs::File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut bytes))
+        .map_err(|err| files::io_error(path, err))?;
+    Ok(Expr::Bytes(bytes))
+}
+
+// (write-bytes path bytes)
+fn write_bytes(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("write-bytes", args);
+    let (path, bytes) = (args.str(0)?, args.bytes(1)?);
+    fs::File::create(path)
+        .and_then(|mut file| file.write_all(bytes))
+        .map_err(|err| files::io_error(path, err))?;
+    Ok(Expr::Nil)
+}
+
+// (path-join parts+)
+fn path_join(args: &[Expr], _env: Env) -> Result<Expr> {
+    let mut path = PathBuf::new();
+    for arg in args {
+        path.push(ensure_str("path-join", arg)?);
+    }
+    Ok(Expr::from(path.to_string_lossy().into_owned()))
+}
+
+// (basename path)
+fn basename(args: &[Expr], _env: Env) -> Result<Expr> {
+    let path = Path::new(Args::new("basename", args).str(0)?);
+    Ok(path.file_name()
+        .map(|name| Expr::from(name.to_string_lossy().into_owned()))
+        .unwrap_or(Expr::Nil))
+}
+
+// (dirname path)
+fn dirname(args: &[Expr], _env: Env) -> Result<Expr> {
+    let path = Path::new(Args::new("dirname", args).str(0)?);
+    let dir = match path.parent() {
+        Some(dir) if dir.as_os_str().is_empty() => ".".to_owned(),
+        Some(dir) => dir.to_string_lossy().into_owned(),
+        // The root is its own parent
+        None => path.to_string_lossy().into_owned(),
+    };
+    Ok(Expr::from(dir))
+}
+
+// (exists? path)
+fn exists(args: &[Expr], _env: Env) -> Result<Expr> {
+    Ok(Expr::from(Path::new(Args::new("exists?", args).str(0)?).exists()))
+}
+
+// (dir-list path)
+fn dir_list(args: &[Expr], _env: Env) -> Result<Expr> {
+    let names = files::list(Args::new("dir-list", args).str(0)?)?;
+    Ok(Expr::Vector(Vector::new(names.into_iter().map(Expr::from).collect())))
+}
+
+// (mkdir path)
+fn mkdir(args: &[Expr], _env: Env) -> Result<Expr> {
+    let path = Args::new("mkdir", args).str(0)?;
+    fs::create_dir_all(path).map_err(|err| files::io_error(path, err))?;
+    Ok(Expr::Nil)
+}
+
+// (rm path)
+fn rm(args: &[Expr], _env: Env) -> Result<Expr> {
+    files::remove(Args::new("rm", args).str(0)?)?;
+    Ok(Expr::Nil)
+}
+
+// (copy from to)
+fn copy(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("copy", args);
+    let (from, to) = (args.str(0)?, args.str(1)?);
+    fs::copy(from, to).map_err(|err| files::io_error(from, err))?;
+    Ok(Expr::Nil)
+}
+
+// (temp-dir)
+fn temp_dir(_args: &[Expr], _env: Env) -> Result<Expr> {
+    Ok(Expr::from(::std::env::temp_dir().to_string_lossy().into_owned()))
+}
+
+// (glob pattern)
+fn glob(args: &[Expr], _env: Env) -> Result<Expr> {
+    let paths = files::glob(Args::new("glob", args).str(0)?);
+    Ok(Expr::Vector(Vector::new(paths.into_iter().map(Expr::from).collect())))
+}
+
+fn ensure_index(fn_name: &str, arg: &Expr, len: usize) -> Result<usize> {
+    let index = ensure_int(fn_name, arg)?;
+    ensure!(
+        index >= 0 && index as usize <= len,
+        "#[{}] index {} out of bounds for length {}",
+        fn_name,
+        index,
+        len
+    );
+    Ok(index as usize)
+}
+
+// (parse-int str radix?)
+fn parse_int(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("parse-int", args);
+    let string = args.str(0)?;
+    let radix = match args.opt(1) {
+        Some(arg) => ensure_radix("parse-int", arg)?,
+        None => 10,
+    };
+    i64::from_str_radix(string.trim(), radix)
+        .map(Expr::from)
+        .map_err(|_| format!("#[parse-int] invalid base {} integer: {}", radix, string).into())
+}
+
+// (to-string expr radix?)
+fn to_string(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("to-string", args);
+    match (args.get(0)?, args.opt(1)) {
+        (&Expr::Str(ref s), None) => Ok(Expr::from(s.clone())),
+        (expr, None) => Ok(Expr::from(expr.to_string())),
+        (&Expr::Int(int), Some(arg)) => {
+            let radix = ensure_radix("to-string", arg)?;
+            Ok(Expr::from(int_to_radix(int, radix)))
+        }
+        (x, _) => Err(type_error("to-string", "int", x)),
+    }
+}
+
+fn ensure_radix(fn_name: &str, arg: &Expr) -> Result<u32> {
+    let radix = ensure_int(fn_name, arg)?;
+    ensure!(radix >= 2 && radix <= 36, "#[{}] radix must be in 2-36", fn_name);
+    Ok(radix as u32)
+}
+
+fn int_to_radix(int: i64, radix: u32) -> String {
+    use std::char;
+
+    // Negate through u64 so i64::MIN doesn't overflow
+    let mut magnitude = if int < 0 { (int as u64).wrapping_neg() } else { int as u64 };
+    let mut digits = Vec::new();
+    loop {
+        let digit = (magnitude % radix as u64) as u32;
+        digits.push(char::from_digit(digit, radix).unwrap());
+        magnitude /= radix as u64;
+        if magnitude == 0 {
+            break;
+        }
+    }
+    if int < 0 {
+        digits.push('-');
+    }
+    digits.into_iter().rev().collect()
+}
+
+// (diff a b)
+fn diff(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("diff", args);
+    let differences = diff::diff(args.get(0)?, args.get(1)?);
+    if differences.is_empty() {
+        return Ok(Expr::Nil);
+    }
+    Ok(Expr::Vector(Vector::new(differences.iter().map(diff::Difference::to_expr).collect())))
+}
+
+// (assert-eq expected actual)
+fn assert_equal(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("assert-eq", args);
+    let (expected, actual) = (args.get(0)?, args.get(1)?);
+    let differences = diff::diff(expected, actual);
+    if !differences.is_empty() {
+        let lines = differences.iter().map(|d| format!("\n  {}", d)).collect::<String>();
+        bail!("assert-eq failed: expected {}, got {}{}", expected, actual, lines);
+    }
+    Ok(Expr::Nil)
+}
+
+// (eval form)
+fn eval(args: &[Expr], env: Env) -> Result<Expr> {
+    Args::new("eval", args).get(0)?.eval(env)
+}
+
+// (read-string s)
+fn read_string(args: &[Expr], _env: Env) -> Result<Expr> {
+    parser::parse_expr(Args::new("read-string", args).str(0)?)
+}
+
+// (save-image path)
+fn save_image(args: &[Expr], env: Env) -> Result<Expr> {
+    let path = Args::new("save-image", args).str(0)?;
+    Ok(Expr::from(image::save(&env, path)? as i64))
+}
+
+// (deref future)
+fn deref(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("deref", args);
+    if let Expr::Handle(ref handle) = *args.get(0)? {
+        if let Some(atom) = handle.downcast_ref::<Atom>() {
+            return Ok(atom.get());
+        }
+    }
+    ensure_resource::<Future>("deref", args.get(0)?, "future or atom")?.wait()
+}
+
+// (yield value)
+fn yield_value(args: &[Expr], _env: Env) -> Result<Expr> {
+    generator::yield_value(Args::new("yield", args).get(0)?.clone())?;
+    Ok(Expr::Nil)
+}
+
+// (next! generator)
+fn next(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("next!", args);
+    let generator = ensure_resource::<Generator>("next!", args.get(0)?, "generator")?;
+    Ok(generator.next()?.unwrap_or(Expr::Nil))
+}
+
+// (done? generator)
+fn done(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("done?", args);
+    let generator = ensure_resource::<Generator>("done?", args.get(0)?, "generator")?;
+    Ok(Expr::from(generator.is_done()?))
+}
+
+// (take n coll)
+fn take(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("take", args);
+    let n = args.int(0)?.max(0) as usize;
+    let taken = match *args.get(1)? {
+        // Only run a generator as far as it's needed
+        Expr::Handle(ref handle) if handle.downcast_ref::<Generator>().is_some() => {
+            let generator = handle.downcast_ref::<Generator>().unwrap();
+            let mut taken = Vec::new();
+            while taken.len() < n {
+                match generator.next()? {
+                    Some(value) => taken.push(value),
+                    None => break,
+                }
+            }
+            taken
+        }
+        _ => args.seq(1)?.iter().take(n).cloned().collect(),
+    };
+    Ok(Expr::Vector(Vector::new(taken)))
+}
+
+// (atom value)
+fn atom(args: &[Expr], _env: Env) -> Result<Expr> {
+    Ok(Expr::Handle(Atom::new(Args::new("atom", args).get(0)?.clone())))
+}
+
+// (reset! atom value)
+fn reset(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("reset!", args);
+    let value = args.get(1)?.clone();
+    ensure_resource::<Atom>("reset!", args.get(0)?, "atom")?.set(value.clone())?;
+    Ok(value)
+}
+
+// (swap! atom f args*)
+fn swap(args: &[Expr], env: Env) -> Result<Expr> {
+    let args = Args::new("swap!", args);
+    let (func, extra) = (args.func(1)?, args.rest(2));
+    ensure_resource::<Atom>("swap!", args.get(0)?, "atom")?.swap(|old| {
+        let mut call = vec![old];
+        call.extend_from_slice(extra);
+        func.apply(&call, env.clone())
+    })
+}
+
+// (deep-copy value)
+fn deep_copy(args: &[Expr], _env: Env) -> Result<Expr> {
+    Ok(atom::copy(Args::new("deep-copy", args).get(0)?))
+}
+
+// (freeze value)
+fn freeze(args: &[Expr], _env: Env) -> Result<Expr> {
+    let value = Args::new("freeze", args).get(0)?;
+    atom::freeze(value);
+    Ok(value.clone())
+}
+
+// (frozen? value)
+fn frozen(args: &[Expr], _env: Env) -> Result<Expr> {
+    Ok(Expr::from(atom::is_frozen(Args::new("frozen?", args).get(0)?)))
+}
+
+// (call/ec f)
+fn call_ec(args: &[Expr], env: Env) -> Result<Expr> {
+    let func = Args::new("call/ec", args).func(0)?;
+    // Each call gets its own continuation, so nested ones escape to the right place
+    let id = eval::escape_id();
+    let escape = Function::builtin("escape", Arity::range(0, 1), move |args: &[Expr], _env: Env| {
+        let value = args.first().cloned().unwrap_or(Expr::Nil);
+        Err(ErrorKind::Escape(id, value).into())
+    });
+    let err = match func.apply(&[Expr::from(escape)], env) {
+        Err(err) => err,
+        result => return result,
+    };
+    let escaped = match *err.kind() {
+        ErrorKind::Escape(target, ref value) if target == id => Some(value.clone()),
+        _ => None,
+    };
+    escaped.ok_or(err)
+}
+
+// (partial f args*)
+fn partial(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("partial", args);
+    let func = args.func(0)?;
+    let bound = args.rest(1).to_vec();
+
+    let arity = func.arity();
+    if arity.max.map_or(false, |max| bound.len() > max) {
+        let (name, given) = (func.name(), bound.len());
+        bail!("#[partial] {} takes {} arguments, but {} were given", name, arity, given);
+    }
+    let arity = Arity {
+        min: arity.min.saturating_sub(bound.len()),
+        max: arity.max.map(|max| max - bound.len()),
+    };
+
+    let name = format!("partial {}", func.name());
+    Ok(Expr::from(Function::builtin(name, arity, move |rest: &[Expr], env: Env| {
+        let mut args = bound.clone();
+        args.extend_from_slice(rest);
+        func.apply(&args, env)
+    })))
+}
+
+// (memoize f)
+fn memoize(args: &[Expr], _env: Env) -> Result<Expr> {
+    let func = Args::new("memoize", args).func(0)?;
+    let cache = Mutex::new(HashMap::new());
+
+    let name = format!("memoize {}", func.name());
+    Ok(Expr::from(Function::builtin(name, func.arity(), move |args: &[Expr], env: Env| {
+        // Only calls whose arguments can be map keys are cached
+        let key = match args.iter().map(Key::try_from).collect::<Result<Vec<_>>>() {
+            Ok(key) => key,
+            Err(_) => return func.apply(args, env),
+        };
+        if let Some(value) = lock(&cache).get(&key) {
+            return Ok(value.clone());
+        }
+
+        // The lock isn't held during the call, so recursive calls can use the cache
+        let value = func.apply(args, env)?;
+        lock(&cache).insert(key, value.clone());
+        Ok(value)
+    })))
+}
+
+// (pmap f coll)
+fn pmap(args: &[Expr], env: Env) -> Result<Expr> {
+    ensure_unsandboxed("pmap", &env)?;
+    let args = Args::new("pmap", args);
+    let func = args.func(0)?;
+    let futures = args.seq(1)?
+        .iter()
+        .map(|item| {
+            Future::spawn(Task::Apply(func.clone(), vec![item.clone()], env.clone()))
+        })
+        .collect::<Vec<_>>();
+
+    let results = futures
+        .iter()
+        .map(|handle| handle.downcast_ref::<Future>().expect("pmap spawned a future").wait())
+        .collect::<Result<Vec<_>>>()?;
+    if results.is_empty() {
+        Ok(Expr::Nil)
+    } else {
+        Ok(Expr::List(List::new(results)))
+    }
+}
+
+// (chan)
+fn chan(_args: &[Expr], _env: Env) -> Result<Expr> {
+    Ok(Expr::Handle(Channel::new()))
+}
+
+// (send! chan value)
+fn send(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("send!", args);
+    let value = args.get(1)?;
+    ensure_resource::<Channel>("send!", args.get(0)?, "chan")?.send(value.clone())?;
+    Ok(value.clone())
+}
+
+// (recv! chan)
+fn recv(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("recv!", args);
+    ensure_resource::<Channel>("recv!", args.get(0)?, "chan")?.recv()
+}
+
+// (close! chan)
+fn close(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("close!", args);
+    ensure_resource::<Channel>("close!", args.get(0)?, "chan")?.close();
+    Ok(Expr::Nil)
+}
+
+// (log/debug message key value ...)
+fn log_debug(args: &[Expr], _env: Env) -> Result<Expr> {
+    log_impl("log/debug", log::Level::Debug, args)
+}
+
+// (log/info message key value ...)
+fn log_info(args: &[Expr], _env: Env) -> Result<Expr> {
+    log_impl("log/info", log::Level::Info, args)
+}
+
+// (log/warn message key value ...)
+fn log_warn(args: &[Expr], _env: Env) -> Result<Expr> {
+    log_impl("log/warn", log::Level::Warn, args)
+}
+
+// (log/error message key value ...)
+fn log_error(args: &[Expr], _env: Env) -> Result<Expr> {
+    log_impl("log/error", log::Level::Error, args)
+}
+
+// A message that's a format string takes as many arguments as it has
+// positional placeholders, and named ones are filled in from the fields
+fn log_impl(name: &str, level: log::Level, args: &[Expr]) -> Result<Expr> {
+    let args = Args::new(name, args);
+    let (template, count) = match *args.get(0)? {
+        Expr::Str(ref message) => {
+            let template = Template::parse(message)?;
+            let count = template.positional();
+            (Some(template), count)
+        }
+        _ => (None, 0),
+    };
+    ensure!(args.len() > count, "#[{}] expected {} arguments for the message", name, count);
+    let pairs = args.rest(1 + count);
+    ensure!(pairs.len() % 2 == 0, "#[{}] expected key-value pairs after the message", name);
+
+    let mut named = Map::new();
+    let fields = pairs.chunks(2)
+        .map(|pair| {
+            let key = match pair[0] {
+                Expr::Keyword(ref key) => key.clone(),
+                ref other => other.to_string(),
+            };
+            named.insert(Key::Keyword(key.clone()), pair[1].clone());
+            (key, pair[1].to_string())
+        })
+        .collect::<Vec<_>>();
+    let message = match template {
+        Some(template) => template.render(&args.all()[1..1 + count], Some(&named))?,
+        None => args.get(0)?.to_string(),
+    };
+    log::log(level, &message, &fields)?;
+    Ok(Expr::Nil)
+}
+
+// (log/set-level! :level)
+fn log_set_level(args: &[Expr], _env: Env) -> Result<Expr> {
+    let level = match *Args::new("log/set-level!", args).get(0)? {
+        Expr::Keyword(ref name) => log::Level::from_name(name),
+        _ => None,
+    };
+    let level = level.ok_or("#[log/set-level!] expected :debug, :info, :warn, or :error")?;
+    log::set_level(level);
+    Ok(Expr::Nil)
+}
+
+// (log/set-target! :stderr) or (log/set-target! "path")
+fn log_set_target(args: &[Expr], _env: Env) -> Result<Expr> {
+    match *Args::new("log/set-target!", args).get(0)? {
+        Expr::Keyword(ref name) if name == "stderr" => log::to_stderr(),
+        Expr::Str(ref path) => log::to_file(path)?,
+        ref other => return Err(type_error("log/set-target!", ":stderr or a file path", other)),
+    }
+    Ok(Expr::Nil)
+}
+
+// (set-contracts! enabled)
+fn set_contracts(args: &[Expr], _env: Env) -> Result<Expr> {
+    eval::set_contracts(Args::new("set-contracts!", args).get(0)?.truthiness());
+    Ok(Expr::Nil)
+}
+
+// (set-debug! enabled)
+fn set_debug(args: &[Expr], _env: Env) -> Result<Expr> {
+    eval::set_debug(Args::new("set-debug!", args).get(0)?.truthiness());
+    Ok(Expr::Nil)
+}
+
+// (exit code?)
+fn exit(args: &[Expr], _env: Env) -> Result<Expr> {
+    let args = Args::new("exit", args);
+    let code = match args.opt(0) {
+        Some(arg) => {
+            // Not truncated, so (exit 4294967296) doesn't exit successfully
+            let code = ensure_int("exit", arg)?;
+            if code < i32::min_value() as i64 || code > i32::max_value() as i64 {
+                return Err(type_error("exit", "32-bit int", arg));
+            }
+            code as i32
+        }
+        None => 0,
+    };
+    Err(ErrorKind::Exit(code).into())
+}
+
+// (at-exit f)
+fn at_exit(args: &[Expr], env: Env) -> Result<Expr> {
+    let func = Args::new("at-exit", args).func(0)?;
+    ensure!(func.arity().contains(0), "#[at-exit] {} can't be called with no arguments", func);
+    exit::at_exit(func, env);
+    Ok(Expr::Nil)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use parser;
+
+    fn call(name: &str, args: Vec<Expr>) -> Result<Expr> {
+        let env = env();
+        match env.lookup(name) {
+            Some(Expr::Func(func)) => func.apply(&args, env),
+            _ => panic!("no builtin {}", name),
+        }
+    }
+
+    fn eval_str(source: &str) -> Result<Expr> {
+        Expr::eval_all(&parser::parse_program(source).unwrap(), env())
+    }
+
+    #[test]
+    fn str_len_counts_graphemes() {
+        assert_eq!(Expr::from(5), call("str-len", vec![Expr::from("hello")]).unwrap());
+        assert_eq!(Expr::from(4), call("str-len", vec![Expr::from("café")]).unwrap());
+        // e followed by a combining acute accent
+        assert_eq!(Expr::from(1), call("str-len", vec![Expr::from("e\u{301}")]).unwrap());
+        // Emoji, including a ZWJ sequence and a flag
+        assert_eq!(Expr::from(1), call("str-len", vec![Expr::from("🦀")]).unwrap());
+        assert_eq!(Expr::from(1), call("str-len", vec![Expr::from("👩\u{200d}💻")]).unwrap());
+        assert_eq!(Expr::from(2), call("str-len", vec![Expr::from("🇨🇦!")]).unwrap());
+    }
+
+    #[test]
+    fn substr_keeps_graphemes() {
+        let s = Expr::from("ae\u{301}🦀z");
+        assert_eq!(
+            Expr::from("e\u{301}🦀"),
+            call("substr", vec![s.clone(), Expr::from(1), Expr::from(3)]).unwrap()
+        );
+        assert_eq!(
+            Expr::from("z"),
+            call("substr", vec![s.clone(), Expr::from(3)]).unwrap()
+        );
+        assert_eq!(
+            Expr::from(""),
+            call("substr", vec![s.clone(), Expr::from(4)]).unwrap()
+        );
+        assert!(call("substr", vec![s.clone(), Expr::from(5)]).is_err());
+        assert!(call("substr", vec![s.clone(), Expr::from(2), Expr::from(1)]).is_err());
+    }
+
+    #[test]
+    fn cons_onto_seq() {
+        let list = Expr::List(List::new(vec![Expr::from(2), Expr::from(3)]));
+        assert_eq!(
+            Expr::List(List::new(vec![Expr::from(1), Expr::from(2), Expr::from(3)])),
+            call("cons", vec![Expr::from(1), list]).unwrap()
+        );
+        assert_eq!(
+            Expr::List(List::new(vec![Expr::from(1)])),
+            call("cons", vec![Expr::from(1), Expr::Nil]).unwrap()
+        );
+        assert!(call("cons", vec![Expr::from(1), Expr::from(2)]).is_err());
+    }
+
+    #[test]
+    fn bad_args_are_errors() {
+        let builtins = vec!["not", "-", "/", "=", "<", "first", "rest", "cons", "str-len",
+                            "substr", "parse-int", "to-string", "print", "debug", "eval"];
+        for name in builtins {
+            assert!(call(name, vec![]).is_err());
+        }
+    }
+
+    #[test]
+    fn exit_codes() {
+        match call("exit", vec![Expr::from(3)]) {
+            Err(Error(ErrorKind::Exit(3), _)) => (),
+            other => panic!("expected to exit with 3, got {:?}", other),
+        }
+        let err = call("exit", vec![Expr::from(1i64 << 32)]).unwrap_err();
+        assert_eq!("#[exit] expected 32-bit int, got int", err.to_string());
+    }
+
+    #[test]
+    fn arity_checked_on_apply() {
+        let err = call("first", vec![Expr::from(1), Expr::from(2)]).unwrap_err();
+        assert_eq!("wrong number of args (2) passed to: first (expected 1)", err.to_string());
+
+        let err = call("substr", vec![Expr::from("abc")]).unwrap_err();
+        assert_eq!("wrong number of args (1) passed to: substr (expected 2-3)", err.to_string());
+    }
+
+    #[test]
+    fn channels() {
+        let c = call("chan", vec![]).unwrap();
+        assert_eq!("#<chan>", c.to_string());
+        assert_eq!(Expr::from(1), call("send!", vec![c.clone(), Expr::from(1)]).unwrap());
+        call("send!", vec![c.clone(), Expr::from("two")]).unwrap();
+        // Values come out in the order they were sent
+        assert_eq!(Expr::from(1), call("recv!", vec![c.clone()]).unwrap());
+        assert_eq!(Expr::from("two"), call("recv!", vec![c.clone()]).unwrap());
+        assert!(call("recv!", vec![Expr::from(1)]).is_err());
+
+        // Values sent before closing can still be received, and then it's empty
+        call("send!", vec![c.clone(), Expr::from(3)]).unwrap();
+        call("close!", vec![c.clone()]).unwrap();
+        let err = call("send!", vec![c.clone(), Expr::from(4)]).unwrap_err();
+        assert_eq!("#[send!] channel is closed", err.to_string());
+        assert_eq!(Expr::from(3), call("recv!", vec![c.clone()]).unwrap());
+        let err = call("recv!", vec![c.clone()]).unwrap_err();
+        assert_eq!("#[recv!] channel is closed", err.to_string());
+    }
+
+    #[test]
+    fn arithmetic_promotion() {
+        let ints = vec![Expr::from(7), Expr::from(2)];
+        let mixed = vec![Expr::from(7), Expr::from(2), Expr::from(1.0)];
+        assert_eq!(Expr::from(0), call("+", vec![]).unwrap());
+        assert_eq!(Expr::from(1), call("*", vec![]).unwrap());
+        assert_eq!(Expr::from(5), call("-", ints.clone()).unwrap());
+        assert_eq!(Expr::from(3.5), call("/", ints).unwrap());
+        assert_eq!(Expr::from(3), call("/", vec![Expr::from(6), Expr::from(2)]).unwrap());
+        // One float makes the whole operation floating point
+        assert_eq!(Expr::from(3.5), call("/", mixed).unwrap());
+        assert!(call("/", vec![Expr::from(1), Expr::from(0)]).is_err());
+        assert!(call("+", vec![Expr::from(1), Expr::from("2")]).is_err());
+    }
+
+    #[test]
+    fn partial_application() {
+        let add = env().lookup("+").unwrap();
+        let add3 = call("partial", vec![add, Expr::from(1), Expr::from(2)]).unwrap();
+        let add3 = add3.func().unwrap();
+        assert_eq!(Expr::from(10), add3.apply(&[Expr::from(3), Expr::from(4)], env()).unwrap());
+        assert_eq!("#[partial +]", add3.to_string());
+
+        let not = env().lookup("not").unwrap();
+        let not = call("partial", vec![not, Expr::from(false)]).unwrap();
+        let not = not.func().unwrap();
+        assert_eq!(Expr::from(true), not.apply(&[], env()).unwrap());
+        assert!(not.apply(&[Expr::from(1)], env()).is_err());
+        let not = env().lookup("not").unwrap();
+        let err = call("partial", vec![not, Expr::from(1), Expr::from(2)]).unwrap_err();
+        assert_eq!("#[partial] not takes 1 arguments, but 2 were given", err.to_string());
+    }
+
+    #[test]
+    fn version_matches_manifest() {
+        let version = call("version", vec![]).unwrap();
+        let version = match version {
+            Expr::Map(map) => map,
+            other => panic!("version returned {}", other),
+        };
+        let major = version.get(&Key::Keyword("major".to_owned())).unwrap();
+        assert_eq!(env!("CARGO_PKG_VERSION_MAJOR"), major.to_string());
+        assert_eq!(
+            Some(Expr::from(env!("CARGO_PKG_VERSION"))),
+            env().lookup("*telescope-version*")
+        );
+    }
+
+    #[test]
+    fn byte_strings() {
+        let hi = call("bytes", vec![Expr::from(104), Expr::from(105), Expr::from(255)]).unwrap();
+        assert_eq!(Expr::Bytes(vec![104, 105, 255]), hi);
+        assert!(call("bytes", vec![Expr::from(256)]).is_err());
+        assert_eq!(Expr::from(3), call("bytes-len", vec![hi.clone()]).unwrap());
+        assert_eq!(Expr::from(255), call("get", vec![hi.clone(), Expr::from(2)]).unwrap());
+        assert_eq!(
+            Expr::Bytes(vec![105]),
+            call("bytes-slice", vec![hi.clone(), Expr::from(1), Expr::from(2)]).unwrap()
+        );
+        assert!(call("bytes->str", vec![hi.clone()]).is_err());
+        assert_eq!(
+            Expr::from("hi\u{ff}"),
+            call("bytes->str", vec![hi, Expr::from("latin-1")]).unwrap()
+        );
+        assert_eq!(
+            Expr::Bytes(vec![0xc3, 0xa9]),
+            call("str->bytes", vec![Expr::from("é")]).unwrap()
+        );
+    }
+
+    #[test]
+    fn binary_encodings() {
+        assert_eq!(Expr::from("aGk="), call("base64-encode", vec![Expr::from("hi")]).unwrap());
+        assert_eq!(Expr::Bytes(b"hi".to_vec()), call("base64-decode", vec![Expr::from("aGk=")]).unwrap());
+        assert_eq!(Expr::from("00ff"), call("hex-encode", vec![Expr::Bytes(vec![0, 255])]).unwrap());
+        assert_eq!(Expr::from("a%2Fb"), call("url-encode", vec![Expr::from("a/b")]).unwrap());
+        assert_eq!(Expr::from("a/b"), call("url-decode", vec![Expr::from("a%2Fb")]).unwrap());
+        assert!(call("hex-encode", vec![Expr::from(1)]).is_err());
+    }
+
+    #[test]
+    fn csv_rows() {
+        let text = Expr::from("name,age\nada,36\n");
+        let rows = call("csv/parse", vec![text.clone()]).unwrap();
+        assert_eq!(r#"[["name" "age"] ["ada" "36"]]"#, rows.to_string());
+        assert_eq!(text, call("csv/write", vec![rows]).unwrap());
+
+        let mut opts = Map::new();
+        opts.insert(Key::Keyword("header".to_owned()), Expr::from(true));
+        let people = call("csv/parse", vec![text, Expr::Map(opts)]).unwrap();
+        let ada = call("get", vec![people, Expr::from(0)]).unwrap();
+        assert_eq!(Expr::from("36"), call("get", vec![ada, Expr::from("age")]).unwrap());
+
+        let mut opts = Map::new();
+        opts.insert(Key::Keyword("delimiter".to_owned()), Expr::from("\t"));
+        let rows = parser::parse_expr(r#"[[1 "a b"] [#t "tab\there"]]"#).unwrap();
+        assert_eq!(
+            Expr::from("1\ta b\n#t\t\"tab\there\"\n"),
+            call("csv/write", vec![rows, Expr::Map(opts)]).unwrap()
+        );
+    }
+
+    #[test]
+    fn paths() {
+        let path = call("path-join", vec![Expr::from("a"), Expr::from("b"), Expr::from("c.tl")]).unwrap();
+        assert_eq!(Expr::from("a/b/c.tl"), path);
+        assert_eq!(Expr::from("c.tl"), call("basename", vec![path.clone()]).unwrap());
+        assert_eq!(Expr::from("a/b"), call("dirname", vec![path]).unwrap());
+        assert_eq!(Expr::from("."), call("dirname", vec![Expr::from("c.tl")]).unwrap());
+        assert_eq!(Expr::from("/"), call("dirname", vec![Expr::from("/")]).unwrap());
+        assert_eq!(Expr::Nil, call("basename", vec![Expr::from("/")]).unwrap());
+    }
+
+    #[test]
+    fn file_builtins() {
+        let dir = ::std::env::temp_dir().join(format!("telescope-files-{}", ::std::process::id()));
+        let dir = Expr::from(dir.to_string_lossy().into_owned());
+        let join = |name: &str| call("path-join", vec![dir.clone(), Expr::from(name)]).unwrap();
+
+        call("mkdir", vec![join("sub")]).unwrap();
+        call("write-bytes", vec![join("a.bin"), Expr::Bytes(vec![1, 2])]).unwrap();
+        call("copy", vec![join("a.bin"), join("sub/b.bin")]).unwrap();
+        assert_eq!(Expr::Bytes(vec![1, 2]), call("read-bytes", vec![join("sub/b.bin")]).unwrap());
+        assert_eq!(r#"["a.bin" "sub"]"#, call("dir-list", vec![dir.clone()]).unwrap().to_string());
+
+        let err = call("read-bytes", vec![join("missing")]).unwrap_err();
+        match *err.kind() {
+            ErrorKind::Io(_) => assert!(err.to_string().contains("missing")),
+            ref other => panic!("expected an io error, got {}", other),
+        }
+        // Directories have to be emptied first
+        assert!(call("rm", vec![join("sub")]).is_err());
+        call("rm", vec![join("sub/b.bin")]).unwrap();
+        call("rm", vec![join("sub")]).unwrap();
+        call("rm", vec![join("a.bin")]).unwrap();
+        call("rm", vec![dir.clone()]).unwrap();
+        assert_eq!(Expr::from(false), call("exists?", vec![dir]).unwrap());
+    }
+
+    #[test]
+    fn logging() {
+        let path = files::temp_file().unwrap();
+        let target = Expr::from(path.to_string_lossy().into_owned());
+        call("log/set-target!", vec![target]).unwrap();
+        call("log/set-level!", vec![Expr::Keyword("info".to_owned())]).unwrap();
+
+        call("log/debug", vec![Expr::from("hidden")]).unwrap();
+        let fields = vec![Expr::from("saved"), Expr::Keyword("user".to_owned()), Expr::from("ada")];
+        call("log/info", fields).unwrap();
+        assert!(call("log/warn", vec![Expr::from("odd"), Expr::from(1)]).is_err());
+        let user = Expr::Keyword("user".to_owned());
+        let message = Expr::from("retry {} for {user}");
+        call("log/warn", vec![message, Expr::from(2), user, Expr::from("ada")]).unwrap();
+        assert!(call("log/warn", vec![Expr::from("retry {}")]).is_err());
+        assert!(call("log/set-level!", vec![Expr::Keyword("loud".to_owned())]).is_err());
+        call("log/set-target!", vec![Expr::Keyword("stderr".to_owned())]).unwrap();
+
+        let mut logged = String::new();
+        fs::File::open(&path).unwrap().read_to_string(&mut logged).unwrap();
+        assert!(logged.contains(" INFO saved user=\"ada\"\n"), "logged {:?}", logged);
+        assert!(logged.ends_with(" WARN retry 2 for ada user=\"ada\"\n"), "logged {:?}", logged);
+        assert_eq!(2, logged.lines().count());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn diff_values() {
+        let parse = |source: &str| parser::parse_expr(source).unwrap();
+        assert_eq!(Expr::Nil, call("diff", vec![parse("[1 2]"), parse("[1 2]")]).unwrap());
+        let differences = call("diff", vec![parse("[1 2]"), parse("[1 3]")]).unwrap();
+        let difference = &differences.vector().unwrap().0[0];
+        assert_eq!(Expr::from(3), call("get", vec![difference.clone(), parse(":right")]).unwrap());
+        assert_eq!("[1]", call("get", vec![difference.clone(), parse(":path")]).unwrap().to_string());
+        assert!(call("assert-eq", vec![parse("{:a 1}"), parse("{:a 1}")]).is_ok());
+        let err = call("assert-eq", vec![parse("{:a 1}"), parse("{:a 2}")]).unwrap_err();
+        assert!(err.to_string().ends_with("\n  at [:a]: 1 vs 2"));
+    }
+
+    #[test]
+    fn sequence_utilities() {
+        assert_eq!("[1 3]", eval_str("(get (group-by odd? [1 2 3]) #t)").unwrap().to_string());
+        assert_eq!("2", eval_str("(get (frequencies [:a :b :a]) :a)").unwrap().to_string());
+        assert_eq!("[[1 2] [3 4]]", eval_str("(partition 2 [1 2 3 4 5])").unwrap().to_string());
+        assert_eq!("[[1 2] [2 3]]", eval_str("(partition 2 1 [1 2 3])").unwrap().to_string());
+        assert_eq!(
+            "[[1 3] [2 4] [5]]",
+            eval_str("(partition-by odd? [1 3 2 4 5])").unwrap().to_string()
+        );
+        assert_eq!("[1 :a 2 :b]", eval_str("(interleave [1 2 3] [:a :b])").unwrap().to_string());
+        assert_eq!("[[1 :a] [2 :b]]", eval_str("(zip '(1 2) [:a :b :c])").unwrap().to_string());
+        assert_eq!("2", eval_str("(get (zipmap [:a :b] [1 2]) :b)").unwrap().to_string());
+        assert_eq!("[1 2 3]", eval_str("(distinct [1 2 1 3 2])").unwrap().to_string());
+        assert_eq!("[1 2 3 4]", eval_str("(flatten [1 [2 (3)] [[4]]])").unwrap().to_string());
+    }
+
+    #[test]
+    fn predicates() {
+        assert_eq!("(2 4)", eval_str("(map (fn [x] (* 2 x)) [1 2])").unwrap().to_string());
+        assert_eq!("(1 3)", eval_str("(filter odd? [1 2 3])").unwrap().to_string());
+        assert_eq!("(2)", eval_str("(remove odd? [1 2 3])").unwrap().to_string());
+        assert_eq!(
+            "(1 3)",
+            eval_str("(keep (fn [x] (when (odd? x) x)) [1 2 3])").unwrap().to_string()
+        );
+        assert_eq!("#t", eval_str("(some even? [1 2 3])").unwrap().to_string());
+        assert_eq!("()", eval_str("(some even? [1 3])").unwrap().to_string());
+        assert_eq!("#t", eval_str("(every? odd? [])").unwrap().to_string());
+        assert_eq!("#f", eval_str("(every? odd? [1 2])").unwrap().to_string());
+        assert_eq!("#t", eval_str("(not-any? even? [1 3])").unwrap().to_string());
+        // Short-circuits before reaching the item that would fail
+        assert_eq!("#f", eval_str("(every? (fn [x] (< x 2)) [1 2 :oops])").unwrap().to_string());
+    }
+
+    #[test]
+    fn statistics() {
+        assert_eq!(Expr::from(6), eval_str("(sum [1 2 3])").unwrap());
+        assert_eq!(Expr::from(6.5), eval_str("(sum [1 2 3.5])").unwrap());
+        assert_eq!(Expr::from(0), eval_str("(sum [])").unwrap());
+        assert_eq!(Expr::from(24), eval_str("(product '(2 3 4))").unwrap());
+        assert_eq!(Expr::from(1), eval_str("(product [])").unwrap());
+        assert!(eval_str("(product [9223372036854775807 2])").is_err());
+        assert!(eval_str("(sum [1 :a])").is_err());
+
+        assert_eq!(Expr::from(2.0), eval_str("(mean [1 2 3])").unwrap());
+        assert_eq!(Expr::from(2), eval_str("(median [3 1 2])").unwrap());
+        assert_eq!(Expr::from(2.5), eval_str("(median [4 1 3 2])").unwrap());
+        assert_eq!(Expr::from(2.0), eval_str("(stddev [2 4 4 4 5 5 7 9])").unwrap());
+        for name in &["mean", "median", "stddev"] {
+            assert_eq!(Expr::Nil, eval_str(&format!("({} [])", name)).unwrap());
+        }
+    }
+
+    #[test]
+    fn matrices() {
+        assert_eq!(
+            parser::parse_expr("[[7.0 10.0] [15.0 22.0]]").unwrap(),
+            eval_str("(let [m (matrix [[1 2] [3 4]])] (matrix/to-vector (matmul m m)))").unwrap()
+        );
+        assert_eq!(
+            Expr::from(3.0),
+            eval_str("(matrix/get (transpose (matrix [[1 2] [3 4]])) 0 1)").unwrap()
+        );
+        assert_eq!(
+            Expr::from(5.0),
+            eval_str("(matrix/get (matrix/add (matrix/identity 2) 4) 1 1)").unwrap()
+        );
+        assert_eq!(
+            parser::parse_expr("[2 3]").unwrap(),
+            eval_str("(matrix/shape (matrix/zeros 2 3))").unwrap()
+        );
+        assert_eq!("#matrix[[1 0] [0 1]]", eval_str("(matrix/identity 2)").unwrap().to_string());
+        assert!(eval_str("(matrix/add (matrix [1 2]) (matrix [1 2 3]))").is_err());
+        assert!(eval_str("(matrix/zeros -1)").is_err());
+    }
+
+    #[test]
+    fn building_strings() {
+        assert_eq!(Expr::from("a1:b[2]"), eval_str("(str \"a\" 1 :b [2])").unwrap());
+        assert_eq!(Expr::from(""), eval_str("(str)").unwrap());
+        assert_eq!(Expr::from("1, 2, 3"), eval_str("(join \", \" [1 2 3])").unwrap());
+        assert_eq!(Expr::from("ab"), eval_str("(join '(\"a\" \"b\"))").unwrap());
+        assert_eq!(
+            Expr::from("0-1-2"),
+            eval_str("(join \"-\" (generator (yield 0) (yield 1) (yield 2)))").unwrap()
+        );
+        let built = "(def b (sb \">\")) (doseq [i (range 3)] (sb-append! b \" \" i)) (sb-str b)";
+        assert_eq!(Expr::from("> 0 1 2"), eval_str(built).unwrap());
+        assert!(eval_str("(sb-str \"x\")").is_err());
+    }
+
+    #[test]
+    fn formatting() {
+        let formatted = eval_str("(format \"{:>8.2}|{}\" 1.23456 \"ada\")").unwrap();
+        assert_eq!(Expr::from("    1.23|ada"), formatted);
+        assert_eq!(Expr::from("x=1"), eval_str("(format \"{k}={v}\" {:k \"x\" :v 1})").unwrap());
+        let printed = eval_str("(with-out-str (printf \"{} {}\n\" \"a\" 1))").unwrap();
+        assert_eq!(Expr::from("a 1\n"), printed);
+        assert!(eval_str("(format \"{} {}\" 1)").is_err());
+    }
+
+    #[test]
+    fn showing_source() {
+        let defn = "(defn sq \"Squares x.\" [x] (* x x))";
+        let shown = eval_str(&format!("{} (with-out-str (source sq))", defn)).unwrap();
+        assert_eq!(Expr::from(format!("{}\n", defn)), shown);
+        let anonymous = eval_str("(with-out-str (source (fn [a b] (+ a b))))").unwrap();
+        assert_eq!(Expr::from("(fn [a b] (+ a b))\n"), anonymous);
+        assert!(eval_str("(source map)").is_err());
+        assert!(eval_str("(source 1)").is_err());
+    }
+
+    #[test]
+    fn searching_names() {
+        let found = eval_str("(first (apropos \"sb-ap\"))").unwrap();
+        assert_eq!(Expr::from(Symbol::new("sb-append!".to_owned())), found);
+        assert!(eval_str("(apropos 'sb)").is_err());
+    }
+
+    #[test]
+    fn integer_division() {
+        let ints = |a: i64, b: i64| vec![Expr::from(a), Expr::from(b)];
+        let results = |name: &str| {
+            [(7, 2), (-7, 2), (7, -2), (-7, -2)]
+                .iter()
+                .map(|&(a, b)| call(name, ints(a, b)).unwrap())
+                .collect::<Vec<_>>()
+        };
+        let expect = |values: [i64; 4]| values.iter().cloned().map(Expr::from).collect::<Vec<_>>();
+        assert_eq!(expect([3, -3, -3, 3]), results("quot"));
+        assert_eq!(expect([1, -1, 1, -1]), results("rem"));
+        assert_eq!(expect([1, 1, 1, 1]), results("mod"));
+
+        assert_eq!(Expr::from(0), call("rem", ints(::std::i64::MIN, -1)).unwrap());
+        assert!(call("quot", ints(::std::i64::MIN, -1)).is_err());
+        assert!(call("mod", ints(1, 0)).is_err());
+        let floats = vec![Expr::from(-7.5), Expr::from(2)];
+        assert_eq!(Expr::from(0.5), call("mod", floats.clone()).unwrap());
+        assert_eq!(Expr::from(-3.0), call("quot", floats).unwrap());
+
+        // Division stays exact as long as it can
+        let chain = vec![Expr::from(12), Expr::from(2), Expr::from(4)];
+        assert_eq!(Expr::from(1.5), call("/", chain).unwrap());
+    }
+
+    #[test]
+    fn overflow_behavior() {
+        let max = ::std::i64::MAX;
+        let min = ::std::i64::MIN;
+        let ints = |a: i64, b: i64| vec![Expr::from(a), Expr::from(b)];
+        let err = call("+", ints(max, 1)).unwrap_err();
+        assert_eq!("#[+] integer overflow", err.to_string());
+        assert!(call("*", ints(min, -1)).is_err());
+        assert!(call("-", vec![Expr::from(min)]).is_err());
+
+        assert_eq!(Expr::Nil, call("checked-add", ints(max, 1)).unwrap());
+        assert_eq!(Expr::from(3), call("checked-mul", ints(1, 3)).unwrap());
+        assert_eq!(Expr::from(min), call("wrapping-add", ints(max, 1)).unwrap());
+        assert_eq!(Expr::from(max), call("wrapping-sub", ints(min, 1)).unwrap());
+        assert_eq!(Expr::from(max), call("saturating-add", ints(max, 1)).unwrap());
+        assert_eq!(Expr::from(min), call("saturating-mul", ints(max, -2)).unwrap());
+        assert!(call("wrapping-add", vec![Expr::from(1.0), Expr::from(1)]).is_err());
+    }
+
+    #[test]
+    fn nested_updates() {
+        let parse = |source: &str| parser::parse_expr(source).unwrap();
+        let data = parse("{:users [{:name \"ada\" :age 36}]}");
+        let path = parse("[:users 0 :age]");
+        assert_eq!(Expr::from(36), call("get-in", vec![data.clone(), path.clone()]).unwrap());
+        let missing = vec![data.clone(), parse("[:users 5 :age]"), Expr::from(0)];
+        assert_eq!(Expr::from(0), call("get-in", missing).unwrap());
+
+        let inc = env().lookup("+").unwrap();
+        let older = call("update-in", vec![data.clone(), path.clone(), inc, Expr::from(1)]);
+        assert_eq!(Expr::from(37), call("get-in", vec![older.unwrap(), path]).unwrap());
+        let made = call("assoc-in", vec![Expr::Nil, parse("[:a :b]"), Expr::from(1)]).unwrap();
+        assert_eq!(parse("{:a {:b 1}}"), made);
+        let pushed = call("assoc", vec![parse("[1 2]"), Expr::from(2), Expr::from(3)]).unwrap();
+        assert_eq!(parse("[1 2 3]"), pushed);
+        assert!(call("assoc", vec![parse("[1 2]"), Expr::from(5), Expr::from(3)]).is_err());
+        assert!(call("assoc-in", vec![data, parse("[:users :x]"), Expr::from(1)]).is_err());
+    }
+
+    #[test]
+    fn merging_maps() {
+        let parse = |source: &str| parser::parse_expr(source).unwrap();
+        let (a, b) = (parse("{:x 1 :y 2}"), parse("{:y 3 :z 4}"));
+        let merged = call("merge", vec![a.clone(), Expr::Nil, b.clone()]).unwrap();
+        assert_eq!(parse("{:x 1 :y 3 :z 4}"), merged);
+        assert_eq!(Expr::Nil, call("merge", vec![Expr::Nil]).unwrap());
+        assert!(call("merge", vec![a.clone(), parse("[1]")]).is_err());
+
+        let add = env().lookup("+").unwrap();
+        let summed = call("merge-with", vec![add, a.clone(), b, parse("{:y 10}")]).unwrap();
+        assert_eq!(parse("{:x 1 :y 15 :z 4}"), summed);
+
+        let selected = call("select-keys", vec![a.clone(), parse("[:y :w]")]).unwrap();
+        assert_eq!(parse("{:y 2}"), selected);
+        let swapped = call("rename-keys", vec![a, parse("{:x :y :y :x :w :v}")]).unwrap();
+        assert_eq!(parse("{:x 2 :y 1}"), swapped);
+    }
+
+    #[test]
+    fn transient_types() {
+        let parse = |source: &str| parser::parse_expr(source).unwrap();
+        assert!(call("transient", vec![parse("[1 2]")]).is_ok());
+        assert!(call("transient", vec![parse("{:a 1}")]).is_ok());
+        let err = call("transient", vec![Expr::from(1)]).unwrap_err();
+        assert_eq!("#[transient] expected vector or map, got int", err.to_string());
+    }
 }
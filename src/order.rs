@@ -0,0 +1,146 @@
+//! How values are ordered. `<` and the other comparisons use `compare`, which
+//! orders numbers, and values of the same kind, and is an error for the rest.
+//! `sort` and `compare` use `total`, which puts every value somewhere, so
+//! collections of mixed values can be sorted too, and `Ordered` uses it to
+//! keep values in sorted collections.
+
+use std::cmp::Ordering;
+
+use error::*;
+use types::Expr;
+use util::type_error;
+
+/// How `a` compares to `b`, or None if a NaN is involved, since NaN isn't
+/// ordered against anything. Numbers compare by value, whether ints or floats;
+/// strings, keywords, symbols, and bytes by their contents; and lists and
+/// vectors lexicographically, by their first items that differ, or else by
+/// their lengths.
+pub fn compare(name: &str, a: &Expr, b: &Expr) -> Result<Option<Ordering>> {
+    match (a, b) {
+        (&Expr::Int(x), &Expr::Int(y)) => Ok(Some(x.cmp(&y))),
+        (&Expr::Bool(x), &Expr::Bool(y)) => Ok(Some(x.cmp(&y))),
+        (&Expr::Str(ref x), &Expr::Str(ref y)) => Ok(Some(x.cmp(y))),
+        (&Expr::Keyword(ref x), &Expr::Keyword(ref y)) => Ok(Some(x.cmp(y))),
+        (&Expr::Sym(ref x), &Expr::Sym(ref y)) => Ok(Some(x.0.cmp(&y.0))),
+        (&Expr::Bytes(ref x), &Expr::Bytes(ref y)) => Ok(Some(x.cmp(y))),
+        _ if a.is_num() && b.is_num() => Ok(float(a).partial_cmp(&float(b))),
+        _ => match (a.items(), b.items()) {
+            (Some(xs), Some(ys)) => {
+                for (x, y) in xs.iter().zip(ys) {
+                    match compare(name, x, y)? {
+                        Some(Ordering::Equal) => (),
+                        other => return Ok(other),
+                    }
+                }
+                Ok(Some(xs.len().cmp(&ys.len())))
+            }
+            _ => Err(type_error(name, a.type_name(), b)),
+        },
+    }
+}
+
+/// An order over every value. Values `compare` orders keep that order, with
+/// NaN after every other number. Values of different kinds go in the order
+/// nil, bools, numbers, strings, keywords, symbols, bytes, lists and vectors,
+/// maps, records, and everything else. Values that can't be ordered among
+/// themselves, like two functions, count as equal.
+pub fn total(a: &Expr, b: &Expr) -> Ordering {
+    match rank(a).cmp(&rank(b)) {
+        Ordering::Equal => (),
+        other => return other,
+    }
+    if let (Some(xs), Some(ys)) = (a.items(), b.items()) {
+        for (x, y) in xs.iter().zip(ys) {
+            match total(x, y) {
+                Ordering::Equal => (),
+                other => return other,
+            }
+        }
+        return xs.len().cmp(&ys.len());
+    }
+    match compare("compare", a, b) {
+        Ok(Some(ordering)) => ordering,
+        // Only numbers are unordered, when one is NaN
+        Ok(None) => float(a).is_nan().cmp(&float(b).is_nan()),
+        Err(_) => Ordering::Equal,
+    }
+}
+
+/// A value ordered by `total`, for keys of sorted collections.
+#[derive(Clone, Debug)]
+pub struct Ordered(pub Expr);
+
+impl PartialEq for Ordered {
+    fn eq(&self, other: &Ordered) -> bool {
+        total(&self.0, &other.0) == Ordering::Equal
+    }
+}
+
+impl Eq for Ordered {}
+
+impl PartialOrd for Ordered {
+    fn partial_cmp(&self, other: &Ordered) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Ordered {
+    fn cmp(&self, other: &Ordered) -> Ordering {
+        total(&self.0, &other.0)
+    }
+}
+
+fn rank(expr: &Expr) -> u8 {
+    match *expr {
+        Expr::Nil => 0,
+        Expr::Bool(_) => 1,
+        Expr::Int(_) | Expr::Flt(_) => 2,
+        Expr::Str(_) => 3,
+        Expr::Keyword(_) => 4,
+        Expr::Sym(_) => 5,
+        Expr::Bytes(_) => 6,
+        Expr::List(_) | Expr::Vector(_) => 7,
+        Expr::Map(_) => 8,
+        Expr::Record(_) => 9,
+        _ => 10,
+    }
+}
+
+fn float(expr: &Expr) -> f64 {
+    expr.flt().unwrap_or_else(|| expr.int().unwrap() as f64)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use parser;
+
+    fn read(source: &str) -> Expr {
+        parser::parse_expr(source).unwrap()
+    }
+
+    #[test]
+    fn comparing() {
+        let cmp = |a: &str, b: &str| compare("<", &read(a), &read(b)).unwrap();
+        assert_eq!(Some(Ordering::Less), cmp("[1 2]", "[1 3]"));
+        assert_eq!(Some(Ordering::Less), cmp("[1 2]", "[1 2 0]"));
+        assert_eq!(Some(Ordering::Equal), cmp("'(1 2.0)", "[1 2]"));
+        assert_eq!(Some(Ordering::Greater), cmp(r#"["b"]"#, r#"["a" "z"]"#));
+        assert_eq!(Some(Ordering::Less), cmp("1", "1.5"));
+        assert_eq!(None, cmp("[1 ##NaN]", "[1 2]"));
+        assert!(compare("<", &read("[1]"), &read("[:a]")).is_err());
+        assert!(compare("<", &read("{}"), &read("{}")).is_err());
+    }
+
+    #[test]
+    fn total_order() {
+        let mut items = read(r#"[[1 2] "b" ##NaN :k nil 2 [1] #t "a" 1.5 {}]"#)
+            .items()
+            .unwrap()
+            .to_vec();
+        items.sort_by(total);
+        let expected = read(r#"[nil #t 1.5 2 ##NaN "a" "b" :k [1] [1 2] {}]"#);
+        assert_eq!(expected.items().unwrap(), &items[..]);
+        assert_eq!(Ordering::Less, total(&read("[1 nil]"), &read("[1 :a]")));
+    }
+}
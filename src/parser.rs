@@ -1,56 +1,156 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+
 use combine::{Stream, Parser, ParseError, ParseResult};
 use combine::{between, many, parser, satisfy_map, token, try, not_followed_by};
+use error;
+use location::Locate;
+use reader::Reader;
+use reader_macro;
+use stream::position;
 use token::Token;
-use types::{Expr, List, Vector, Symbol};
+use types::{Expr, Key, List, Map, Meta, Vector, Symbol};
+
+/// A literal that can't be built, e.g. a map with an odd number of forms.
+#[derive(Debug)]
+struct LiteralError(String);
+
+impl fmt::Display for LiteralError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl StdError for LiteralError {
+    fn description(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Parse all the forms in `source`, which may span any number of lines.
+/// Lexing and parsing failures are `ErrorKind::Lex` and `ErrorKind::Parse`
+/// errors, and a form left open at the end is an error too.
+pub fn parse_program(source: &str) -> error::Result<Vec<Expr>> {
+    Reader::new(io::Cursor::new(source.as_bytes())).collect()
+}
+
+/// Parse `source` as exactly one form.
+pub fn parse_expr(source: &str) -> error::Result<Expr> {
+    let mut forms = parse_program(source)?;
+    match forms.len() {
+        1 => Ok(forms.remove(0)),
+        0 => bail!("expected a form, found none"),
+        count => bail!("expected one form, found {}", count),
+    }
+}
 
 pub fn parse<I>(input: I) -> Result<(Vec<Expr>, I), ParseError<I>>
 where
     I: Stream<Item = Token>,
+    I::Position: Locate,
 {
     // Balanced delimiters
     many(parser(expr))
         .skip(not_followed_by(token(Token::RParen)))
         .skip(not_followed_by(token(Token::RBracket)))
+        .skip(not_followed_by(token(Token::RBrace)))
         .parse(input)
 }
 
 fn expr<I>(input: I) -> ParseResult<Expr, I>
 where
     I: Stream<Item = Token>,
+    I::Position: Locate,
 {
     choice!(
         parser(atom),
         parser(quote),
+        parser(meta),
+        parser(tagged),
         parser(list),
-        parser(vector)
+        parser(vector),
+        parser(map)
     ).parse_stream(input)
 }
 
 fn quote<I>(input: I) -> ParseResult<Expr, I>
 where
     I: Stream<Item = Token>,
+    I::Position: Locate,
 {
     (
         token(Token::Quote),
         parser(expr)
     )
     .map(|(_, expr)| {
-        let quote_symbol = Expr::Sym(Symbol("quote".into()));
-        Expr::List(List(vec![quote_symbol, expr]))
+        let quote_symbol = Expr::Sym(Symbol::new("quote"));
+        Expr::List(List::new(vec![quote_symbol, expr]))
+    }).parse_stream(input)
+}
+
+// #tag form, expanded by the reader macro for tag
+fn tagged<I>(input: I) -> ParseResult<Expr, I>
+where
+    I: Stream<Item = Token>,
+    I::Position: Locate,
+{
+    (
+        satisfy_map(|token| match token {
+            Token::Tag(name) => Some(name),
+            _ => None,
+        }),
+        parser(expr)
+    )
+    .and_then(|(tag, form)| {
+        reader_macro::expand(&tag, form).map_err(|err| LiteralError(err.to_string()))
+    }).parse_stream(input)
+}
+
+// ^{meta} form, or ^:flag form as shorthand for ^{:flag #t} form
+fn meta<I>(input: I) -> ParseResult<Expr, I>
+where
+    I: Stream<Item = Token>,
+    I::Position: Locate,
+{
+    (
+        token(Token::Caret),
+        parser(expr),
+        parser(expr)
+    )
+    .and_then(|(_, meta, form)| {
+        let meta = match meta {
+            Expr::Map(map) => map,
+            Expr::Keyword(name) => {
+                let mut map = Map::new();
+                map.insert(Key::Keyword(name), Expr::from(true));
+                map
+            }
+            // ^type is short for ^{:tag type}
+            Expr::Sym(tag) => {
+                let mut map = Map::new();
+                map.insert(Key::Keyword("tag".into()), Expr::Sym(tag));
+                map
+            }
+            _ => return Err(LiteralError("metadata must be a map, keyword, or symbol".into())),
+        };
+        form.merge_meta(&meta).map_err(|err| LiteralError(err.to_string()))
     }).parse_stream(input)
 }
 
 fn atom<I>(input: I) -> ParseResult<Expr, I>
 where
     I: Stream<Item = Token>,
+    I::Position: Locate,
 {
     satisfy_map(|token| match token {
-        Token::Literal(lit) => Some(Expr::from(lit)),
+        Token::Literal(lit) => Some(lit),
+        Token::Keyword(name) => Some(Expr::Keyword(name)),
         Token::Symbol(sym) => {
             if sym == "nil" {
                 Some(Expr::Nil)
             } else {
-                Some(Expr::from(Symbol(sym)))
+                Some(Expr::from(Symbol::new(sym)))
             }
         },
         _ => None,
@@ -60,27 +160,66 @@ where
 fn list<I>(input: I) -> ParseResult<Expr, I>
 where
     I: Stream<Item = Token>,
+    I::Position: Locate,
 {
-        try(between(
-            token(Token::LParen),
-            token(Token::RParen),
-            many(parser(expr)).map(List).map(Expr::List),
-        ))
-        .parse_stream(input)
+    try((
+        parser(position),
+        between(token(Token::LParen), token(Token::RParen), many(parser(expr))),
+    ))
+    .map(|(position, items)| Expr::List(List(items, located(&position))))
+    .parse_stream(input)
 }
 
 fn vector<I>(input: I) -> ParseResult<Expr, I>
 where
     I: Stream<Item = Token>,
+    I::Position: Locate,
+{
+    try((
+        parser(position),
+        between(token(Token::LBracket), token(Token::RBracket), many(parser(expr))),
+    ))
+    .map(|(position, items)| Expr::Vector(Vector(items, located(&position))))
+    .parse_stream(input)
+}
+
+fn map<I>(input: I) -> ParseResult<Expr, I>
+where
+    I: Stream<Item = Token>,
+    I::Position: Locate,
 {
-    try(between(
-        token(Token::LBracket),
-        token(Token::RBracket),
-        many(parser(expr)).map(Vector).map(Expr::Vector),
+    try((
+        parser(position),
+        between(token(Token::LBrace), token(Token::RBrace), many(parser(expr))),
     ))
+    .and_then(|(position, exprs)| -> Result<Expr, LiteralError> {
+        let mut map = map_literal(exprs)?;
+        map.1 = located(&position);
+        Ok(Expr::Map(map))
+    })
     .parse_stream(input)
 }
 
+// Source location metadata for a form starting at `position`
+fn located<P: Locate>(position: &P) -> Meta {
+    position.locate()
+        .map(|location| Meta::new(location.to_meta()))
+        .unwrap_or_default()
+}
+
+fn map_literal(exprs: Vec<Expr>) -> Result<Map, LiteralError> {
+    if exprs.len() % 2 != 0 {
+        return Err(LiteralError("map literal must contain key-value pairs".into()));
+    }
+
+    let mut map = Map::new();
+    for pair in exprs.chunks(2) {
+        let key = Key::try_from(&pair[0]).map_err(|err| LiteralError(err.to_string()))?;
+        map.insert(key, pair[1].clone());
+    }
+    Ok(map)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -88,7 +227,7 @@ mod test {
     #[test]
     fn empty_list() {
         let input = vec![Token::LParen, Token::RParen];
-        let output = vec![Expr::List(List(Vec::new()))];
+        let output = vec![Expr::List(List::new(Vec::new()))];
         let empty: &[Token] = &[];
         assert_eq!(
             Ok((output, empty)),
@@ -99,11 +238,57 @@ mod test {
     #[test]
     fn empty_vector() {
         let input = vec![Token::LBracket, Token::RBracket];
-        let output = vec![Expr::Vector(Vector(Vec::new()))];
+        let output = vec![Expr::Vector(Vector::new(Vec::new()))];
         let empty: &[Token] = &[];
         assert_eq!(
             Ok((output, empty)),
             parse(&*input)
         );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn map_literal() {
+        let input = vec![
+            Token::LBrace,
+            Token::from("a"),
+            Token::from(1),
+            Token::RBrace,
+        ];
+        let mut map = Map::new();
+        map.insert(Key::Str("a".into()), Expr::from(1));
+        let empty: &[Token] = &[];
+        assert_eq!(
+            Ok((vec![Expr::Map(map)], empty)),
+            parse(&*input)
+        );
+
+        let odd = vec![Token::LBrace, Token::from(1), Token::RBrace];
+        assert!(parse(&*odd).is_err());
+    }
+
+    #[test]
+    fn metadata() {
+        let input = vec![Token::Caret, Token::Keyword("test".into()), Token::Symbol("x".into())];
+        let (exprs, _) = parse(&*input).unwrap();
+        assert_eq!(vec![Expr::Sym(Symbol::new("x"))], exprs);
+        let meta = exprs[0].meta().unwrap();
+        assert_eq!(Some(&Expr::from(true)), meta.get(&Key::Keyword("test".into())));
+
+        let input = vec![Token::Caret, Token::from(1), Token::Symbol("x".into())];
+        assert!(parse(&*input).is_err());
+    }
+
+    #[test]
+    fn program_and_expr() {
+        let forms = parse_program("(def x\n  1)\n; comment\n[x 2]").unwrap();
+        assert_eq!(2, forms.len());
+        assert_eq!("[x 2]", forms[1].to_string());
+        assert!(parse_program("").unwrap().is_empty());
+        assert!(parse_program("(def x").is_err());
+        assert!(parse_program(")").is_err());
+
+        assert_eq!(Expr::from(42), parse_expr(" 42 ").unwrap());
+        assert!(parse_expr("").is_err());
+        assert!(parse_expr("1 2").is_err());
+    }
+}
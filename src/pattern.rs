@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+
+use error::*;
+use types::{Expr, List, Map, Vector};
+
+/// Destructure `value` against `pattern`, recording bound symbols in
+/// `bindings`.
+///
+/// Returns `Ok(false)` if the value doesn't match, or an error if the pattern
+/// itself is malformed.
+pub fn bind(pattern: &Expr, value: &Expr, bindings: &mut HashMap<String, Expr>) -> Result<bool> {
+    match *pattern {
+        Expr::Sym(ref sym) if sym.0 == "_" => Ok(true),
+        Expr::Sym(ref sym) => {
+            // A symbol appearing twice must match equal values
+            let bound = bindings.get(&sym.0).cloned();
+            match bound {
+                Some(bound) => Ok(bound == *value),
+                None => {
+                    bindings.insert(sym.0.clone(), value.clone());
+                    Ok(true)
+                }
+            }
+        }
+        Expr::Vector(ref patterns) => bind_seq(&patterns.0, value, bindings),
+        Expr::Map(ref patterns) => bind_map(patterns, value, bindings),
+        Expr::List(ref list) => match list.0.split_first() {
+            // 'form matches form literally
+            Some((&Expr::Sym(ref quote), quoted)) if quote.0 == "quote" && quoted.len() == 1 => {
+                Ok(quoted[0] == *value)
+            }
+            _ => Err(format!("#[match] invalid pattern: {}", pattern).into()),
+        },
+        _ => Ok(*pattern == *value),
+    }
+}
+
+/// The symbols `pattern` binds, leaving out `_`.
+pub fn symbols(pattern: &Expr) -> Vec<String> {
+    match *pattern {
+        Expr::Sym(ref sym) if sym.0 == "_" || sym.0 == "&" => Vec::new(),
+        Expr::Sym(ref sym) => vec![sym.0.clone()],
+        Expr::Vector(ref patterns) => patterns.0.iter().flat_map(symbols).collect(),
+        Expr::Map(ref patterns) => patterns.iter().flat_map(|(_, pattern)| symbols(pattern)).collect(),
+        _ => Vec::new(),
+    }
+}
+
+// [patterns* (& rest)?]
+fn bind_seq(patterns: &[Expr], value: &Expr, bindings: &mut HashMap<String, Expr>) -> Result<bool> {
+    let (items, is_list) = match *value {
+        Expr::List(ref list) => (&list.0, true),
+        Expr::Vector(ref vec) => (&vec.0, false),
+        _ => return Ok(false),
+    };
+
+    let rest_index = patterns.iter().position(|p| p.sym().map_or(false, |s| s.0 == "&"));
+    let (fixed, rest) = match rest_index {
+        Some(i) => {
+            ensure!(i + 2 == patterns.len(), "#[match] expected one pattern after &");
+            (&patterns[..i], Some(&patterns[i + 1]))
+        }
+        None => (patterns, None),
+    };
+
+    if items.len() < fixed.len() || (rest.is_none() && items.len() != fixed.len()) {
+        return Ok(false);
+    }
+
+    for (pattern, item) in fixed.iter().zip(items.iter()) {
+        if !bind(pattern, item, bindings)? {
+            return Ok(false);
+        }
+    }
+
+    match rest {
+        Some(pattern) => {
+            let tail = items[fixed.len()..].to_vec();
+            let tail = if is_list { Expr::List(List::new(tail)) } else { Expr::Vector(Vector::new(tail)) };
+            bind(pattern, &tail, bindings)
+        }
+        None => Ok(true),
+    }
+}
+
+// {key pattern*}
+fn bind_map(patterns: &Map, value: &Expr, bindings: &mut HashMap<String, Expr>) -> Result<bool> {
+    let map = match *value {
+        Expr::Map(ref map) => map,
+        _ => return Ok(false),
+    };
+
+    for (key, pattern) in patterns.iter() {
+        match map.get(key) {
+            Some(item) => if !bind(pattern, item, bindings)? {
+                return Ok(false);
+            },
+            None => return Ok(false),
+        }
+    }
+    Ok(true)
+}
@@ -0,0 +1,156 @@
+//! Redirectable input and output for builtins, so embedders and tests aren't
+//! tied to the process's stdio.
+//!
+//! Each thread has its own current output and input. They're shared, so a
+//! future or generator can write to and read from the same ones as the thread
+//! that started it.
+
+use std::cell::RefCell;
+use std::io;
+use std::io::prelude::*;
+use std::sync::{Arc, Mutex};
+
+use error::*;
+use types::Expr;
+use util::lock;
+
+type Output = Arc<Mutex<Box<Write + Send>>>;
+type Input = Arc<Mutex<Box<BufRead + Send>>>;
+
+thread_local! {
+    static OUTPUT: RefCell<Output> = RefCell::new(shared_output(Box::new(io::stdout())));
+    static INPUT: RefCell<Input> =
+        RefCell::new(shared_input(Box::new(io::BufReader::new(io::stdin()))));
+}
+
+/// This thread's output and input, for another thread to use.
+#[derive(Clone)]
+pub struct Ports {
+    output: Output,
+    input: Input,
+}
+
+/// The current output and input.
+pub fn current() -> Ports {
+    Ports {
+        output: OUTPUT.with(|out| out.borrow().clone()),
+        input: INPUT.with(|input| input.borrow().clone()),
+    }
+}
+
+/// Run `body` with `ports` as the output and input, putting back the thread's
+/// own afterwards.
+pub fn with_ports<F, T>(ports: Ports, body: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    let output = OUTPUT.with(|out| ::std::mem::replace(&mut *out.borrow_mut(), ports.output));
+    let input = INPUT.with(|cur| ::std::mem::replace(&mut *cur.borrow_mut(), ports.input));
+    let result = body();
+    OUTPUT.with(|out| *out.borrow_mut() = output);
+    INPUT.with(|cur| *cur.borrow_mut() = input);
+    result
+}
+
+/// Replace the current output sink.
+pub fn set_output(sink: Box<Write + Send>) {
+    replace_output(shared_output(sink));
+}
+
+fn replace_output(sink: Output) -> Output {
+    OUTPUT.with(|out| ::std::mem::replace(&mut *out.borrow_mut(), sink))
+}
+
+/// Run `f` against the current output sink.
+pub fn with_output<F, T>(f: F) -> T
+where
+    F: FnOnce(&mut Write) -> T,
+{
+    let sink = OUTPUT.with(|out| out.borrow().clone());
+    let mut sink = lock(&sink);
+    f(&mut **sink)
+}
+
+/// Replace the current input source.
+pub fn set_input(source: Box<BufRead + Send>) {
+    replace_input(shared_input(source));
+}
+
+fn replace_input(source: Input) -> Input {
+    INPUT.with(|input| ::std::mem::replace(&mut *input.borrow_mut(), source))
+}
+
+/// Read a line from the current input source, without its line ending.
+/// Returns `None` at end of input.
+pub fn read_line() -> Result<Option<String>> {
+    let source = INPUT.with(|input| input.borrow().clone());
+    let mut source = lock(&source);
+    let mut line = String::new();
+    if source.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+    Ok(Some(line))
+}
+
+/// Evaluate `body` reading its input from `source`.
+pub fn with_input<F>(source: Box<BufRead + Send>, body: F) -> Result<Expr>
+where
+    F: FnOnce() -> Result<Expr>,
+{
+    let previous = replace_input(shared_input(source));
+    let result = body();
+    replace_input(previous);
+    result
+}
+
+/// Evaluate `body` with output captured into a string.
+pub fn capture<F>(body: F) -> Result<String>
+where
+    F: FnOnce() -> Result<Expr>,
+{
+    let (result, bytes) = capture_all(body);
+    result?;
+    String::from_utf8(bytes).map_err(|_| "captured output is not valid UTF-8".into())
+}
+
+/// Evaluate `body` with output captured, keeping the output even if `body`
+/// fails.
+pub fn capture_all<F>(body: F) -> (Result<Expr>, Vec<u8>)
+where
+    F: FnOnce() -> Result<Expr>,
+{
+    let buffer = SharedBuffer::default();
+    let previous = replace_output(shared_output(Box::new(buffer.clone())));
+    let result = body();
+    replace_output(previous);
+
+    let bytes = lock(&buffer.0).clone();
+    (result, bytes)
+}
+
+fn shared_output(sink: Box<Write + Send>) -> Output {
+    Arc::new(Mutex::new(sink))
+}
+
+fn shared_input(source: Box<BufRead + Send>) -> Input {
+    Arc::new(Mutex::new(source))
+}
+
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        lock(&self.0).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
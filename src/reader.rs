@@ -0,0 +1,184 @@
+use std::collections::VecDeque;
+use std::io;
+use std::io::prelude::*;
+use std::sync::Arc;
+
+use {lexer, parser};
+use error::*;
+use location::Location;
+use types::Expr;
+use token::Token;
+use stream::{StringStream, TokenStream};
+
+/// Reads top-level forms one at a time, pulling in only as many lines as it
+/// takes to complete the next form. Collection forms get :file, :line, and
+/// :column metadata for where they were read.
+#[derive(Debug)]
+pub struct Reader<B> {
+    source: B,
+    file: Option<Arc<str>>,
+    line: usize,
+    tokens: Vec<(Token, Location)>,
+    forms: VecDeque<Expr>,
+}
+
+impl<R: Read> Reader<io::BufReader<R>> {
+    pub fn from_read(source: R) -> Self {
+        Reader::new(io::BufReader::new(source))
+    }
+}
+
+impl<B: BufRead> Reader<B> {
+    pub fn new(source: B) -> Self {
+        Reader {
+            source: source,
+            file: None,
+            line: 0,
+            tokens: Vec::with_capacity(128),
+            forms: VecDeque::new(),
+        }
+    }
+
+    /// Name the source, for the locations of forms read from it.
+    pub fn with_file<S: Into<String>>(mut self, file: S) -> Self {
+        self.file = Some(Arc::from(file.into()));
+        self
+    }
+
+    pub fn get_mut(&mut self) -> &mut B {
+        &mut self.source
+    }
+
+    pub fn get_ref(&self) -> &B {
+        &self.source
+    }
+
+    /// The number of lines read so far.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Whether forms or part of a form have been read but not yet returned.
+    pub fn is_pending(&self) -> bool {
+        !self.tokens.is_empty() || !self.forms.is_empty()
+    }
+
+    /// Read the next form, or None at the end of input. A form left unclosed
+    /// at the end of input is an error. After an error the partially read
+    /// form is discarded, so reading can resume on the next line.
+    pub fn read_form(&mut self) -> Result<Option<Expr>> {
+        loop {
+            if let Some(form) = self.forms.pop_front() {
+                return Ok(Some(form));
+            }
+
+            let mut line = String::new();
+            if self.source.read_line(&mut line)? == 0 {
+                if self.tokens.is_empty() {
+                    return Ok(None);
+                }
+                self.tokens.clear();
+                bail!("unexpected end of input in unclosed form");
+            }
+
+            self.line += 1;
+
+            let tokens = match lexer::lex_positions(StringStream::new(&line)) {
+                Ok((tokens, _)) => tokens,
+                Err(err) => {
+                    self.tokens.clear();
+                    return Err(err.into());
+                }
+            };
+            for (token, column) in tokens {
+                let location = Location::new(self.file.clone(), self.line, column + 1);
+                self.tokens.push((token, location));
+            }
+
+            let tokens = TokenStream::with_locations(self.tokens.drain(..));
+            let (forms, unparsed) = parser::parse(tokens)?;
+            self.tokens = unparsed.unwrap_with_locations();
+            self.forms.extend(forms);
+        }
+    }
+}
+
+impl<B: BufRead> Iterator for Reader<B> {
+    type Item = Result<Expr>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.read_form() {
+            Ok(Some(form)) => Some(Ok(form)),
+            Ok(None) => None,
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn read_all(input: &str) -> Result<Vec<Expr>> {
+        Reader::new(io::Cursor::new(input.as_bytes())).collect()
+    }
+
+    #[test]
+    fn forms_on_one_line() {
+        assert_eq!(
+            vec![Expr::from(1), Expr::from(2), Expr::from("three")],
+            read_all("1 2 \"three\"").unwrap()
+        );
+    }
+
+    #[test]
+    fn forms_across_lines() {
+        let forms = read_all("(+ 1\n   2)\n\n; done\n3\n").unwrap();
+        assert_eq!(2, forms.len());
+        assert_eq!("(+ 1 2)", forms[0].to_string());
+        assert_eq!(Expr::from(3), forms[1]);
+    }
+
+    #[test]
+    fn one_form_at_a_time() {
+        let mut reader = Reader::new(io::Cursor::new("1 2\n3\n".as_bytes()));
+        assert_eq!(Some(Expr::from(1)), reader.read_form().unwrap());
+        assert!(reader.is_pending());
+        assert_eq!(Some(Expr::from(2)), reader.read_form().unwrap());
+        assert!(!reader.is_pending());
+        // The second line hasn't been touched yet
+        assert_eq!(4, reader.get_ref().position());
+        assert_eq!(Some(Expr::from(3)), reader.read_form().unwrap());
+        assert_eq!(None, reader.read_form().unwrap());
+    }
+
+    #[test]
+    fn unclosed_form() {
+        assert!(read_all("(+ 1 2").is_err());
+    }
+
+    #[test]
+    fn resumes_after_error() {
+        let mut reader = Reader::new(io::Cursor::new("(+ 1\n\"oops\n2\n".as_bytes()));
+        assert!(reader.read_form().is_err());
+        assert!(!reader.is_pending());
+        assert_eq!(Some(Expr::from(2)), reader.read_form().unwrap());
+    }
+
+    #[test]
+    fn source_locations() {
+        let input = "(def x\n  [1 2])\n  (f {1 2})";
+        let mut reader = Reader::new(io::Cursor::new(input.as_bytes())).with_file("test.tl");
+
+        let def = reader.read_form().unwrap().unwrap();
+        let file = Some(Arc::from("test.tl"));
+        assert_eq!(Some(Location::new(file.clone(), 1, 1)), Location::of(&def));
+        let vector = &def.list().unwrap().0[2];
+        assert_eq!(Some(Location::new(file.clone(), 2, 3)), Location::of(vector));
+
+        let call = reader.read_form().unwrap().unwrap();
+        assert_eq!(Some(Location::new(file.clone(), 3, 3)), Location::of(&call));
+        let map = &call.list().unwrap().0[1];
+        assert_eq!(Some(Location::new(file, 3, 6)), Location::of(map));
+    }
+}
@@ -0,0 +1,116 @@
+//! Reader macros: `#tag form` is read as the result of calling the handler
+//! registered for `tag` on the (unevaluated) form, so libraries can add
+//! literal syntaxes like `#path "a/b"` without changing the parser.
+//!
+//! Handlers run while reading, and may run more than once for a form that
+//! spans several lines, so they shouldn't have side effects.
+//!
+//! Records print as `#Name{:field value ...}`, and read back that way: each
+//! `defrecord` makes its name a tag, unless there's a handler for it.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use encoding;
+use env::Env;
+use error::*;
+use types::{Expr, Function, Key, Record, RecordType};
+
+// #t and #f are booleans, and #b"..." is a byte string
+const BUILTIN: &[&str] = &["t", "f", "b"];
+
+lazy_static! {
+    static ref HANDLERS: RwLock<HashMap<String, (Arc<Function>, Env)>> = RwLock::new(HashMap::new());
+    static ref RECORDS: RwLock<HashMap<String, Arc<RecordType>>> = RwLock::new(HashMap::new());
+}
+
+/// Register `func` as the handler for `#tag`, replacing any existing one.
+/// Builtin handlers are called with `env`.
+pub fn set(tag: &str, func: Arc<Function>, env: Env) -> Result<()> {
+    ensure!(!BUILTIN.contains(&tag), "#{} is built into the reader", tag);
+    HANDLERS.write()
+        .unwrap_or_else(|err| err.into_inner())
+        .insert(tag.to_owned(), (func, env));
+    Ok(())
+}
+
+/// Remove the handler for `#tag`, returning whether there was one.
+pub fn remove(tag: &str) -> bool {
+    HANDLERS.write().unwrap_or_else(|err| err.into_inner()).remove(tag).is_some()
+}
+
+/// Make `#Name{...}` read as a record of `rtype`, the latest type of that name.
+pub fn set_record(rtype: Arc<RecordType>) {
+    RECORDS.write()
+        .unwrap_or_else(|err| err.into_inner())
+        .insert(rtype.name.clone(), rtype);
+}
+
+/// The form `#tag form` reads as.
+pub fn expand(tag: &str, form: Expr) -> Result<Expr> {
+    if tag == "b" {
+        return match form {
+            Expr::Str(ref string) => Ok(Expr::Bytes(encoding::byte_literal(string)?)),
+            _ => bail!("#b must be followed by a string"),
+        };
+    }
+
+    // Don't hold the lock while the handler runs, in case it reads tagged forms
+    let handler = HANDLERS.read().unwrap_or_else(|err| err.into_inner()).get(tag).cloned();
+    match handler {
+        Some((func, env)) => func.apply(&[form], env)
+            .map_err(|err| Error::from(format!("in reader macro #{}: {}", tag, err))),
+        None => match RECORDS.read().unwrap_or_else(|err| err.into_inner()).get(tag) {
+            Some(rtype) => record(rtype, &form),
+            None => bail!("no reader macro for #{}", tag),
+        },
+    }
+}
+
+// A record from a map with a keyword for each field
+fn record(rtype: &Arc<RecordType>, form: &Expr) -> Result<Expr> {
+    let map = match *form {
+        Expr::Map(ref map) => map,
+        _ => bail!("#{} must be followed by a map", rtype.name),
+    };
+    let values = rtype.fields
+        .iter()
+        .map(|field| {
+            map.get(&Key::Keyword(field.0.clone()))
+                .cloned()
+                .ok_or_else(|| format!("#{} is missing :{}", rtype.name, field).into())
+        })
+        .collect::<Result<Vec<_>>>()?;
+    ensure!(map.len() == values.len(), "#{} has fields it doesn't declare", rtype.name);
+    Ok(Expr::Record(Record::new(rtype.clone(), values)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ops;
+    use parser;
+
+    #[test]
+    fn tagged_forms() {
+        let env = ops::env();
+        let twice = parser::parse_expr("(fn [form] (list form form))").unwrap();
+        let twice = twice.eval(env.clone()).unwrap().func().unwrap();
+        set("twice", twice, env).unwrap();
+
+        assert_eq!("[(1 1) (#t #t)]", parser::parse_expr("[#twice 1 #twice #t]").unwrap().to_string());
+        assert_eq!("((x x) (x x))", parser::parse_expr("#twice #twice x").unwrap().to_string());
+        assert!(remove("twice"));
+        assert!(parser::parse_expr("#twice 1").is_err());
+        let not = ops::env().lookup("not").unwrap().func().unwrap();
+        assert!(set("t", not, ops::env()).is_err());
+    }
+
+    #[test]
+    fn byte_strings() {
+        assert_eq!(Expr::Bytes(vec![b'h', b'i', 0, 0xff]), parser::parse_expr(r#"#b"hi\x00\xff""#).unwrap());
+        assert_eq!(r#"#b"hi\x00\xff""#, parser::parse_expr(r#"#b "hi\x00\xff""#).unwrap().to_string());
+        assert!(parser::parse_expr("#b 1").is_err());
+        assert!(parser::parse_expr(r#"#b"🦀""#).is_err());
+    }
+}
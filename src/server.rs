@@ -0,0 +1,102 @@
+//! A REPL served over TCP, for editors and other tools to evaluate code in a
+//! running session.
+//!
+//! Clients send source text. After each complete top-level form, the server
+//! replies with the printed value on one line, or with a line starting with
+//! `error: ` if evaluation failed. Output printed by the form comes before the
+//! reply. All connections share one environment.
+
+use std::io::{self, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use {location, port};
+use env::Env;
+use error::*;
+use reader::Reader;
+
+/// Accept connections on `addr` forever, serving each on its own thread.
+pub fn serve(addr: &str, env: Env) -> Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    println!("listening on {}", listener.local_addr()?);
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let env = env.clone();
+        thread::spawn(move || {
+            let peer = stream.peer_addr().map(|addr| addr.to_string()).ok();
+            if let Err(err) = session(stream, env) {
+                println!("{}: {}", peer.unwrap_or_else(|| "client".into()), err);
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Evaluate forms from one connection until it closes or calls `exit`.
+pub fn session(stream: TcpStream, env: Env) -> Result<()> {
+    let name = match stream.peer_addr() {
+        Ok(addr) => format!("<{}>", addr),
+        Err(_) => "<client>".to_owned(),
+    };
+    let mut out = stream.try_clone()?;
+
+    // Output ports are per thread, so printing goes back to this client
+    port::set_output(Box::new(stream.try_clone()?));
+    port::set_input(Box::new(io::empty()));
+
+    let mut reader = Reader::new(BufReader::new(stream)).with_file(name);
+    loop {
+        let form = match reader.read_form() {
+            Ok(Some(form)) => form,
+            Ok(None) => return Ok(()),
+            Err(err) => {
+                writeln!(out, "error: {}", err)?;
+                continue;
+            }
+        };
+
+        location::reset();
+        match form.eval(env.clone()) {
+            Ok(value) => writeln!(out, "{}", value)?,
+            Err(err) => match *err.kind() {
+                ErrorKind::Eof | ErrorKind::Exit(_) => return Ok(()),
+                _ => match location::current() {
+                    Some(location) => writeln!(out, "error: {} (at {})", err, location)?,
+                    None => writeln!(out, "error: {}", err)?,
+                },
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::BufRead;
+    use std::net::Shutdown;
+    use ops;
+
+    #[test]
+    fn evaluates_forms() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let env = ops::env();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            session(stream, env).unwrap();
+        });
+
+        let mut client = TcpStream::connect(addr).unwrap();
+        client.write_all(b"(def x 40) (+ x\n 2)\n(first 1)\n(print \"hi\")\n").unwrap();
+        client.shutdown(Shutdown::Write).unwrap();
+
+        let lines = BufReader::new(client)
+            .lines()
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!("x", lines[0]);
+        assert_eq!("42", lines[1]);
+        assert!(lines[2].starts_with("error: "));
+        assert_eq!(&["\"hi\"", "()"], &lines[3..]);
+    }
+}
@@ -0,0 +1,188 @@
+//! Collections kept in order: sorted maps, whose entries are in the order of
+//! their keys, and priority queues, which give back the least item first.
+//! Both order values the way `sort` does, so a priority queue of
+//! `[priority item]` vectors gives items back by priority.
+
+use std::any::Any;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, BTreeMap};
+use std::sync::{Arc, Mutex};
+
+use order::{self, Ordered};
+use types::{Expr, Handle, Resource};
+use util::lock;
+
+/// A map whose entries are in the order of their keys, which can be any
+/// values. Like other maps, a sorted map value never changes: `assoc` changes
+/// a copy.
+#[derive(Clone, Debug, Default)]
+pub struct SortedMap {
+    entries: BTreeMap<Ordered, Expr>,
+}
+
+impl SortedMap {
+    pub fn new() -> SortedMap {
+        SortedMap::default()
+    }
+
+    pub fn into_handle(self) -> Handle {
+        Handle::new(Arc::new(self))
+    }
+
+    pub fn get(&self, key: &Expr) -> Option<&Expr> {
+        self.entries.get(&Ordered(key.clone()))
+    }
+
+    pub fn insert(&mut self, key: Expr, value: Expr) {
+        self.entries.insert(Ordered(key), value);
+    }
+
+    /// The entries as `[key value]` vectors, in order.
+    pub fn entries(&self) -> Vec<Expr> {
+        self.entries.iter().map(entry).collect()
+    }
+
+    /// The entry with the least key.
+    pub fn first(&self) -> Option<Expr> {
+        self.entries.iter().next().map(entry)
+    }
+
+    /// The entry with the greatest key.
+    pub fn last(&self) -> Option<Expr> {
+        self.entries.iter().next_back().map(entry)
+    }
+
+    /// The entries with keys from `start` up to but not including `end`.
+    pub fn range(&self, start: &Expr, end: &Expr) -> Vec<Expr> {
+        // BTreeMap::range panics on a backwards range
+        if order::total(start, end) == Ordering::Greater {
+            return Vec::new();
+        }
+        self.entries.range(Ordered(start.clone())..Ordered(end.clone())).map(entry).collect()
+    }
+}
+
+fn entry((key, value): (&Ordered, &Expr)) -> Expr {
+    Expr::new_vector(vec![key.0.clone(), value.clone()])
+}
+
+impl Resource for SortedMap {
+    fn type_name(&self) -> &'static str {
+        "sorted-map"
+    }
+
+    fn as_any(&self) -> &Any {
+        self
+    }
+
+    fn describe(&self) -> String {
+        let entries = self.entries
+            .iter()
+            .map(|(key, value)| format!("{} {}", key.0, value))
+            .collect::<Vec<_>>();
+        format!("#sorted-map{{{}}}", entries.join(" "))
+    }
+}
+
+/// A heap of items, which gives back the least first, and items that are
+/// equal in the order they went in. It's changed in place, like a deque.
+#[derive(Debug, Default)]
+pub struct PriorityQueue {
+    // Each item with how many went in before it, to break ties
+    heap: Mutex<(BinaryHeap<Reverse<(Ordered, u64)>>, u64)>,
+}
+
+impl PriorityQueue {
+    pub fn new(items: &[Expr]) -> Handle {
+        let queue = PriorityQueue::default();
+        for item in items {
+            queue.insert(item.clone());
+        }
+        Handle::new(Arc::new(queue))
+    }
+
+    pub fn insert(&self, item: Expr) {
+        let mut heap = lock(&self.heap);
+        let count = heap.1;
+        heap.0.push(Reverse((Ordered(item), count)));
+        heap.1 += 1;
+    }
+
+    pub fn peek_min(&self) -> Option<Expr> {
+        lock(&self.heap).0.peek().map(|item| ((item.0).0).0.clone())
+    }
+
+    pub fn pop_min(&self) -> Option<Expr> {
+        lock(&self.heap).0.pop().map(|item| ((item.0).0).0)
+    }
+
+    pub fn len(&self) -> usize {
+        lock(&self.heap).0.len()
+    }
+
+    /// The items, least first.
+    pub fn to_vec(&self) -> Vec<Expr> {
+        let mut items = lock(&self.heap).0.iter().map(|item| item.0.clone()).collect::<Vec<_>>();
+        items.sort();
+        items.into_iter().map(|(item, _)| item.0).collect()
+    }
+}
+
+impl Resource for PriorityQueue {
+    fn type_name(&self) -> &'static str {
+        "priority-queue"
+    }
+
+    fn as_any(&self) -> &Any {
+        self
+    }
+
+    fn describe(&self) -> String {
+        format!("#<priority-queue {}>", Expr::new_vector(self.to_vec()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use parser;
+
+    fn read(source: &str) -> Expr {
+        parser::parse_expr(source).unwrap()
+    }
+
+    #[test]
+    fn sorted_maps() {
+        let mut map = SortedMap::new();
+        for &(key, value) in &[("[2 1]", ":c"), ("1.5", ":a"), ("[1 9]", ":b"), ("1.5", ":d")] {
+            map.insert(read(key), read(value));
+        }
+        assert_eq!(Some(&read(":d")), map.get(&read("1.5")));
+        assert_eq!(None, map.get(&read("[1]")));
+        assert_eq!(read("[[1.5 :d] [[1 9] :b] [[2 1] :c]]"), Expr::new_vector(map.entries()));
+        assert_eq!((Some(read("[1.5 :d]")), Some(read("[[2 1] :c]"))), (map.first(), map.last()));
+        assert_eq!(vec![read("[[1 9] :b]")], map.range(&read("[1]"), &read("[2]")));
+        assert!(map.range(&read("[2]"), &read("[1]")).is_empty());
+        assert_eq!("#sorted-map{1.5 :d [1 9] :b [2 1] :c}", map.describe());
+    }
+
+    #[test]
+    fn priority_queues() {
+        let handle = PriorityQueue::new(&[read("[3 :c]"), read("[1 :a]")]);
+        let queue = handle.downcast_ref::<PriorityQueue>().unwrap();
+        queue.insert(read("[2 :b]"));
+        queue.insert(read("[1 :a]"));
+        assert_eq!(4, queue.len());
+        assert_eq!(Some(read("[1 :a]")), queue.peek_min());
+        assert_eq!("#<priority-queue [[1 :a] [1 :a] [2 :b] [3 :c]]>", queue.describe());
+        let popped = (0..5).map(|_| queue.pop_min()).collect::<Vec<_>>();
+        let expected = vec![
+            Some(read("[1 :a]")),
+            Some(read("[1 :a]")),
+            Some(read("[2 :b]")),
+            Some(read("[3 :c]")),
+            None,
+        ];
+        assert_eq!(expected, popped);
+    }
+}
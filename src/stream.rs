@@ -1,10 +1,17 @@
 use std::collections::VecDeque;
 
-use combine::StreamOnce;
-use combine::primitives::{Error, Positioner, SourcePosition};
+use combine::{ParseResult, Stream, StreamOnce};
+use combine::primitives::{Consumed, Error};
 
+use location::Location;
 use token::Token;
 
+/// A parser that consumes nothing, returning the current stream position.
+pub fn position<I: Stream>(input: I) -> ParseResult<I::Position, I> {
+    let position = input.position();
+    Ok((position, Consumed::Empty(input)))
+}
+
 #[derive(Clone, Debug)]
 pub struct StringStream {
     line: Vec<char>,
@@ -38,23 +45,36 @@ impl<'a> StreamOnce for StringStream {
     }
 }
 
+/// Tokens along with where each was read, so parsed forms can record their
+/// source location.
 #[derive(Clone, Debug)]
 pub struct TokenStream {
-    line: VecDeque<Token>,
-    position: SourcePosition,
+    line: VecDeque<(Token, Location)>,
+    position: Location,
 }
 
 impl TokenStream {
+    /// Tokens with unknown locations.
     pub fn new<T>(line: T) -> Self
     where T: Iterator<Item = Token>
+    {
+        TokenStream::with_locations(line.map(|token| (token, Location::default())))
+    }
+
+    pub fn with_locations<T>(line: T) -> Self
+    where T: Iterator<Item = (Token, Location)>
     {
         TokenStream {
             line: line.collect(),
-            position: SourcePosition {column: 0, line: 0},
+            position: Location::default(),
         }
     }
 
     pub fn unwrap(self) -> Vec<Token> {
+        self.line.into_iter().map(|(token, _)| token).collect()
+    }
+
+    pub fn unwrap_with_locations(self) -> Vec<(Token, Location)> {
         self.line.into()
     }
 }
@@ -62,18 +82,21 @@ impl TokenStream {
 impl StreamOnce for TokenStream {
     type Item = Token;
     type Range = Token;
-    type Position = SourcePosition;
+    type Position = Location;
 
     fn uncons(&mut self) -> Result<Token, Error<Token, Token>> {
-        if let Some(token) = self.line.pop_front() {
-            token.update(&mut self.position);
-            Ok(token.clone())
+        if let Some((token, location)) = self.line.pop_front() {
+            self.position = location;
+            Ok(token)
         } else {
             Err(Error::end_of_input())
         }
     }
 
+    // The location of the next token, or of the last one at the end
     fn position(&self) -> Self::Position {
-        self.position
+        self.line.front()
+            .map(|&(_, ref location)| location.clone())
+            .unwrap_or_else(|| self.position.clone())
     }
 }
\ No newline at end of file
@@ -0,0 +1,151 @@
+//! Building strings without copying them over and over. `str` and `join`
+//! size their result up front, and a string builder can be appended to in
+//! place, where `(str acc s)` in a loop would copy everything so far each time.
+//!
+//! Also Unicode normalization and case folding, which don't depend on the
+//! locale, so text compares the same way everywhere.
+
+use std::any::Any;
+use std::cmp::Ordering;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+use unicode_normalization::UnicodeNormalization;
+
+use error::*;
+use types::{Expr, Handle, Resource};
+use util::lock;
+
+#[derive(Debug)]
+pub struct StringBuilder {
+    text: Mutex<String>,
+}
+
+impl StringBuilder {
+    pub fn new(init: String) -> Handle {
+        Handle::new(Arc::new(StringBuilder { text: Mutex::new(init) }))
+    }
+
+    pub fn append(&self, items: &[Expr]) {
+        let mut text = lock(&self.text);
+        text.reserve(size_hint(items.iter()));
+        for item in items {
+            push(&mut text, item);
+        }
+    }
+
+    pub fn text(&self) -> String {
+        lock(&self.text).clone()
+    }
+}
+
+impl Resource for StringBuilder {
+    fn type_name(&self) -> &'static str {
+        "string-builder"
+    }
+
+    fn as_any(&self) -> &Any {
+        self
+    }
+}
+
+/// `items` written one after another, with strings as their contents rather
+/// than quoted.
+pub fn concat(items: &[Expr]) -> String {
+    let mut text = String::with_capacity(size_hint(items.iter()));
+    for item in items {
+        push(&mut text, item);
+    }
+    text
+}
+
+/// `items` written with `separator` between them. Items are taken one at a
+/// time, so they can come from a generator.
+pub fn join<I>(separator: &str, items: I) -> Result<String>
+where
+    I: IntoIterator<Item = Result<Expr>>,
+{
+    let mut text = String::new();
+    for (i, item) in items.into_iter().enumerate() {
+        if i > 0 {
+            text.push_str(separator);
+        }
+        push(&mut text, &item?);
+    }
+    Ok(text)
+}
+
+/// `text` in normalization form `form`: "nfc", "nfd", "nfkc", or "nfkd".
+pub fn normalize(text: &str, form: &str) -> Result<String> {
+    Ok(match &*form.to_lowercase() {
+        "nfc" => text.nfc().collect(),
+        "nfd" => text.nfd().collect(),
+        "nfkc" => text.nfkc().collect(),
+        "nfkd" => text.nfkd().collect(),
+        _ => bail!("unknown normalization form {:?}, expected nfc, nfd, nfkc, or nfkd", form),
+    })
+}
+
+/// `text` with case differences removed, for comparing without regard to
+/// case. Going through upper case first folds characters like `ß` that have
+/// no single lower case form.
+pub fn casefold(text: &str) -> String {
+    text.nfd().collect::<String>().to_uppercase().to_lowercase().nfc().collect()
+}
+
+/// Compare two strings by code point once both are normalized, so the same
+/// text spelled with different combining characters is equal.
+pub fn compare(a: &str, b: &str) -> Ordering {
+    a.nfd().cmp(b.nfd())
+}
+
+fn push(text: &mut String, item: &Expr) {
+    match *item {
+        Expr::Str(ref s) => text.push_str(s),
+        ref other => {
+            let _ = write!(text, "{}", other);
+        }
+    }
+}
+
+// The room the strings among `items` need, which is all of it unless there are
+// other values to print
+fn size_hint<'a, I: Iterator<Item = &'a Expr>>(items: I) -> usize {
+    items.map(|item| item.str().map_or(0, str::len)).sum()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn building() {
+        let items = vec![Expr::from("a"), Expr::from(1), Expr::from(":b")];
+        assert_eq!("a1:b", concat(&items));
+        assert_eq!("a, 1, :b", join(", ", items.iter().cloned().map(Ok)).unwrap());
+        assert_eq!("", join(", ", Vec::new()).unwrap());
+        assert!(join(", ", vec![Ok(Expr::from(1)), Err("stop".into())]).is_err());
+
+        let handle = StringBuilder::new("x".to_owned());
+        let builder = handle.downcast_ref::<StringBuilder>().unwrap();
+        builder.append(&items);
+        builder.append(&[Expr::from("!")]);
+        assert_eq!("xa1:b!", builder.text());
+    }
+
+    #[test]
+    fn unicode() {
+        let composed = "caf\u{e9}";
+        let decomposed = "cafe\u{301}";
+        assert_eq!(decomposed, normalize(composed, "nfd").unwrap());
+        assert_eq!(composed, normalize(decomposed, "NFC").unwrap());
+        assert_eq!("fi", normalize("\u{fb01}", "nfkc").unwrap());
+        assert!(normalize(composed, "nfx").is_err());
+
+        assert_eq!("strasse", casefold("Stra\u{df}e"));
+        assert_eq!(casefold(composed), casefold("CAFE\u{301}"));
+        assert_eq!(Ordering::Equal, compare(composed, decomposed));
+        assert_eq!(Ordering::Less, compare("apple", "apples"));
+        assert_eq!(Ordering::Greater, compare("b", "a\u{301}"));
+    }
+}
@@ -1,14 +1,7 @@
 use combine::primitives::Positioner;
 use conv::ValueFrom;
 use std::fmt;
-
-#[derive(Clone, Debug, PartialEq)]
-pub enum Literal {
-    Bool(bool),
-    Int(i64),
-    Flt(f64),
-    Str(String),
-}
+use types::Expr;
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Token {
@@ -16,9 +9,16 @@ pub enum Token {
     RParen,
     LBracket,
     RBracket,
+    LBrace,
+    RBrace,
     Quote,
-    Literal(Literal),
+    Caret,
+    /// An atom, as the value it stands for.
+    Literal(Expr),
     Symbol(String),
+    Keyword(String),
+    /// `#name`, applying a reader macro to the next form.
+    Tag(String),
 }
 
 impl Positioner for Token {
@@ -34,22 +34,16 @@ impl Positioner for Token {
             Token::RParen => position.column += 1,
             Token::LBracket => position.column += 1,
             Token::RBracket => position.column += 1,
+            Token::LBrace => position.column += 1,
+            Token::RBrace => position.column += 1,
             Token::Quote => position.column += 1,
+            Token::Caret => position.column += 1,
             Token::Literal(ref l) => {
                 position.column += i32::value_from(l.to_string().len()).unwrap()
             }
             Token::Symbol(ref s) => position.column += i32::value_from(s.len()).unwrap(),
-        }
-    }
-}
-
-impl fmt::Display for Literal {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            Literal::Bool(ref b) => write!(f, "{}", b),
-            Literal::Int(ref i) => write!(f, "{}", i),
-            Literal::Flt(ref x) => write!(f, "{}", x),
-            Literal::Str(ref s) => write!(f, "{}", s),
+            Token::Keyword(ref s) => position.column += i32::value_from(s.len() + 1).unwrap(),
+            Token::Tag(ref s) => position.column += i32::value_from(s.len() + 1).unwrap(),
         }
     }
 }
@@ -59,56 +53,16 @@ impl fmt::Display for Token {
         match *self {
             Token::Literal(ref lit) => write!(f, "{}", lit),
             Token::Symbol(ref s) => write!(f, "{}", s),
+            Token::Keyword(ref s) => write!(f, ":{}", s),
+            Token::Tag(ref s) => write!(f, "#{}", s),
             _ => write!(f, "{:#?}", self),
         }
     }
 }
 
-impl From<i32> for Literal {
-    fn from(x: i32) -> Self {
-        Literal::Int(x.into())
-    }
-}
-
-impl From<i64> for Literal {
-    fn from(x: i64) -> Self {
-        Literal::Int(x)
-    }
-}
-
-impl From<f32> for Literal {
-    fn from(x: f32) -> Self {
-        Literal::Flt(x.into())
-    }
-}
-
-impl From<f64> for Literal {
-    fn from(x: f64) -> Self {
-        Literal::Flt(x)
-    }
-}
-
-impl From<bool> for Literal {
-    fn from(x: bool) -> Self {
-        Literal::Bool(x)
-    }
-}
-
-impl<'a> From<&'a str> for Literal {
-    fn from(x: &'a str) -> Self {
-        Literal::Str(x.to_owned())
-    }
-}
-
-impl From<String> for Literal {
-    fn from(x: String) -> Self {
-        Literal::Str(x)
-    }
-}
-
 impl<T> From<T> for Token
 where
-    T: Into<Literal>,
+    T: Into<Expr>,
 {
     fn from(x: T) -> Self {
         Token::Literal(x.into())
@@ -121,15 +75,11 @@ mod test {
 
     #[test]
     fn format_literal() {
-        let int = Literal::from(32i64);
-        let flt = Literal::from(3.14f64);
-        let boolean = Literal::from(true);
-        let string = Literal::from("jkl;");
-
-        assert_eq!("32", int.to_string());
-        assert_eq!("3.14", flt.to_string());
-        assert_eq!("true", boolean.to_string());
-        assert_eq!("jkl;", string.to_string());
+        // Literals print the same as the values they stand for
+        assert_eq!("32", Token::from(32i64).to_string());
+        assert_eq!("3.14", Token::from(3.14f64).to_string());
+        assert_eq!("#t", Token::from(true).to_string());
+        assert_eq!("\"jkl;\"", Token::from("jkl;").to_string());
     }
 
     #[test]
@@ -0,0 +1,121 @@
+//! Transient collections, for building a big vector or map in a loop. `assoc`
+//! copies the whole collection to change one entry, so building one entry at a
+//! time takes quadratic time. A transient is changed in place instead, then
+//! turned back into an ordinary value with `persistent!`, after which it can't
+//! be used again.
+
+use std::any::Any;
+use std::sync::{Arc, Mutex};
+
+use error::*;
+use types::{Expr, Handle, Key, Resource};
+use util::{lock, type_error};
+
+#[derive(Debug)]
+pub struct Transient {
+    // The vector or map, until it's made persistent
+    coll: Mutex<Option<Expr>>,
+}
+
+impl Transient {
+    pub fn new(coll: Expr) -> Handle {
+        Handle::new(Arc::new(Transient { coll: Mutex::new(Some(coll)) }))
+    }
+
+    /// Add `item` to the end of a vector, or the entry `[key value]` to a map.
+    pub fn conj(&self, item: &Expr) -> Result<()> {
+        self.with("conj!", |coll| match *coll {
+            Expr::Vector(ref mut vec) => {
+                vec.0.push(item.clone());
+                Ok(())
+            }
+            Expr::Map(ref mut map) => match item.items() {
+                Some(entry) if entry.len() == 2 => {
+                    map.insert(Key::try_from(&entry[0])?, entry[1].clone());
+                    Ok(())
+                }
+                _ => bail!("#[conj!] expected a [key value] entry, got {}", item),
+            },
+            _ => unreachable!(),
+        })
+    }
+
+    /// Put `value` under `key` in a map, or at index `key` of a vector, which
+    /// can be its length to add to the end.
+    pub fn assoc(&self, key: &Expr, value: &Expr) -> Result<()> {
+        self.with("assoc!", |coll| match *coll {
+            Expr::Vector(ref mut vec) => {
+                let len = vec.0.len();
+                match key.int() {
+                    Some(i) if i >= 0 && (i as usize) < len => vec.0[i as usize] = value.clone(),
+                    Some(i) if i >= 0 && i as usize == len => vec.0.push(value.clone()),
+                    Some(i) => bail!("#[assoc!] index {} out of range for length {}", i, len),
+                    None => return Err(type_error("assoc!", "int", key)),
+                }
+                Ok(())
+            }
+            Expr::Map(ref mut map) => {
+                map.insert(Key::try_from(key)?, value.clone());
+                Ok(())
+            }
+            _ => unreachable!(),
+        })
+    }
+
+    /// The collection as an ordinary value, leaving the transient unusable.
+    pub fn persistent(&self) -> Result<Expr> {
+        let mut coll = lock(&self.coll);
+        coll.take().ok_or_else(|| finished("persistent!"))
+    }
+
+    fn with<F>(&self, name: &str, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut Expr) -> Result<()>,
+    {
+        let mut coll = lock(&self.coll);
+        match *coll {
+            Some(ref mut coll) => f(coll),
+            None => Err(finished(name)),
+        }
+    }
+}
+
+fn finished(name: &str) -> Error {
+    format!("#[{}] the transient was already made persistent", name).into()
+}
+
+impl Resource for Transient {
+    fn type_name(&self) -> &'static str {
+        "transient"
+    }
+
+    fn as_any(&self) -> &Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use parser;
+
+    #[test]
+    fn building() {
+        let handle = Transient::new(parser::parse_expr("[1]").unwrap());
+        let transient = handle.downcast_ref::<Transient>().unwrap();
+        transient.conj(&Expr::from(2)).unwrap();
+        transient.assoc(&Expr::from(0), &Expr::from(0)).unwrap();
+        transient.assoc(&Expr::from(2), &Expr::from(3)).unwrap();
+        assert!(transient.assoc(&Expr::from(9), &Expr::Nil).is_err());
+        assert_eq!(parser::parse_expr("[0 2 3]").unwrap(), transient.persistent().unwrap());
+        assert!(transient.conj(&Expr::from(4)).is_err());
+        assert!(transient.persistent().is_err());
+
+        let handle = Transient::new(parser::parse_expr("{}").unwrap());
+        let transient = handle.downcast_ref::<Transient>().unwrap();
+        transient.conj(&parser::parse_expr("[:a 1]").unwrap()).unwrap();
+        transient.assoc(&Expr::Keyword("b".to_owned()), &Expr::from(2)).unwrap();
+        assert!(transient.conj(&Expr::from(1)).is_err());
+        assert_eq!(parser::parse_expr("{:a 1 :b 2}").unwrap(), transient.persistent().unwrap());
+    }
+}
@@ -0,0 +1,342 @@
+//! Gradual type annotations. A parameter written `^int x` has `int` as its
+//! `:tag` metadata, and `-> int` after a parameter vector declares the type a
+//! function returns. Unannotated code is never checked.
+//!
+//! `tele check` reads code without running it and reports calls and
+//! returns whose types are known and don't match. In debug mode, annotated
+//! functions also check their arguments and results when they're called.
+
+use std::collections::HashMap;
+use std::io;
+
+use error::*;
+use lint::Warning;
+use location::Location;
+use reader::Reader;
+use types::{Expr, Key, Symbol};
+
+/// The type `symbol` is annotated with, if any.
+pub fn tag(symbol: &Symbol) -> Option<&str> {
+    symbol.1.get()
+        .and_then(|meta| meta.get(&Key::Keyword("tag".to_owned())))
+        .and_then(Expr::sym)
+        .map(|tag| &*tag.0)
+}
+
+/// Split a `-> type` annotation off the start of a function body.
+pub fn split_return(body: &[Expr]) -> (Option<Symbol>, &[Expr]) {
+    let arrow = body.first().and_then(Expr::sym).map_or(false, |sym| sym.0 == "->");
+    match body.get(1).and_then(Expr::sym) {
+        // The annotation needs a body after it
+        Some(returns) if arrow && body.len() > 2 => (Some(returns.clone()), &body[2..]),
+        _ => (None, body),
+    }
+}
+
+/// Whether a value whose type is named `actual` satisfies the annotation
+/// `expected`. `any` matches everything, and `num` both ints and floats.
+pub fn matches(expected: &str, actual: &str) -> bool {
+    expected == "any" || expected == actual || (expected == "num" && (actual == "int" || actual == "flt"))
+}
+
+/// Whether `value` satisfies the annotation `expected`, which may also name a
+/// record type.
+pub fn is_a(expected: &str, value: &Expr) -> bool {
+    match *value {
+        Expr::Record(ref record) if record.rtype.name == expected => true,
+        _ => matches(expected, value.type_name()),
+    }
+}
+
+/// Check the source of `file`, failing only if it can't be read.
+pub fn check(source: &str, file: &str) -> Result<Vec<Warning>> {
+    let forms = Reader::new(io::Cursor::new(source.as_bytes()))
+        .with_file(file)
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut checker = Checker { signatures: HashMap::new(), scopes: Vec::new(), warnings: Vec::new() };
+    for form in &forms {
+        checker.define(form);
+    }
+    let top = Location::new(Some(file.into()), 0, 0);
+    for form in &forms {
+        checker.form(form, &top);
+    }
+    let mut warnings = checker.warnings;
+    warnings.sort_by(|a, b| a.location.cmp(&b.location));
+    Ok(warnings)
+}
+
+// The annotations of one clause of a function
+#[derive(Debug)]
+struct Signature {
+    params: Vec<(String, Option<String>)>,
+    returns: Option<String>,
+}
+
+impl Signature {
+    fn of(clause: &[Expr]) -> Option<Signature> {
+        let params = clause.first().and_then(Expr::vector)?
+            .0.iter()
+            .map(|param| match param.sym() {
+                Some(sym) => (sym.0.clone(), tag(sym).map(str::to_owned)),
+                None => (param.to_string(), None),
+            })
+            .collect();
+        let (returns, _) = split_return(&clause[1..]);
+        Some(Signature { params, returns: returns.map(|sym| sym.0) })
+    }
+}
+
+#[derive(Debug)]
+struct Checker {
+    // The annotated clauses of each top-level function
+    signatures: HashMap<String, Vec<Signature>>,
+    // The type of each local binding, or None if it's unknown
+    scopes: Vec<HashMap<String, Option<String>>>,
+    warnings: Vec<Warning>,
+}
+
+impl Checker {
+    fn define(&mut self, form: &Expr) {
+        let items = match form.list() {
+            Some(list) => &list.0,
+            None => return,
+        };
+        if items.len() < 3 || items[0].sym().map_or(true, |sym| sym.0 != "defn") {
+            return;
+        }
+        if let Some(name) = items[1].sym() {
+            let signatures = clauses(&items[2..]).into_iter().filter_map(Signature::of).collect();
+            self.signatures.insert(name.0.clone(), signatures);
+        }
+    }
+
+    fn local(&self, name: &str) -> Option<&Option<String>> {
+        self.scopes.iter().rev().filter_map(|scope| scope.get(name)).next()
+    }
+
+    // The signature a call to global `name` with `count` arguments uses
+    fn signature(&self, name: &str, count: usize) -> Option<&Signature> {
+        if self.local(name).is_some() {
+            return None;
+        }
+        self.signatures.get(name)?.iter().find(|signature| signature.params.len() == count)
+    }
+
+    // The type `form` evaluates to, when it's obvious
+    fn infer(&self, form: &Expr) -> Option<String> {
+        match *form {
+            Expr::Sym(ref sym) => self.local(&sym.0).cloned().and_then(|ty| ty),
+            Expr::List(ref list) => {
+                let (head, args) = list.0.split_first()?;
+                match &*head.sym()?.0 {
+                    "quote" => args.first().map(|quoted| quoted.type_name().to_owned()),
+                    "fn" => Some("fn".to_owned()),
+                    name => self.signature(name, args.len())?.returns.clone(),
+                }
+            }
+            _ => Some(form.type_name().to_owned()),
+        }
+    }
+
+    fn form(&mut self, form: &Expr, at: &Location) {
+        match *form {
+            Expr::Vector(ref vec) => self.forms(&vec.0, at),
+            Expr::Map(ref map) => {
+                for (_, value) in map.iter() {
+                    self.form(value, at);
+                }
+            }
+            Expr::List(ref list) => {
+                let at = Location::of(form).unwrap_or_else(|| at.clone());
+                if let Some((head, args)) = list.0.split_first() {
+                    match head.sym() {
+                        Some(sym) => self.call(&sym.0, args, &at),
+                        None => self.forms(&list.0, &at),
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn forms(&mut self, forms: &[Expr], at: &Location) {
+        for form in forms {
+            self.form(form, at);
+        }
+    }
+
+    fn call(&mut self, name: &str, args: &[Expr], at: &Location) {
+        match name {
+            "quote" => (),
+            "defn" if args.len() > 1 => {
+                let rest = match args[1] {
+                    Expr::Str(_) if args.len() > 2 => &args[2..],
+                    _ => &args[1..],
+                };
+                let name = args[0].to_string();
+                for clause in clauses(rest) {
+                    self.clause(&name, clause, at);
+                }
+            }
+            "fn" => {
+                let rest = if args.first().and_then(Expr::sym).is_some() { &args[1..] } else { args };
+                for clause in clauses(rest) {
+                    self.clause("fn", clause, at);
+                }
+            }
+            "let" if !args.is_empty() => {
+                self.scopes.push(HashMap::new());
+                if let Some(bindings) = args.first().and_then(Expr::vector) {
+                    for pair in bindings.0.chunks(2) {
+                        let ty = pair.get(1).and_then(|init| {
+                            self.form(init, at);
+                            self.infer(init)
+                        });
+                        self.bind(&pair[0], ty);
+                    }
+                }
+                self.forms(&args[1..], at);
+                self.scopes.pop();
+            }
+            _ => {
+                let mut mismatches = Vec::new();
+                if let Some(signature) = self.signature(name, args.len()) {
+                    for (arg, &(ref param, ref expected)) in args.iter().zip(&signature.params) {
+                        match (expected.as_ref(), self.infer(arg)) {
+                            (Some(expected), Some(actual)) if !matches(expected, &actual) => {
+                                let message = format!("{} expects {} for {}, got {}", name, expected, param, actual);
+                                let at = Location::of(arg).unwrap_or_else(|| at.clone());
+                                mismatches.push(Warning { location: at, kind: "type", message });
+                            }
+                            _ => (),
+                        }
+                    }
+                }
+                self.warnings.extend(mismatches);
+                self.forms(args, at);
+            }
+        }
+    }
+
+    fn clause(&mut self, name: &str, clause: &[Expr], at: &Location) {
+        let params = match clause.first().and_then(Expr::vector) {
+            Some(params) => params,
+            None => return self.forms(clause, at),
+        };
+        self.scopes.push(HashMap::new());
+        for param in &params.0 {
+            let ty = param.sym().and_then(tag).map(str::to_owned);
+            self.bind(param, ty);
+        }
+
+        let (returns, body) = split_return(&clause[1..]);
+        self.forms(body, at);
+        if let (Some(expected), Some(last)) = (returns, body.last()) {
+            if let Some(actual) = self.infer(last) {
+                if !matches(&expected.0, &actual) {
+                    let message = format!("{} is declared to return {}, but returns {}", name, expected, actual);
+                    let at = Location::of(last).unwrap_or_else(|| at.clone());
+                    self.warnings.push(Warning { location: at, kind: "type", message });
+                }
+            }
+        }
+        self.scopes.pop();
+    }
+
+    // Bind the symbols in a pattern, with a type if it's a plain symbol
+    fn bind(&mut self, pattern: &Expr, ty: Option<String>) {
+        match *pattern {
+            Expr::Sym(ref sym) => {
+                self.scopes.last_mut().expect("bind outside a scope").insert(sym.0.clone(), ty);
+            }
+            Expr::Vector(ref patterns) => {
+                for pattern in &patterns.0 {
+                    self.bind(pattern, None);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+// Either [params*] exprs*, or one ([params*] exprs*) per arity
+fn clauses(forms: &[Expr]) -> Vec<&[Expr]> {
+    match forms.first() {
+        Some(&Expr::List(_)) => forms.iter().filter_map(Expr::list).map(|list| &list.0[..]).collect(),
+        Some(_) => vec![forms],
+        None => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ops;
+    use parser;
+    use types::Function;
+
+    fn messages(source: &str) -> Vec<String> {
+        check(source, "test.tl").unwrap().into_iter().map(|w| w.message).collect()
+    }
+
+    #[test]
+    fn annotations() {
+        let form = parser::parse_expr("[^int a b]").unwrap();
+        let params = form.vector().unwrap();
+        assert_eq!(Some("int"), params.0[0].sym().and_then(tag));
+        assert_eq!(None, params.0[1].sym().and_then(tag));
+
+        let body = parser::parse_program("-> int (+ a b)").unwrap();
+        let (returns, rest) = split_return(&body);
+        assert_eq!(Some(Symbol::new("int")), returns);
+        assert_eq!(1, rest.len());
+        assert_eq!(None, split_return(&body[1..]).0);
+
+        assert!(matches("num", "flt") && matches("any", "nil") && !matches("int", "flt"));
+    }
+
+    #[test]
+    fn static_checks() {
+        let source = "
+            (defn add [^int a ^int b] -> int (+ a b))
+            (defn greet [^str name] -> int (str \"hi \" name))
+            (defn label [^int n] -> str \"n\")";
+        assert_eq!(Vec::<String>::new(), messages(&format!("{} (add 1 (add 2 3))", source)));
+        assert_eq!(
+            vec!["add expects int for b, got str".to_owned()],
+            messages(&format!("{} (add 1 \"2\")", source))
+        );
+        assert_eq!(
+            vec!["add expects int for a, got str".to_owned()],
+            messages(&format!("{} (let [x (label 1)] (add x 2))", source))
+        );
+        // Shadowing the function, or an unknown type, isn't checked
+        assert!(messages(&format!("{} (let [add list] (add 1 \"2\"))", source)).is_empty());
+        assert!(messages(&format!("{} (fn [x] (add x 1))", source)).is_empty());
+        assert_eq!(
+            vec!["label is declared to return str, but returns int".to_owned()],
+            messages("(defn label [^int n] -> str n)")
+        );
+    }
+
+    #[test]
+    fn runtime_checks() {
+        let env = ops::env();
+        let run = |source: &str| parser::parse_expr(source).unwrap().eval(env.clone());
+        run("(defn add [^int a b] -> int (+ a b))").unwrap();
+        run("(defn wrong [] -> str 1)").unwrap();
+        let (add, wrong) = (env.lookup("add").unwrap(), env.lookup("wrong").unwrap());
+        let (add, wrong) = (add.func().unwrap(), wrong.func().unwrap());
+        let checked = |func: &Function, args: &[Expr]| func.apply_with(args, env.clone(), true);
+
+        let err = checked(&add, &[Expr::from(1.5), Expr::from(1)]).unwrap_err();
+        assert_eq!("#[add] expected int for a, got flt", err.to_string());
+        let err = checked(&wrong, &[]).unwrap_err();
+        assert!(err.to_string().contains("return value of type str"));
+        assert_eq!(Expr::from(3), checked(&add, &[Expr::from(1), Expr::from(2)]).unwrap());
+        let unchecked = add.apply_with(&[Expr::from(1.5), Expr::from(1)], env.clone(), false);
+        assert_eq!(Expr::from(2.5), unchecked.unwrap());
+    }
+}
@@ -0,0 +1,36 @@
+use std::fmt;
+
+/// The number of arguments a function accepts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Arity {
+    pub min: usize,
+    pub max: Option<usize>,
+}
+
+impl Arity {
+    pub fn exact(count: usize) -> Self {
+        Arity { min: count, max: Some(count) }
+    }
+
+    pub fn range(min: usize, max: usize) -> Self {
+        Arity { min, max: Some(max) }
+    }
+
+    pub fn at_least(min: usize) -> Self {
+        Arity { min, max: None }
+    }
+
+    pub fn contains(&self, count: usize) -> bool {
+        count >= self.min && self.max.map_or(true, |max| count <= max)
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.max {
+            Some(max) if max == self.min => write!(f, "{}", max),
+            Some(max) => write!(f, "{}-{}", self.min, max),
+            None => write!(f, "at least {}", self.min),
+        }
+    }
+}
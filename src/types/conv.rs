@@ -1,18 +1,45 @@
 use super::*;
 use std::sync::Arc;
-use token::Literal;
 
-impl<T> From<T> for Expr
-where
-    T: Into<Literal>,
-{
-    fn from(x: T) -> Self {
-        match x.into() {
-            Literal::Bool(y) => Expr::Bool(y),
-            Literal::Int(y) => Expr::Int(y),
-            Literal::Flt(y) => Expr::Flt(y),
-            Literal::Str(y) => Expr::Str(y),
-        }
+impl From<i32> for Expr {
+    fn from(x: i32) -> Self {
+        Expr::Int(x.into())
+    }
+}
+
+impl From<i64> for Expr {
+    fn from(x: i64) -> Self {
+        Expr::Int(x)
+    }
+}
+
+impl From<f32> for Expr {
+    fn from(x: f32) -> Self {
+        Expr::Flt(x.into())
+    }
+}
+
+impl From<f64> for Expr {
+    fn from(x: f64) -> Self {
+        Expr::Flt(x)
+    }
+}
+
+impl From<bool> for Expr {
+    fn from(x: bool) -> Self {
+        Expr::Bool(x)
+    }
+}
+
+impl<'a> From<&'a str> for Expr {
+    fn from(x: &'a str) -> Self {
+        Expr::Str(x.to_owned())
+    }
+}
+
+impl From<String> for Expr {
+    fn from(x: String) -> Self {
+        Expr::Str(x)
     }
 }
 
@@ -24,13 +51,13 @@ impl From<Symbol> for Expr {
 
 impl From<Vector> for List {
     fn from(x: Vector) -> Self {
-        List(x.0)
+        List(x.0, x.1)
     }
 }
 
 impl From<List> for Vector {
     fn from(x: List) -> Self {
-        Vector(x.0)
+        Vector(x.0, x.1)
     }
 }
 
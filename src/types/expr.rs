@@ -1,7 +1,10 @@
 #![allow(dead_code)]
 
 use super::*;
+use encoding;
+use error::*;
 use std::fmt;
+use std::ops::Index;
 use std::sync::Arc;
 
 #[derive(Clone, Debug)]
@@ -11,15 +14,60 @@ pub enum Expr {
     Int(i64),
     Flt(f64),
     Str(String),
+    Bytes(Vec<u8>),
     Sym(Symbol),
+    Keyword(String),
     Func(Arc<Function>),
     Macro(Arc<Macro>),
     List(List),
     Vector(Vector),
     Map(Map),
+    Record(Record),
+    Handle(Handle),
 }
 
 impl Expr {
+    pub fn new_symbol<S: Into<String>>(name: S) -> Expr {
+        Expr::Sym(Symbol::new(name))
+    }
+
+    pub fn new_keyword<S: Into<String>>(name: S) -> Expr {
+        Expr::Keyword(name.into())
+    }
+
+    /// A list of `items`, each converted with `Into<Expr>`.
+    pub fn new_list<I>(items: I) -> Expr
+    where
+        I: IntoIterator,
+        I::Item: Into<Expr>,
+    {
+        Expr::List(List::new(items.into_iter().map(Into::into).collect()))
+    }
+
+    /// A vector of `items`, each converted with `Into<Expr>`.
+    pub fn new_vector<I>(items: I) -> Expr
+    where
+        I: IntoIterator,
+        I::Item: Into<Expr>,
+    {
+        Expr::Vector(Vector::new(items.into_iter().map(Into::into).collect()))
+    }
+
+    /// A map of `entries`, failing if a key isn't nil, a bool, an int, a
+    /// string, or a keyword.
+    pub fn new_map<I, K, V>(entries: I) -> Result<Expr>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<Expr>,
+        V: Into<Expr>,
+    {
+        let mut map = Map::new();
+        for (key, value) in entries {
+            map.insert(Key::try_from(&key.into())?, value.into());
+        }
+        Ok(Expr::Map(map))
+    }
+
     pub fn boolean(&self) -> Option<bool> {
         if let Expr::Bool(x) = *self {
             Some(x)
@@ -44,6 +92,14 @@ impl Expr {
         }
     }
 
+    pub fn bytes(&self) -> Option<&[u8]> {
+        if let Expr::Bytes(ref x) = *self {
+            Some(x)
+        } else {
+            None
+        }
+    }
+
     pub fn str(&self) -> Option<&str> {
         if let Expr::Str(ref x) = *self {
             Some(x)
@@ -83,6 +139,109 @@ impl Expr {
         }
     }
 
+    pub fn handle(&self) -> Option<&Handle> {
+        if let Expr::Handle(ref x) = *self {
+            Some(x)
+        } else {
+            None
+        }
+    }
+
+    pub fn keyword(&self) -> Option<&str> {
+        if let Expr::Keyword(ref x) = *self {
+            Some(x)
+        } else {
+            None
+        }
+    }
+
+    pub fn map(&self) -> Option<&Map> {
+        if let Expr::Map(ref x) = *self {
+            Some(x)
+        } else {
+            None
+        }
+    }
+
+    /// The items of a list or a vector.
+    pub fn items(&self) -> Option<&[Expr]> {
+        match *self {
+            Expr::List(ref list) => Some(&list.0),
+            Expr::Vector(ref vec) => Some(&vec.0),
+            _ => None,
+        }
+    }
+
+    /// The value under `key` in a map, the item at index `key` of a list or
+    /// vector, or the field named by the keyword `key` of a record.
+    pub fn get<K: Into<Expr>>(&self, key: K) -> Option<&Expr> {
+        let key = key.into();
+        match (self, key) {
+            (&Expr::Map(ref map), key) => map.get(&Key::try_from(&key).ok()?),
+            (&Expr::Record(ref record), Expr::Keyword(ref field)) => record.get(field),
+            (_, Expr::Int(i)) if i >= 0 => self.items()?.get(i as usize),
+            _ => None,
+        }
+    }
+
+    /// The metadata attached to this value, if any.
+    pub fn meta(&self) -> Option<&Map> {
+        match *self {
+            Expr::Sym(ref sym) => sym.1.get(),
+            Expr::List(ref list) => list.1.get(),
+            Expr::Vector(ref vec) => vec.1.get(),
+            Expr::Map(ref map) => map.1.get(),
+            Expr::Func(ref func) => func.meta(),
+            _ => None,
+        }
+    }
+
+    /// A copy of this value with `meta` as its metadata. Only symbols,
+    /// collections, and user functions can carry metadata.
+    pub fn with_meta(&self, meta: Map) -> Result<Expr> {
+        let meta = Meta::new(meta);
+        match *self {
+            Expr::Sym(ref sym) => Ok(Expr::Sym(Symbol(sym.0.clone(), meta))),
+            Expr::List(ref list) => Ok(Expr::List(List(list.0.clone(), meta))),
+            Expr::Vector(ref vec) => Ok(Expr::Vector(Vector(vec.0.clone(), meta))),
+            Expr::Map(ref map) => {
+                let mut map = map.clone();
+                map.1 = meta;
+                Ok(Expr::Map(map))
+            }
+            Expr::Func(ref func) => func.with_meta(meta).map(Expr::from),
+            _ => bail!("cannot attach metadata to {}", self.type_name()),
+        }
+    }
+
+    /// A copy of this value with `meta` merged into its existing metadata.
+    pub fn merge_meta(&self, meta: &Map) -> Result<Expr> {
+        let mut merged = self.meta().cloned().unwrap_or_default();
+        merged.merge(meta);
+        self.with_meta(merged)
+    }
+
+    /// The name of this value's type, as used in error messages.
+    pub fn type_name(&self) -> &'static str {
+        match *self {
+            Expr::Nil => "nil",
+            Expr::Bool(_) => "bool",
+            Expr::Int(_) => "int",
+            Expr::Flt(_) => "flt",
+            Expr::Str(_) => "str",
+            Expr::Bytes(_) => "bytes",
+            Expr::Sym(_) => "symbol",
+            Expr::Keyword(_) => "keyword",
+            Expr::Func(_) => "fn",
+            Expr::Macro(_) => "macro",
+            Expr::List(_) => "list",
+            Expr::Vector(_) => "vector",
+            Expr::Map(_) => "map",
+            Expr::Record(_) => "record",
+            Expr::Handle(ref handle) => handle.type_name(),
+        }
+    }
+
     pub fn truthiness(&self) -> bool {
         match *self {
             Expr::Nil => false,
@@ -129,20 +288,41 @@ impl Expr {
     }
 }
 
+/// Item `index` of a list or vector, panicking for anything else, like
+/// indexing a `Vec` out of bounds does.
+impl Index<usize> for Expr {
+    type Output = Expr;
+
+    fn index(&self, index: usize) -> &Expr {
+        match self.items() {
+            Some(items) => &items[index],
+            None => panic!("cannot index into a {}", self.type_name()),
+        }
+    }
+}
+
 impl fmt::Display for Expr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Expr::Nil => write!(f, "()"),
+            Expr::Nil => write!(f, "nil"),
             Expr::Bool(boolean) => write!(f, "#{}", if boolean { "t" } else { "f" }),
             Expr::Int(int) => write!(f, "{}", int),
-            Expr::Flt(flt) => write!(f, "{}", flt),
-            Expr::Str(ref string) => write!(f, "\"{}\"", string),
+            // Debug formatting keeps the decimal point on whole numbers, so they
+            // read back as floats
+            Expr::Flt(flt) if flt.is_finite() => write!(f, "{:?}", flt),
+            Expr::Flt(flt) if flt.is_nan() => write!(f, "##NaN"),
+            Expr::Flt(flt) => write!(f, "##{}Inf", if flt < 0.0 { "-" } else { "" }),
+            Expr::Str(ref string) => write!(f, "{}", encoding::escape_str(string)),
+            Expr::Bytes(ref bytes) => write!(f, "{}", encoding::escape_bytes(bytes)),
             Expr::Sym(ref sym) => write!(f, "{}", sym.0),
+            Expr::Keyword(ref name) => write!(f, ":{}", name),
             Expr::Func(ref func) => write!(f, "{}", func),
             Expr::Macro(ref mac) => write!(f, "{}", mac),
             Expr::List(ref list) => write!(f, "{}", list),
             Expr::Vector(ref vec) => write!(f, "{}", vec),
             Expr::Map(ref map) => write!(f, "{}", map),
+            Expr::Record(ref record) => write!(f, "{}", record),
+            Expr::Handle(ref handle) => write!(f, "{}", handle),
         }
     }
 }
@@ -154,14 +334,20 @@ impl PartialEq for Expr {
             (&Nil, &Nil) => true,
             (&Bool(ref a), &Bool(ref b)) => a == b,
             (&Int(ref a), &Int(ref b)) => a == b,
-            (&Flt(ref a), &Flt(ref b)) => a == b,
+            // NaN is equal to itself, so every value is, and NaN reads back
+            // equal to what was printed
+            (&Flt(ref a), &Flt(ref b)) => a == b || (a.is_nan() && b.is_nan()),
             (&Str(ref a), &Str(ref b)) => a == b,
+            (&Bytes(ref a), &Bytes(ref b)) => a == b,
             (&Sym(ref a), &Sym(ref b)) => a == b,
+            (&Keyword(ref a), &Keyword(ref b)) => a == b,
             (&Func(_), &Func(_)) => false,
             (&Macro(_), &Macro(_)) => false,
             (&List(ref a), &List(ref b)) => a == b,
             (&Vector(ref a), &Vector(ref b)) => a == b,
             (&Map(ref a), &Map(ref b)) => a == b,
+            (&Record(ref a), &Record(ref b)) => a == b,
+            (&Handle(ref a), &Handle(ref b)) => a == b,
             _ => false,
         }
     }
@@ -172,6 +358,8 @@ mod test {
     use super::*;
     use env::Env;
     use ops;
+    use parser;
+    use quickcheck::{Arbitrary, Gen};
 
     #[test]
     fn call_fn() {
@@ -190,4 +378,125 @@ mod test {
         let new_scope = Env::default();
         assert!(new_scope.lookup("hello").is_none());
     }
+
+    #[test]
+    fn building_and_reading() {
+        let x = 2;
+        let value = expr!({
+            :name => "telescope",
+            :sizes => [1, x, (x + 1)],
+            :call => list [Expr::new_symbol("+"), 1.5, -1],
+            :empty => [],
+            nil => nil,
+        });
+        assert_eq!(
+            Expr::new_map(vec![
+                (Expr::new_keyword("name"), Expr::from("telescope")),
+                (Expr::new_keyword("sizes"), Expr::new_vector(vec![1, 2, 3])),
+                (
+                    Expr::new_keyword("call"),
+                    Expr::new_list(vec![Expr::new_symbol("+"), Expr::from(1.5), Expr::from(-1)]),
+                ),
+                (Expr::new_keyword("empty"), Expr::new_vector(Vec::<Expr>::new())),
+                (Expr::Nil, Expr::Nil),
+            ]).unwrap(),
+            value
+        );
+        assert!(Expr::new_map(vec![(1.5, 1)]).is_err());
+
+        let sizes = value.get(Expr::new_keyword("sizes")).unwrap();
+        assert_eq!(Some(3), sizes.get(2).and_then(Expr::int));
+        assert_eq!(None, sizes.get(-1));
+        assert_eq!(3, sizes.items().unwrap().len());
+        assert_eq!(Some("telescope"), value.get(Expr::new_keyword("name")).and_then(Expr::str));
+        assert_eq!(None, value.get(1.5));
+        assert_eq!(5, value.map().unwrap().len());
+    }
+
+    #[test]
+    fn iterating() {
+        let list = List::new(vec![Expr::from(1), Expr::from(2)]);
+        assert_eq!(Expr::from(2), list[1]);
+        assert_eq!(3, list.iter().filter_map(Expr::int).sum::<i64>());
+        let doubled = list.into_iter().map(|x| x.map_int(|i| i * 2)).collect::<Vector>();
+        assert_eq!(vec![Expr::from(2), Expr::from(4)], doubled.0);
+        assert_eq!(Expr::from(4), Expr::Vector(doubled.clone())[1]);
+        for item in &doubled {
+            assert!(item.is_int());
+        }
+
+        let map = vec![(Key::Int(1), Expr::from("one"))].into_iter().collect::<Map>();
+        assert_eq!(Expr::from("one"), map[&Key::Int(1)]);
+        assert_eq!(vec![&Key::Int(1)], map.keys().collect::<Vec<_>>());
+        for (key, value) in &map {
+            assert_eq!((&Key::Int(1), &Expr::from("one")), (key, value));
+        }
+    }
+
+    // Plain data, the values the reader can make
+    #[derive(Clone, Debug)]
+    struct Data(Expr);
+
+    impl Arbitrary for Data {
+        fn arbitrary<G: Gen>(g: &mut G) -> Data {
+            Data(data(g, 3))
+        }
+    }
+
+    fn data<G: Gen>(g: &mut G, depth: usize) -> Expr {
+        let kinds = if depth == 0 { 8 } else { 11 };
+        match usize::arbitrary(g) % kinds {
+            0 => Expr::Nil,
+            1 => Expr::Bool(bool::arbitrary(g)),
+            2 => Expr::Int(i64::arbitrary(g)),
+            3 => match usize::arbitrary(g) % 8 {
+                0 => Expr::Flt(::std::f64::NAN),
+                1 => Expr::Flt(::std::f64::NEG_INFINITY),
+                _ => Expr::Flt(f64::arbitrary(g)),
+            },
+            4 => Expr::Str(String::arbitrary(g)),
+            5 => Expr::Bytes(Vec::arbitrary(g)),
+            6 => Expr::new_symbol(name(g)),
+            7 => Expr::new_keyword(name(g)),
+            8 => Expr::new_list((0..usize::arbitrary(g) % 4).map(|_| data(g, depth - 1))),
+            9 => Expr::new_vector((0..usize::arbitrary(g) % 4).map(|_| data(g, depth - 1))),
+            _ => {
+                let entries = (0..usize::arbitrary(g) % 4)
+                    .map(|_| {
+                        let key = match usize::arbitrary(g) % 5 {
+                            0 => Expr::Nil,
+                            1 => Expr::Bool(bool::arbitrary(g)),
+                            2 => Expr::Int(i64::arbitrary(g)),
+                            3 => Expr::Str(String::arbitrary(g)),
+                            _ => Expr::new_keyword(name(g)),
+                        };
+                        (key, data(g, depth - 1))
+                    })
+                    .collect::<Vec<_>>();
+                Expr::new_map(entries).unwrap()
+            }
+        }
+    }
+
+    fn name<G: Gen>(g: &mut G) -> String {
+        let names = ["x", "total", "a->b", "x2", "set!", "*env*", "café", "_", "+"];
+        names[usize::arbitrary(g) % names.len()].to_owned()
+    }
+
+    quickcheck! {
+        fn display_round_trip(data: Data) -> bool {
+            parser::parse_expr(&data.0.to_string()).map_or(false, |read| read == data.0)
+        }
+    }
+
+    #[test]
+    fn display_is_readable() {
+        assert_eq!("nil", Expr::Nil.to_string());
+        assert_eq!("2.0", Expr::from(2.0).to_string());
+        assert_eq!("[##Inf ##-Inf ##NaN]", expr!([1.0 / 0.0, -1.0 / 0.0, 0.0 / 0.0]).to_string());
+        assert_eq!(Expr::from(::std::f64::NAN), parser::parse_expr("##NaN").unwrap());
+        assert_eq!(r#""a \"b\"\n\x01""#, Expr::from("a \"b\"\n\u{1}").to_string());
+        let map = expr!({:b => "two", nil => [1, 2.5], "k" => 1});
+        assert_eq!(r#"{"k" 1 :b "two" nil [1 2.5]}"#, map.to_string());
+    }
 }
@@ -2,35 +2,256 @@
 
 use itertools::Itertools;
 
+use super::arity::Arity;
 use super::expr::Expr;
+use super::map::{Key, Map};
+use super::meta::Meta;
+use super::record::RecordType;
 use super::symbol::Symbol;
+use super::vector::Vector;
 use env::Env;
 use error::*;
+use typecheck;
+use std::sync::RwLock;
 use std::fmt;
+use std::sync::Arc;
 
 pub enum Function {
     Builtin {
         name: String,
-        func: Lambda
+        arity: Arity,
+        func: Closure,
+        meta: Meta,
     },
+    /// `source` is the form that defined the function, for `(source f)`.
     User {
         name: Option<String>,
-        params: Vec<Symbol>,
-        body: Vec<Expr>,
+        clauses: Vec<Clause>,
         env: Env,
+        meta: Meta,
+        source: Option<Arc<Expr>>,
     },
+    /// A generic function: `dispatch` is called with the arguments, and the
+    /// method registered for its result is applied.
+    Multi {
+        name: String,
+        dispatch: Arc<Function>,
+        methods: RwLock<Vec<(Expr, Arc<Function>)>>,
+    },
+    /// The constructor, predicate, or a field accessor of a record type.
+    Record {
+        name: String,
+        rtype: Arc<RecordType>,
+        op: RecordOp,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RecordOp {
+    New,
+    Is,
+    Get(usize),
+}
+
+/// One parameter list and body of a user function. Multi-arity functions have
+/// a clause per argument count. `returns` is the type from a `-> type`
+/// annotation, and `pre` and `post` are the conditions from a
+/// `{:pre [...] :post [...]}` map at the start of the body.
+#[derive(Clone, Debug)]
+pub struct Clause {
+    pub params: Vec<Symbol>,
+    pub returns: Option<Symbol>,
+    pub pre: Vec<Expr>,
+    pub post: Vec<Expr>,
+    pub body: Vec<Expr>,
+}
+
+impl Clause {
+    /// The parameter vector and return type, with their type annotations.
+    pub fn signature(&self) -> String {
+        let params = self.params.iter()
+            .map(|param| match typecheck::tag(param) {
+                Some(tag) => format!("^{} {}", tag, param),
+                None => param.to_string(),
+            })
+            .join(" ");
+        match self.returns {
+            Some(ref returns) => format!("[{}] -> {}", params, returns),
+            None => format!("[{}]", params),
+        }
+    }
+
+    /// The conditions as the map they were written as, if there are any.
+    pub fn conditions(&self) -> Option<Expr> {
+        if self.pre.is_empty() && self.post.is_empty() {
+            return None;
+        }
+        let mut map = Map::new();
+        for &(name, conditions) in &[("pre", &self.pre), ("post", &self.post)] {
+            if !conditions.is_empty() {
+                map.insert(Key::Keyword(name.to_owned()), Expr::Vector(Vector::new(conditions.clone())));
+            }
+        }
+        Some(Expr::Map(map))
+    }
 }
 
 pub type Lambda = fn(&[Expr], Env) -> Result<Expr>;
 
+/// The body of a builtin, which may capture state, such as the function and
+/// arguments of a `partial`.
+pub type Closure = Arc<Fn(&[Expr], Env) -> Result<Expr> + Send + Sync>;
+
 impl Function {
-    pub fn builtin<S>(name: S, func: Lambda) -> Self
+    pub fn builtin<S, F>(name: S, arity: Arity, func: F) -> Self
     where
         S: Into<String>,
+        F: Fn(&[Expr], Env) -> Result<Expr> + Send + Sync + 'static,
     {
         Function::Builtin {
             name: name.into(),
-            func: func,
+            arity: arity,
+            func: Arc::new(func),
+            meta: Meta::default(),
+        }
+    }
+
+    pub fn multi<S>(name: S, dispatch: Arc<Function>) -> Self
+    where
+        S: Into<String>,
+    {
+        Function::Multi {
+            name: name.into(),
+            dispatch: dispatch,
+            methods: RwLock::new(Vec::new()),
+        }
+    }
+
+    pub fn record<S>(name: S, rtype: Arc<RecordType>, op: RecordOp) -> Self
+    where
+        S: Into<String>,
+    {
+        Function::Record {
+            name: name.into(),
+            rtype: rtype,
+            op: op,
+        }
+    }
+
+    /// Register the method for a dispatch value on a generic function,
+    /// replacing any existing method for that value.
+    pub fn add_method(&self, value: Expr, method: Arc<Function>) -> Result<()> {
+        match *self {
+            Function::Multi { ref methods, .. } => {
+                let mut methods = methods.write().unwrap_or_else(|err| err.into_inner());
+                methods.retain(|&(ref v, _)| *v != value);
+                methods.push((value, method));
+                Ok(())
+            }
+            _ => bail!("{} is not a generic function", self.name()),
+        }
+    }
+
+    /// The method of a generic function for a dispatch value, falling back to
+    /// the method for 'default.
+    pub fn method(&self, value: &Expr) -> Option<Arc<Function>> {
+        match *self {
+            Function::Multi { ref methods, .. } => {
+                let methods = methods.read().unwrap_or_else(|err| err.into_inner());
+                let default = Expr::Sym(Symbol::new("default"));
+                methods.iter()
+                    .find(|&&(ref v, _)| v == value)
+                    .or_else(|| methods.iter().find(|&&(ref v, _)| *v == default))
+                    .map(|&(_, ref method)| method.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// The methods of a generic function, in the order they were added.
+    pub fn methods(&self) -> Vec<(Expr, Arc<Function>)> {
+        match *self {
+            Function::Multi { ref methods, .. } => {
+                methods.read().unwrap_or_else(|err| err.into_inner()).clone()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match *self {
+            Function::Builtin { ref name, .. } => name,
+            Function::Multi { ref name, .. } => name,
+            Function::Record { ref name, .. } => name,
+            Function::User { ref name, .. } => name.as_ref().map(String::as_str).unwrap_or("fn"),
+        }
+    }
+
+    /// The number of arguments the function accepts, checked on every call.
+    pub fn arity(&self) -> Arity {
+        match *self {
+            Function::Builtin { arity, .. } => arity,
+            Function::Multi { ref dispatch, .. } => dispatch.arity(),
+            Function::Record { ref rtype, op: RecordOp::New, .. } => Arity::exact(rtype.fields.len()),
+            Function::Record { .. } => Arity::exact(1),
+            Function::User { ref clauses, .. } => {
+                let min = clauses.iter().map(|c| c.params.len()).min().unwrap_or(0);
+                let max = clauses.iter().map(|c| c.params.len()).max().unwrap_or(0);
+                Arity::range(min, max)
+            }
+        }
+    }
+
+    pub fn meta(&self) -> Option<&Map> {
+        match *self {
+            Function::Builtin { ref meta, .. } | Function::User { ref meta, .. } => meta.get(),
+            _ => None,
+        }
+    }
+
+    /// A copy of a builtin or user function with different metadata.
+    pub fn with_meta(&self, meta: Meta) -> Result<Function> {
+        match *self {
+            Function::Builtin { ref name, arity, ref func, .. } => Ok(Function::Builtin {
+                name: name.clone(),
+                arity: arity,
+                func: func.clone(),
+                meta: meta,
+            }),
+            Function::User { ref name, ref clauses, ref env, ref source, .. } => Ok(Function::User {
+                name: name.clone(),
+                clauses: clauses.clone(),
+                env: env.clone(),
+                meta: meta,
+                source: source.clone(),
+            }),
+            _ => bail!("cannot attach metadata to {}", self),
+        }
+    }
+
+    /// What to use instead, if the function's `:deprecated` metadata says so,
+    /// or an empty string if it's deprecated without a replacement.
+    pub fn deprecation(&self) -> Option<&str> {
+        match *self.meta()?.get(&Key::Keyword("deprecated".to_owned()))? {
+            Expr::Str(ref note) => Some(note),
+            Expr::Bool(false) | Expr::Nil => None,
+            _ => Some(""),
+        }
+    }
+
+    /// The form that defined a user function, if it was defined by one.
+    pub fn source(&self) -> Option<&Expr> {
+        match *self {
+            Function::User { ref source, .. } => source.as_ref().map(|source| &**source),
+            _ => None,
+        }
+    }
+
+    /// The clause of a user function taking `count` arguments.
+    pub fn clause(&self, count: usize) -> Option<&Clause> {
+        match *self {
+            Function::User { ref clauses, .. } => clauses.iter().find(|c| c.params.len() == count),
+            _ => None,
         }
     }
 }
@@ -38,15 +259,27 @@ impl Function {
 impl fmt::Debug for Function {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Function::Builtin { ref name, .. }
+            Function::Builtin { ref name, ref arity, .. }
                 => f.debug_struct("Function::Builtin")
                     .field("name", &name)
+                    .field("arity", &arity)
                     .finish(),
-            Function::User { ref name, ref params, ref body, env: _ }
+            Function::User { ref name, ref clauses, .. }
                 => f.debug_struct("Function::User")
                     .field("name", &name)
-                    .field("params", &params)
-                    .field("body", &body)
+                    .field("clauses", &clauses)
+                    .finish(),
+            Function::Multi { ref name, .. } => {
+                let values = self.methods().into_iter().map(|(value, _)| value).collect::<Vec<_>>();
+                f.debug_struct("Function::Multi")
+                    .field("name", &name)
+                    .field("methods", &values)
+                    .finish()
+            }
+            Function::Record { ref name, ref op, .. }
+                => f.debug_struct("Function::Record")
+                    .field("name", &name)
+                    .field("op", &op)
                     .finish(),
         }
     }
@@ -55,13 +288,25 @@ impl fmt::Debug for Function {
 impl fmt::Display for Function {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Function::Builtin { ref name, func: _ } => write!(f, "#[{}]", name),
-            Function::User { name: _, ref params, ref body, env: _ } => {
-                write!( f, "(fn [{}] {})",
-                    params.iter().join(" "),
-                    body.iter().join("\n")
-                )
+            Function::Builtin { ref name, .. } => write!(f, "#[{}]", name),
+            Function::Multi { ref name, .. } => write!(f, "#[multi {}]", name),
+            Function::Record { ref name, .. } => write!(f, "#[{}]", name),
+            Function::User { ref clauses, .. } if clauses.len() == 1 => {
+                write!(f, "(fn {})", clauses[0])
+            }
+            Function::User { ref clauses, .. } => {
+                write!(f, "(fn {})", clauses.iter().map(|c| format!("({})", c)).join(" "))
             }
         }
     }
 }
+
+impl fmt::Display for Clause {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ", self.signature())?;
+        if let Some(conditions) = self.conditions() {
+            write!(f, "{}\n", conditions)?;
+        }
+        write!(f, "{}", self.body.iter().join("\n"))
+    }
+}
@@ -0,0 +1,47 @@
+use std::any::Any;
+use std::fmt;
+use std::sync::Arc;
+
+/// A runtime object the language can only pass around, such as a future.
+pub trait Resource: Any + Send + Sync + fmt::Debug {
+    /// The name of the resource's type, as printed and used in errors.
+    fn type_name(&self) -> &'static str;
+
+    fn as_any(&self) -> &Any;
+
+    /// How the resource is printed.
+    fn describe(&self) -> String {
+        format!("#<{}>", self.type_name())
+    }
+}
+
+/// A shared reference to a resource. Handles are equal only when they refer
+/// to the same resource.
+#[derive(Clone, Debug)]
+pub struct Handle(Arc<Resource>);
+
+impl Handle {
+    pub fn new<R: Resource>(resource: Arc<R>) -> Self {
+        Handle(resource)
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        self.0.type_name()
+    }
+
+    pub fn downcast_ref<R: Resource>(&self) -> Option<&R> {
+        self.0.as_any().downcast_ref::<R>()
+    }
+}
+
+impl PartialEq for Handle {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Display for Handle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.describe())
+    }
+}
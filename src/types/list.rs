@@ -1,9 +1,64 @@
 use super::Expr;
+use super::meta::Meta;
 use itertools::Itertools;
 use std::fmt;
+use std::iter::FromIterator;
+use std::ops::Index;
+use std::slice;
+use std::vec;
 
 #[derive(Clone, Debug)]
-pub struct List(pub Vec<Expr>);
+pub struct List(pub Vec<Expr>, pub Meta);
+
+impl List {
+    pub fn new(items: Vec<Expr>) -> Self {
+        List(items, Meta::default())
+    }
+
+    pub fn iter(&self) -> slice::Iter<Expr> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Index<usize> for List {
+    type Output = Expr;
+
+    fn index(&self, index: usize) -> &Expr {
+        &self.0[index]
+    }
+}
+
+impl IntoIterator for List {
+    type Item = Expr;
+    type IntoIter = vec::IntoIter<Expr>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a List {
+    type Item = &'a Expr;
+    type IntoIter = slice::Iter<'a, Expr>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl FromIterator<Expr> for List {
+    fn from_iter<I: IntoIterator<Item = Expr>>(items: I) -> Self {
+        List::new(items.into_iter().collect())
+    }
+}
 
 impl fmt::Display for List {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
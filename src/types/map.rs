@@ -2,8 +2,12 @@
 
 use std::fmt;
 use std::collections::HashMap;
-use itertools::Itertools;
+use std::collections::hash_map;
+use std::iter::FromIterator;
+use std::ops::Index;
 use super::Expr;
+use super::meta::Meta;
+use encoding;
 use error::*;
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -12,6 +16,7 @@ pub enum Key {
     Bool(bool),
     Int(i64),
     Str(String),
+    Keyword(String),
 }
 
 impl Key {
@@ -21,42 +26,128 @@ impl Key {
             Expr::Bool(b) => Ok(Key::Bool(b)),
             Expr::Int(i) => Ok(Key::Int(i)),
             Expr::Str(ref s) => Ok(Key::Str(s.clone())),
+            Expr::Keyword(ref k) => Ok(Key::Keyword(k.clone())),
             _ => Err(format!("cannot use as key: {}", expr).into()),
         }
     }
+
+    /// The value this key was made from.
+    pub fn to_expr(&self) -> Expr {
+        match *self {
+            Key::Nil => Expr::Nil,
+            Key::Bool(b) => Expr::Bool(b),
+            Key::Int(i) => Expr::Int(i),
+            Key::Str(ref s) => Expr::Str(s.clone()),
+            Key::Keyword(ref k) => Expr::Keyword(k.clone()),
+        }
+    }
 }
 
 impl fmt::Display for Key {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Key::Nil => write!(f, "()"),
-            Key::Bool(b) => write!(f, "{}", b),
+            Key::Nil => write!(f, "nil"),
+            Key::Bool(b) => write!(f, "#{}", if b { "t" } else { "f" }),
             Key::Int(i) => write!(f, "{}", i),
-            Key::Str(ref s) => write!(f, "{}", s),
+            Key::Str(ref s) => write!(f, "{}", encoding::escape_str(s)),
+            Key::Keyword(ref k) => write!(f, ":{}", k),
         }
     }
 }
 
 #[derive(Clone, Debug, PartialEq)]
-pub struct Map(HashMap<Key, Expr>);
+pub struct Map(HashMap<Key, Expr>, pub Meta);
 
 impl Map {
     pub fn new() -> Self {
-        Map(HashMap::new())
+        Map(HashMap::new(), Meta::default())
+    }
+
+    pub fn get(&self, key: &Key) -> Option<&Expr> {
+        self.0.get(key)
+    }
+
+    pub fn insert(&mut self, key: Key, value: Expr) -> Option<Expr> {
+        self.0.insert(key, value)
+    }
+
+    pub fn remove(&mut self, key: &Key) -> Option<Expr> {
+        self.0.remove(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> hash_map::Iter<Key, Expr> {
+        self.0.iter()
+    }
+
+    pub fn keys(&self) -> hash_map::Keys<Key, Expr> {
+        self.0.keys()
+    }
+
+    pub fn values(&self) -> hash_map::Values<Key, Expr> {
+        self.0.values()
+    }
+
+    /// Insert every entry of `other`, overwriting existing keys.
+    pub fn merge(&mut self, other: &Map) {
+        for (key, value) in other.iter() {
+            self.0.insert(key.clone(), value.clone());
+        }
+    }
+}
+
+impl<'a> Index<&'a Key> for Map {
+    type Output = Expr;
+
+    fn index(&self, key: &Key) -> &Expr {
+        self.0.get(key).unwrap_or_else(|| panic!("no key {} in map", key))
+    }
+}
+
+impl IntoIterator for Map {
+    type Item = (Key, Expr);
+    type IntoIter = hash_map::IntoIter<Key, Expr>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Map {
+    type Item = (&'a Key, &'a Expr);
+    type IntoIter = hash_map::Iter<'a, Key, Expr>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl FromIterator<(Key, Expr)> for Map {
+    fn from_iter<I: IntoIterator<Item = (Key, Expr)>>(entries: I) -> Self {
+        Map(entries.into_iter().collect(), Meta::default())
     }
 }
 
 impl Default for Map {
     fn default() -> Self {
-        Map(HashMap::new())
+        Map::new()
     }
 }
 
 impl fmt::Display for Map {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let pairs = self.0.iter()
-            .map(|(key, val)| format!("{}: {}", key, val))
-            .join(", ");
-        write!(f, "{{{}}}", pairs)
+        // Sorted, so the same map always prints the same way
+        let mut pairs = self.0.iter()
+            .map(|(key, val)| format!("{} {}", key, val))
+            .collect::<Vec<_>>();
+        pairs.sort();
+        write!(f, "{{{}}}", pairs.join(" "))
     }
 }
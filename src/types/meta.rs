@@ -0,0 +1,43 @@
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+use super::map::Map;
+
+/// Metadata attached to a value, such as a docstring. Metadata never affects
+/// equality, ordering, or hashing of the value it's attached to.
+#[derive(Clone, Debug, Default)]
+pub struct Meta(Option<Arc<Map>>);
+
+impl Meta {
+    pub fn new(map: Map) -> Self {
+        Meta(Some(Arc::new(map)))
+    }
+
+    pub fn get(&self) -> Option<&Map> {
+        self.0.as_ref().map(|map| &**map)
+    }
+}
+
+impl PartialEq for Meta {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for Meta {}
+
+impl PartialOrd for Meta {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Meta {
+    fn cmp(&self, _other: &Self) -> Ordering {
+        Ordering::Equal
+    }
+}
+
+impl Hash for Meta {
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
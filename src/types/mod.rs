@@ -1,3 +1,4 @@
+mod arity;
 mod expr;
 mod symbol;
 mod function;
@@ -5,12 +6,19 @@ mod mac;
 mod list;
 mod vector;
 mod map;
+mod handle;
+mod meta;
+mod record;
 mod conv;
 
+pub use self::arity::Arity;
 pub use self::expr::Expr;
-pub use self::function::{Function, Lambda};
+pub use self::function::{Clause, Closure, Function, Lambda, RecordOp};
 pub use self::mac::Macro;
 pub use self::list::List;
 pub use self::symbol::Symbol;
 pub use self::vector::Vector;
-pub use self::map::Map;
+pub use self::map::{Key, Map};
+pub use self::handle::{Handle, Resource};
+pub use self::meta::Meta;
+pub use self::record::{Record, RecordType};
@@ -0,0 +1,55 @@
+#![allow(dead_code)]
+
+use std::fmt;
+use std::sync::Arc;
+use itertools::Itertools;
+use super::{Expr, Symbol};
+
+/// A type declared with defrecord. Each declaration makes a new type, even if
+/// the name is reused.
+#[derive(Debug)]
+pub struct RecordType {
+    pub name: String,
+    pub fields: Vec<Symbol>,
+}
+
+/// An instance of a record type, with a value for each field.
+#[derive(Clone, Debug)]
+pub struct Record {
+    pub rtype: Arc<RecordType>,
+    pub values: Vec<Expr>,
+}
+
+impl Record {
+    pub fn new(rtype: Arc<RecordType>, values: Vec<Expr>) -> Self {
+        debug_assert_eq!(rtype.fields.len(), values.len());
+        Record { rtype, values }
+    }
+
+    pub fn is_a(&self, rtype: &Arc<RecordType>) -> bool {
+        Arc::ptr_eq(&self.rtype, rtype)
+    }
+
+    pub fn get(&self, field: &str) -> Option<&Expr> {
+        self.rtype.fields
+            .iter()
+            .position(|f| f.0 == field)
+            .map(|i| &self.values[i])
+    }
+}
+
+impl PartialEq for Record {
+    fn eq(&self, other: &Self) -> bool {
+        self.is_a(&other.rtype) && self.values == other.values
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fields = self.rtype.fields
+            .iter()
+            .zip(self.values.iter())
+            .map(|(field, value)| format!(":{} {}", field, value));
+        write!(f, "#{}{{{}}}", self.rtype.name, fields.join(" "))
+    }
+}
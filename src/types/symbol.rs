@@ -1,7 +1,14 @@
 use std::fmt;
+use super::meta::Meta;
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
-pub struct Symbol(pub String);
+pub struct Symbol(pub String, pub Meta);
+
+impl Symbol {
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        Symbol(name.into(), Meta::default())
+    }
+}
 
 impl fmt::Display for Symbol {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -1,9 +1,64 @@
 use super::expr::Expr;
+use super::meta::Meta;
 use itertools::Itertools;
 use std::fmt;
+use std::iter::FromIterator;
+use std::ops::Index;
+use std::slice;
+use std::vec;
 
 #[derive(Clone, Debug)]
-pub struct Vector(pub Vec<Expr>);
+pub struct Vector(pub Vec<Expr>, pub Meta);
+
+impl Vector {
+    pub fn new(items: Vec<Expr>) -> Self {
+        Vector(items, Meta::default())
+    }
+
+    pub fn iter(&self) -> slice::Iter<Expr> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Index<usize> for Vector {
+    type Output = Expr;
+
+    fn index(&self, index: usize) -> &Expr {
+        &self.0[index]
+    }
+}
+
+impl IntoIterator for Vector {
+    type Item = Expr;
+    type IntoIter = vec::IntoIter<Expr>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Vector {
+    type Item = &'a Expr;
+    type IntoIter = slice::Iter<'a, Expr>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl FromIterator<Expr> for Vector {
+    fn from_iter<I: IntoIterator<Item = Expr>>(items: I) -> Self {
+        Vector::new(items.into_iter().collect())
+    }
+}
 
 impl fmt::Display for Vector {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
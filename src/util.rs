@@ -1,39 +1,269 @@
 #![allow(dead_code)]
 
-use types::{Expr, List, Vector, Symbol};
+use std::cmp;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use env::Env;
+use types::{Arity, Expr, Function, List, Map, Resource, Vector, Symbol};
 use error::*;
 
+/// Lock `mutex`, even if another thread panicked while holding it. Evaluation
+/// catches panics, so one failed call shouldn't make a shared value unusable.
+pub fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<T> {
+    mutex.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+pub fn ensure_arity(fn_name: &str, args: &[Expr], arity: Arity) -> Result<()> {
+    if arity.contains(args.len()) {
+        Ok(())
+    } else {
+        Err(ErrorKind::Arity {
+            name: fn_name.to_owned(),
+            expected: arity,
+            got: args.len(),
+        }.into())
+    }
+}
+
 pub fn ensure_args(fn_name: &str, args: &[Expr], count: usize) -> Result<()> {
-    ensure!(args.len() == count, "#[{}] expected {} args", fn_name, count);
-    Ok(())
+    ensure_arity(fn_name, args, Arity::exact(count))
 }
 
 pub fn ensure_range_args(fn_name: &str, args: &[Expr], min: usize, max: usize) -> Result<()> {
-    ensure!(args.len() >= min && args.len() <= max, "#[{}] expected {}-{} args", fn_name, min, max);
-    Ok(())
+    ensure_arity(fn_name, args, Arity::range(min, max))
 }
 
 pub fn ensure_min_args(fn_name: &str, args: &[Expr], count: usize) -> Result<()> {
-    ensure!(args.len() >= count, "#[{}] expected at least {} args", fn_name, count);
+    ensure_arity(fn_name, args, Arity::at_least(count))
+}
+
+/// Fail if `env` is sandboxed, for builtins and forms that reach past what a
+/// sandboxed interpreter allows.
+pub fn ensure_unsandboxed(fn_name: &str, env: &Env) -> Result<()> {
+    if env.is_sandboxed() {
+        bail!("{} isn't available in a sandbox", fn_name)
+    }
     Ok(())
 }
 
+pub fn type_error(fn_name: &str, expected: &str, got: &Expr) -> Error {
+    ErrorKind::Type {
+        name: fn_name.to_owned(),
+        expected: expected.to_owned(),
+        got: got.type_name().to_owned(),
+    }.into()
+}
+
 pub fn ensure_int(fn_name: &str, arg: &Expr) -> Result<i64> {
-	arg.int().ok_or_else(|| format!("#[{}] expected integer", fn_name).into())
+	arg.int().ok_or_else(|| type_error(fn_name, "int", arg))
 }
 
 pub fn ensure_flt(fn_name: &str, arg: &Expr) -> Result<f64> {
-	arg.flt().ok_or_else(|| format!("#[{}] expected float", fn_name).into())
+	arg.flt().ok_or_else(|| type_error(fn_name, "flt", arg))
+}
+
+pub fn ensure_str<'a>(fn_name: &str, arg: &'a Expr) -> Result<&'a str> {
+	arg.str().ok_or_else(|| type_error(fn_name, "str", arg))
 }
 
 pub fn ensure_sym<'a>(fn_name: &str, arg: &'a Expr) -> Result<&'a Symbol> {
-	arg.sym().ok_or_else(|| format!("#[{}] expected symbol", fn_name).into())
+	arg.sym().ok_or_else(|| type_error(fn_name, "symbol", arg))
 }
 
 pub fn ensure_list<'a>(fn_name: &str, arg: &'a Expr) -> Result<&'a List> {
-	arg.list().ok_or_else(|| format!("#[{}] expected list", fn_name).into())
+	arg.list().ok_or_else(|| type_error(fn_name, "list", arg))
 }
 
 pub fn ensure_vector<'a>(fn_name: &str, arg: &'a Expr) -> Result<&'a Vector> {
-	arg.vector().ok_or_else(|| format!("#[{}] expected vector", fn_name).into())
+	arg.vector().ok_or_else(|| type_error(fn_name, "vector", arg))
+}
+
+pub fn ensure_bytes<'a>(fn_name: &str, arg: &'a Expr) -> Result<&'a [u8]> {
+	arg.bytes().ok_or_else(|| type_error(fn_name, "bytes", arg))
+}
+
+pub fn ensure_map<'a>(fn_name: &str, arg: &'a Expr) -> Result<&'a Map> {
+    arg.map().ok_or_else(|| type_error(fn_name, "map", arg))
+}
+
+pub fn ensure_func(fn_name: &str, arg: &Expr) -> Result<Arc<Function>> {
+	arg.func().ok_or_else(|| type_error(fn_name, "fn", arg))
+}
+
+/// The items of a list or vector, or none for nil.
+pub fn ensure_seq<'a>(fn_name: &str, arg: &'a Expr) -> Result<&'a [Expr]> {
+    match *arg {
+        Expr::List(ref list) => Ok(&list.0),
+        Expr::Vector(ref vec) => Ok(&vec.0),
+        Expr::Nil => Ok(&[]),
+        _ => Err(type_error(fn_name, "list", arg)),
+    }
+}
+
+/// The resource behind a handle, if it's a `R`, described as `expected` in
+/// the type error otherwise.
+pub fn ensure_resource<'a, R: Resource>(fn_name: &str, arg: &'a Expr, expected: &str) -> Result<&'a R> {
+    arg.handle()
+        .and_then(|handle| handle.downcast_ref::<R>())
+        .ok_or_else(|| type_error(fn_name, expected, arg))
+}
+
+/// Checked access to a builtin's arguments. The arity has already been
+/// validated by `Function::apply`, but every getter still returns an error
+/// rather than panicking on a missing or mistyped argument.
+#[derive(Clone, Copy, Debug)]
+pub struct Args<'a> {
+    name: &'a str,
+    args: &'a [Expr],
+}
+
+impl<'a> Args<'a> {
+    pub fn new(name: &'a str, args: &'a [Expr]) -> Self {
+        Args { name: name, args: args }
+    }
+
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    pub fn len(&self) -> usize {
+        self.args.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.args.is_empty()
+    }
+
+    /// All arguments, for variadic builtins.
+    pub fn all(&self) -> &'a [Expr] {
+        self.args
+    }
+
+    /// The arguments from `index` on, empty if there are none.
+    pub fn rest(&self, index: usize) -> &'a [Expr] {
+        if index < self.args.len() {
+            &self.args[index..]
+        } else {
+            &[]
+        }
+    }
+
+    pub fn opt(&self, index: usize) -> Option<&'a Expr> {
+        self.args.get(index)
+    }
+
+    pub fn get(&self, index: usize) -> Result<&'a Expr> {
+        self.args.get(index).ok_or_else(|| {
+            ErrorKind::Arity {
+                name: self.name.to_owned(),
+                expected: Arity::at_least(index + 1),
+                got: self.args.len(),
+            }.into()
+        })
+    }
+
+    pub fn int(&self, index: usize) -> Result<i64> {
+        ensure_int(self.name, self.get(index)?)
+    }
+
+    pub fn flt(&self, index: usize) -> Result<f64> {
+        ensure_flt(self.name, self.get(index)?)
+    }
+
+    pub fn str(&self, index: usize) -> Result<&'a str> {
+        ensure_str(self.name, self.get(index)?)
+    }
+
+    pub fn sym(&self, index: usize) -> Result<&'a Symbol> {
+        ensure_sym(self.name, self.get(index)?)
+    }
+
+    pub fn list(&self, index: usize) -> Result<&'a List> {
+        ensure_list(self.name, self.get(index)?)
+    }
+
+    pub fn vector(&self, index: usize) -> Result<&'a Vector> {
+        ensure_vector(self.name, self.get(index)?)
+    }
+
+    pub fn map(&self, index: usize) -> Result<&'a Map> {
+        ensure_map(self.name, self.get(index)?)
+    }
+
+    pub fn bytes(&self, index: usize) -> Result<&'a [u8]> {
+        ensure_bytes(self.name, self.get(index)?)
+    }
+
+    pub fn func(&self, index: usize) -> Result<Arc<Function>> {
+        ensure_func(self.name, self.get(index)?)
+    }
+
+    pub fn seq(&self, index: usize) -> Result<&'a [Expr]> {
+        ensure_seq(self.name, self.get(index)?)
+    }
+}
+
+/// Levenshtein distance between two strings, counted in chars.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut prev = (0..b.len() + 1).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1];
+        for (j, &cb) in b.iter().enumerate() {
+            let cost = if ca == cb { 0 } else { 1 };
+            let dist = cmp::min(cmp::min(prev[j + 1] + 1, cur[j] + 1), prev[j] + cost);
+            cur.push(dist);
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+/// Up to three of `candidates` closest to `name`, for "did you mean" hints.
+pub fn suggest<I>(name: &str, candidates: I) -> Vec<String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let limit = cmp::max(1, name.chars().count() / 3);
+    let mut close = candidates
+        .into_iter()
+        .map(|c| (edit_distance(name, &c), c))
+        .filter(|&(dist, _)| dist <= limit)
+        .collect::<Vec<_>>();
+    close.sort();
+    close.dedup();
+    close.into_iter().take(3).map(|(_, c)| c).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(0, edit_distance("first", "first"));
+        assert_eq!(2, edit_distance("frist", "first"));
+        assert_eq!(3, edit_distance("", "abc"));
+        assert_eq!(1, edit_distance("prnt", "print"));
+    }
+
+    #[test]
+    fn suggestions() {
+        let candidates = vec!["print", "first", "rest", "+"].into_iter().map(String::from);
+        assert_eq!(vec!["print".to_owned()], suggest("prnt", candidates.clone()));
+        assert!(suggest("xyzzy", candidates).is_empty());
+    }
+
+    #[test]
+    fn checked_args() {
+        let exprs = vec![Expr::from(1), Expr::from("two")];
+        let args = Args::new("f", &exprs);
+        assert_eq!(1, args.int(0).unwrap());
+        assert_eq!("two", args.str(1).unwrap());
+        assert!(args.str(0).is_err());
+        assert!(args.get(2).is_err());
+        assert!(args.int(5).is_err());
+        assert_eq!(None, args.opt(2));
+        assert!(args.rest(5).is_empty());
+    }
 }
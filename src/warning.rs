@@ -0,0 +1,110 @@
+//! Warnings: problems worth mentioning that don't stop evaluation, such as
+//! redefining a builtin. They're printed to stderr, once for each place in the
+//! source, unless `--werror` makes them errors.
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering, ATOMIC_BOOL_INIT};
+
+use color::{self, Color};
+use error::*;
+use location::{self, Location};
+use util::lock;
+
+static WERROR: AtomicBool = ATOMIC_BOOL_INIT;
+
+lazy_static! {
+    // Warnings already printed, with where they happened
+    static ref SEEN: Mutex<HashSet<(String, Location)>> = Mutex::new(HashSet::new());
+    // Warnings only given the first time, whatever the location
+    static ref ONCE: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+    // Forms that have already been checked for warnings
+    static ref CHECKED: Mutex<HashSet<Location>> = Mutex::new(HashSet::new());
+}
+
+thread_local! {
+    // Where warnings go instead of stderr, while they're being recorded
+    static RECORDED: RefCell<Option<Vec<String>>> = RefCell::new(None);
+}
+
+/// Make warnings errors, or not.
+pub fn set_werror(werror: bool) {
+    WERROR.store(werror, Ordering::SeqCst);
+}
+
+/// Warn about the form being evaluated, or fail if warnings are errors.
+pub fn warn(message: &str) -> Result<()> {
+    if let Some(line) = report(message, location::current(), WERROR.load(Ordering::SeqCst))? {
+        let recorded = RECORDED.with(|recorded| match *recorded.borrow_mut() {
+            Some(ref mut messages) => {
+                messages.push(message.to_owned());
+                true
+            }
+            None => false,
+        });
+        if !recorded {
+            eprintln!("{}", line);
+        }
+    }
+    Ok(())
+}
+
+/// Warn the first time this is called with `message`. If warnings are errors,
+/// every call fails.
+pub fn warn_once(message: &str) -> Result<()> {
+    if lock(&ONCE).insert(message.to_owned()) || WERROR.load(Ordering::SeqCst) {
+        warn(message)
+    } else {
+        Ok(())
+    }
+}
+
+/// Run `f`, returning the warnings it gives instead of printing them.
+#[cfg(test)]
+pub fn record<F: FnOnce() -> T, T>(f: F) -> (T, Vec<String>) {
+    RECORDED.with(|recorded| *recorded.borrow_mut() = Some(Vec::new()));
+    let result = f();
+    let messages = RECORDED.with(|recorded| recorded.borrow_mut().take());
+    (result, messages.unwrap_or_default())
+}
+
+/// Whether the form at `at` is yet to be checked for warnings, which it
+/// then counts as. Checks that are too slow to repeat each time a form runs
+/// use this to run once.
+pub fn first_check(at: &Location) -> bool {
+    lock(&CHECKED).insert(at.clone())
+}
+
+// The line to print for a warning, or None if it's been printed before
+fn report(message: &str, at: Option<Location>, werror: bool) -> Result<Option<String>> {
+    ensure!(!werror, "{} (warnings are errors)", message);
+    let line = format!("{} {}", color::paint(Color::Yellow, "warning:"), message);
+    match at {
+        Some(at) => {
+            let line = format!("{}\n{}", line, color::dim(format!("  at {}", at)));
+            Ok(if lock(&SEEN).insert((message.to_owned(), at)) { Some(line) } else { None })
+        }
+        None => Ok(Some(line)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reporting() {
+        let at = Location::new(Some("warning-test.tl".into()), 3, 1);
+        let line = report("x is never used", Some(at.clone()), false).unwrap().unwrap();
+        assert!(line.contains("x is never used") && line.contains("warning-test.tl:3:1"));
+        assert_eq!(None, report("x is never used", Some(at.clone()), false).unwrap());
+        assert!(report("y is never used", Some(at.clone()), false).unwrap().is_some());
+
+        let err = report("x is never used", Some(at.clone()), true).unwrap_err();
+        assert_eq!("x is never used (warnings are errors)", err.to_string());
+
+        assert!(first_check(&at));
+        assert!(!first_check(&at));
+    }
+}
@@ -0,0 +1,58 @@
+//! The WebAssembly build, for running Telescope in a browser. Build it as a
+//! `cdylib` with the `wasm` feature and run `wasm-bindgen` on it, and
+//! JavaScript gets `eval`, which returns text, and `evalJson`, which returns a
+//! value to `JSON.parse`.
+//!
+//! Code runs in a sandboxed interpreter that lasts as long as the page, so
+//! definitions carry over from one call to the next. Printed output is
+//! collected and returned along with the value, and input is always empty.
+
+use std::io;
+
+use wasm_bindgen::prelude::*;
+
+use error::*;
+use json;
+use port;
+use types::Expr;
+use Interpreter;
+
+thread_local! {
+    static INTERPRETER: Interpreter = {
+        port::set_input(Box::new(io::empty()));
+        Interpreter::sandboxed()
+    };
+}
+
+/// Evaluate `code`, returning what it printed followed by its value, or the
+/// error it failed with.
+#[wasm_bindgen]
+pub fn eval(code: &str) -> String {
+    match run(code) {
+        Ok((output, value)) => format!("{}{}", output, value),
+        Err(err) => format!("error: {}", err),
+    }
+}
+
+/// Evaluate `code`, returning `{"output": ..., "value": ...}` as JSON, or
+/// `{"error": ...}` if it failed.
+#[wasm_bindgen(js_name = evalJson)]
+pub fn eval_json(code: &str) -> String {
+    let result = match run(code) {
+        Ok((output, value)) => json!({ "output": output, "value": json::to_json(&value) }),
+        Err(err) => json!({ "error": err.to_string() }),
+    };
+    result.to_string()
+}
+
+// The output of evaluating `code`, and its value
+fn run(code: &str) -> Result<(String, Expr)> {
+    INTERPRETER.with(|interpreter| {
+        let mut value = Expr::Nil;
+        let output = port::capture(|| {
+            value = interpreter.eval_str(code)?;
+            Ok(Expr::Nil)
+        })?;
+        Ok((output, value))
+    })
+}
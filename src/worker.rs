@@ -0,0 +1,45 @@
+//! What futures and generators inherit from the thread that starts them. Their
+//! bodies run on other threads, but should see the same dynamic bindings and
+//! print to and read from the same places, so that `with-out-str` captures
+//! what a future prints, for instance.
+
+use std::fmt;
+
+use dynamic::{self, Bindings};
+use port::{self, Ports};
+
+/// Stack size for worker threads. Evaluation recurses on the native stack, so
+/// workers get as much room as the main thread usually does.
+pub const STACK_SIZE: usize = 8 * 1024 * 1024;
+
+/// The state a worker thread takes on from the thread that started it.
+pub struct Inherited {
+    bindings: Bindings,
+    ports: Ports,
+}
+
+impl Inherited {
+    /// The current thread's state.
+    pub fn capture() -> Self {
+        Inherited {
+            bindings: dynamic::current(),
+            ports: port::current(),
+        }
+    }
+
+    /// Run `body` on this thread with the inherited state, putting the
+    /// thread's own back afterwards.
+    pub fn run<F, T>(self, body: F) -> T
+    where
+        F: FnOnce() -> T,
+    {
+        let ports = self.ports;
+        dynamic::with_inherited(self.bindings, || port::with_ports(ports, body))
+    }
+}
+
+impl fmt::Debug for Inherited {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Inherited").field("bindings", &self.bindings).finish()
+    }
+}
@@ -0,0 +1,120 @@
+//! `tl!`, for embedding Telescope code in a Rust program. The code is parsed
+//! when the program is compiled, and the macro expands to the `Expr` it reads
+//! as, so fixed scripts cost nothing to parse at run time, and syntax errors
+//! are compile errors:
+//!
+//! ```ignore
+//! #[macro_use]
+//! extern crate telescope_macros;
+//! extern crate telescope;
+//!
+//! let form = tl!{ (+ 1 2) };
+//! assert_eq!(telescope::Expr::from(3), telescope::Interpreter::new().eval(&form)?);
+//! ```
+//!
+//! Several forms are wrapped in a `do`. Rust tokenizes the code before the
+//! macro sees it, which loses the spacing inside names like `string-length`
+//! and numbers like `-1`, so code with those goes in a string literal instead:
+//!
+//! ```ignore
+//! let form = tl!(r#"(string-length "hello")"#);
+//! ```
+
+extern crate proc_macro;
+extern crate telescope;
+
+use proc_macro::{Delimiter, Spacing, TokenStream, TokenTree};
+
+use telescope::embed;
+
+#[proc_macro]
+pub fn tl(input: TokenStream) -> TokenStream {
+    let source = string_literal(&input).unwrap_or_else(|| {
+        let mut source = String::new();
+        write_tokens(input, &mut source, &mut false);
+        source
+    });
+    let code = match embed::parse(&source).and_then(|form| embed::to_rust(&form)) {
+        Ok(code) => code,
+        Err(err) => format!("compile_error!({:?})", format!("tl!: {}", err)),
+    };
+    code.parse().expect("tl! generated invalid Rust")
+}
+
+// The contents of the input, if it's a single string literal
+fn string_literal(input: &TokenStream) -> Option<String> {
+    let mut tokens = input.clone().into_iter();
+    let literal = match (tokens.next(), tokens.next()) {
+        (Some(TokenTree::Literal(literal)), None) => literal.to_string(),
+        _ => return None,
+    };
+    if literal.starts_with('r') {
+        let hashes = literal[1..].find('"')?;
+        return Some(literal[hashes + 2..literal.len() - hashes - 1].to_owned());
+    }
+    if !literal.starts_with('"') {
+        return None;
+    }
+    let mut chars = literal[1..literal.len() - 1].chars();
+    let mut text = String::new();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => text.push('\n'),
+            't' => text.push('\t'),
+            'r' => text.push('\r'),
+            '0' => text.push('\0'),
+            // A line continuation skips the newline and the indentation after it
+            '\n' => {
+                let rest = chars.as_str().trim_left();
+                chars = rest.chars();
+            }
+            'u' => {
+                let rest = chars.as_str();
+                let end = rest.find('}')?;
+                let code = u32::from_str_radix(&rest[1..end], 16).ok()?;
+                text.push(::std::char::from_u32(code)?);
+                chars = rest[end + 1..].chars();
+            }
+            other => text.push(other),
+        }
+    }
+    Some(text)
+}
+
+// Write the tokens back out as source. Prefixes like the # of #t and the : of
+// keywords are joined to what follows, and ! and ? to what they end.
+fn write_tokens(input: TokenStream, out: &mut String, glued: &mut bool) {
+    for token in input {
+        let attach = match token {
+            TokenTree::Punct(ref punct) => "!?".contains(punct.as_char()),
+            _ => false,
+        };
+        if !*glued && !attach && !out.is_empty() && !out.ends_with(|c: char| "([{".contains(c)) {
+            out.push(' ');
+        }
+        *glued = false;
+        match token {
+            TokenTree::Group(group) => {
+                let (open, close) = match group.delimiter() {
+                    Delimiter::Parenthesis => ("(", ")"),
+                    Delimiter::Bracket => ("[", "]"),
+                    Delimiter::Brace => ("{", "}"),
+                    Delimiter::None => ("", ""),
+                };
+                out.push_str(open);
+                write_tokens(group.stream(), out, glued);
+                out.push_str(close);
+                *glued = false;
+            }
+            TokenTree::Punct(punct) => {
+                out.push(punct.as_char());
+                *glued = punct.spacing() == Spacing::Joint || "#:'`~@^".contains(punct.as_char());
+            }
+            other => out.push_str(&other.to_string()),
+        }
+    }
+}
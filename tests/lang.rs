@@ -0,0 +1,102 @@
+//! The golden tests in `tests/lang`. Each `.tl` file there runs in a fresh
+//! interpreter, and what it prints is compared with the `;>` comments in it,
+//! line for line. If evaluation fails, the error is the last line of output,
+//! as `error: <message>`.
+//!
+//! These pin down what programs do, not how, so the evaluator can be changed
+//! underneath them.
+
+extern crate telescope;
+
+use std::cmp;
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::path::Path;
+
+use telescope::Interpreter;
+
+#[test]
+fn golden() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("lang");
+    let mut paths = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "tl"))
+        .collect::<Vec<_>>();
+    paths.sort();
+    assert!(!paths.is_empty(), "no golden tests in {}", dir.display());
+
+    let failures = paths.iter().filter_map(|path| check(path)).collect::<Vec<_>>();
+    if !failures.is_empty() {
+        panic!(
+            "{} of {} golden tests failed:\n\n{}",
+            failures.len(),
+            paths.len(),
+            failures.join("\n\n")
+        );
+    }
+}
+
+// How the output differed from what was expected, if it did
+fn check(path: &Path) -> Option<String> {
+    let mut source = String::new();
+    File::open(path).unwrap().read_to_string(&mut source).unwrap();
+    let expected = source.lines().filter_map(expected_line).collect::<Vec<_>>();
+
+    let (result, output) = Interpreter::new().eval_str_captured(&source);
+    let mut actual = output.lines().map(str::to_owned).collect::<Vec<_>>();
+    if let Err(err) = result {
+        actual.push(format!("error: {}", err));
+    }
+    if actual == expected {
+        None
+    } else {
+        Some(format!("{} (- expected, + actual):\n{}", path.display(), diff(&expected, &actual)))
+    }
+}
+
+fn expected_line(line: &str) -> Option<String> {
+    let line = line.trim_left();
+    if line == ";>" {
+        Some(String::new())
+    } else if line.starts_with(";> ") {
+        Some(line[3..].to_owned())
+    } else {
+        None
+    }
+}
+
+// A line diff, from the longest common subsequence of the lines
+fn diff(expected: &[String], actual: &[String]) -> String {
+    // common[i][j] is the length of the longest common subsequence of
+    // expected[i..] and actual[j..]
+    let mut common = vec![vec![0; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            common[i][j] = if expected[i] == actual[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                cmp::max(common[i + 1][j], common[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            lines.push(format!("  {}", expected[i]));
+            i += 1;
+            j += 1;
+        } else if j < actual.len()
+            && (i == expected.len() || common[i][j + 1] >= common[i + 1][j])
+        {
+            lines.push(format!("+ {}", actual[j]));
+            j += 1;
+        } else {
+            lines.push(format!("- {}", expected[i]));
+            i += 1;
+        }
+    }
+    lines.join("\n")
+}